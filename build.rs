@@ -1,6 +1,8 @@
 use std::{
     env,
     path::{Path, PathBuf},
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 fn main() {
@@ -18,11 +20,45 @@ fn main() {
     compile_error!("feature \"portable\" or feature \"xdg\" must be enabled");
     #[cfg(all(feature = "portable", feature = "xdg"))]
     compile_error!("feature \"portable\" and feature \"xdg\" cannot be enabled at the same time");
+    build_metadata();
     if env::var("CARGO_CFG_TARGET_OS").unwrap() == "android" {
         android();
     }
 }
 
+/// Passes the commit, build time, compiler and target to `/api/version`
+fn build_metadata() {
+    let output = |cmd: &str, args: &[&str]| {
+        Command::new(cmd)
+            .args(args)
+            .output()
+            .ok()
+            .filter(|o| o.status.success())
+            .and_then(|o| String::from_utf8(o.stdout).ok())
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+    };
+    // source tarballs have no git history, the commit is left unset there
+    if let Some(commit) = output("git", &["rev-parse", "--short=12", "HEAD"]) {
+        println!("cargo:rustc-env=CLEWDR_GIT_COMMIT={commit}");
+    }
+    // reproducible builds pin the time through SOURCE_DATE_EPOCH
+    let timestamp = env::var("SOURCE_DATE_EPOCH").ok().unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs())
+            .to_string()
+    });
+    println!("cargo:rustc-env=CLEWDR_BUILD_TIMESTAMP={timestamp}");
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version = output(&rustc, &["--version"]).unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=CLEWDR_RUSTC_VERSION={rustc_version}");
+    println!(
+        "cargo:rustc-env=CLEWDR_TARGET={}",
+        env::var("TARGET").unwrap()
+    );
+}
+
 fn android() {
     println!("cargo:rustc-link-lib=c++_shared");
     let out_dir = env::var("OUT_DIR").unwrap();
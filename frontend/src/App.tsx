@@ -9,6 +9,7 @@ import AuthGatekeeper from "./components/auth/AuthGatekeeper";
 import LogoutPanel from "./components/auth/LogoutPanel";
 import ClaudeTabs from "./components/claude";
 import ConfigTab from "./components/config";
+import SloTab from "./components/slo";
 import StatusMessage from "./components/common/StatusMessage";
 import ErrorBoundary from "./components/common/ErrorBoundary";
 import { useAppContext } from "./context/AppContext";
@@ -50,6 +51,7 @@ function App() {
   const tabs = [
     { id: "claude", label: t("tabs.claude"), color: "cyan" },
     { id: "config", label: t("tabs.config"), color: "green" },
+    { id: "slo", label: t("tabs.slo"), color: "amber" },
     { id: "token", label: t("tabs.auth"), color: "violet" },
   ];
 
@@ -71,6 +73,8 @@ function App() {
                 <ClaudeTabs />
               ) : activeTab === "config" ? (
                 <ConfigTab />
+              ) : activeTab === "slo" ? (
+                <SloTab />
               ) : (
                 <LogoutPanel onLogout={handleLogout} />
               )}
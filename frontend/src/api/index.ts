@@ -7,6 +7,19 @@ export async function getVersion() {
   return await response.text();
 }
 
+/**
+ * Fetches the version with build details and uptime
+ */
+export async function getVersionDetails(): Promise<VersionResponse> {
+  const response = await fetch("/api/version", {
+    headers: { Accept: "application/json" },
+  });
+  if (!response.ok) {
+    throw new Error(`Failed to fetch version: ${response.status}`);
+  }
+  return await response.json();
+}
+
 /**
  * Validates authentication token
  * @param token The auth token to validate
@@ -46,7 +59,10 @@ export async function postCookie(cookie: string) {
   });
 
   if (response.status === 400) {
-    throw new Error("Invalid cookie format");
+    const body = await response.json().catch(() => ({}));
+    throw new Error(body.error || "Invalid cookie format");
+  } else if (response.status === 409) {
+    throw new Error("Cookie already exists");
   } else if (response.status === 401) {
     throw new Error("Authentication failed. Please set a valid auth token.");
   } else if (response.status === 500) {
@@ -99,6 +115,40 @@ export async function getCookieStatus(forceRefresh = false) {
   };
 }
 
+/**
+ * Gets one page of the cookies, optionally of a single status
+ * @param page Page to fetch, counting from 1
+ * @param perPage Cookies per page
+ * @param status Only list cookies in this state
+ */
+export async function getCookiePage(
+  page: number,
+  perPage: number,
+  status?: "valid" | "exhausted" | "invalid"
+) {
+  const token = localStorage.getItem("authToken") || "";
+  const params = new URLSearchParams({
+    page: String(page),
+    per_page: String(perPage),
+  });
+  if (status) {
+    params.set("status", status);
+  }
+
+  const response = await fetch(`/api/cookies?${params}`, {
+    method: "GET",
+    headers: {
+      Authorization: `Bearer ${token}`,
+    },
+  });
+
+  if (!response.ok) {
+    throw new Error(`Error ${response.status}: ${response.statusText}`);
+  }
+
+  return await response.json();
+}
+
 /**
  * Deletes a cookie from the server.
  * @param cookie The cookie string to delete
@@ -167,11 +217,71 @@ export async function getConfig() {
   return await response.json();
 }
 
+/**
+ * Changes part of the config, leaving every other field as it is on the server
+ * @param patch JSON merge patch, null removes an optional field
+ * @returns The resulting config with secrets redacted, and warnings
+ */
+export async function patchConfig(patch: Partial<ConfigData>) {
+  const token = localStorage.getItem("authToken") || "";
+  const response = await fetch("/api/config", {
+    method: "PATCH",
+    headers: {
+      "Content-Type": "application/json",
+      Authorization: `Bearer ${token}`,
+    },
+    body: JSON.stringify(patch),
+  });
+
+  if (!response.ok) {
+    const errorData = await response.json().catch(() => ({}));
+    throw new Error(
+      configError(errorData) || `Failed to patch config: ${response.status}`
+    );
+  }
+
+  return await response.json();
+}
+
+/**
+ * Fetches the config data with its secrets, asking for the admin password again
+ * @param password The admin password, sent as re-authentication
+ */
+export async function revealConfig(password: string) {
+  const token = localStorage.getItem("authToken") || "";
+  const response = await fetch("/api/config?reveal=true", {
+    method: "GET",
+    headers: {
+      "Content-Type": "application/json",
+      Authorization: `Bearer ${token}`,
+      "X-Clewdr-Reauth": password,
+    },
+  });
+
+  if (!response.ok) {
+    throw new Error(`Failed to reveal config: ${response.status}`);
+  }
+
+  return await response.json();
+}
+
 /**
  * Saves config data to the server
  * @param configData The config data to save
  */
-import type { ConfigData } from "../types/config.types";
+import type {
+  ApiKey,
+  ApiKeyUsage,
+  ConfigData,
+  FieldError,
+  KeyEndpoint,
+  RateLimitOffender,
+} from "../types/config.types";
+import type { SloData } from "../types/slo.types";
+import type { ConformanceData } from "../types/conformance.types";
+import type { AuditData } from "../types/audit.types";
+import type { VersionResponse } from "../types/api.types";
+import type { BatchReport, DeleteReport } from "../types/cookie.types";
 
 export async function saveConfig(configData: ConfigData) {
   const token = localStorage.getItem("authToken") || "";
@@ -186,18 +296,27 @@ export async function saveConfig(configData: ConfigData) {
 
   if (!response.ok) {
     // Try to include server error message when available for easier debugging
-    try {
-      const data = await response.json();
-      const serverMsg = typeof data?.error === "string" ? ` - ${data.error}` : "";
-      throw new Error(`Failed to save config: ${response.status}${serverMsg}`);
-    } catch (_) {
-      throw new Error(`Failed to save config: ${response.status}`);
-    }
+    const data = await response.json().catch(() => ({}));
+    const serverMsg = configError(data);
+    throw new Error(
+      `Failed to save config: ${response.status}${serverMsg ? ` - ${serverMsg}` : ""}`
+    );
   }
 
   return response;
 }
 
+/**
+ * Renders the error body of a rejected config update, with each invalid field
+ */
+function configError(data: { error?: unknown; errors?: FieldError[] }) {
+  if (typeof data?.error !== "string") {
+    return "";
+  }
+  const fields = (data.errors ?? []).map((e) => `${e.field} ${e.message}`);
+  return fields.length ? `${data.error}: ${fields.join("; ")}` : data.error;
+}
+
 // Add this new function to frontend/src/api/index.ts
 
 /**
@@ -221,10 +340,17 @@ export async function postMultipleCookies(cookies: string[]) {
       });
 
       if (response.status === 400) {
+        const body = await response.json().catch(() => ({}));
         results.push({
           cookie,
           success: false,
-          message: "Invalid cookie format",
+          message: body.error || "Invalid cookie format",
+        });
+      } else if (response.status === 409) {
+        results.push({
+          cookie,
+          success: false,
+          message: "Cookie already exists",
         });
       } else if (response.status === 401) {
         results.push({
@@ -262,3 +388,330 @@ export async function postMultipleCookies(cookies: string[]) {
 
   return results;
 }
+
+/**
+ * Fetches SLO compliance, error budget burn and recent alert events
+ */
+export async function getSlo(): Promise<SloData> {
+  const token = localStorage.getItem("authToken") || "";
+  const response = await fetch("/api/slo", {
+    method: "GET",
+    headers: {
+      "Content-Type": "application/json",
+      Authorization: `Bearer ${token}`,
+    },
+  });
+
+  if (!response.ok) {
+    throw new Error(`Failed to fetch SLO data: ${response.status}`);
+  }
+
+  return await response.json();
+}
+
+/**
+ * Fetches the last conformance report of each backend
+ */
+export async function getConformance(): Promise<ConformanceData> {
+  const token = localStorage.getItem("authToken") || "";
+  const response = await fetch("/api/conformance", {
+    method: "GET",
+    headers: {
+      "Content-Type": "application/json",
+      Authorization: `Bearer ${token}`,
+    },
+  });
+
+  if (!response.ok) {
+    throw new Error(`Failed to fetch conformance reports: ${response.status}`);
+  }
+
+  return await response.json();
+}
+
+/**
+ * Fetches recorded admin actions, newest first
+ * @param limit Most entries returned
+ * @param before Only entries older than this id
+ */
+export async function getAudit(
+  limit?: number,
+  before?: number
+): Promise<AuditData> {
+  const token = localStorage.getItem("authToken") || "";
+  const params = new URLSearchParams();
+  if (limit !== undefined) params.set("limit", String(limit));
+  if (before !== undefined) params.set("before", String(before));
+  const response = await fetch(`/api/audit?${params}`, {
+    method: "GET",
+    headers: {
+      "Content-Type": "application/json",
+      Authorization: `Bearer ${token}`,
+    },
+  });
+
+  if (!response.ok) {
+    throw new Error(`Failed to fetch audit log: ${response.status}`);
+  }
+
+  return await response.json();
+}
+
+/**
+ * Submits many cookies at once, as newline separated text or a JSON array
+ * @param body Cookies to submit
+ * @param validate Check each new cookie against claude.ai first
+ * @param storeInvalid Store cookies that failed validation anyway
+ */
+export async function postCookieBatch(
+  body: string,
+  validate = false,
+  storeInvalid = false
+): Promise<BatchReport> {
+  const token = localStorage.getItem("authToken") || "";
+  const params = new URLSearchParams();
+  if (validate) params.set("validate", "true");
+  if (storeInvalid) params.set("store_invalid", "true");
+  const response = await fetch(`/api/cookies/batch?${params}`, {
+    method: "POST",
+    headers: {
+      "Content-Type": "text/plain",
+      Authorization: `Bearer ${token}`,
+    },
+    body,
+  });
+
+  if (!response.ok) {
+    throw new Error(`Failed to submit cookies: ${response.status}`);
+  }
+
+  return await response.json();
+}
+
+/**
+ * Deletes many cookies at once
+ * @param selection Cookie strings, or every cookie with the given status
+ */
+export async function deleteCookies(
+  selection: string[] | { status: "valid" | "exhausted" | "invalid" }
+): Promise<DeleteReport> {
+  const token = localStorage.getItem("authToken") || "";
+  const response = await fetch("/api/cookies/delete", {
+    method: "POST",
+    headers: {
+      "Content-Type": "application/json",
+      Authorization: `Bearer ${token}`,
+    },
+    body: JSON.stringify(selection),
+  });
+
+  if (!response.ok) {
+    throw new Error(`Failed to delete cookies: ${response.status}`);
+  }
+
+  return await response.json();
+}
+
+/**
+ * Downloads a slice of the log files as a gzip file
+ * @param from RFC 3339 time of the first line kept
+ * @param to RFC 3339 time of the last line kept
+ * @param level Least severe level kept
+ */
+export async function downloadLogs(
+  from?: string,
+  to?: string,
+  level?: string
+): Promise<Blob> {
+  const token = localStorage.getItem("authToken") || "";
+  const params = new URLSearchParams();
+  if (from) params.set("from", from);
+  if (to) params.set("to", to);
+  if (level) params.set("level", level);
+  const response = await fetch(`/api/logs/download?${params}`, {
+    headers: {
+      Authorization: `Bearer ${token}`,
+    },
+  });
+
+  if (!response.ok) {
+    throw new Error(`Failed to download logs: ${response.status}`);
+  }
+
+  return await response.blob();
+}
+
+/**
+ * Downloads one log file as stored
+ * @param file Name of the file, `clewdr.log` or a rotated file
+ */
+export async function downloadLogFile(file: string): Promise<Blob> {
+  const token = localStorage.getItem("authToken") || "";
+  const params = new URLSearchParams({ file });
+  const response = await fetch(`/api/logs/download?${params}`, {
+    headers: {
+      Authorization: `Bearer ${token}`,
+    },
+  });
+
+  if (!response.ok) {
+    throw new Error(`Failed to download log file: ${response.status}`);
+  }
+
+  return await response.blob();
+}
+
+/**
+ * Replaces the admin password with a random one and keeps using the new one
+ * @returns The new admin password, shown by the server only this once
+ */
+export async function rotateAdminPassword(): Promise<string> {
+  const token = localStorage.getItem("authToken") || "";
+  const response = await fetch("/api/auth/rotate", {
+    method: "POST",
+    headers: {
+      Authorization: `Bearer ${token}`,
+    },
+  });
+
+  if (!response.ok) {
+    throw new Error(`Failed to rotate admin password: ${response.status}`);
+  }
+
+  const { admin_password } = await response.json();
+  localStorage.setItem("authToken", admin_password);
+  return admin_password;
+}
+
+/**
+ * Lists the client API keys with their use since startup
+ * @returns Keys, shortened, with request counts and last use
+ */
+export async function getApiKeys(): Promise<ApiKeyUsage[]> {
+  const token = localStorage.getItem("authToken") || "";
+  const response = await fetch("/api/keys", {
+    headers: {
+      Authorization: `Bearer ${token}`,
+    },
+  });
+
+  if (!response.ok) {
+    throw new Error(`Failed to get API keys: ${response.status}`);
+  }
+
+  const { keys } = await response.json();
+  return keys;
+}
+
+/**
+ * Creates a client API key with a random value
+ * @param name Name of the key
+ * @param allowedEndpoints Endpoints the key may use, every endpoint when empty
+ * @returns The key, its value shown by the server only this once
+ */
+export async function createApiKey(
+  name: string,
+  allowedEndpoints: KeyEndpoint[] = []
+): Promise<ApiKey> {
+  const token = localStorage.getItem("authToken") || "";
+  const response = await fetch("/api/keys", {
+    method: "POST",
+    headers: {
+      "Content-Type": "application/json",
+      Authorization: `Bearer ${token}`,
+    },
+    body: JSON.stringify({ name, allowed_endpoints: allowedEndpoints }),
+  });
+
+  if (!response.ok) {
+    throw new Error(`Failed to create API key: ${response.status}`);
+  }
+
+  return await response.json();
+}
+
+/**
+ * Disables a client API key, or removes it
+ * @param name Name of the key
+ * @param purge Remove the key instead of disabling it
+ */
+export async function revokeApiKey(name: string, purge = false) {
+  const token = localStorage.getItem("authToken") || "";
+  const response = await fetch(
+    `/api/keys/${encodeURIComponent(name)}?purge=${purge}`,
+    {
+      method: "DELETE",
+      headers: {
+        Authorization: `Bearer ${token}`,
+      },
+    }
+  );
+
+  if (!response.ok) {
+    throw new Error(`Failed to revoke API key: ${response.status}`);
+  }
+
+  return await response.json();
+}
+
+/**
+ * Drops every cached response
+ */
+export async function clearResponseCache() {
+  const token = localStorage.getItem("authToken") || "";
+  const response = await fetch("/api/cache", {
+    method: "DELETE",
+    headers: {
+      Authorization: `Bearer ${token}`,
+    },
+  });
+
+  if (!response.ok) {
+    throw new Error(`Failed to clear response cache: ${response.status}`);
+  }
+
+  return await response.json();
+}
+
+/**
+ * Lists the client addresses the rate limiter rejected
+ * @returns Offenders, most rejected first
+ */
+export async function getRateLimitOffenders(): Promise<RateLimitOffender[]> {
+  const token = localStorage.getItem("authToken") || "";
+  const response = await fetch("/api/ratelimit/offenders", {
+    headers: {
+      Authorization: `Bearer ${token}`,
+    },
+  });
+
+  if (!response.ok) {
+    throw new Error(`Failed to get rate limit offenders: ${response.status}`);
+  }
+
+  const { offenders } = await response.json();
+  return offenders;
+}
+
+/**
+ * Clears the limits and block of one client address
+ * @param ip Address of the client
+ */
+export async function resetRateLimit(ip: string) {
+  const token = localStorage.getItem("authToken") || "";
+  const response = await fetch(
+    `/api/ratelimit/offenders/${encodeURIComponent(ip)}`,
+    {
+      method: "DELETE",
+      headers: {
+        Authorization: `Bearer ${token}`,
+      },
+    }
+  );
+
+  if (!response.ok) {
+    throw new Error(`Failed to reset rate limit: ${response.status}`);
+  }
+
+  return await response.json();
+}
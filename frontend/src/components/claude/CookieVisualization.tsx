@@ -128,6 +128,7 @@ const CookieVisualization: React.FC = () => {
           | "sonnet_output_tokens"
           | "opus_input_tokens"
           | "opus_output_tokens"
+          | "cost_usd"
         >
       >;
       showSonnet: boolean;
@@ -141,6 +142,7 @@ const CookieVisualization: React.FC = () => {
       sonnet_output_tokens: x.sonnet_output_tokens ?? 0,
       opus_input_tokens: x.opus_input_tokens ?? 0,
       opus_output_tokens: x.opus_output_tokens ?? 0,
+      cost_usd: x.cost_usd ?? 0,
     });
 
     const sReq = toReq(s);
@@ -223,6 +225,11 @@ const CookieVisualization: React.FC = () => {
               <span>
                 {t("cookieStatus.usage.totalOutput")}: {b.total_output_tokens}
               </span>
+              {b.cost_usd > 0 && (
+                <span>
+                  {t("cookieStatus.usage.cost")}: ${b.cost_usd.toFixed(2)}
+                </span>
+              )}
             </div>
             {showSonnet && (
               <div className="flex gap-3 flex-wrap pl-1 text-gray-500">
@@ -616,9 +623,22 @@ const CookieVisualization: React.FC = () => {
                   )}
                 </div>
                 <div className="flex items-center">
-                  <span className="text-gray-400">
-                    {t("cookieStatus.status.available")}
-                  </span>
+                  {(status.in_flight ?? 0) > 0 && (
+                    <span className="text-cyan-300 mr-2">
+                      {t("cookieStatus.status.inFlight", {
+                        count: status.in_flight,
+                      })}
+                    </span>
+                  )}
+                  {status.needs_reauth ? (
+                    <span className="text-amber-300">
+                      {t("cookieStatus.status.needsReauth")}
+                    </span>
+                  ) : (
+                    <span className="text-gray-400">
+                      {t("cookieStatus.status.available")}
+                    </span>
+                  )}
                   <DeleteButton
                     cookie={status.cookie}
                     onDelete={handleDeleteCookie}
@@ -0,0 +1,80 @@
+import React, { useState, useEffect } from "react";
+import { useTranslation } from "react-i18next";
+import { getConformance } from "../../api";
+import { ConformanceReport, Verdict } from "../../types/conformance.types";
+
+const verdictColor: Record<Verdict, string> = {
+  pass: "text-green-400",
+  fail: "text-red-400",
+  skip: "text-yellow-400",
+};
+
+const Conformance: React.FC = () => {
+  const { t } = useTranslation();
+  const [reports, setReports] = useState<ConformanceReport[]>([]);
+  const [error, setError] = useState("");
+
+  useEffect(() => {
+    getConformance()
+      .then((data) => setReports(data.reports))
+      .catch((err) =>
+        setError(
+          t("common.error", {
+            message: err instanceof Error ? err.message : String(err),
+          })
+        )
+      );
+  }, [t]);
+
+  return (
+    <div className="space-y-2">
+      <h4 className="text-sm font-medium text-gray-300">
+        {t("conformance.title")}
+      </h4>
+      {error && <p className="text-red-400 text-sm">{error}</p>}
+      {!error && reports.length === 0 && (
+        <p className="text-gray-400 text-xs">{t("conformance.empty")}</p>
+      )}
+      {reports.map((report) => (
+        <div key={report.backend} className="bg-gray-700 p-4 rounded-lg">
+          <div className="flex items-center justify-between mb-2">
+            <span className="font-medium text-white">
+              {report.backend}
+              {report.demo && ` (${t("conformance.demo")})`}
+            </span>
+            <span className="text-xs text-gray-400">
+              {t("conformance.summary", {
+                score: (report.score * 100).toFixed(0),
+                passed: report.passed,
+                failed: report.failed,
+                skipped: report.skipped,
+              })}{" "}
+              {new Date(report.finished_at * 1000).toLocaleString()}
+            </span>
+          </div>
+          <table className="w-full text-xs text-gray-300">
+            <tbody>
+              {report.invariants.map((r) => (
+                <tr key={`${r.scenario}-${r.invariant}`} title={r.detail}>
+                  <td className={verdictColor[r.verdict]}>{r.verdict}</td>
+                  <td>{r.scenario}</td>
+                  <td>
+                    {r.invariant}
+                    {r.adapted && (
+                      <span className="text-gray-500">
+                        {" "}
+                        ({t("conformance.adapted")})
+                      </span>
+                    )}
+                  </td>
+                </tr>
+              ))}
+            </tbody>
+          </table>
+        </div>
+      ))}
+    </div>
+  );
+};
+
+export default Conformance;
@@ -0,0 +1,125 @@
+import React, { useState, useEffect } from "react";
+import { useTranslation } from "react-i18next";
+import { getSlo } from "../../api";
+import { SloData } from "../../types/slo.types";
+import Button from "../common/Button";
+import Conformance from "./Conformance";
+import LoadingSpinner from "../common/LoadingSpinner";
+
+const percent = (value: number | null) =>
+  value === null ? "-" : `${(value * 100).toFixed(2)}%`;
+
+const SloTab: React.FC = () => {
+  const { t } = useTranslation();
+  const [data, setData] = useState<SloData | null>(null);
+  const [loading, setLoading] = useState(true);
+  const [error, setError] = useState("");
+
+  useEffect(() => {
+    fetchSlo();
+  }, []);
+
+  const fetchSlo = async () => {
+    setLoading(true);
+    setError("");
+    try {
+      setData(await getSlo());
+    } catch (err) {
+      setError(
+        t("common.error", {
+          message: err instanceof Error ? err.message : String(err),
+        })
+      );
+    } finally {
+      setLoading(false);
+    }
+  };
+
+  if (loading) {
+    return (
+      <div className="flex justify-center py-8">
+        <LoadingSpinner />
+      </div>
+    );
+  }
+
+  return (
+    <div className="space-y-4">
+      <div className="flex items-center justify-between">
+        <h3 className="text-lg font-medium text-white">{t("slo.title")}</h3>
+        <Button onClick={fetchSlo} className="py-1 px-3 text-sm">
+          {t("slo.refresh")}
+        </Button>
+      </div>
+      {error && <p className="text-red-400 text-sm">{error}</p>}
+      {data && data.slos.length === 0 && (
+        <p className="text-gray-400 text-sm">{t("slo.empty")}</p>
+      )}
+      {data?.slos.map((slo) => (
+        <div key={slo.name} className="bg-gray-700 p-4 rounded-lg">
+          <div className="flex items-center justify-between mb-2">
+            <span className="font-medium text-white">{slo.name}</span>
+            <span className="text-xs text-gray-400">
+              {t("slo.objective", {
+                target: percent(slo.target),
+                latency: slo.latency_secs,
+              })}
+            </span>
+          </div>
+          <table className="w-full text-sm text-gray-300">
+            <thead>
+              <tr className="text-gray-400 text-left">
+                <th>{t("slo.window")}</th>
+                <th>{t("slo.requests")}</th>
+                <th>{t("slo.compliance")}</th>
+                <th>{t("slo.burnRate")}</th>
+              </tr>
+            </thead>
+            <tbody>
+              {slo.windows.map((w) => (
+                <tr key={w.window}>
+                  <td>{w.window}</td>
+                  <td>{w.total}</td>
+                  <td>{percent(w.compliance)}</td>
+                  <td>{w.burn_rate === null ? "-" : w.burn_rate.toFixed(2)}</td>
+                </tr>
+              ))}
+            </tbody>
+          </table>
+          <div className="flex gap-4 mt-2 text-xs">
+            <span className="text-gray-400">
+              {t("slo.budgetRemaining")}: {percent(slo.budget_remaining)}
+            </span>
+            {slo.fast_burn_firing && (
+              <span className="text-red-400">{t("slo.fastBurn")}</span>
+            )}
+            {slo.slow_burn_firing && (
+              <span className="text-yellow-400">{t("slo.slowBurn")}</span>
+            )}
+          </div>
+        </div>
+      ))}
+      {data && data.events.length > 0 && (
+        <div>
+          <h4 className="text-sm font-medium text-gray-300 mb-1">
+            {t("slo.events")}
+          </h4>
+          <ul className="text-xs text-gray-400 space-y-1">
+            {[...data.events].reverse().map((e) => (
+              <li key={`${e.slo}-${e.kind}-${e.timestamp}`}>
+                {new Date(e.timestamp * 1000).toLocaleString()} {e.slo}{" "}
+                {e.kind === "fast_burn" ? t("slo.fastBurn") : t("slo.slowBurn")}{" "}
+                {e.firing
+                  ? t("slo.firing", { rate: e.burn_rate.toFixed(2) })
+                  : t("slo.resolved")}
+              </li>
+            ))}
+          </ul>
+        </div>
+      )}
+      <Conformance />
+    </div>
+  );
+};
+
+export default SloTab;
@@ -10,4 +10,15 @@ export interface CookieSubmitRequest {
 
 export interface VersionResponse {
   version: string;
+  package_version: string;
+  git_commit: string | null;
+  /** Unix timestamp of the build */
+  build_timestamp: number | null;
+  rustc_version: string;
+  target: string;
+  uptime_secs: number;
+  features: {
+    mcp: boolean;
+    oauth: boolean;
+  };
 }
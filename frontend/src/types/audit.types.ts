@@ -0,0 +1,26 @@
+export type AuditAction =
+  | "config_update"
+  | "config_import"
+  | "config_reveal"
+  | "cookie_add"
+  | "cookie_update"
+  | "cookie_delete"
+  | "transcript_purge"
+  | "admin_rotate"
+  | "api_key_create"
+  | "api_key_revoke"
+  | "cache_flush"
+  | "rate_limit_reset";
+
+export interface AuditEntry {
+  id: number;
+  timestamp: number;
+  actor: string;
+  action: AuditAction;
+  summary: string;
+  error: string | null;
+}
+
+export interface AuditData {
+  entries: AuditEntry[];
+}
@@ -1,4 +1,7 @@
 export interface ConfigData {
+  // Format of the config file, set by the server
+  config_version?: number;
+
   // Server settings
   ip: string;
   port: number;
@@ -7,20 +10,55 @@ export interface ConfigData {
   check_update: boolean;
   auto_update: boolean;
   no_fs?: boolean;
+  storage?: "file" | "sqlite";
+  drain_timeout_secs?: number;
   log_to_file?: boolean;
+  log_format?: "text" | "json";
+  log_rotation?: LogRotation;
+  log_download_max_mb?: number;
+  record_transcripts?: boolean;
+  transcript_max_mb?: number;
+  redaction?: RedactionRules;
+  demo?: boolean;
+  demo_error_rate?: number;
 
   // Network settings
   password: string;
   admin_password: string;
+  api_keys?: ApiKey[];
   proxy: string | null;
   rproxy: string | null;
 
   // API settings
   max_retries: number;
+  upstream_retry?: UpstreamRetry;
+  response_cache?: ResponseCacheConfig;
+  cookie_strategy?: "round_robin" | "least_used" | "random";
+  debug_account_header?: boolean;
   preserve_chats: boolean;
+  conversation_reuse?: ConversationReuse;
   web_search: boolean;
   enable_web_count_tokens: boolean;
   sanitize_messages: boolean;
+  model_aliases?: Record<string, string>;
+  default_model?: string | null;
+  default_thinking_budget?: number | null;
+  strip_thinking?: boolean;
+  request_reports?: "off" | "admin" | "all";
+  header_passthrough?: HeaderPassthrough;
+  rate_limit?: RateLimitConfig;
+  sse_keep_alive_secs?: number;
+  account_cache_ttl_secs?: number;
+  model_list_ttl_secs?: number;
+  token_expiry_skew_secs?: number;
+  readiness_requires_cookie?: boolean;
+  language_policy?: LanguagePolicy;
+  prompt_preset?: PromptPreset;
+
+  // Spend tracking
+  pricing?: ModelPrice[];
+  default_price?: TokenRates;
+  price_web_requests?: boolean;
 
   // Claude Code settings
   claude_code_telemetry?: boolean;
@@ -32,6 +70,13 @@ export interface ConfigData {
   skip_non_pro: boolean;
   skip_rate_limit: boolean;
   skip_normal_pro: boolean;
+  ban_auto_remove_after?: number;
+  web_cookie_concurrency?: number;
+  code_cookie_concurrency?: number;
+  queue_timeout_ms?: number;
+  max_queued?: number;
+  retry_window_secs?: number;
+  max_retry_boost?: number;
 
   // Prompt configurations
   use_real_roles: boolean;
@@ -50,3 +95,121 @@ export interface ConfigState {
   saving: boolean;
   error: string;
 }
+
+export interface HeaderPassthrough {
+  claude_code: string[];
+  claude_web: string[];
+  user: string[] | null;
+  synthesize_web: boolean;
+}
+
+export interface TokenRates {
+  input: number;
+  output: number;
+  cache_read: number;
+  cache_write: number;
+}
+
+export interface ModelPrice extends TokenRates {
+  model: string;
+  from: string | null;
+  until: string | null;
+}
+
+export interface RedactionRules {
+  emails: boolean;
+  names: boolean;
+  known_names: string[];
+  secrets: string[];
+  placeholder_user_messages: boolean;
+  digest_tool_results: boolean;
+}
+
+export type LanguageMode = "off" | "annotate" | "warn" | "enforce";
+
+export interface LogRotation {
+  max_mb: number;
+  max_age_hours: number;
+  retain_mb: number;
+  retain_days: number;
+  compress: boolean;
+}
+
+export type KeyEndpoint = "claude_web" | "claude_code";
+
+export interface ApiKey {
+  name: string;
+  key: string;
+  enabled: boolean;
+  allowed_endpoints: KeyEndpoint[];
+}
+
+export interface ApiKeyUsage extends Omit<ApiKey, "key"> {
+  /** Start of the key only */
+  key: string;
+  requests: number;
+  last_used: number | null;
+}
+
+export interface UpstreamRetry {
+  max_retries: number;
+  statuses: number[];
+  backoff_ms: number;
+}
+
+export interface ConversationReuse {
+  enabled: boolean;
+  ttl_secs: number;
+  max_sessions: number;
+}
+
+export interface BucketLimit {
+  burst: number;
+  per_minute: number;
+}
+
+export interface RateLimitConfig {
+  enabled: boolean;
+  authenticated: BucketLimit;
+  unauthenticated: BucketLimit;
+  trusted_proxies: string[];
+  block_after: number;
+  violation_window_secs: number;
+  block_secs: number;
+}
+
+export interface RateLimitOffender {
+  ip: string;
+  rejected: number;
+  violations: number;
+  blocked_secs: number | null;
+  last_rejected: number | null;
+}
+
+export interface ResponseCacheConfig {
+  enabled: boolean;
+  ttl_secs: number;
+  max_entries: number;
+  cache_nondeterministic: boolean;
+}
+
+export interface PromptPreset {
+  system_prefix: string | null;
+  system_suffix: string | null;
+  extra_stop_sequences: string[];
+  force_temperature: number | null;
+  temperature_range: [number, number] | null;
+  apply_to_code: boolean;
+}
+
+export interface LanguagePolicy {
+  mode: LanguageMode;
+  required: string | null;
+  prefix_chars: number;
+}
+
+/** A value a config update was rejected for, `field` is a JSON pointer */
+export interface FieldError {
+  field: string;
+  message: string;
+}
@@ -0,0 +1,25 @@
+export type Verdict = "pass" | "fail" | "skip";
+
+export interface InvariantResult {
+  scenario: string;
+  invariant: string;
+  verdict: Verdict;
+  detail: string;
+  adapted: boolean;
+}
+
+export interface ConformanceReport {
+  backend: "web" | "code";
+  model: string;
+  demo: boolean;
+  finished_at: number;
+  passed: number;
+  failed: number;
+  skipped: number;
+  score: number;
+  invariants: InvariantResult[];
+}
+
+export interface ConformanceData {
+  reports: ConformanceReport[];
+}
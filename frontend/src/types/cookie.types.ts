@@ -6,6 +6,7 @@ export interface UsageBreakdown {
   sonnet_output_tokens?: number;
   opus_input_tokens?: number;
   opus_output_tokens?: number;
+  cost_usd?: number;
 }
 
 export interface CookieStatus {
@@ -14,6 +15,10 @@ export interface CookieStatus {
   supports_claude_1m_sonnet?: boolean | null;
   supports_claude_1m_opus?: boolean | null;
   count_tokens_allowed?: boolean | null;
+  // Claude Code refresh token was rejected, the cookie must re-authorize
+  needs_reauth?: boolean;
+  // Warning or restriction told by the latest classified upstream error
+  flag?: AccountFlag;
   // New usage buckets
   session_usage?: UsageBreakdown;
   weekly_usage?: UsageBreakdown;
@@ -30,17 +35,41 @@ export interface CookieStatus {
   seven_day_resets_at?: string | null;
   seven_day_opus_resets_at?: string | null;
   seven_day_sonnet_resets_at?: string | null;
+  // Requests currently served by this cookie, attached by /api/cookies only
+  in_flight?: number;
+  // Last anthropic-ratelimit-* headers seen for this cookie, attached by /api/cookies only
+  rate_limits?: RateLimitSnapshot | null;
+}
+
+export interface RateLimitSnapshot {
+  observed_at: number;
+  headers: Record<string, string>;
+}
+
+export type AccountStanding = "warning" | "restricted" | "banned";
+
+export interface AccountFlag {
+  standing: AccountStanding;
+  // Upstream error message as received
+  message: string;
+  seen_at: number;
+  until?: number | null;
+  // Ban responses in a row
+  strikes?: number;
 }
 
 export interface UselessCookie {
   cookie: string;
   reason: unknown;
+  flag?: AccountFlag;
 }
 
 export interface CookieStatusInfo {
   valid: CookieStatus[];
   exhausted: CookieStatus[];
   invalid: UselessCookie[];
+  // Requests waiting for a free cookie
+  queued?: number;
 }
 
 export type CookieItem = Partial<CookieStatus> & Pick<CookieStatus, "cookie"> & {
@@ -55,3 +84,26 @@ export interface CookieFormState {
     message: string;
   };
 }
+
+export type BatchStatus = "added" | "duplicate" | "malformed" | "invalid_upstream";
+
+export interface BatchEntry {
+  index: number;
+  cookie: string | null;
+  status: BatchStatus;
+  reason: string | null;
+  stored: boolean;
+}
+
+export interface BatchReport {
+  added: number;
+  duplicate: number;
+  malformed: number;
+  invalid_upstream: number;
+  entries: BatchEntry[];
+}
+
+export interface DeleteReport {
+  deleted: number;
+  not_found: number;
+}
@@ -0,0 +1,31 @@
+export interface SloWindow {
+  window: string;
+  total: number;
+  good: number;
+  compliance: number | null;
+  burn_rate: number | null;
+}
+
+export interface SloReport {
+  name: string;
+  target: number;
+  latency_secs: number;
+  endpoints: string[];
+  windows: SloWindow[];
+  budget_remaining: number | null;
+  fast_burn_firing: boolean;
+  slow_burn_firing: boolean;
+}
+
+export interface SloAlertEvent {
+  slo: string;
+  kind: "fast_burn" | "slow_burn";
+  firing: boolean;
+  burn_rate: number;
+  timestamp: number;
+}
+
+export interface SloData {
+  slos: SloReport[];
+  events: SloAlertEvent[];
+}
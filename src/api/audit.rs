@@ -0,0 +1,61 @@
+use axum::{Json, extract::Query};
+use axum_auth::AuthBearer;
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use super::error::ApiError;
+use crate::{
+    config::CLEWDR_CONFIG,
+    services::audit::{self, AuditAction, MAX_ENTRIES},
+};
+
+/// Query parameters for the audit log endpoint
+#[derive(Deserialize)]
+pub struct AuditQuery {
+    /// Most entries returned, 100 by default
+    pub limit: Option<usize>,
+    /// Only entries with a smaller id, the `id` of the last entry of a page
+    pub before: Option<u64>,
+}
+
+/// API endpoint to list recorded admin actions, newest first
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+/// * `query` - Page size and cursor
+///
+/// # Returns
+/// * `Result<Json<Value>, ApiError>` - Audit entries
+pub async fn api_get_audit(
+    AuthBearer(t): AuthBearer,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<Value>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    let limit = query.limit.unwrap_or(100).clamp(1, MAX_ENTRIES);
+    Ok(Json(
+        json!({ "entries": audit::entries(limit, query.before) }),
+    ))
+}
+
+/// Records the outcome of an admin action and passes it through
+///
+/// # Arguments
+/// * `action` - What was attempted
+/// * `summary` - What it touched, never secret values
+/// * `result` - Outcome of the action, failures are recorded with their reason
+pub(super) async fn audited<T>(
+    action: AuditAction,
+    summary: impl Into<String>,
+    result: Result<T, ApiError>,
+) -> Result<T, ApiError> {
+    let error = result.as_ref().err().map(|e| {
+        e.body["error"]
+            .as_str()
+            .map(str::to_string)
+            .unwrap_or_else(|| e.body.to_string())
+    });
+    audit::record(action, summary.into(), error).await;
+    result
+}
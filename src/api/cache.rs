@@ -0,0 +1,44 @@
+use axum::Json;
+use axum_auth::AuthBearer;
+use serde_json::{Value, json};
+
+use super::{audit::audited, error::ApiError};
+use crate::{
+    config::CLEWDR_CONFIG,
+    services::{audit::AuditAction, response_cache},
+};
+
+/// API endpoint to retrieve the response cache counters
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+///
+/// # Returns
+/// * `Result<Json<Value>, ApiError>` - Entries, hits and misses since startup
+pub async fn api_get_cache(AuthBearer(t): AuthBearer) -> Result<Json<Value>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    Ok(Json(json!(response_cache::stats())))
+}
+
+/// API endpoint to drop every cached response
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+///
+/// # Returns
+/// * `Result<Json<Value>, ApiError>` - Number of dropped responses
+pub async fn api_delete_cache(AuthBearer(t): AuthBearer) -> Result<Json<Value>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    let deleted = response_cache::clear();
+    audited(
+        AuditAction::CacheFlush,
+        format!("{deleted} cached responses"),
+        Ok(()),
+    )
+    .await?;
+    Ok(Json(json!({ "deleted": deleted })))
+}
@@ -3,21 +3,45 @@ use std::sync::Arc;
 use axum::{Extension, extract::State, response::Response};
 
 use crate::{
+    config::{ClientAuth, KeyEndpoint},
     error::ClewdrError,
     middleware::claude::{ClaudeCodePreprocess, ClaudeContext},
     providers::{
         LLMProvider,
         claude::{ClaudeCodeProvider, ClaudeInvocation, ClaudeProviderResponse},
     },
+    services::response_cache::{self, CacheStatus},
 };
 
 pub async fn api_claude_code(
     State(provider): State<Arc<ClaudeCodeProvider>>,
-    ClaudeCodePreprocess(params, context): ClaudeCodePreprocess,
+    client: Option<Extension<ClientAuth>>,
+    ClaudeCodePreprocess(params, mut context): ClaudeCodePreprocess,
 ) -> Result<(Extension<ClaudeContext>, Response), ClewdrError> {
-    let ClaudeProviderResponse { context, response } = provider
+    let key = response_cache::key(
+        KeyEndpoint::ClaudeCode,
+        client.as_ref().map(|Extension(c)| c),
+        &params,
+    );
+    if let Some(key) = key.as_deref()
+        && let Some(response) = response_cache::get(key)
+    {
+        context.set_cache(CacheStatus::Hit);
+        return Ok((Extension(context), response));
+    }
+    let ClaudeProviderResponse {
+        mut context,
+        response,
+    } = provider
         .invoke(ClaudeInvocation::messages(params, context.clone()))
         .await?;
+    let response = match key {
+        Some(key) => {
+            context.set_cache(CacheStatus::Miss);
+            response_cache::store(key, response).await?
+        }
+        None => response,
+    };
     Ok((Extension(context), response))
 }
 
@@ -3,12 +3,14 @@ use std::sync::Arc;
 use axum::{Extension, extract::State, response::Response};
 
 use crate::{
+    config::{ClientAuth, KeyEndpoint},
     error::ClewdrError,
     middleware::claude::{ClaudeContext, ClaudeWebPreprocess},
     providers::{
         LLMProvider,
         claude::{ClaudeInvocation, ClaudeProviderResponse, ClaudeWebProvider},
     },
+    services::response_cache::{self, CacheStatus},
 };
 /// Axum handler for the API messages
 /// Main API endpoint for handling message requests to Claude
@@ -23,10 +25,32 @@ use crate::{
 /// * `Response` - Stream or JSON response from Claude
 pub async fn api_claude_web(
     State(provider): State<Arc<ClaudeWebProvider>>,
-    ClaudeWebPreprocess(params, context): ClaudeWebPreprocess,
+    client: Option<Extension<ClientAuth>>,
+    ClaudeWebPreprocess(params, mut context): ClaudeWebPreprocess,
 ) -> Result<(Extension<ClaudeContext>, Response), ClewdrError> {
-    let ClaudeProviderResponse { context, response } = provider
+    let key = response_cache::key(
+        KeyEndpoint::ClaudeWeb,
+        client.as_ref().map(|Extension(c)| c),
+        &params,
+    );
+    if let Some(key) = key.as_deref()
+        && let Some(response) = response_cache::get(key)
+    {
+        context.set_cache(CacheStatus::Hit);
+        return Ok((Extension(context), response));
+    }
+    let ClaudeProviderResponse {
+        mut context,
+        response,
+    } = provider
         .invoke(ClaudeInvocation::messages(params, context.clone()))
         .await?;
+    let response = match key {
+        Some(key) => {
+            context.set_cache(CacheStatus::Miss);
+            response_cache::store(key, response).await?
+        }
+        None => response,
+    };
     Ok((Extension(context), response))
 }
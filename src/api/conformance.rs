@@ -0,0 +1,20 @@
+use axum::Json;
+use axum_auth::AuthBearer;
+use serde_json::{Value, json};
+
+use super::error::ApiError;
+use crate::{config::CLEWDR_CONFIG, services::conformance::load_reports};
+
+/// API endpoint to retrieve the last conformance report of each backend
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+///
+/// # Returns
+/// * `Result<Json<Value>, ApiError>` - Saved reports, written by `clewdr conformance`
+pub async fn api_get_conformance(AuthBearer(t): AuthBearer) -> Result<Json<Value>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    Ok(Json(json!({ "reports": load_reports().await })))
+}
@@ -0,0 +1,439 @@
+use std::{collections::HashSet, str::FromStr};
+
+use axum::{
+    Json,
+    extract::{Query, State},
+};
+use axum_auth::AuthBearer;
+use futures::{StreamExt, stream};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use super::{
+    audit::audited,
+    error::ApiError,
+    misc::{COOKIE_STATUS_CACHE_KEY, COOKIES_CACHE, CookieListStatus},
+};
+use crate::{
+    claude_web_state::ClaudeWebState,
+    config::{CLEWDR_CONFIG, ClewdrCookie, CookieStatus, Reason},
+    error::ClewdrError,
+    services::{audit::AuditAction, conversations, cookie_actor::CookieActorHandle, demo},
+};
+
+/// Cookies checked against claude.ai at once
+const VALIDATE_CONCURRENCY: usize = 4;
+
+/// Query parameters for the batch cookie endpoint
+#[derive(Deserialize)]
+pub struct BatchQuery {
+    /// Check each new cookie against claude.ai before storing it
+    #[serde(default)]
+    validate: bool,
+    /// Store cookies that failed validation, filed under their reason
+    #[serde(default)]
+    store_invalid: bool,
+}
+
+/// Outcome of one submitted entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchStatus {
+    Added,
+    Duplicate,
+    Malformed,
+    InvalidUpstream,
+}
+
+/// Report of one submitted entry
+#[derive(Debug, Serialize)]
+pub struct BatchEntry {
+    /// Position in the array, or line in the text, counting from 0
+    pub index: usize,
+    /// Start of the normalized cookie, `None` when it could not be parsed
+    pub cookie: Option<String>,
+    pub status: BatchStatus,
+    pub reason: Option<String>,
+    /// Whether the cookie is now stored
+    pub stored: bool,
+}
+
+impl BatchEntry {
+    /// An entry that was never sent to the cookie manager
+    fn skipped(
+        index: usize,
+        cookie: Option<&ClewdrCookie>,
+        status: BatchStatus,
+        reason: impl ToString,
+    ) -> Self {
+        Self {
+            index,
+            cookie: cookie.map(|c| c.ellipse()),
+            status,
+            reason: Some(reason.to_string()),
+            stored: false,
+        }
+    }
+}
+
+/// Report of a whole batch
+#[derive(Debug, Serialize)]
+pub struct BatchReport {
+    pub added: usize,
+    pub duplicate: usize,
+    pub malformed: usize,
+    pub invalid_upstream: usize,
+    pub entries: Vec<BatchEntry>,
+}
+
+impl BatchReport {
+    fn new(mut entries: Vec<BatchEntry>) -> Self {
+        entries.sort_by_key(|e| e.index);
+        let count = |status| entries.iter().filter(|e| e.status == status).count();
+        Self {
+            added: count(BatchStatus::Added),
+            duplicate: count(BatchStatus::Duplicate),
+            malformed: count(BatchStatus::Malformed),
+            invalid_upstream: count(BatchStatus::InvalidUpstream),
+            entries,
+        }
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "batch: {} added, {} duplicate, {} malformed, {} invalid upstream",
+            self.added, self.duplicate, self.malformed, self.invalid_upstream
+        )
+    }
+}
+
+/// Splits a batch body into its non-blank entries with their positions
+///
+/// A body starting with `[` is a JSON array of strings, anything else is
+/// newline separated text.
+fn split_batch(body: &str) -> Result<Vec<(usize, String)>, ApiError> {
+    let entries = if body.trim_start().starts_with('[') {
+        serde_json::from_str::<Vec<String>>(body)
+            .map_err(|e| ApiError::bad_request(format!("Invalid cookie array: {e}")))?
+    } else {
+        body.lines().map(str::to_string).collect()
+    };
+    Ok(entries
+        .into_iter()
+        .enumerate()
+        .filter(|(_, e)| !e.trim().is_empty())
+        .collect())
+}
+
+/// Parses one entry, with or without its `sessionKey=` prefix
+fn normalize(entry: &str) -> Result<ClewdrCookie, ClewdrError> {
+    let entry = entry.trim();
+    let entry = entry.strip_prefix("sessionKey=").unwrap_or(entry);
+    ClewdrCookie::from_str(entry.trim())
+}
+
+/// Whether a checked cookie is stored
+///
+/// Only cookies claude.ai rejected are filed as invalid. A check that failed
+/// for another reason, such as a timeout, says nothing about the cookie, so
+/// it is neither stored as valid nor filed under a reason it does not have.
+///
+/// # Arguments
+/// * `failed` - Whether the check failed
+/// * `reason` - Reason claude.ai rejected the cookie for
+/// * `store_invalid` - Whether rejected cookies are stored
+fn stores(failed: bool, reason: Option<&Reason>, store_invalid: bool) -> bool {
+    match (failed, reason) {
+        (false, _) => true,
+        (true, Some(_)) => store_invalid,
+        (true, None) => false,
+    }
+}
+
+/// API endpoint to submit many cookies at once
+/// Accepts a JSON array of cookie strings or newline separated text
+///
+/// # Arguments
+/// * `s` - Application state containing event sender
+/// * `t` - Auth bearer token for admin authentication
+/// * `query` - Whether to validate the cookies and store invalid ones
+/// * `body` - The cookies to submit
+///
+/// # Returns
+/// * `Result<Json<BatchReport>, ApiError>` - Outcome of every entry
+pub async fn api_post_cookie_batch(
+    State(s): State<CookieActorHandle>,
+    AuthBearer(t): AuthBearer,
+    Query(query): Query<BatchQuery>,
+    body: String,
+) -> Result<Json<BatchReport>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    let result = submit_batch(&s, &query, &body).await;
+    let summary = match &result {
+        Ok(report) => report.summary(),
+        Err(_) => "batch".to_string(),
+    };
+    audited(AuditAction::CookieAdd, summary, result.map(Json)).await
+}
+
+async fn submit_batch(
+    s: &CookieActorHandle,
+    query: &BatchQuery,
+    body: &str,
+) -> Result<BatchReport, ApiError> {
+    if query.validate {
+        demo::ensure_live(&CLEWDR_CONFIG.load())
+            .map_err(|e| ApiError::bad_request(e.to_string()))?;
+    }
+    let status = s
+        .get_status()
+        .await
+        .map_err(|e| ApiError::internal(format!("Failed to get cookie status: {e}")))?;
+    let mut known = status
+        .valid
+        .iter()
+        .chain(status.exhausted.iter())
+        .map(|c| c.cookie.to_owned())
+        .chain(status.invalid.iter().map(|c| c.cookie.to_owned()))
+        .collect::<HashSet<_>>();
+
+    let mut report = vec![];
+    let mut fresh = vec![];
+    for (index, entry) in split_batch(body)? {
+        match normalize(&entry) {
+            Err(e) => report.push(BatchEntry::skipped(index, None, BatchStatus::Malformed, e)),
+            Ok(cookie) if !known.insert(cookie.to_owned()) => report.push(BatchEntry::skipped(
+                index,
+                Some(&cookie),
+                BatchStatus::Duplicate,
+                "already stored",
+            )),
+            Ok(cookie) => fresh.push((index, cookie)),
+        }
+    }
+
+    // (index, cookie, failure reason, filed reason)
+    let checked = stream::iter(fresh)
+        .map(|(index, cookie)| async move {
+            let status = CookieStatus::new(&cookie, None)?;
+            if !query.validate {
+                return Ok::<_, ClewdrError>((index, status, None, None));
+            }
+            Ok(
+                match ClaudeWebState::validate_cookie(s.to_owned(), &status).await {
+                    Ok(()) => (index, status, None, None),
+                    Err(ClewdrError::InvalidCookie { reason }) => {
+                        (index, status, Some(reason.to_string()), Some(reason))
+                    }
+                    Err(e) => (index, status, Some(e.to_string()), None),
+                },
+            )
+        })
+        .buffered(VALIDATE_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| ApiError::internal(e.to_string()))?;
+
+    let mut stored = vec![];
+    let mut outcomes = vec![];
+    for (index, status, failure, reason) in checked {
+        let store = stores(failure.is_some(), reason.as_ref(), query.store_invalid);
+        if store {
+            stored.push((status.to_owned(), reason));
+        }
+        outcomes.push((index, status, failure, store));
+    }
+    let added = s
+        .submit_batch(stored)
+        .await
+        .map_err(|e| ApiError::internal(format!("Failed to submit cookies: {e}")))?;
+    let mut added = added.into_iter();
+    for (index, status, failure, store) in outcomes {
+        let stored = store && added.next().unwrap_or_default();
+        let status_of = match (&failure, stored) {
+            (Some(_), _) => BatchStatus::InvalidUpstream,
+            (None, true) => BatchStatus::Added,
+            // another request stored it in the meantime
+            (None, false) => BatchStatus::Duplicate,
+        };
+        report.push(BatchEntry {
+            index,
+            cookie: Some(status.cookie.ellipse()),
+            status: status_of,
+            reason: failure,
+            stored,
+        });
+    }
+    let report = BatchReport::new(report);
+    if report.entries.iter().any(|e| e.stored) {
+        COOKIES_CACHE.invalidate(COOKIE_STATUS_CACHE_KEY);
+        info!("{}", report.summary());
+    }
+    Ok(report)
+}
+
+/// Cookies picked for a bulk delete
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum DeleteSelection {
+    /// Cookie strings, with or without their `sessionKey=` prefix
+    Cookies(Vec<String>),
+    /// Every cookie in one list
+    Status { status: CookieListStatus },
+}
+
+/// Outcome of a bulk delete
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct DeleteReport {
+    pub deleted: usize,
+    /// Cookies that were not stored, malformed ones included
+    pub not_found: usize,
+}
+
+/// API endpoint to delete many cookies at once
+/// Accepts a JSON array of cookie strings or a `{"status": ...}` selector
+///
+/// # Arguments
+/// * `s` - Application state containing event sender
+/// * `t` - Auth bearer token for admin authentication
+/// * `selection` - The cookies to delete
+///
+/// # Returns
+/// * `Result<Json<DeleteReport>, ApiError>` - Counts of deleted and unknown cookies
+pub async fn api_post_cookie_delete(
+    State(s): State<CookieActorHandle>,
+    AuthBearer(t): AuthBearer,
+    Json(selection): Json<DeleteSelection>,
+) -> Result<Json<DeleteReport>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    let result = delete_selected(&s, selection).await;
+    let summary = match &result {
+        Ok(report) => format!(
+            "bulk: {} deleted, {} not found",
+            report.deleted, report.not_found
+        ),
+        Err(_) => "bulk".to_string(),
+    };
+    audited(AuditAction::CookieDelete, summary, result.map(Json)).await
+}
+
+async fn delete_selected(
+    s: &CookieActorHandle,
+    selection: DeleteSelection,
+) -> Result<DeleteReport, ApiError> {
+    let (cookies, malformed) = match selection {
+        DeleteSelection::Cookies(entries) => {
+            let mut cookies = vec![];
+            let mut malformed = 0;
+            for entry in entries {
+                match normalize(&entry).and_then(|c| CookieStatus::new(&c, None)) {
+                    Ok(cookie) => cookies.push(cookie),
+                    Err(_) => malformed += 1,
+                }
+            }
+            (cookies, malformed)
+        }
+        DeleteSelection::Status { status } => {
+            let info = s
+                .get_status()
+                .await
+                .map_err(|e| ApiError::internal(format!("Failed to get cookie status: {e}")))?;
+            let cookies = match status {
+                CookieListStatus::Valid => info.valid,
+                CookieListStatus::Exhausted => info.exhausted,
+                CookieListStatus::Invalid => info
+                    .invalid
+                    .iter()
+                    .map(|c| CookieStatus::new(&c.cookie, None))
+                    .collect::<Result<_, _>>()
+                    .map_err(|e| ApiError::internal(e.to_string()))?,
+            };
+            (cookies, 0)
+        }
+    };
+    let found = s
+        .delete_cookies(cookies.to_owned())
+        .await
+        .map_err(|e| ApiError::internal(format!("Failed to delete cookies: {e}")))?;
+    let mut deleted = 0;
+    for (cookie, _) in cookies.iter().zip(&found).filter(|(_, found)| **found) {
+        conversations::forget_cookie(cookie);
+        deleted += 1;
+    }
+    if deleted > 0 {
+        COOKIES_CACHE.invalidate(COOKIE_STATUS_CACHE_KEY);
+        info!("{} cookies deleted", deleted);
+    }
+    Ok(DeleteReport {
+        deleted,
+        not_found: found.len() - deleted + malformed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base(i: usize) -> String {
+        format!("{:a<86}-bbbbbbAA", i)
+    }
+
+    #[test]
+    fn only_rejected_cookies_are_filed_as_invalid() {
+        assert!(stores(false, None, false));
+        assert!(stores(true, Some(&Reason::Banned), true));
+        assert!(!stores(true, Some(&Reason::Banned), false));
+        // a check that could not be made
+        assert!(!stores(true, None, true));
+    }
+
+    #[test]
+    fn splits_text_and_arrays() {
+        let text = format!(
+            "{}\n\n  sessionKey={}  \r\nnot a cookie\n",
+            base(1),
+            base(2)
+        );
+        let entries = split_batch(&text).unwrap();
+        assert_eq!(
+            entries.iter().map(|(i, _)| *i).collect::<Vec<_>>(),
+            [0, 2, 3]
+        );
+        let array =
+            serde_json::to_string(&[format!("sk-ant-sid01-{}", base(3)), "".into()]).unwrap();
+        assert_eq!(split_batch(&array).unwrap().len(), 1);
+        assert!(split_batch("[1, 2]").is_err());
+    }
+
+    #[test]
+    fn normalizes_every_form() {
+        let plain = normalize(&base(1)).unwrap();
+        assert_eq!(
+            normalize(&format!(" sessionKey={} ", base(1))).unwrap(),
+            plain
+        );
+        let full = format!("sk-ant-sid01-{}", base(1));
+        assert_eq!(&*normalize(&format!("sessionKey={full}")).unwrap(), full);
+        assert!(normalize("sessionKey=short").is_err());
+    }
+
+    #[test]
+    fn delete_selection_forms() {
+        let cookies = serde_json::from_str::<DeleteSelection>(&format!("[\"{}\"]", base(1)));
+        assert!(matches!(cookies, Ok(DeleteSelection::Cookies(c)) if c.len() == 1));
+        let status = serde_json::from_str::<DeleteSelection>(r#"{"status": "rate_limited"}"#);
+        assert!(matches!(
+            status,
+            Ok(DeleteSelection::Status {
+                status: CookieListStatus::Exhausted
+            })
+        ));
+        assert!(serde_json::from_str::<DeleteSelection>(r#"{"status": "gone"}"#).is_err());
+    }
+}
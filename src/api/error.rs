@@ -23,6 +23,18 @@ impl ApiError {
             body: serde_json::json!({"error": msg.into()}),
         }
     }
+    pub fn not_found(msg: impl Into<String>) -> Self {
+        Self {
+            code: StatusCode::NOT_FOUND,
+            body: serde_json::json!({"error": msg.into()}),
+        }
+    }
+    pub fn conflict(msg: impl Into<String>) -> Self {
+        Self {
+            code: StatusCode::CONFLICT,
+            body: serde_json::json!({"error": msg.into()}),
+        }
+    }
     pub fn internal(msg: impl Into<String>) -> Self {
         Self {
             code: StatusCode::INTERNAL_SERVER_ERROR,
@@ -35,6 +47,12 @@ impl ApiError {
             body: serde_json::json!({"error": msg.into()}),
         }
     }
+    pub fn payload_too_large(msg: impl Into<String>) -> Self {
+        Self {
+            code: StatusCode::PAYLOAD_TOO_LARGE,
+            body: serde_json::json!({"error": msg.into()}),
+        }
+    }
     pub fn service_unavailable(msg: impl Into<String>) -> Self {
         Self {
             code: StatusCode::SERVICE_UNAVAILABLE,
@@ -0,0 +1,192 @@
+use std::path::Path;
+
+use axum::{Json, extract::State};
+use serde::Serialize;
+use wreq::StatusCode;
+
+use crate::{
+    config::{CLEWDR_CONFIG, CONFIG_PATH},
+    services::{
+        cookie_actor::CookieActorHandle,
+        shutdown,
+        startup::{STARTUP, SubsystemStatus},
+    },
+};
+
+/// Outcome of one readiness check
+#[derive(Debug, Clone, Serialize)]
+pub struct ReadinessCheck {
+    pub name: &'static str,
+    /// Whether a failure makes the instance unready
+    pub required: bool,
+    pub ok: bool,
+    /// What was found, never secret values
+    pub message: String,
+}
+
+impl ReadinessCheck {
+    fn new(name: &'static str, required: bool, result: Result<String, String>) -> Self {
+        let ok = result.is_ok();
+        Self {
+            name,
+            required,
+            ok,
+            message: result.unwrap_or_else(|e| e),
+        }
+    }
+}
+
+/// Readiness of the instance and the checks that decided it
+#[derive(Debug, Clone, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub checks: Vec<ReadinessCheck>,
+}
+
+impl Readiness {
+    fn new(checks: Vec<ReadinessCheck>) -> Self {
+        Self {
+            ready: checks.iter().all(|c| c.ok || !c.required),
+            checks,
+        }
+    }
+}
+
+/// Checks that files can be created next to `path`, the way the config is saved
+async fn check_writable(path: &Path) -> Result<String, String> {
+    let dir = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let probe = dir.join(format!(".{}.probe", uuid::Uuid::new_v4()));
+    match tokio::fs::write(&probe, b"").await {
+        Ok(()) => {
+            let _ = tokio::fs::remove_file(&probe).await;
+            Ok("config directory is writable".to_string())
+        }
+        Err(e) => Err(format!("config directory is not writable: {}", e.kind())),
+    }
+}
+
+/// Liveness probe, answers as long as the server runs and the config is loaded
+///
+/// # Returns
+/// * `&'static str` - Always `ok`
+pub async fn api_healthz() -> &'static str {
+    let _ = CLEWDR_CONFIG.load();
+    "ok"
+}
+
+/// Whether every subsystem started, naming those that did not
+fn startup_check() -> Result<String, String> {
+    let startup = STARTUP.report();
+    let failed = startup
+        .subsystems
+        .iter()
+        .filter(|s| s.status != SubsystemStatus::Ready)
+        .map(|s| s.name)
+        .collect::<Vec<_>>();
+    if startup.ready {
+        Ok(format!("{} subsystems ready", startup.subsystems.len()))
+    } else {
+        Err(format!("not ready: {}", failed.join(", ")))
+    }
+}
+
+/// Readiness probe served until the router is attached, never ready
+///
+/// # Returns
+/// * `(StatusCode, Json<Readiness>)` - 503 with the subsystems still starting
+pub async fn api_readyz_starting() -> (StatusCode, Json<Readiness>) {
+    let startup = match startup_check() {
+        Ok(_) => Err("starting the router".to_string()),
+        starting => starting,
+    };
+    let readiness = Readiness::new(vec![ReadinessCheck::new("startup", true, startup)]);
+    (StatusCode::SERVICE_UNAVAILABLE, Json(readiness))
+}
+
+/// Readiness probe, checks that the instance can serve requests
+///
+/// # Arguments
+/// * `s` - Cookie actor handle, asked for the usable cookies
+///
+/// # Returns
+/// * `(StatusCode, Json<Readiness>)` - 200 when every required check passes, 503 otherwise
+pub async fn api_readyz(State(s): State<CookieActorHandle>) -> (StatusCode, Json<Readiness>) {
+    let config = CLEWDR_CONFIG.load_full();
+    let startup = startup_check();
+
+    let cookies = if config.demo {
+        Ok("demo mode, no cookies needed".to_string())
+    } else {
+        match s.get_status().await {
+            Ok(status) if !status.valid.is_empty() => {
+                Ok(format!("{} usable cookies", status.valid.len()))
+            }
+            Ok(status) => Err(format!(
+                "no usable cookie, {} exhausted, {} invalid",
+                status.exhausted.len(),
+                status.invalid.len()
+            )),
+            Err(_) => Err("cookie manager is not responding".to_string()),
+        }
+    };
+
+    let writable = if config.no_fs || config.demo {
+        Ok("config is kept in memory".to_string())
+    } else {
+        check_writable(&CONFIG_PATH).await
+    };
+
+    let draining = if shutdown::is_draining() {
+        Err("draining for shutdown".to_string())
+    } else {
+        Ok("accepting requests".to_string())
+    };
+
+    let readiness = Readiness::new(vec![
+        ReadinessCheck::new("startup", true, startup),
+        ReadinessCheck::new("cookies", config.readiness_requires_cookie, cookies),
+        ReadinessCheck::new("config_writable", true, writable),
+        ReadinessCheck::new("shutdown", true, draining),
+    ]);
+    let code = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (code, Json(readiness))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn optional_checks_do_not_block() {
+        let readiness = Readiness::new(vec![
+            ReadinessCheck::new("startup", true, Ok("ready".into())),
+            ReadinessCheck::new("cookies", false, Err("no usable cookie".into())),
+        ]);
+        assert!(readiness.ready);
+        let readiness = Readiness::new(vec![ReadinessCheck::new(
+            "cookies",
+            true,
+            Err("no usable cookie".into()),
+        )]);
+        assert!(!readiness.ready);
+        assert_eq!(readiness.checks[0].message, "no usable cookie");
+    }
+
+    #[tokio::test]
+    async fn probes_the_config_directory() {
+        let dir = std::env::temp_dir().join(format!("clewdr-readyz-{}", uuid::Uuid::new_v4()));
+        tokio::fs::create_dir(&dir).await.unwrap();
+        assert!(check_writable(&dir.join("clewdr.toml")).await.is_ok());
+        // the probe file is cleaned up
+        assert!(std::fs::read_dir(&dir).unwrap().next().is_none());
+        tokio::fs::remove_dir(&dir).await.unwrap();
+        assert!(check_writable(&dir.join("clewdr.toml")).await.is_err());
+    }
+}
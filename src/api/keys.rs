@@ -0,0 +1,161 @@
+use axum::{
+    Json,
+    extract::{Path, Query},
+};
+use axum_auth::AuthBearer;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tracing::info;
+
+use super::{audit::audited, error::ApiError};
+use crate::{
+    config::{ApiKey, CLEWDR_CONFIG, ClewdrConfig, KeyEndpoint, random_password},
+    services::{audit::AuditAction, key_usage},
+};
+
+/// Body of a new client API key
+#[derive(Deserialize)]
+pub struct NewKey {
+    pub name: String,
+    /// Endpoints the key may use, every endpoint when empty
+    #[serde(default)]
+    pub allowed_endpoints: Vec<KeyEndpoint>,
+}
+
+/// Query parameters for revoking a key
+#[derive(Deserialize)]
+pub struct RevokeQuery {
+    /// Remove the key instead of disabling it
+    #[serde(default)]
+    pub purge: bool,
+}
+
+/// Applies a change to the live `api_keys` and saves the config
+async fn update_keys(
+    change: impl Fn(&mut Vec<ApiKey>) -> Result<(), ApiError>,
+) -> Result<(), ApiError> {
+    let mut result = Ok(());
+    CLEWDR_CONFIG.rcu(|old| {
+        let mut new = ClewdrConfig::clone(old);
+        result = change(&mut new.api_keys);
+        new
+    });
+    result?;
+    CLEWDR_CONFIG
+        .load()
+        .save()
+        .await
+        .map_err(|e| ApiError::internal(format!("Failed to save config: {e}")))
+}
+
+/// API endpoint to list the client API keys with their use since startup
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+///
+/// # Returns
+/// * `Result<Json<Value>, ApiError>` - Keys, shortened, with request counts
+pub async fn api_get_keys(AuthBearer(t): AuthBearer) -> Result<Json<Value>, ApiError> {
+    let config = CLEWDR_CONFIG.load();
+    if !config.admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    let keys = config
+        .api_keys
+        .iter()
+        .map(|k| {
+            let usage = key_usage::usage(&k.name);
+            json!({
+                "name": k.name,
+                "key": k.ellipse(),
+                "enabled": k.enabled,
+                "allowed_endpoints": k.allowed_endpoints,
+                "requests": usage.requests,
+                "last_used": usage.last_used,
+            })
+        })
+        .collect::<Vec<_>>();
+    Ok(Json(json!({ "keys": keys })))
+}
+
+/// API endpoint to create a client API key with a random value
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+/// * `new_key` - Name and allowed endpoints of the key
+///
+/// # Returns
+/// * `Result<Json<ApiKey>, ApiError>` - The key, its value returned only this once
+pub async fn api_post_key(
+    AuthBearer(t): AuthBearer,
+    Json(new_key): Json<NewKey>,
+) -> Result<Json<ApiKey>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    let name = new_key.name.trim().to_string();
+    let key = ApiKey {
+        name: name.to_owned(),
+        key: random_password(),
+        enabled: true,
+        allowed_endpoints: new_key.allowed_endpoints,
+    };
+    let result = update_keys(|keys| {
+        if name.is_empty() {
+            return Err(ApiError::bad_request("Key name must not be empty"));
+        }
+        if keys.iter().any(|k| k.name == name) {
+            return Err(ApiError::bad_request(format!("Key {name} already exists")));
+        }
+        keys.push(key.to_owned());
+        Ok(())
+    })
+    .await;
+    audited(AuditAction::ApiKeyCreate, format!("key {name}"), result).await?;
+    info!("API key {} created", name);
+    Ok(Json(key))
+}
+
+/// API endpoint to disable a client API key, or remove it with `purge=true`
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+/// * `name` - Name of the key
+/// * `query` - Whether to remove the key
+///
+/// # Returns
+/// * `Result<Json<Value>, ApiError>` - Success message on success, 404 for unknown keys
+pub async fn api_delete_key(
+    AuthBearer(t): AuthBearer,
+    Path(name): Path<String>,
+    Query(query): Query<RevokeQuery>,
+) -> Result<Json<Value>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    let result = update_keys(|keys| {
+        let position = keys
+            .iter()
+            .position(|k| k.name == name)
+            .ok_or_else(|| ApiError::not_found(format!("Unknown key {name}")))?;
+        if query.purge {
+            keys.remove(position);
+        } else {
+            keys[position].enabled = false;
+        }
+        Ok(())
+    })
+    .await;
+    let verb = if query.purge { "removed" } else { "disabled" };
+    audited(
+        AuditAction::ApiKeyRevoke,
+        format!("key {name} {verb}"),
+        result,
+    )
+    .await?;
+    if query.purge {
+        key_usage::forget(&name);
+    }
+    info!("API key {} {}", name, verb);
+    Ok(Json(json!({ "message": format!("Key {name} {verb}") })))
+}
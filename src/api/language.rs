@@ -0,0 +1,20 @@
+use axum::Json;
+use axum_auth::AuthBearer;
+use serde_json::Value;
+
+use super::error::ApiError;
+use crate::{config::CLEWDR_CONFIG, services::language};
+
+/// API endpoint to retrieve response language detection counts since startup
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+///
+/// # Returns
+/// * `Result<Json<Value>, ApiError>` - Detected languages and policy outcomes
+pub async fn api_get_language(AuthBearer(t): AuthBearer) -> Result<Json<Value>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    Ok(Json(language::stats()))
+}
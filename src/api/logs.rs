@@ -0,0 +1,627 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::Query,
+    response::{IntoResponse, Response},
+};
+use axum_auth::AuthBearer;
+use chrono::{DateTime, FixedOffset, NaiveTime};
+use flate2::{Compression, write::GzEncoder};
+use http::{
+    HeaderMap, HeaderValue, StatusCode,
+    header::{
+        ACCEPT_RANGES, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, RANGE,
+    },
+};
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::{
+    io::{AsyncReadExt, AsyncSeekExt},
+    sync::mpsc,
+};
+use tracing::Level;
+
+use super::error::ApiError;
+use crate::{
+    config::{CLEWDR_CONFIG, LOG_DIR},
+    utils::{is_compressed, rotated_files},
+};
+
+/// Query parameters for the log download
+#[derive(Deserialize)]
+pub struct LogQuery {
+    /// RFC 3339 time of the first line kept
+    from: Option<String>,
+    /// RFC 3339 time of the last line kept
+    to: Option<String>,
+    /// Least severe level kept, `warn` keeps warnings and errors
+    level: Option<String>,
+    /// Name of one log file sent as stored, `clewdr.log` or a rotated file
+    file: Option<String>,
+}
+
+/// Which lines of the log files a download keeps
+#[derive(Debug, Clone, Default)]
+struct Selection {
+    from: Option<DateTime<FixedOffset>>,
+    to: Option<DateTime<FixedOffset>>,
+    level: Option<Level>,
+}
+
+impl Selection {
+    fn parse(query: &LogQuery) -> Result<Self, ApiError> {
+        let time = |value: &Option<String>, name: &str| {
+            value
+                .as_deref()
+                .map(|v| {
+                    DateTime::parse_from_rfc3339(v).map_err(|e| {
+                        ApiError::bad_request(format!(
+                            "Invalid {name} time, expected RFC 3339: {e}"
+                        ))
+                    })
+                })
+                .transpose()
+        };
+        let level = query
+            .level
+            .as_deref()
+            .map(|l| {
+                l.parse::<Level>()
+                    .map_err(|_| ApiError::bad_request(format!("Unknown log level {l}")))
+            })
+            .transpose()?;
+        Ok(Self {
+            from: time(&query.from, "from")?,
+            to: time(&query.to, "to")?,
+            level,
+        })
+    }
+
+    fn is_timed(&self) -> bool {
+        self.from.is_some() || self.to.is_some()
+    }
+
+    /// Whether a line starting an event is kept, lines of older versions carry no date
+    fn keeps(&self, time: Option<DateTime<FixedOffset>>, level: Level) -> bool {
+        // tracing orders more verbose levels as greater
+        let level_ok = self.level.is_none_or(|min| level <= min);
+        let time_ok = match time {
+            Some(t) => self.from.is_none_or(|f| t >= f) && self.to.is_none_or(|to| t <= to),
+            None => !self.is_timed(),
+        };
+        level_ok && time_ok
+    }
+
+    /// Attachment name naming the selected range
+    fn file_name(&self) -> String {
+        let format = |t: DateTime<FixedOffset>| t.format("%Y%m%dT%H%M%S").to_string();
+        let from = self.from.map(format).unwrap_or_else(|| "start".to_string());
+        let to = self
+            .to
+            .map(format)
+            .unwrap_or_else(|| format(chrono::Local::now().fixed_offset()));
+        format!("clewdr-{from}-{to}.log.gz")
+    }
+}
+
+/// Time and level of a line starting an event, `None` for continuation lines
+fn header(line: &str) -> Option<(Option<DateTime<FixedOffset>>, Level)> {
+    if line.starts_with('{') {
+        let entry = serde_json::from_str::<Value>(line).ok()?;
+        let level = entry["level"].as_str()?.parse().ok()?;
+        let time = entry["timestamp"]
+            .as_str()
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok());
+        return Some((time, level));
+    }
+    let mut tokens = line.split_whitespace();
+    let stamp = tokens.next()?;
+    let level = tokens.next()?.parse().ok()?;
+    match DateTime::parse_from_rfc3339(stamp) {
+        Ok(time) => Some((Some(time), level)),
+        Err(_) => NaiveTime::parse_from_str(stamp, "%H:%M:%S%.f")
+            .ok()
+            .map(|_| (None, level)),
+    }
+}
+
+/// A log file opened once for the download
+///
+/// Reads stop at the length seen when it was opened, so lines appended to the
+/// live file while the download runs never show up halfway, and the handle
+/// stays valid when the file is rotated or removed in the meantime.
+struct Source {
+    file: File,
+    len: u64,
+    compressed: bool,
+}
+
+impl Source {
+    fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        Ok(Self {
+            file,
+            len,
+            compressed: is_compressed(path),
+        })
+    }
+
+    fn reader(&mut self) -> io::Result<Box<dyn BufRead + '_>> {
+        self.file.rewind()?;
+        let taken = Read::by_ref(&mut self.file).take(self.len);
+        Ok(if self.compressed {
+            Box::new(BufReader::new(zstd::Decoder::new(taken)?))
+        } else {
+            Box::new(BufReader::new(taken))
+        })
+    }
+
+    /// Time of the first line starting an event
+    fn first_time(&mut self) -> io::Result<Option<DateTime<FixedOffset>>> {
+        let mut reader = self.reader()?;
+        let mut line = vec![];
+        while reader.read_until(b'\n', &mut line)? > 0 {
+            if let Some((time, _)) = header(&String::from_utf8_lossy(&line)) {
+                return Ok(time);
+            }
+            line.clear();
+        }
+        Ok(None)
+    }
+}
+
+/// Opens the rotated files and the live file, oldest first
+///
+/// A file is skipped without reading it when the next one starts before
+/// `from`, or when it starts itself after `to`.
+fn open_sources(dir: &Path, selection: &Selection) -> io::Result<Vec<Source>> {
+    let mut paths = rotated_files(dir, "clewdr")?;
+    paths.push(dir.join("clewdr.log"));
+    let mut sources = vec![];
+    for path in paths {
+        match Source::open(&path) {
+            Ok(source) => sources.push(source),
+            // removed by retention since it was listed
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    if !selection.is_timed() {
+        return Ok(sources);
+    }
+    let starts = sources
+        .iter_mut()
+        .map(Source::first_time)
+        .collect::<io::Result<Vec<_>>>()?;
+    Ok(sources
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| {
+            let ends_early = selection.from.is_some_and(|from| {
+                starts
+                    .get(i + 1)
+                    .copied()
+                    .flatten()
+                    .is_some_and(|next| next <= from)
+            });
+            let starts_late = selection
+                .to
+                .is_some_and(|to| starts[*i].is_some_and(|start| start > to));
+            !ends_early && !starts_late
+        })
+        .map(|(_, source)| source)
+        .collect())
+}
+
+/// Copies the selected lines to `out`, stopping once more than `limit` bytes were selected
+///
+/// # Returns
+/// * `u64` - Bytes of selected lines, above `limit` when the selection was cut short
+fn copy_selected(
+    sources: &mut [Source],
+    selection: &Selection,
+    out: &mut impl Write,
+    limit: u64,
+) -> io::Result<u64> {
+    let mut total = 0;
+    let mut line = vec![];
+    // continuation lines follow the event they belong to, even across files
+    let mut keep = !selection.is_timed() && selection.level.is_none();
+    for source in sources {
+        let mut reader = source.reader()?;
+        while reader.read_until(b'\n', &mut line)? > 0 {
+            if let Some((time, level)) = header(&String::from_utf8_lossy(&line)) {
+                keep = selection.keeps(time, level);
+            }
+            if keep {
+                total += line.len() as u64;
+                if total > limit {
+                    return Ok(total);
+                }
+                out.write_all(&line)?;
+            }
+            line.clear();
+        }
+    }
+    Ok(total)
+}
+
+/// Bytes of a file asked for by a `Range` header
+#[derive(Debug, PartialEq, Eq)]
+enum ByteRange {
+    /// First and last byte, both included
+    Bytes(u64, u64),
+    /// Starts past the end of the file
+    Unsatisfiable,
+}
+
+/// Parses a `Range` header asking for a single range
+///
+/// # Returns
+/// * `Option<ByteRange>` - `None` for several ranges or a malformed header,
+///   which are ignored and answered with the whole file
+fn parse_range(value: &str, len: u64) -> Option<ByteRange> {
+    let spec = value.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+    if start.is_empty() {
+        // the last `suffix` bytes
+        let suffix = end.parse::<u64>().ok()?;
+        if suffix == 0 || len == 0 {
+            return Some(ByteRange::Unsatisfiable);
+        }
+        return Some(ByteRange::Bytes(len.saturating_sub(suffix), len - 1));
+    }
+    let start = start.parse::<u64>().ok()?;
+    let end = match end {
+        "" => u64::MAX,
+        end => end.parse::<u64>().ok().filter(|&end| end >= start)?,
+    };
+    if start >= len {
+        return Some(ByteRange::Unsatisfiable);
+    }
+    Some(ByteRange::Bytes(start, end.min(len - 1)))
+}
+
+/// The live log file or a rotated one, by file name
+///
+/// Only names listed in `dir` qualify, so a name can never reach outside it.
+fn pick_file(dir: &Path, name: &str) -> io::Result<Option<PathBuf>> {
+    if name == "clewdr.log" {
+        return Ok(Some(dir.join(name)));
+    }
+    Ok(rotated_files(dir, "clewdr")?
+        .into_iter()
+        .find(|path| path.file_name().is_some_and(|n| n == name)))
+}
+
+/// Sends one log file as stored, honouring a single range `Range` header
+async fn download_file(name: &str, range: Option<&str>) -> Result<Response, ApiError> {
+    if name.contains(['/', '\\']) || name.starts_with('.') {
+        return Err(ApiError::bad_request("Invalid log file name"));
+    }
+    let unknown = || ApiError::not_found(format!("Unknown log file {name}"));
+    let read_error = |e: io::Error| ApiError::internal(format!("Failed to read log file: {e}"));
+    let dir = LOG_DIR.to_owned();
+    let picked = name.to_string();
+    let path = tokio::task::spawn_blocking(move || pick_file(&dir, &picked))
+        .await
+        .map_err(|e| ApiError::internal(e.to_string()))?
+        .map_err(read_error)?
+        .ok_or_else(unknown)?;
+    let mut file = match tokio::fs::File::open(&path).await {
+        Ok(file) => file,
+        // removed by retention since it was listed
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(unknown()),
+        Err(e) => return Err(read_error(e)),
+    };
+    // lines appended while the download runs are left for a later request
+    let len = file.metadata().await.map_err(read_error)?.len();
+
+    let mut headers = HeaderMap::new();
+    headers.insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    let (status, start, end) = match range.and_then(|r| parse_range(r, len)) {
+        None => (StatusCode::OK, 0, len),
+        Some(ByteRange::Bytes(first, last)) => {
+            let content_range = format!("bytes {first}-{last}/{len}");
+            if let Ok(value) = HeaderValue::from_str(&content_range) {
+                headers.insert(CONTENT_RANGE, value);
+            }
+            (StatusCode::PARTIAL_CONTENT, first, last + 1)
+        }
+        Some(ByteRange::Unsatisfiable) => {
+            if let Ok(value) = HeaderValue::from_str(&format!("bytes */{len}")) {
+                headers.insert(CONTENT_RANGE, value);
+            }
+            return Ok((StatusCode::RANGE_NOT_SATISFIABLE, headers).into_response());
+        }
+    };
+    file.seek(SeekFrom::Start(start))
+        .await
+        .map_err(read_error)?;
+
+    let content_type = if is_compressed(&path) {
+        "application/zstd"
+    } else {
+        "text/plain; charset=utf-8"
+    };
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+    headers.insert(CONTENT_LENGTH, (end - start).into());
+    if let Ok(value) = HeaderValue::from_str(&format!("attachment; filename=\"{name}\"")) {
+        headers.insert(CONTENT_DISPOSITION, value);
+    }
+    let body = futures::stream::unfold(file.take(end - start), |mut reader| async move {
+        let mut chunk = vec![0; 64 * 1024];
+        match reader.read(&mut chunk).await {
+            Ok(0) => None,
+            Ok(n) => {
+                chunk.truncate(n);
+                Some((Ok(Bytes::from(chunk)), reader))
+            }
+            Err(e) => Some((Err(e), reader)),
+        }
+    });
+    Ok((status, headers, Body::from_stream(body)).into_response())
+}
+
+/// Hands the compressed output to the response body
+struct ChannelWriter(mpsc::Sender<io::Result<Bytes>>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .blocking_send(Ok(Bytes::copy_from_slice(buf)))
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// API endpoint to download a slice of the log files
+/// Streams the selected lines file by file as a gzip attachment, or a single
+/// file as stored when `file` names one
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+/// * `headers` - Request headers, a `Range` resumes a single file download
+/// * `query` - Optional RFC 3339 `from` and `to` bounds and minimum `level`, or a `file` name
+///
+/// # Returns
+/// * `Result<Response, ApiError>` - Gzip attachment, 413 when the selection exceeds `log_download_max_mb`
+pub async fn api_download_logs(
+    AuthBearer(t): AuthBearer,
+    headers: HeaderMap,
+    Query(query): Query<LogQuery>,
+) -> Result<Response, ApiError> {
+    let config = CLEWDR_CONFIG.load();
+    if !config.admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    if config.no_fs || !config.log_to_file {
+        return Err(ApiError::not_found("Logs are not written to files"));
+    }
+    if let Some(name) = query.file.as_deref() {
+        if query.from.is_some() || query.to.is_some() || query.level.is_some() {
+            return Err(ApiError::bad_request(
+                "file cannot be combined with from, to or level",
+            ));
+        }
+        let range = headers.get(RANGE).and_then(|v| v.to_str().ok());
+        return download_file(name, range).await;
+    }
+    let selection = Selection::parse(&query)?;
+    let limit = config.log_download_max_mb * 1024 * 1024;
+    let name = selection.file_name();
+
+    // count first, the status cannot change once the body is streaming
+    let counted = selection.to_owned();
+    let (mut sources, total) = tokio::task::spawn_blocking(move || {
+        let mut sources = open_sources(&LOG_DIR, &counted)?;
+        let total = copy_selected(&mut sources, &counted, &mut io::sink(), limit)?;
+        Ok::<_, io::Error>((sources, total))
+    })
+    .await
+    .map_err(|e| ApiError::internal(e.to_string()))?
+    .map_err(|e| ApiError::internal(format!("Failed to read log files: {e}")))?;
+    if total > limit {
+        return Err(ApiError::payload_too_large(format!(
+            "Selected logs exceed {} MiB, narrow the time range or raise the level",
+            config.log_download_max_mb
+        )));
+    }
+
+    let (tx, rx) = mpsc::channel(8);
+    tokio::task::spawn_blocking(move || {
+        let mut out = GzEncoder::new(ChannelWriter(tx.to_owned()), Compression::default());
+        let result = copy_selected(&mut sources, &selection, &mut out, limit)
+            .and_then(|_| out.finish())
+            .map(drop);
+        if let Err(e) = result {
+            let _ = tx.blocking_send(Err(e));
+        }
+    });
+    let body = futures::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    });
+    Ok((
+        [
+            (CONTENT_TYPE, "application/gzip".to_string()),
+            (
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{name}\""),
+            ),
+        ],
+        Body::from_stream(body),
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn time(t: &str) -> Option<DateTime<FixedOffset>> {
+        Some(DateTime::parse_from_rfc3339(t).unwrap())
+    }
+
+    fn line(t: &str, level: &str, msg: &str) -> String {
+        format!("{t}  {level} clewdr: {msg}\n")
+    }
+
+    #[test]
+    fn parses_text_and_json_headers() {
+        let text = line("2026-10-14T01:00:00.000+00:00", "WARN", "slow");
+        assert_eq!(
+            header(&text),
+            Some((time("2026-10-14T01:00:00+00:00"), Level::WARN))
+        );
+        let json = r#"{"timestamp":"2026-10-14T01:00:00.000+00:00","level":"ERROR","message":"x"}"#;
+        assert_eq!(
+            header(json),
+            Some((time("2026-10-14T01:00:00+00:00"), Level::ERROR))
+        );
+        // lines of older versions only carry the time of day
+        assert_eq!(
+            header("01:00:00.000  INFO clewdr: x"),
+            Some((None, Level::INFO))
+        );
+        assert_eq!(header("  at src/main.rs:12 ERROR"), None);
+    }
+
+    #[test]
+    fn parses_single_ranges() {
+        assert_eq!(
+            parse_range("bytes=0-99", 1000),
+            Some(ByteRange::Bytes(0, 99))
+        );
+        assert_eq!(
+            parse_range("bytes=900-", 1000),
+            Some(ByteRange::Bytes(900, 999))
+        );
+        assert_eq!(
+            parse_range("bytes=900-5000", 1000),
+            Some(ByteRange::Bytes(900, 999))
+        );
+        assert_eq!(
+            parse_range("bytes=-100", 1000),
+            Some(ByteRange::Bytes(900, 999))
+        );
+        assert_eq!(
+            parse_range("bytes=-5000", 1000),
+            Some(ByteRange::Bytes(0, 999))
+        );
+        assert_eq!(
+            parse_range("bytes=1000-", 1000),
+            Some(ByteRange::Unsatisfiable)
+        );
+        assert_eq!(
+            parse_range("bytes=-0", 1000),
+            Some(ByteRange::Unsatisfiable)
+        );
+        // answered with the whole file
+        assert_eq!(parse_range("bytes=0-1,5-9", 1000), None);
+        assert_eq!(parse_range("bytes=9-5", 1000), None);
+        assert_eq!(parse_range("items=0-9", 1000), None);
+    }
+
+    #[test]
+    fn picks_only_listed_files() {
+        let dir = std::env::temp_dir().join(format!("clewdr-pick-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let rotated = "clewdr.20261014-000000.000000.log.zst";
+        fs::write(dir.join(rotated), "").unwrap();
+        fs::write(dir.join("secret.txt"), "").unwrap();
+        assert_eq!(pick_file(&dir, rotated).unwrap(), Some(dir.join(rotated)));
+        assert_eq!(
+            pick_file(&dir, "clewdr.log").unwrap(),
+            Some(dir.join("clewdr.log"))
+        );
+        assert_eq!(pick_file(&dir, "secret.txt").unwrap(), None);
+        assert_eq!(pick_file(&dir, "../clewdr.toml").unwrap(), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn selects_lines_across_files() {
+        let dir = std::env::temp_dir().join(format!("clewdr-logs-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let old = [
+            line("2026-10-13T22:00:00+00:00", "INFO", "old"),
+            line("2026-10-13T23:00:00+00:00", "ERROR", "old error"),
+        ]
+        .concat();
+        let mid = [
+            line("2026-10-14T01:00:00+00:00", "INFO", "inside"),
+            line("2026-10-14T02:00:00+00:00", "ERROR", "inside error"),
+            "continued\n".to_string(),
+        ]
+        .concat();
+        fs::write(dir.join("clewdr.20261014-000000.000000.log"), old).unwrap();
+        let encoded = zstd::encode_all(mid.as_bytes(), 0).unwrap();
+        fs::write(dir.join("clewdr.20261014-030000.000001.log.zst"), encoded).unwrap();
+        fs::write(
+            dir.join("clewdr.log"),
+            line("2026-10-14T04:00:00+00:00", "ERROR", "late"),
+        )
+        .unwrap();
+
+        let selection = Selection {
+            from: time("2026-10-14T01:30:00+00:00"),
+            to: time("2026-10-14T03:00:00+00:00"),
+            level: None,
+        };
+        let mut sources = open_sources(&dir, &selection).unwrap();
+        // the oldest file ends before the next one starts, ahead of `from`,
+        // and the live one starts after `to`
+        assert_eq!(sources.len(), 1);
+        let mut out = vec![];
+        let total = copy_selected(&mut sources, &selection, &mut out, u64::MAX).unwrap();
+        let expected = [
+            line("2026-10-14T02:00:00+00:00", "ERROR", "inside error"),
+            "continued\n".to_string(),
+        ]
+        .concat();
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+        assert_eq!(total, expected.len() as u64);
+
+        let errors = Selection {
+            level: Some(Level::ERROR),
+            ..Default::default()
+        };
+        let mut sources = open_sources(&dir, &errors).unwrap();
+        assert_eq!(sources.len(), 3);
+        let mut out = vec![];
+        copy_selected(&mut sources, &errors, &mut out, u64::MAX).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(
+            out.lines().collect::<Vec<_>>(),
+            [
+                line("2026-10-13T23:00:00+00:00", "ERROR", "old error").trim_end(),
+                line("2026-10-14T02:00:00+00:00", "ERROR", "inside error").trim_end(),
+                "continued",
+                line("2026-10-14T04:00:00+00:00", "ERROR", "late").trim_end(),
+            ]
+        );
+
+        // the count stops right past the limit
+        let mut sources = open_sources(&dir, &Selection::default()).unwrap();
+        let total =
+            copy_selected(&mut sources, &Selection::default(), &mut io::sink(), 10).unwrap();
+        assert!(total > 10 && total < 100);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,4 +1,5 @@
 use std::{
+    collections::HashMap,
     sync::LazyLock,
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
@@ -6,7 +7,8 @@ use std::{
 use axum::{
     Json,
     extract::{Query, State},
-    http::HeaderMap,
+    http::{HeaderMap, header::ACCEPT},
+    response::{IntoResponse, Response},
 };
 use axum_auth::AuthBearer;
 use moka::sync::Cache;
@@ -15,12 +17,21 @@ use serde_json::{Value, json};
 use tracing::{error, info, warn};
 use wreq::StatusCode;
 
-use super::error::ApiError;
+use super::{audit::audited, error::ApiError};
 use crate::{
-    VERSION_INFO,
+    BUILD_TIMESTAMP, GIT_COMMIT, RUSTC_VERSION, STARTED_AT, TARGET, VERSION_INFO,
     claude_code_state::ClaudeCodeState,
-    config::{CLEWDR_CONFIG, CookieStatus},
-    services::cookie_actor::CookieActorHandle,
+    claude_web_state::ClaudeWebState,
+    config::{CLEWDR_CONFIG, ClewdrConfig, ClewdrCookie, CookieStatus, random_password},
+    error::ClewdrError,
+    services::{
+        audit::AuditAction,
+        conversations,
+        cookie_actor::{CookieActorHandle, CookieStatusInfo},
+        demo, model_list, queue, rate_limits,
+        resources::{FnReporter, ResourceUsage, register_reporter},
+        transcript::hash_cookie,
+    },
 };
 
 /// Cache entry for cookie status responses
@@ -30,15 +41,98 @@ struct CookieStatusCache {
     timestamp: u64,
 }
 
+/// Cookies listed per page when only `page` or `status` is given
+const DEFAULT_PER_PAGE: usize = 50;
+const MAX_PER_PAGE: usize = 500;
+
 /// Query parameters for cookie status endpoint
+///
+/// Without `page`, `per_page` and `status` the three lists are returned whole.
 #[derive(Deserialize)]
 pub struct CookieStatusQuery {
     #[serde(default)]
     refresh: bool,
+    /// Page to return, counting from 1
+    page: Option<usize>,
+    per_page: Option<usize>,
+    status: Option<CookieListStatus>,
+}
+
+/// Lists the cookie manager keeps cookies in
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CookieListStatus {
+    Valid,
+    /// Rate limited or restricted until their reset time
+    #[serde(alias = "rate_limited")]
+    Exhausted,
+    Invalid,
+}
+
+impl CookieListStatus {
+    const ALL: [Self; 3] = [Self::Valid, Self::Exhausted, Self::Invalid];
+
+    /// Key of the list in the status report
+    fn key(self) -> &'static str {
+        match self {
+            Self::Valid => "valid",
+            Self::Exhausted => "exhausted",
+            Self::Invalid => "invalid",
+        }
+    }
+}
+
+/// Shapes a status report for the query, a page of one list when asked for
+///
+/// # Returns
+/// * `Value` - The report, or `{items, total, page, per_page, queued}` with
+///   each item tagged with its `status`
+fn paginate(data: Value, query: &CookieStatusQuery) -> Value {
+    if query.page.is_none() && query.per_page.is_none() && query.status.is_none() {
+        return data;
+    }
+    let page = query.page.unwrap_or(1).max(1);
+    let per_page = query
+        .per_page
+        .unwrap_or(DEFAULT_PER_PAGE)
+        .clamp(1, MAX_PER_PAGE);
+    let items = CookieListStatus::ALL
+        .into_iter()
+        .filter(|s| query.status.is_none_or(|q| q == *s))
+        .flat_map(|s| {
+            let list = data[s.key()].as_array().cloned().unwrap_or_default();
+            list.into_iter().map(move |mut cookie| {
+                cookie["status"] = json!(s.key());
+                cookie
+            })
+        })
+        .collect::<Vec<_>>();
+    let total = items.len();
+    let items = items
+        .into_iter()
+        .skip((page - 1).saturating_mul(per_page))
+        .take(per_page)
+        .collect::<Vec<_>>();
+    json!({
+        "items": items,
+        "total": total,
+        "page": page,
+        "per_page": per_page,
+        "queued": data["queued"],
+    })
 }
 
 /// Global cache for cookie status responses (TTL: 5 minutes)
-static COOKIES_CACHE: LazyLock<Cache<String, CookieStatusCache>> = LazyLock::new(|| {
+pub(super) static COOKIES_CACHE: LazyLock<Cache<String, CookieStatusCache>> = LazyLock::new(|| {
+    register_reporter(FnReporter::new("cookie_status_cache", || ResourceUsage {
+        bytes: Some(
+            COOKIES_CACHE
+                .iter()
+                .map(|(k, v)| (k.len() + v.data.to_string().len()) as u64)
+                .sum(),
+        ),
+        entries: COOKIES_CACHE.entry_count(),
+    }));
     Cache::builder()
         .max_capacity(1)
         .time_to_live(Duration::from_secs(300)) // 5 minutes
@@ -46,7 +140,15 @@ static COOKIES_CACHE: LazyLock<Cache<String, CookieStatusCache>> = LazyLock::new
 });
 
 /// Cache key for cookie status
-const COOKIE_STATUS_CACHE_KEY: &str = "all_cookies";
+pub(super) const COOKIE_STATUS_CACHE_KEY: &str = "all_cookies";
+
+/// Query parameters for submitting a cookie
+#[derive(Deserialize)]
+pub struct PostCookieQuery {
+    /// Store the cookie without checking it against claude.ai, for offline imports
+    #[serde(default)]
+    pub skip_validation: bool,
+}
 
 /// API endpoint to submit a new cookie
 /// Validates and adds the cookie to the cookie manager
@@ -54,13 +156,16 @@ const COOKIE_STATUS_CACHE_KEY: &str = "all_cookies";
 /// # Arguments
 /// * `s` - Application state containing event sender
 /// * `t` - Auth bearer token for admin authentication
+/// * `query` - Whether to skip the check against claude.ai
 /// * `c` - Cookie status to be submitted
 ///
 /// # Returns
-/// * `StatusCode` - HTTP status code indicating success or failure
+/// * `StatusCode` - 200 once stored, 400 with the upstream reason when claude.ai
+///   rejects the cookie, 409 when it is already stored
 pub async fn api_post_cookie(
     State(s): State<CookieActorHandle>,
     AuthBearer(t): AuthBearer,
+    Query(query): Query<PostCookieQuery>,
     Json(mut c): Json<CookieStatus>,
 ) -> Result<StatusCode, ApiError> {
     if !CLEWDR_CONFIG.load().admin_auth(&t) {
@@ -73,15 +178,55 @@ pub async fn api_post_cookie(
     if c.supports_claude_1m_opus.is_none() {
         c.supports_claude_1m_opus = Some(true);
     }
+    let summary = format!("cookie {}", hash_cookie(&c));
+    let result = submit_cookie(&s, c, query.skip_validation).await;
+    audited(AuditAction::CookieAdd, summary, result).await
+}
+
+/// Checks a submitted cookie unless told not to and stores it once
+async fn submit_cookie(
+    s: &CookieActorHandle,
+    c: CookieStatus,
+    skip_validation: bool,
+) -> Result<StatusCode, ApiError> {
+    let status = s
+        .get_status()
+        .await
+        .map_err(|e| ApiError::internal(format!("Failed to get cookie status: {e}")))?;
+    let known = status.valid.contains(&c)
+        || status.exhausted.contains(&c)
+        || status.invalid.iter().any(|u| *u == c);
+    if known {
+        return Err(ApiError::conflict("Cookie already stored"));
+    }
+    // a demo instance has no upstream to ask
+    if !skip_validation && !CLEWDR_CONFIG.load().demo {
+        match ClaudeWebState::validate_cookie(s.to_owned(), &c).await {
+            Ok(()) => {}
+            Err(ClewdrError::InvalidCookie { reason }) => {
+                warn!("Cookie rejected by claude.ai: {}", reason);
+                return Err(ApiError::bad_request(format!(
+                    "Cookie rejected by claude.ai: {reason}"
+                )));
+            }
+            Err(e) => {
+                return Err(ApiError::service_unavailable(format!(
+                    "Could not validate cookie: {e}, retry or pass skip_validation=true"
+                )));
+            }
+        }
+    }
     info!("Cookie accepted: {}", c.cookie);
-    match s.submit(c).await {
-        Ok(_) => {
+    match s.submit_batch(vec![(c, None)]).await {
+        Ok(added) if added.contains(&true) => {
             info!("Cookie submitted successfully");
             // Clear cache to ensure fresh data on next request
             COOKIES_CACHE.invalidate(COOKIE_STATUS_CACHE_KEY);
             info!("Cookie status cache invalidated after adding new cookie");
             Ok(StatusCode::OK)
         }
+        // another request stored it in the meantime
+        Ok(_) => Err(ApiError::conflict("Cookie already stored")),
         Err(e) => {
             error!("Failed to submit cookie: {}", e);
             Err(ApiError::internal(format!(
@@ -109,7 +254,13 @@ pub async fn api_put_cookie(
         c.supports_claude_1m_opus = Some(true);
     }
 
-    match s.update_cookie_1m_support(c.clone()).await {
+    let summary = format!(
+        "cookie {}, 1m sonnet {:?}, 1m opus {:?}",
+        hash_cookie(&c),
+        c.supports_claude_1m_sonnet,
+        c.supports_claude_1m_opus
+    );
+    let result = match s.update_cookie_1m_support(c.clone()).await {
         Ok(_) => {
             info!("Cookie 1M flags updated: {}", c.cookie);
             COOKIES_CACHE.invalidate(COOKIE_STATUS_CACHE_KEY);
@@ -123,7 +274,8 @@ pub async fn api_put_cookie(
                 e
             )))
         }
-    }
+    };
+    audited(AuditAction::CookieUpdate, summary, result).await
 }
 
 /// API endpoint to retrieve all cookies and their status
@@ -132,7 +284,7 @@ pub async fn api_put_cookie(
 /// # Arguments
 /// * `s` - Application state containing event sender
 /// * `t` - Auth bearer token for admin authentication
-/// * `query` - Query parameters including optional refresh flag
+/// * `query` - Query parameters including optional refresh flag, page and status filter
 ///
 /// # Returns
 /// * `Result<(HeaderMap, Json<Value>), ApiError>` - Response with cache headers and cookie status
@@ -158,7 +310,14 @@ pub async fn api_get_cookies(
                 .unwrap_or_else(|_| HeaderValue::from_static("0")),
         );
         info!("Cookie status served from cache");
-        return Ok((headers, Json(cached.data)));
+        // load changes by the second, never serve it from the cache
+        let in_flight = s
+            .get_status()
+            .await
+            .map(|status| status.in_flight)
+            .unwrap_or_default();
+        let data = with_load(cached.data, &in_flight);
+        return Ok((headers, Json(paginate(data, &query))));
     }
 
     // Cache miss or force refresh - fetch fresh data
@@ -208,7 +367,8 @@ pub async fn api_get_cookies(
                 info!("Cookie status fetched and cached");
             }
 
-            Ok((headers, Json(response_data)))
+            let data = with_load(response_data, &status.in_flight);
+            Ok((headers, Json(paginate(data, &query))))
         }
         Err(e) => Err(ApiError::internal(format!(
             "Failed to get cookie status: {}",
@@ -217,6 +377,63 @@ pub async fn api_get_cookies(
     }
 }
 
+/// Token usage and account standing of each cookie, by the hash shown in transcripts
+fn usage_report(status: CookieStatusInfo) -> Value {
+    let usage = |cookie: &CookieStatus, state: &str| {
+        json!({
+            "cookie": hash_cookie(cookie),
+            "state": state,
+            "standing": cookie.flag.as_ref().map(|f| f.standing),
+            "flag": cookie.flag,
+            "session": cookie.session_usage,
+            "weekly": cookie.weekly_usage,
+            "weekly_sonnet": cookie.weekly_sonnet_usage,
+            "weekly_opus": cookie.weekly_opus_usage,
+            "lifetime": cookie.lifetime_usage,
+        })
+    };
+    let valid = status.valid.iter().map(|c| usage(c, "valid"));
+    let exhausted = status.exhausted.iter().map(|c| usage(c, "exhausted"));
+    // invalid cookies keep no usage, only why they were dropped
+    let invalid = status.invalid.into_iter().map(|u| {
+        let cookie = CookieStatus {
+            cookie: u.cookie,
+            flag: u.flag,
+            ..Default::default()
+        };
+        json!({
+            "cookie": hash_cookie(&cookie),
+            "state": "invalid",
+            "reason": u.reason,
+            "standing": cookie.flag.as_ref().map(|f| f.standing),
+            "flag": cookie.flag,
+        })
+    });
+    json!({ "cookies": valid.chain(exhausted).chain(invalid).collect::<Vec<_>>() })
+}
+
+/// API endpoint to retrieve the token usage of each cookie with its account standing
+///
+/// # Arguments
+/// * `s` - Cookie actor handle owning the cookie pool
+/// * `t` - Auth bearer token for admin authentication
+///
+/// # Returns
+/// * `Result<Json<Value>, ApiError>` - Usage per cookie, valid, exhausted and invalid ones
+pub async fn api_get_usage(
+    State(s): State<CookieActorHandle>,
+    AuthBearer(t): AuthBearer,
+) -> Result<Json<Value>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    let status = s
+        .get_status()
+        .await
+        .map_err(|e| ApiError::internal(format!("Failed to get cookie status: {}", e)))?;
+    Ok(Json(usage_report(status)))
+}
+
 /// API endpoint to delete a specific cookie
 /// Removes the cookie from all collections in the cookie manager
 ///
@@ -236,9 +453,11 @@ pub async fn api_delete_cookie(
         return Err(ApiError::unauthorized());
     }
 
-    match s.delete_cookie(c.to_owned()).await {
+    let summary = format!("cookie {}", hash_cookie(&c));
+    let result = match s.delete_cookie(c.to_owned()).await {
         Ok(_) => {
             info!("Cookie deleted successfully: {}", c.cookie);
+            conversations::forget_cookie(&c);
             // Clear cache to ensure fresh data on next request
             COOKIES_CACHE.invalidate(COOKIE_STATUS_CACHE_KEY);
             info!("Cookie status cache invalidated");
@@ -251,15 +470,47 @@ pub async fn api_delete_cookie(
                 e
             )))
         }
-    }
+    };
+    audited(AuditAction::CookieDelete, summary, result).await
 }
 
 /// API endpoint to get the application version information
+/// Clients sending `Accept: application/json` also get the build and uptime
+///
+/// # Arguments
+/// * `headers` - Request headers, to pick plain text or JSON
 ///
 /// # Returns
-/// * `String` - Version information string
-pub async fn api_version() -> String {
-    VERSION_INFO.to_string()
+/// * `Response` - Version information string, or the build details as JSON
+pub async fn api_version(headers: HeaderMap) -> Response {
+    let version = if CLEWDR_CONFIG.load().demo {
+        format!("{} (demo mode)", *VERSION_INFO)
+    } else {
+        VERSION_INFO.to_string()
+    };
+    let wants_json = headers
+        .get(ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("application/json"));
+    if !wants_json {
+        return version.into_response();
+    }
+    Json(json!({
+        "version": version,
+        "package_version": env!("CARGO_PKG_VERSION"),
+        "git_commit": GIT_COMMIT,
+        "build_timestamp": BUILD_TIMESTAMP.parse::<i64>().ok(),
+        "rustc_version": RUSTC_VERSION,
+        "target": TARGET,
+        "uptime_secs": STARTED_AT.elapsed().as_secs(),
+        "features": {
+            // this build has no MCP server
+            "mcp": false,
+            // Claude Code tokens always come from the OAuth exchange
+            "oauth": true,
+        },
+    }))
+    .into_response()
 }
 
 /// API endpoint to verify authentication
@@ -278,39 +529,61 @@ pub async fn api_auth(AuthBearer(t): AuthBearer) -> StatusCode {
     StatusCode::OK
 }
 
-const MODEL_LIST: [&str; 26] = [
-    "claude-3-7-sonnet-20250219",
-    "claude-3-7-sonnet-20250219-thinking",
-    "claude-sonnet-4-20250514",
-    "claude-sonnet-4-20250514-thinking",
-    "claude-sonnet-4-20250514-1M",
-    "claude-sonnet-4-20250514-1M-thinking",
-    "claude-sonnet-4-5-20250929",
-    "claude-sonnet-4-5-20250929-thinking",
-    "claude-sonnet-4-5-20250929-1M",
-    "claude-sonnet-4-5-20250929-1M-thinking",
-    "claude-sonnet-4-6",
-    "claude-sonnet-4-6-thinking",
-    "claude-sonnet-4-6-1M",
-    "claude-sonnet-4-6-1M-thinking",
-    "claude-opus-4-20250514",
-    "claude-opus-4-20250514-thinking",
-    "claude-opus-4-1-20250805",
-    "claude-opus-4-1-20250805-thinking",
-    "claude-opus-4-5-20251101",
-    "claude-opus-4-5-20251101-thinking",
-    "claude-opus-4-5",
-    "claude-opus-4-5-thinking",
-    "claude-opus-4-6",
-    "claude-opus-4-6-thinking",
-    "claude-opus-4-6-1M",
-    "claude-opus-4-6-1M-thinking",
-];
+/// API endpoint to replace the admin password with a random one
+/// Requests already past authentication finish, later checks need the new password
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+///
+/// # Returns
+/// * `Result<Json<Value>, ApiError>` - The new password, returned only this once
+pub async fn api_rotate_admin(AuthBearer(t): AuthBearer) -> Result<Json<Value>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    let password = random_password();
+    CLEWDR_CONFIG.rcu(|old| {
+        let mut new = ClewdrConfig::clone(old);
+        new.set_admin_password(password.to_owned());
+        new
+    });
+    let saved = CLEWDR_CONFIG.load().save().await.map_err(|e| {
+        // a password that is lost on restart would lock the admin out
+        CLEWDR_CONFIG.rcu(|old| {
+            let mut new = ClewdrConfig::clone(old);
+            // the token that passed the check is the previous password
+            if new.admin_auth(&password) {
+                new.set_admin_password(t.to_owned());
+            }
+            new
+        });
+        ApiError::internal(format!("Failed to save config, password unchanged: {e}"))
+    });
+    audited(AuditAction::AdminRotate, "admin_password", saved).await?;
+    info!("Admin password rotated");
+    Ok(Json(json!({ "admin_password": password })))
+}
+
+/// Query parameters for the model list
+#[derive(Deserialize)]
+pub struct ModelsQuery {
+    /// Fetch the list from upstream even when it is fresh
+    #[serde(default)]
+    pub refresh: bool,
+}
 
 /// API endpoint to get the list of available models
-/// Retrieves the list of models from the configuration
-pub async fn api_get_models() -> Json<Value> {
-    let data: Vec<Value> = MODEL_LIST
+/// Lists the models offered upstream followed by the configured `model_aliases`
+///
+/// # Arguments
+/// * `query` - Whether to fetch the list again
+///
+/// # Returns
+/// * `Json<Value>` - The models, `stale` when the latest upstream fetch failed
+pub async fn api_get_models(Query(query): Query<ModelsQuery>) -> Json<Value> {
+    let list = model_list::current(query.refresh).await;
+    let data: Vec<Value> = list
+        .models(&CLEWDR_CONFIG.load().model_aliases)
         .iter()
         .map(|model| {
             json!({
@@ -324,6 +597,8 @@ pub async fn api_get_models() -> Json<Value> {
     Json(json!({
         "object": "list",
         "data": data,
+        "stale": list.stale,
+        "fetched_at": list.fetched_at,
     }))
 }
 
@@ -333,6 +608,30 @@ pub async fn api_get_models() -> Json<Value> {
 use futures::{StreamExt, stream};
 use http::HeaderValue;
 
+/// Adds the in-flight requests and last rate-limit headers of each cookie and
+/// the queue depth to a status report
+fn with_load(mut data: Value, in_flight: &HashMap<ClewdrCookie, usize>) -> Value {
+    for list in ["valid", "exhausted"] {
+        let Some(cookies) = data.get_mut(list).and_then(Value::as_array_mut) else {
+            continue;
+        };
+        for cookie in cookies.iter_mut() {
+            let parsed = cookie
+                .get("cookie")
+                .and_then(Value::as_str)
+                .and_then(|c| c.parse::<ClewdrCookie>().ok());
+            let count = parsed
+                .as_ref()
+                .and_then(|c| in_flight.get(c).copied())
+                .unwrap_or_default();
+            cookie["in_flight"] = json!(count);
+            cookie["rate_limits"] = json!(parsed.as_ref().and_then(rate_limits::last_seen));
+        }
+    }
+    data["queued"] = json!(queue::queued());
+    data
+}
+
 async fn augment_utilization(cookies: Vec<CookieStatus>, handle: CookieActorHandle) -> Vec<Value> {
     let concurrency = 5usize;
     stream::iter(cookies.into_iter().map(move |cookie| {
@@ -383,9 +682,14 @@ async fn fetch_usage_percent(
     u32,
     Option<String>,
 )> {
-    let mut state = ClaudeCodeState::from_cookie(handle, cookie).ok()?;
-    let usage = state.fetch_usage_metrics().await.ok()?;
-    state.return_cookie(None).await;
+    let usage = if CLEWDR_CONFIG.load().demo {
+        demo::usage_metrics(&cookie)
+    } else {
+        let mut state = ClaudeCodeState::from_cookie(handle, cookie).ok()?;
+        let usage = state.fetch_usage_metrics().await.ok()?;
+        state.return_cookie(None).await;
+        usage
+    };
     let five = usage
         .get("five_hour")
         .and_then(|o| o.get("utilization"))
@@ -441,3 +745,89 @@ async fn fetch_usage_percent(
         sonnet_reset,
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AccountFlag, AccountStanding, Reason, UselessCookie};
+
+    fn query(
+        page: Option<usize>,
+        per_page: Option<usize>,
+        status: Option<&str>,
+    ) -> CookieStatusQuery {
+        CookieStatusQuery {
+            refresh: false,
+            page,
+            per_page,
+            status: status.map(|s| serde_json::from_value(json!(s)).unwrap()),
+        }
+    }
+
+    #[test]
+    fn pages_through_filtered_lists() {
+        let data = json!({
+            "valid": [{"cookie": "a"}, {"cookie": "b"}, {"cookie": "c"}],
+            "exhausted": [{"cookie": "d"}],
+            "invalid": [{"cookie": "e", "reason": "Null"}],
+            "queued": 0,
+        });
+        // no parameters keeps the full report
+        assert_eq!(paginate(data.to_owned(), &query(None, None, None)), data);
+
+        let page = paginate(data.to_owned(), &query(Some(2), Some(2), None));
+        assert_eq!(page["total"], 5);
+        assert_eq!(
+            page["items"],
+            json!([{"cookie": "c", "status": "valid"}, {"cookie": "d", "status": "exhausted"}])
+        );
+
+        let limited = paginate(data.to_owned(), &query(None, None, Some("rate_limited")));
+        assert_eq!(limited["total"], 1);
+        assert_eq!(limited["page"], 1);
+        assert_eq!(limited["per_page"], DEFAULT_PER_PAGE);
+
+        let past_end = paginate(data, &query(Some(9), Some(500_000), Some("valid")));
+        assert_eq!(past_end["items"], json!([]));
+        assert_eq!(past_end["per_page"], MAX_PER_PAGE);
+    }
+
+    #[test]
+    fn usage_carries_the_account_standing() {
+        let cookie = |i: usize| {
+            let body = format!("{:0<86}", format!("usage-test-{i:02}-"));
+            CookieStatus::new(&format!("sk-ant-sid01-{body}-{i:06}AA"), None).unwrap()
+        };
+        let ban = AccountFlag {
+            standing: AccountStanding::Banned,
+            message: "This account has been suspended.".into(),
+            seen_at: 1_000,
+            until: None,
+            strikes: 1,
+        };
+        let mut rested = cookie(2);
+        rested.flag = Some(ban.to_owned());
+        rested.session_usage.total_output_tokens = 42;
+        let report = usage_report(CookieStatusInfo {
+            valid: vec![cookie(1)],
+            exhausted: vec![rested.to_owned()],
+            invalid: vec![UselessCookie {
+                flag: Some(ban),
+                ..UselessCookie::new(cookie(3).cookie, Reason::Banned)
+            }],
+            in_flight: HashMap::new(),
+        });
+        let cookies = report["cookies"].as_array().unwrap();
+        assert_eq!(cookies[0]["standing"], Value::Null);
+        assert_eq!(cookies[1]["cookie"], hash_cookie(&rested));
+        assert_eq!(cookies[1]["standing"], "banned");
+        assert_eq!(cookies[1]["session"]["total_output_tokens"], 42);
+        assert_eq!(cookies[2]["state"], "invalid");
+        assert_eq!(
+            cookies[2]["flag"]["message"],
+            "This account has been suspended."
+        );
+        // values never leave as they are
+        assert!(!report.to_string().contains("usage-test"));
+    }
+}
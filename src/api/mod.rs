@@ -1,17 +1,63 @@
+mod audit;
+mod cache;
 mod claude_code;
 mod claude_web;
 mod config;
+mod conformance;
+mod cookie_batch;
 mod error;
+mod health;
+mod keys;
+mod language;
+mod logs;
 mod misc;
+mod ratelimit;
+mod report;
+mod resources;
+mod slo;
+mod startup;
+mod transcript;
+/// Audit trail of admin actions
+pub use audit::api_get_audit;
+/// Response cache statistics and flushing
+pub use cache::{api_delete_cache, api_get_cache};
 pub use claude_code::{api_claude_code, api_claude_code_count_tokens};
 /// Message handling endpoints for creating and managing chat conversations
 pub use claude_web::api_claude_web;
 /// Configuration related endpoints for retrieving and updating Clewdr settings
-pub use config::{api_get_config, api_post_config};
+pub use config::{
+    api_export_config, api_get_config, api_import_config, api_patch_config, api_post_config,
+};
+/// Saved backend conformance reports
+pub use conformance::api_get_conformance;
+/// Batch cookie submission with a per entry report
+pub use cookie_batch::{api_post_cookie_batch, api_post_cookie_delete};
 pub use error::ApiError;
+/// Unauthenticated liveness and readiness probes
+pub use health::{api_healthz, api_readyz, api_readyz_starting};
+/// Client API key management
+pub use keys::{api_delete_key, api_get_keys, api_post_key};
+/// Response language detection counts
+pub use language::api_get_language;
+/// Time range selected log file downloads
+pub use logs::api_download_logs;
 /// Miscellaneous endpoints for authentication, cookies, and version information
 pub use misc::{
-    api_auth, api_delete_cookie, api_get_cookies, api_get_models, api_post_cookie, api_put_cookie,
-    api_version,
+    api_auth, api_delete_cookie, api_get_cookies, api_get_models, api_get_usage, api_post_cookie,
+    api_put_cookie, api_rotate_admin, api_version,
+};
+/// Rate limited client addresses and their reset
+pub use ratelimit::{api_delete_offender, api_get_offenders};
+/// Request report retrieval for clients that cannot take body changes
+pub use report::api_get_report;
+/// Process resource usage endpoint
+pub use resources::api_get_resources;
+/// Service level objective endpoint
+pub use slo::api_get_slo;
+/// Per subsystem startup progress
+pub use startup::api_get_startup;
+/// Transcript endpoints for browsing and purging recorded exchanges
+pub use transcript::{
+    api_delete_transcripts, api_get_transcript, api_get_transcripts, api_redact_transcript,
 };
 // merged above
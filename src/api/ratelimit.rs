@@ -0,0 +1,60 @@
+use std::net::IpAddr;
+
+use axum::{Json, extract::Path};
+use axum_auth::AuthBearer;
+use serde_json::{Value, json};
+use tracing::info;
+
+use super::{audit::audited, error::ApiError};
+use crate::{
+    config::CLEWDR_CONFIG,
+    services::{audit::AuditAction, throttle},
+};
+
+/// API endpoint to list the client addresses the rate limiter rejected
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+///
+/// # Returns
+/// * `Result<Json<Value>, ApiError>` - Offenders, most rejected first, with their blocks
+pub async fn api_get_offenders(AuthBearer(t): AuthBearer) -> Result<Json<Value>, ApiError> {
+    let config = CLEWDR_CONFIG.load();
+    if !config.admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    Ok(Json(json!({
+        "enabled": config.rate_limit.enabled,
+        "offenders": throttle::offenders(),
+    })))
+}
+
+/// API endpoint to clear the limits and block of one client address
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+/// * `ip` - Address of the client
+///
+/// # Returns
+/// * `Result<Json<Value>, ApiError>` - Success message on success, 404 for untracked addresses
+pub async fn api_delete_offender(
+    AuthBearer(t): AuthBearer,
+    Path(ip): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    let result = ip
+        .parse::<IpAddr>()
+        .map_err(|_| ApiError::bad_request(format!("Invalid address {ip}")))
+        .and_then(|addr| {
+            throttle::reset(addr)
+                .then_some(())
+                .ok_or_else(|| ApiError::not_found(format!("Address {ip} is not tracked")))
+        });
+    audited(AuditAction::RateLimitReset, format!("address {ip}"), result).await?;
+    info!("Rate limit of {} reset", ip);
+    Ok(Json(
+        json!({ "message": format!("Rate limit of {ip} reset") }),
+    ))
+}
@@ -0,0 +1,24 @@
+use axum::{Extension, Json, extract::Path};
+
+use super::error::ApiError;
+use crate::{
+    config::ClientAuth,
+    middleware::claude::{RequestReport, get_report},
+};
+
+/// API endpoint to retrieve a request report delivered by header
+///
+/// # Arguments
+/// * `auth` - Client the auth layer admitted, reports of other clients are not found
+/// * `id` - Report id from the `x-clewdr-report-id` response header
+///
+/// # Returns
+/// * `Result<Json<RequestReport>, ApiError>` - The report, 404 once it expired or when it is another client's
+pub async fn api_get_report(
+    Extension(auth): Extension<ClientAuth>,
+    Path(id): Path<String>,
+) -> Result<Json<RequestReport>, ApiError> {
+    get_report(&id, &auth)
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found(format!("Report {} not found", id)))
+}
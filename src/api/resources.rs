@@ -0,0 +1,31 @@
+use axum::Json;
+use axum_auth::AuthBearer;
+use serde_json::{Value, json};
+
+use super::error::ApiError;
+use crate::{
+    config::CLEWDR_CONFIG,
+    services::{account_cache, conversations, resources::RESOURCES, response_cache},
+};
+
+/// API endpoint to retrieve process resource usage broken down by subsystem
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+///
+/// # Returns
+/// * `Result<Json<Value>, ApiError>` - Current sample, the last hour of samples and
+///   the account metadata and response cache statistics and the reused conversations
+pub async fn api_get_resources(AuthBearer(t): AuthBearer) -> Result<Json<Value>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    let current = RESOURCES.sample(chrono::Utc::now().timestamp());
+    Ok(Json(json!({
+        "current": current,
+        "history": RESOURCES.history(),
+        "account_cache": account_cache::stats(),
+        "response_cache": response_cache::stats(),
+        "web_sessions": conversations::active(),
+    })))
+}
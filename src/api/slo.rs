@@ -0,0 +1,32 @@
+use std::sync::PoisonError;
+
+use axum::Json;
+use axum_auth::AuthBearer;
+use serde_json::{Value, json};
+
+use super::error::ApiError;
+use crate::{
+    config::CLEWDR_CONFIG,
+    services::{retry, slo::SLO_TRACKER},
+};
+
+/// API endpoint to retrieve compliance and error budget burn of every objective
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+///
+/// # Returns
+/// * `Result<Json<Value>, ApiError>` - Objective reports and recent alert events
+pub async fn api_get_slo(AuthBearer(t): AuthBearer) -> Result<Json<Value>, ApiError> {
+    let config = CLEWDR_CONFIG.load();
+    if !config.admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    let now = chrono::Utc::now().timestamp();
+    let tracker = SLO_TRACKER.lock().unwrap_or_else(PoisonError::into_inner);
+    Ok(Json(json!({
+        "slos": tracker.report(&config.slo, now),
+        "events": tracker.events().collect::<Vec<_>>(),
+        "retries": retry::stats(),
+    })))
+}
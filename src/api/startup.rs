@@ -0,0 +1,22 @@
+use axum::Json;
+use axum_auth::AuthBearer;
+
+use super::error::ApiError;
+use crate::{
+    config::CLEWDR_CONFIG,
+    services::startup::{STARTUP, StartupReport},
+};
+
+/// API endpoint to retrieve the startup status and timing of each subsystem
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+///
+/// # Returns
+/// * `Result<Json<StartupReport>, ApiError>` - Startup report
+pub async fn api_get_startup(AuthBearer(t): AuthBearer) -> Result<Json<StartupReport>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    Ok(Json(STARTUP.report()))
+}
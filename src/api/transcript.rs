@@ -0,0 +1,142 @@
+use axum::{
+    Json,
+    extract::{Path, Query},
+};
+use axum_auth::AuthBearer;
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use super::{audit::audited, error::ApiError};
+use crate::{
+    config::{CLEWDR_CONFIG, RedactionRules},
+    services::{
+        audit::AuditAction,
+        redact::redact_transcript,
+        transcript::{get_transcript, list_transcripts, purge_transcripts},
+    },
+};
+
+/// Query parameters for transcript listing
+#[derive(Deserialize)]
+pub struct TranscriptQuery {
+    #[serde(default = "default_limit")]
+    limit: usize,
+    #[serde(default)]
+    offset: usize,
+}
+
+fn default_limit() -> usize {
+    50
+}
+
+/// API endpoint to list recorded transcripts, newest first
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+/// * `q` - Pagination parameters
+///
+/// # Returns
+/// * `Result<Json<Value>, ApiError>` - Total count and summaries without bodies
+pub async fn api_get_transcripts(
+    AuthBearer(t): AuthBearer,
+    Query(q): Query<TranscriptQuery>,
+) -> Result<Json<Value>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    let (total, items) = list_transcripts(q.limit.min(500), q.offset)
+        .await
+        .map_err(|e| ApiError::internal(format!("Failed to list transcripts: {}", e)))?;
+    Ok(Json(json!({ "total": total, "items": items })))
+}
+
+/// API endpoint to fetch a single transcript with request and response bodies
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+/// * `id` - Transcript id
+///
+/// # Returns
+/// * `Result<Json<Value>, ApiError>` - The transcript, 404 if it does not exist
+pub async fn api_get_transcript(
+    AuthBearer(t): AuthBearer,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    match get_transcript(&id).await {
+        Ok(Some(transcript)) => Ok(Json(json!(transcript))),
+        Ok(None) => Err(ApiError::not_found(format!("Transcript {} not found", id))),
+        Err(e) => Err(ApiError::internal(format!(
+            "Failed to read transcript: {}",
+            e
+        ))),
+    }
+}
+
+/// API endpoint to produce a redacted variant of a transcript for sharing
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+/// * `id` - Transcript id
+/// * `rules` - Rules overriding the configured `redaction` rules
+///
+/// # Returns
+/// * `Result<Json<Value>, ApiError>` - The watermarked transcript, 404 if it does not exist
+pub async fn api_redact_transcript(
+    AuthBearer(t): AuthBearer,
+    Path(id): Path<String>,
+    rules: Option<Json<RedactionRules>>,
+) -> Result<Json<Value>, ApiError> {
+    let config = CLEWDR_CONFIG.load();
+    if !config.admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    let transcript = match get_transcript(&id).await {
+        Ok(Some(transcript)) => transcript,
+        Ok(None) => return Err(ApiError::not_found(format!("Transcript {} not found", id))),
+        Err(e) => {
+            return Err(ApiError::internal(format!(
+                "Failed to read transcript: {}",
+                e
+            )));
+        }
+    };
+    let rules = rules
+        .map(|Json(r)| r)
+        .unwrap_or_else(|| config.redaction.to_owned());
+    // settings that shape the output, as of now
+    let settings = json!({
+        "typography": config.typography,
+        "use_real_roles": config.use_real_roles,
+        "sanitize_messages": config.sanitize_messages,
+        "web_search": config.web_search,
+        "preserve_chats": config.preserve_chats,
+    });
+    let redacted = redact_transcript(&transcript, &rules, settings)
+        .map_err(|e| ApiError::internal(format!("Failed to redact transcript: {}", e)))?;
+    Ok(Json(json!(redacted)))
+}
+
+/// API endpoint to delete all recorded transcripts
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+///
+/// # Returns
+/// * `Result<Json<Value>, ApiError>` - Number of deleted transcripts
+pub async fn api_delete_transcripts(AuthBearer(t): AuthBearer) -> Result<Json<Value>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    let result = purge_transcripts()
+        .await
+        .map_err(|e| ApiError::internal(format!("Failed to purge transcripts: {}", e)));
+    let summary = match result {
+        Ok(deleted) => format!("{deleted} transcripts"),
+        Err(_) => "all transcripts".to_string(),
+    };
+    let deleted = audited(AuditAction::TranscriptPurge, summary, result).await?;
+    Ok(Json(json!({ "deleted": deleted })))
+}
@@ -2,12 +2,12 @@ use std::collections::HashSet;
 
 use axum::{
     Json,
-    response::{IntoResponse, Sse, sse::Event as SseEvent},
+    response::{IntoResponse, sse::Event as SseEvent},
 };
 use colored::Colorize;
 use eventsource_stream::Eventsource;
 use futures::TryStreamExt;
-use http::header::{ACCEPT, USER_AGENT};
+use http::header::{ACCEPT, CONTENT_TYPE, USER_AGENT};
 use snafu::{GenerateImplicitData, ResultExt};
 use tracing::{Instrument, error, info, warn};
 use wreq::Method;
@@ -17,10 +17,12 @@ use crate::{
         ClaudeCodeState, TokenStatus,
         telemetry::{self, EventData},
     },
-    config::{CLAUDE_CODE_USER_AGENT, CLEWDR_CONFIG, Claude1mChannel, ModelFamily},
+    config::{BilledTokens, CLAUDE_CODE_USER_AGENT, CLEWDR_CONFIG, Claude1mChannel, ModelFamily},
     error::{CheckClaudeErr, ClewdrError, WreqSnafu},
-    services::cookie_actor::CookieActorHandle,
+    middleware::claude::{UpstreamHeaders, mark_served_by},
+    services::{cookie_actor::CookieActorHandle, queue::hold_permit, rate_limits},
     types::claude::{CountMessageTokensResponse, CreateMessageParams},
+    utils::{LogOnDrop, sse_response},
 };
 
 pub(super) const CLAUDE_BETA_BASE: &str = "oauth-2025-04-20";
@@ -93,7 +95,8 @@ impl ClaudeCodeState {
             ));
             match retry.await {
                 Ok(res) => {
-                    return Ok(res);
+                    let res = mark_served_by(res, state.cookie.as_ref());
+                    return Ok(hold_permit(res, state.permit.take()));
                 }
                 Err(e) => {
                     error!(
@@ -191,7 +194,9 @@ impl ClaudeCodeState {
                     {
                         self.persist_claude_1m_support(ch, true).await;
                     }
-                    return self.handle_success_response(response, model_family).await;
+                    return self
+                        .handle_success_response(response, &p.model, model_family)
+                        .await;
                 }
                 Err(err) => {
                     // Emit tengu_api_error
@@ -292,7 +297,8 @@ impl ClaudeCodeState {
         }
     }
 
-    pub async fn fetch_usage_metrics(&mut self) -> Result<serde_json::Value, ClewdrError> {
+    /// Access token of the current cookie, obtained or refreshed when needed
+    async fn access_token(&mut self) -> Result<String, ClewdrError> {
         match self.check_token() {
             TokenStatus::None => {
                 let org = self.get_organization().await?;
@@ -304,8 +310,7 @@ impl ClaudeCodeState {
             }
             TokenStatus::Valid => {}
         }
-
-        let access_token = self
+        Ok(self
             .cookie
             .as_ref()
             .and_then(|c| c.token.as_ref())
@@ -313,8 +318,11 @@ impl ClaudeCodeState {
                 msg: "No access token available",
             })?
             .access_token
-            .to_owned();
+            .to_owned())
+    }
 
+    pub async fn fetch_usage_metrics(&mut self) -> Result<serde_json::Value, ClewdrError> {
+        let access_token = self.access_token().await?;
         self.client
             .request(Method::GET, CLAUDE_USAGE_URL)
             .bearer_auth(access_token)
@@ -335,6 +343,45 @@ impl ClaudeCodeState {
             })
     }
 
+    /// Lists the model ids the Anthropic API offers to the current cookie
+    pub async fn fetch_models(&mut self) -> Result<Vec<String>, ClewdrError> {
+        #[derive(serde::Deserialize)]
+        struct ModelPage {
+            data: Vec<ModelEntry>,
+        }
+        #[derive(serde::Deserialize)]
+        struct ModelEntry {
+            id: String,
+        }
+
+        let access_token = self.access_token().await?;
+        let page = self
+            .client
+            .get(
+                self.endpoint
+                    .join("v1/models?limit=1000")
+                    .expect("Url parse error")
+                    .to_string(),
+            )
+            .bearer_auth(access_token)
+            .header(USER_AGENT, CLAUDE_CODE_USER_AGENT)
+            .header("anthropic-beta", CLAUDE_BETA_BASE)
+            .header("anthropic-version", CLAUDE_API_VERSION)
+            .send()
+            .await
+            .context(WreqSnafu {
+                msg: "Failed to fetch models",
+            })?
+            .check_claude()
+            .await?
+            .json::<ModelPage>()
+            .await
+            .context(WreqSnafu {
+                msg: "Failed to parse models response",
+            })?;
+        Ok(page.data.into_iter().map(|m| m.id).collect())
+    }
+
     pub async fn try_count_tokens(
         &mut self,
         p: CreateMessageParams,
@@ -486,27 +533,46 @@ impl ClaudeCodeState {
     async fn handle_success_response(
         &mut self,
         response: wreq::Response,
+        model: &str,
         model_family: ModelFamily,
     ) -> Result<axum::response::Response, ClewdrError> {
+        let upstream = UpstreamHeaders::select(
+            response.headers(),
+            &CLEWDR_CONFIG.load().header_passthrough.claude_code,
+        );
+        if let Some(cookie) = self.cookie.as_ref() {
+            rate_limits::record(&cookie.cookie, response.headers());
+        }
         if !self.stream {
-            let (resp, usage_pair) = Self::materialize_non_stream_response(response).await?;
-            let (input, output) = usage_pair.unwrap_or((self.usage.input_tokens as u64, 0));
-            self.persist_usage_totals(input, output, model_family).await;
-            Ok(resp)
+            let (resp, usage) = Self::materialize_non_stream_response(response).await?;
+            let tokens =
+                usage.unwrap_or_else(|| BilledTokens::uncached(self.usage.input_tokens as u64, 0));
+            self.persist_usage_totals(tokens, model, model_family).await;
+            Ok(upstream.attach(resp))
         } else {
             // Stream pass-through while accumulating output token usage from message_delta events
-            return self.forward_stream_with_usage(response, model_family).await;
+            let resp = self
+                .forward_stream_with_usage(response, model.to_owned(), model_family)
+                .await?;
+            Ok(upstream.attach(resp))
         }
     }
 
-    async fn persist_usage_totals(&mut self, input: u64, output: u64, family: ModelFamily) {
-        if input == 0 && output == 0 {
+    async fn persist_usage_totals(
+        &mut self,
+        tokens: BilledTokens,
+        model: &str,
+        family: ModelFamily,
+    ) {
+        let BilledTokens { input, output, .. } = tokens;
+        if self.smoke || (input == 0 && output == 0) {
             return;
         }
         if let Some(cookie) = self.cookie.as_mut() {
             // Lazy boundary refresh if due, then reset period counters and start fresh
             Self::update_cookie_boundaries_if_due(cookie, &self.cookie_actor_handle).await;
-            cookie.add_and_bucket_usage(input, output, family);
+            let cost = crate::config::cost_now(model, tokens, false);
+            cookie.add_and_bucket_usage(input, output, family, cost);
             let cloned = cookie.clone();
             if let Err(err) = self.cookie_actor_handle.return_cookie(cloned, None).await {
                 warn!("Failed to persist usage statistics: {}", err);
@@ -517,6 +583,7 @@ impl ClaudeCodeState {
     async fn forward_stream_with_usage(
         &mut self,
         response: wreq::Response,
+        model: String,
         family: ModelFamily,
     ) -> Result<axum::response::Response, ClewdrError> {
         use std::sync::{
@@ -527,10 +594,19 @@ impl ClaudeCodeState {
         let input_tokens = self.usage.input_tokens as u64;
         let output_sum = Arc::new(AtomicU64::new(0));
         let handle = self.cookie_actor_handle.clone();
-        let cookie = self.cookie.clone();
+        // smoke checks are not counted, skip persisting totals for them
+        let cookie = self.cookie.clone().filter(|_| !self.smoke);
 
         let osum = output_sum.clone();
-        let stream = response.bytes_stream().eventsource().map_ok(move |event| {
+        // input usage, cached input included, is only reported by message_start
+        let mut started = BilledTokens::default();
+        let upstream = LogOnDrop::new(response.bytes_stream());
+        let stream = upstream.eventsource().map_ok(move |event| {
+            if event.event == "message_start"
+                && let Ok(start) = serde_json::from_str::<serde_json::Value>(&event.data)
+            {
+                started = BilledTokens::from_usage(&start["message"]["usage"]);
+            }
             // accumulate output tokens from message_delta usage if present
             if let Ok(parsed) =
                 serde_json::from_str::<crate::types::claude::StreamEvent>(&event.data)
@@ -544,11 +620,13 @@ impl ClaudeCodeState {
                         if let (Some(cookie), handle) = (cookie.clone(), handle.clone()) {
                             let total_out = osum.load(Ordering::Relaxed);
                             let mut c = cookie.clone();
+                            let tokens = Self::stream_tokens(started, input_tokens, total_out);
+                            let cost = crate::config::cost_now(&model, tokens, false);
                             tokio::spawn(async move {
                                 // Update period boundaries if needed, then accumulate
                                 ClaudeCodeState::update_cookie_boundaries_if_due(&mut c, &handle)
                                     .await;
-                                c.add_and_bucket_usage(input_tokens, total_out, family);
+                                c.add_and_bucket_usage(tokens.input, tokens.output, family, cost);
                                 let _ = handle.return_cookie(c, None).await;
                             });
                         }
@@ -566,24 +644,42 @@ impl ClaudeCodeState {
             e.data(event.data)
         });
 
-        Ok(Sse::new(stream)
-            .keep_alive(Default::default())
-            .into_response())
+        Ok(sse_response(stream))
+    }
+
+    /// Tokens a finished stream is billed for
+    ///
+    /// # Arguments
+    /// * `started` - Usage reported by `message_start`
+    /// * `estimate` - Input tokens counted locally, used when upstream reported none
+    /// * `output` - Output tokens summed from `message_delta` events
+    fn stream_tokens(started: BilledTokens, estimate: u64, output: u64) -> BilledTokens {
+        // the estimate counts cached input too, upstream splits it off
+        let input = match started.input {
+            0 => estimate,
+            reported => reported,
+        };
+        BilledTokens {
+            input,
+            output,
+            ..started
+        }
     }
 
     async fn materialize_non_stream_response(
         response: wreq::Response,
-    ) -> Result<(axum::response::Response, Option<(u64, u64)>), ClewdrError> {
+    ) -> Result<(axum::response::Response, Option<BilledTokens>), ClewdrError> {
         let status = response.status();
-        let headers = response.headers().clone();
+        // other upstream headers are forwarded by the passthrough policy only
+        let content_type = response.headers().get(CONTENT_TYPE).cloned();
         let bytes = response.bytes().await.context(WreqSnafu {
             msg: "Failed to read Claude response body",
         })?;
         let usage = Self::extract_usage_from_bytes(&bytes);
 
         let mut builder = http::Response::builder().status(status);
-        for (key, value) in headers.iter() {
-            builder = builder.header(key, value);
+        if let Some(content_type) = content_type {
+            builder = builder.header(CONTENT_TYPE, content_type);
         }
         let response =
             builder
@@ -595,7 +691,7 @@ impl ClaudeCodeState {
         Ok((response, usage))
     }
 
-    fn extract_usage_from_bytes(bytes: &[u8]) -> Option<(u64, u64)> {
+    fn extract_usage_from_bytes(bytes: &[u8]) -> Option<BilledTokens> {
         // Prefer explicit usage if present
         if let Ok(value) = serde_json::from_slice::<serde_json::Value>(bytes)
             && let Some(usage) = value.get("usage")
@@ -606,8 +702,12 @@ impl ClaudeCodeState {
             let output = usage
                 .get("output_tokens")
                 .and_then(|v| v.as_u64().or_else(|| v.as_i64().map(|n| n.max(0) as u64)));
-            if let (Some(i), Some(o)) = (input, output) {
-                return Some((i, o));
+            if let (Some(input), Some(output)) = (input, output) {
+                return Some(BilledTokens {
+                    input,
+                    output,
+                    ..BilledTokens::from_usage(usage)
+                });
             }
         }
 
@@ -617,7 +717,7 @@ impl ClaudeCodeState {
         {
             let output_tokens = parsed.count_tokens() as u64;
             // Input tokens already computed earlier and present in self.usage; only estimate output here
-            return Some((0, output_tokens));
+            return Some(BilledTokens::uncached(0, output_tokens));
         }
         None
     }
@@ -900,3 +1000,39 @@ impl ClaudeCodeState {
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::config::CookieStatus;
+
+    #[test]
+    fn streams_record_the_input_they_are_billed_for() {
+        // the local estimate counts the cached prefix, upstream reports it apart
+        let started = BilledTokens::from_usage(&json!({
+            "input_tokens": 24,
+            "cache_read_input_tokens": 900,
+            "output_tokens": 1,
+        }));
+        let tokens = ClaudeCodeState::stream_tokens(started, 1000, 50);
+        assert_eq!(
+            tokens,
+            BilledTokens {
+                input: 24,
+                output: 50,
+                cache_read: 900,
+                cache_write: 0,
+            }
+        );
+        let mut cookie = CookieStatus::default();
+        cookie.add_and_bucket_usage(tokens.input, tokens.output, ModelFamily::Sonnet, 0.0);
+        assert_eq!(cookie.session_usage.total_input_tokens, 24);
+        assert_eq!(cookie.session_usage.total_output_tokens, 50);
+
+        // without a reported count the estimate is all there is
+        let tokens = ClaudeCodeState::stream_tokens(BilledTokens::default(), 1000, 50);
+        assert_eq!(tokens, BilledTokens::uncached(1000, 50));
+    }
+}
@@ -25,6 +25,7 @@ use crate::{
         TokenInfo,
     },
     error::{CheckClaudeErr, ClewdrError, UnexpectedNoneSnafu, UrlSnafu, WreqSnafu},
+    services::token_refresh,
 };
 
 use super::chat::{CLAUDE_API_VERSION, CLAUDE_BETA_BASE};
@@ -203,7 +204,10 @@ impl ClaudeCodeState {
         let token = token_request.request_async(&my_client).await?;
 
         if let Some(cookie) = self.cookie.as_mut() {
-            cookie.token = Some(TokenInfo::new(token, code_res.org_uuid.clone()));
+            let token = TokenInfo::new(token, code_res.org_uuid.clone());
+            token_refresh::remember_token(&cookie.cookie.to_string(), &token);
+            cookie.token = Some(token);
+            cookie.needs_reauth = false;
         } else {
             return Err(ClewdrError::UnexpectedNone {
                 msg: "No cookie found to update with token info",
@@ -215,6 +219,7 @@ impl ClaudeCodeState {
     pub async fn refresh_token(&mut self) -> Result<(), ClewdrError> {
         let wreq_client = self.get_wreq_client();
         let Some(CookieStatus {
+            ref cookie,
             token: Some(ref mut token),
             ..
         }) = self.cookie
@@ -226,6 +231,18 @@ impl ClaudeCodeState {
         if !token.is_expired() {
             return Ok(());
         }
+        // one refresh per cookie at a time, waiters reuse the winner's token
+        let cookie_key = cookie.to_string();
+        let lock = token_refresh::token_lock(&cookie_key);
+        let _guard = lock.lock().await;
+        if let Some(fresh) = token_refresh::refreshed_token(&cookie_key) {
+            *token = fresh;
+            return Ok(());
+        }
+        if token.refresh_token.is_empty() {
+            tracing::warn!("Token expired without a refresh token, attempting to re-authorize");
+            return self.reauthorize().await;
+        }
 
         let cc_client_id = CLEWDR_CONFIG.load().cc_client_id();
 
@@ -250,6 +267,7 @@ impl ClaudeCodeState {
         match refresh_result {
             Ok(new_token) => {
                 *token = TokenInfo::new(new_token, org_uuid);
+                token_refresh::remember_token(&cookie_key, token);
                 Ok(())
             }
             Err(e) => {
@@ -260,36 +278,47 @@ impl ClaudeCodeState {
                 tracing::warn!(
                     "Refresh token invalid (invalid_grant), attempting to re-authorize with new OAuth2 flow"
                 );
-                // Clear the old token to force re-authorization
-                if let Some(cookie) = self.cookie.as_mut() {
-                    cookie.token = None;
-                }
+                self.reauthorize().await
+            }
+        }
+    }
 
-                // First, verify the cookie is still valid and check account type
-                // This will return Reason::Null if cookie is invalid,
-                // or Reason::Free if account was downgraded
-                let org_uuid = self
-                    .get_organization()
-                    .await
-                    .inspect_err(|e| tracing::error!("Cannot re-authorize: {}", e))?;
-
-                // Cookie is valid and account has Pro+ permissions, proceed with re-authorization
-                let code_res = self.exchange_code(&org_uuid).await.inspect_err(|e| {
-                    tracing::error!("Failed to exchange code during re-authorization: {}", e)
-                })?;
-                match self.exchange_token(code_res).await {
-                    Ok(_) => {
-                        tracing::info!("Successfully re-authorized with new OAuth2 flow");
-                        Ok(())
-                    }
-                    Err(token_err) => {
-                        tracing::error!(
-                            "Failed to exchange token during re-authorization: {}",
-                            token_err
-                        );
-                        Err(token_err)
-                    }
-                }
+    /// Drops the token of the current cookie and runs the OAuth2 flow again
+    ///
+    /// The cookie is saved as needing re-authorization first, so the listing
+    /// shows it if the flow fails.
+    async fn reauthorize(&mut self) -> Result<(), ClewdrError> {
+        // Clear the old token to force re-authorization
+        if let Some(cookie) = self.cookie.as_mut() {
+            cookie.token = None;
+            cookie.needs_reauth = true;
+        }
+        // persist the state so the listing shows it if re-authorization fails
+        self.return_cookie(None).await;
+
+        // First, verify the cookie is still valid and check account type
+        // This will return Reason::Null if cookie is invalid,
+        // or Reason::Free if account was downgraded
+        let org_uuid = self
+            .get_organization()
+            .await
+            .inspect_err(|e| tracing::error!("Cannot re-authorize: {}", e))?;
+
+        // Cookie is valid and account has Pro+ permissions, proceed with re-authorization
+        let code_res = self.exchange_code(&org_uuid).await.inspect_err(|e| {
+            tracing::error!("Failed to exchange code during re-authorization: {}", e)
+        })?;
+        match self.exchange_token(code_res).await {
+            Ok(_) => {
+                tracing::info!("Successfully re-authorized with new OAuth2 flow");
+                Ok(())
+            }
+            Err(token_err) => {
+                tracing::error!(
+                    "Failed to exchange token during re-authorization: {}",
+                    token_err
+                );
+                Err(token_err)
             }
         }
     }
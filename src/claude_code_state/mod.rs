@@ -2,6 +2,8 @@ mod chat;
 mod exchange;
 mod organization;
 pub mod telemetry;
+use std::sync::Arc;
+
 use http::{
     HeaderValue, Method,
     header::{COOKIE, ORIGIN, REFERER, USER_AGENT},
@@ -16,7 +18,7 @@ use crate::{
     config::{CLAUDE_CODE_USER_AGENT, CLAUDE_ENDPOINT, CLEWDR_CONFIG, CookieStatus, Reason},
     error::{ClewdrError, WreqSnafu},
     middleware::claude::ClaudeApiFormat,
-    services::cookie_actor::CookieActorHandle,
+    services::{cookie_actor::CookieActorHandle, demo, queue::CookiePermit},
     types::claude::Usage,
 };
 
@@ -33,6 +35,12 @@ pub struct ClaudeCodeState {
     pub system_prompt_hash: Option<u64>,
     pub anthropic_beta_header: Option<String>,
     pub usage: Usage,
+    // smoke checks are not counted in usage stats
+    pub smoke: bool,
+    // request slot on the cookie, released once the response is finished
+    pub permit: Option<Arc<CookiePermit>>,
+    // waits ahead of fresh requests when the client retried
+    pub retry_boost: u32,
 }
 
 impl ClaudeCodeState {
@@ -50,6 +58,9 @@ impl ClaudeCodeState {
             system_prompt_hash: None,
             anthropic_beta_header: None,
             usage: Usage::default(),
+            smoke: false,
+            permit: None,
+            retry_boost: 0,
         }
     }
 
@@ -58,6 +69,7 @@ impl ClaudeCodeState {
         cookie_actor_handle: CookieActorHandle,
         cookie: CookieStatus,
     ) -> Result<Self, ClewdrError> {
+        demo::ensure_live(&CLEWDR_CONFIG.load())?;
         let mut state = Self::new(cookie_actor_handle);
         state.cookie = Some(cookie);
         let cookie_value = state
@@ -119,11 +131,14 @@ impl ClaudeCodeState {
     /// Requests a new cookie from the cookie manager
     /// Updates the internal state with the new cookie and proxy configuration
     pub async fn request_cookie(&mut self) -> Result<CookieStatus, ClewdrError> {
-        let res = self
+        demo::ensure_live(&CLEWDR_CONFIG.load())?;
+        let limit = CLEWDR_CONFIG.load().code_cookie_concurrency;
+        let (res, permit) = self
             .cookie_actor_handle
-            .request(self.system_prompt_hash)
+            .acquire(self.system_prompt_hash, limit, self.retry_boost, &[])
             .await?;
         self.cookie = Some(res.to_owned());
+        self.permit = Some(Arc::new(permit));
         self.cookie_header_value = HeaderValue::from_str(res.cookie.to_string().as_str())?;
         // Always pull latest proxy/endpoint before building the client
         self.proxy = CLEWDR_CONFIG.load().wreq_proxy.to_owned();
@@ -7,11 +7,30 @@ use super::ClaudeCodeState;
 use crate::{
     config::Reason,
     error::{CheckClaudeErr, ClewdrError, WreqSnafu},
+    services::account_cache::{self, AccountMetadata},
     utils::print_out_json,
 };
 
 impl ClaudeCodeState {
     pub async fn get_organization(&self) -> Result<String, ClewdrError> {
+        let cookie = self.cookie.to_owned().ok_or(ClewdrError::UnexpectedNone {
+            msg: "Getting organization without a cookie",
+        })?;
+        let metadata = account_cache::account_metadata(&cookie, || self.fetch_metadata()).await?;
+        if !metadata.is_pro() {
+            return Err(Reason::Free.into());
+        }
+        println!(
+            "[{}]\nemail: {}\ncapabilities: {}",
+            cookie.cookie.ellipse().green(),
+            metadata.email.blue(),
+            metadata.capabilities.join(", ").blue()
+        );
+        Ok(metadata.membership_org_uuid)
+    }
+
+    /// Fetches the bootstrap and organizations of the cookie from Claude.ai
+    async fn fetch_metadata(&self) -> Result<AccountMetadata, ClewdrError> {
         let end_point = self
             .endpoint
             .join("api/bootstrap")
@@ -32,46 +51,23 @@ impl ClaudeCodeState {
         if bootstrap["account"].is_null() {
             return Err(Reason::Null.into());
         }
-        let memberships = bootstrap["account"]["memberships"]
-            .as_array()
-            .ok_or(Reason::Null)?;
-        let boot_acc_info = memberships
-            .iter()
-            .find(|m| {
-                m["organization"]["capabilities"]
-                    .as_array()
-                    .is_some_and(|c| c.iter().any(|c| c.as_str() == Some("chat")))
-            })
-            .and_then(|m| m["organization"].as_object())
-            .ok_or(Reason::Null)?;
-        let capabilities = boot_acc_info["capabilities"]
-            .as_array()
-            .map(|a| a.iter().filter_map(|c| c.as_str()).collect::<Vec<_>>())
-            .unwrap_or_default();
-        if !capabilities.iter().any(|c| {
-            c.contains("pro")
-                || c.contains("enterprise")
-                || c.contains("raven")
-                || c.contains("max")
-        }) {
-            return Err(Reason::Free.into());
-        }
-        let email = bootstrap["account"]["email_address"]
-            .as_str()
-            .unwrap_or_default();
-        let uuid = boot_acc_info["uuid"]
-            .as_str()
-            .ok_or(ClewdrError::UnexpectedNone {
-                msg: "Failed to get organization UUID",
+        let end_point = self
+            .endpoint
+            .join("api/organizations")
+            .expect("Url parse error");
+        let res = self
+            .build_request(Method::GET, end_point)
+            .send()
+            .await
+            .context(WreqSnafu {
+                msg: "Failed to get organizations",
             })?
-            .to_string();
-
-        println!(
-            "[{}]\nemail: {}\ncapabilities: {}",
-            self.cookie.as_ref().unwrap().cookie.ellipse().green(),
-            email.blue(),
-            capabilities.join(", ").blue()
-        );
-        Ok(uuid)
+            .check_claude()
+            .await?;
+        let organizations = res.json::<Value>().await.context(WreqSnafu {
+            msg: "Failed to parse organizations response",
+        })?;
+        print_out_json(&organizations, "org.json");
+        AccountMetadata::from_responses(&bootstrap, &organizations, chrono::Utc::now().timestamp())
     }
 }
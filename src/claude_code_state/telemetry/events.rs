@@ -174,7 +174,9 @@ pub struct EventLoggingClient {
 
 impl EventLoggingClient {
     pub fn new() -> Self {
-        let client = wreq::Client::builder()
+        let client = crate::config::CLEWDR_CONFIG
+            .load()
+            .http_client_builder()
             .timeout(std::time::Duration::from_millis(EVENT_LOGGING_TIMEOUT_MS))
             .build()
             .expect("Failed to create event logging client");
@@ -9,6 +9,7 @@ use crate::{
     claude_web_state::ClaudeWebState,
     config::{CLEWDR_CONFIG, Reason},
     error::{CheckClaudeErr, ClewdrError, WreqSnafu},
+    services::account_cache::{self, AccountMetadata},
     utils::print_out_json,
 };
 
@@ -16,15 +17,37 @@ impl ClaudeWebState {
     /// Bootstraps the application state by initializing connections to Claude.ai
     ///
     /// This function performs the following operations:
-    /// 1. Sends a request to get the bootstrap data from Claude.ai
-    /// 2. Validates the cookie and account information
-    /// 3. Collects capabilities and checks if the account is pro
-    /// 4. Retrieves organization information
-    /// 5. Checks for account flags (restrictions, warnings, bans)
+    /// 1. Reads the account metadata of the cookie, fetching bootstrap data and
+    ///    organization information from Claude.ai when it is not cached
+    /// 2. Collects capabilities and checks if the account is pro
+    /// 3. Checks for account flags (restrictions, warnings, bans)
     ///
     /// # Returns
     /// * `Result<(), ClewdrError>` - Success or an error with details about cookie validity
     pub async fn bootstrap(&mut self) -> Result<(), ClewdrError> {
+        let cookie = self.cookie.to_owned().ok_or(ClewdrError::UnexpectedNone {
+            msg: "Bootstrapping without a cookie",
+        })?;
+        let metadata = account_cache::account_metadata(&cookie, || self.fetch_metadata()).await?;
+        self.capabilities = metadata.capabilities.to_owned();
+        if !self.is_pro() && CLEWDR_CONFIG.load().skip_non_pro {
+            return Err(Reason::Free.into());
+        }
+        let mut w = String::new();
+        writeln!(
+            w,
+            "[{}]\nemail: {}\ncapabilities: {}",
+            cookie.cookie.ellipse().green(),
+            metadata.email.blue(),
+            self.capabilities.join(", ").blue()
+        )?;
+        self.check_flags(&metadata.organization, w)?;
+        self.org_uuid = Some(metadata.org_uuid);
+        Ok(())
+    }
+
+    /// Fetches the bootstrap and organizations of the cookie from Claude.ai
+    async fn fetch_metadata(&self) -> Result<AccountMetadata, ClewdrError> {
         let end_point = self
             .endpoint
             .join("api/bootstrap")
@@ -45,48 +68,7 @@ impl ClaudeWebState {
         if bootstrap["account"].is_null() {
             return Err(Reason::Null.into());
         }
-        let memberships =
-            bootstrap["account"]["memberships"]
-                .as_array()
-                .ok_or(ClewdrError::UnexpectedNone {
-                    msg: "Failed to get memberships from bootstrap",
-                })?;
-        let boot_acc_info = memberships
-            .iter()
-            .find(|m| {
-                m["organization"]["capabilities"]
-                    .as_array()
-                    .is_some_and(|c| c.iter().any(|c| c.as_str() == Some("chat")))
-            })
-            .and_then(|m| m["organization"].as_object())
-            .ok_or(ClewdrError::UnexpectedNone {
-                msg: "Failed to find a valid organization in bootstrap",
-            })?;
-        let email = bootstrap["account"]["email_address"]
-            .as_str()
-            .unwrap_or_default();
-        self.capabilities = boot_acc_info["capabilities"]
-            .as_array()
-            .map(|a| {
-                a.iter()
-                    .filter_map(|c| c.as_str())
-                    .map(|c| c.to_string())
-                    .collect::<Vec<_>>()
-            })
-            .unwrap_or_default();
-        if !self.is_pro() && CLEWDR_CONFIG.load().skip_non_pro {
-            return Err(Reason::Free.into());
-        }
-        let mut w = String::new();
-        writeln!(
-            w,
-            "[{}]\nemail: {}\ncapabilities: {}",
-            self.cookie.as_ref().unwrap().cookie.ellipse().green(),
-            email.blue(),
-            self.capabilities.join(", ").blue()
-        )?;
 
-        // Bootstrap complete
         let end_point = self
             .endpoint
             .join("api/organizations")
@@ -100,41 +82,11 @@ impl ClaudeWebState {
             })?
             .check_claude()
             .await?;
-        let ret_json = res.json::<Value>().await.context(WreqSnafu {
+        let organizations = res.json::<Value>().await.context(WreqSnafu {
             msg: "Failed to parse organizations response",
         })?;
-        print_out_json(&ret_json, "org.json");
-        let acc_info = ret_json
-            .as_array()
-            .and_then(|a| {
-                a.iter()
-                    .filter(|v| {
-                        v.get("capabilities")
-                            .and_then(|c| c.as_array())
-                            .is_some_and(|c| c.iter().any(|c| c.as_str() == Some("chat")))
-                    })
-                    .max_by_key(|v| {
-                        v.get("capabilities")
-                            .and_then(|c| c.as_array())
-                            .map(|c| c.len())
-                            .unwrap_or_default()
-                    })
-            })
-            .ok_or(ClewdrError::UnexpectedNone {
-                msg: "Failed to find a valid organization in response",
-            })?;
-
-        self.check_flags(acc_info, w)?;
-
-        let u =
-            acc_info
-                .get("uuid")
-                .and_then(|u| u.as_str())
-                .ok_or(ClewdrError::UnexpectedNone {
-                    msg: "Failed to find UUID in organization response",
-                })?;
-        self.org_uuid = Some(u.to_string());
-        Ok(())
+        print_out_json(&organizations, "org.json");
+        AccountMetadata::from_responses(&bootstrap, &organizations, chrono::Utc::now().timestamp())
     }
 
     /// Checks if the account has any restrictions, warnings or bans
@@ -1,3 +1,4 @@
+use chrono::Utc;
 use colored::Colorize;
 use futures::TryFutureExt;
 use serde_json::json;
@@ -7,10 +8,15 @@ use wreq::{Method, Response, header::ACCEPT};
 
 use super::ClaudeWebState;
 use crate::{
-    config::CLEWDR_CONFIG,
+    config::{AccountFlag, AccountStanding, CLEWDR_CONFIG, Failover},
     error::{CheckClaudeErr, ClewdrError, WreqSnafu},
+    middleware::claude::{UpstreamHeaders, mark_served_by, synthesize_rate_limits},
+    services::{
+        conversations::{self, Resume},
+        queue::hold_permit,
+    },
     types::claude::CreateMessageParams,
-    utils::print_out_json,
+    utils::{print_out_json, random_unit},
 };
 
 impl ClaudeWebState {
@@ -36,26 +42,67 @@ impl ClaudeWebState {
         &mut self,
         p: CreateMessageParams,
     ) -> Result<axum::response::Response, ClewdrError> {
-        for i in 0..CLEWDR_CONFIG.load().max_retries + 1 {
-            if i > 0 {
-                info!("[RETRY] attempt: {}", i.to_string().green());
+        let (max_retries, policy) = {
+            let config = CLEWDR_CONFIG.load();
+            (config.max_retries, config.upstream_retry.to_owned())
+        };
+        let mut cookie_retries = 0;
+        let mut upstream_retries = 0;
+        // state kept for another attempt with the same cookie
+        let mut kept: Option<Self> = None;
+        loop {
+            let attempt = cookie_retries + upstream_retries as usize;
+            if attempt > 0 {
+                info!("[RETRY] attempt: {}", attempt.to_string().green());
             }
-            let mut state = self.to_owned();
-            let p = p.to_owned();
-
-            let cookie = state.request_cookie().await?;
+            let mut state = match kept.take() {
+                Some(state) => state,
+                None => {
+                    let mut state = self.to_owned();
+                    state.request_cookie().await?;
+                    state
+                }
+            };
+            let params = p.to_owned();
+            let cookie = state.cookie.as_ref().map(|c| c.cookie.ellipse());
             // check if request is successful
-            let web_res = async { state.bootstrap().await.and(state.send_chat(p).await) };
+            let web_res = async { state.bootstrap().await.and(state.send_chat(params).await) };
             let transform_res = web_res
-                .and_then(async |r| self.transform_response(r).await)
-                .instrument(info_span!("claude_web", "cookie" = cookie.cookie.ellipse()));
+                .and_then(async |r| {
+                    let upstream = UpstreamHeaders::select(
+                        r.headers(),
+                        &CLEWDR_CONFIG.load().header_passthrough.claude_web,
+                    );
+                    let resp = self.transform_response(r).await?;
+                    Ok(upstream.attach(resp))
+                })
+                .instrument(info_span!("claude_web", "cookie" = cookie));
 
             match transform_res.await {
-                Ok(b) => {
-                    if let Err(e) = state.clean_chat().await {
+                Ok(mut b) => {
+                    if !state.keep_session(&p)
+                        && let Err(e) = state.clean_chat().await
+                    {
                         warn!("Failed to clean chat: {}", e);
                     }
-                    return Ok(b);
+                    let (synthesize, limit) = {
+                        let config = CLEWDR_CONFIG.load();
+                        (
+                            config.header_passthrough.synthesize_web,
+                            config.web_cookie_concurrency,
+                        )
+                    };
+                    if synthesize && let Ok(status) = state.cookie_actor_handle.get_status().await {
+                        // the web API sends no rate-limit headers of its own
+                        let mut upstream = b
+                            .extensions_mut()
+                            .remove::<UpstreamHeaders>()
+                            .unwrap_or_default();
+                        upstream.0.extend(synthesize_rate_limits(&status, limit));
+                        b.extensions_mut().insert(upstream);
+                    }
+                    let b = mark_served_by(b, state.cookie.as_ref());
+                    return Ok(hold_permit(b, state.permit.take()));
                 }
                 Err(e) => {
                     // delete chat after an error
@@ -63,12 +110,53 @@ impl ClaudeWebState {
                         warn!("Failed to clean chat: {}", e);
                     }
                     error!("{e}");
+                    // the account was warned, restricted or banned
+                    if let Some(flag) = AccountFlag::classify(&e, Utc::now().timestamp())
+                        && let Some(cookie) = state.cookie.to_owned()
+                    {
+                        let standing = flag.standing;
+                        if let Err(e) = state.cookie_actor_handle.flag_cookie(cookie, flag).await {
+                            warn!("Failed to flag cookie: {}", e);
+                        }
+                        if standing != AccountStanding::Warning {
+                            if cookie_retries >= max_retries {
+                                break;
+                            }
+                            cookie_retries += 1;
+                            continue;
+                        }
+                    }
                     // 429 error
                     if let ClewdrError::InvalidCookie { reason } = e {
                         state.return_cookie(Some(reason.to_owned())).await;
+                        if cookie_retries >= max_retries {
+                            break;
+                        }
+                        cookie_retries += 1;
                         continue;
                     }
-                    return Err(e);
+                    // nothing was sent to the client yet, so the request can be sent again
+                    if upstream_retries < policy.max_retries
+                        && let Some(failover) = policy.classify(&e)
+                    {
+                        upstream_retries += 1;
+                        let delay = policy.backoff(upstream_retries, random_unit());
+                        warn!(
+                            "[RETRY] upstream failure, next attempt in {}ms",
+                            delay.as_millis()
+                        );
+                        tokio::time::sleep(delay).await;
+                        match failover {
+                            Failover::SameCookie => kept = Some(state),
+                            // a session pinned to the cookie must not land on it again
+                            Failover::NextCookie => self
+                                .failed_cookies
+                                .extend(state.cookie.as_ref().map(|c| c.cookie.to_owned())),
+                        }
+                        continue;
+                    }
+                    let attempts = cookie_retries as u32 + upstream_retries + 1;
+                    return Err(e.after_attempts(attempts));
                 }
             }
         }
@@ -79,7 +167,8 @@ impl ClaudeWebState {
     /// Sends a message to the Claude API by creating a new conversation and processing the request
     ///
     /// This method performs several key operations:
-    /// - Creates a new conversation with a unique UUID
+    /// - Creates a new conversation with a unique UUID, or continues the one of
+    ///   the client session when the request extends it
     /// - Configures thinking mode if applicable
     /// - Transforms the client request to the Claude API format
     /// - Handles image uploads if present
@@ -102,7 +191,80 @@ impl ClaudeWebState {
                 msg: "Organization UUID is not set",
             })?;
 
-        // Create a new conversation
+        let resume = match (self.session.as_deref(), self.cookie.as_ref()) {
+            (Some(key), Some(cookie)) => conversations::resume(key, cookie, &org_uuid, &p),
+            _ => Resume::Fresh { stale: None },
+        };
+        // preserve original params for possible post-call token accounting
+        self.last_params = Some(p.clone());
+        let (conv_uuid, p) = match resume {
+            Resume::Continue { session, turns } => {
+                debug!("Continuing conversation: {}", session.conv_uuid);
+                self.conv_uuid = Some(session.conv_uuid.to_owned());
+                // the conversation already holds the system prompt and earlier turns
+                let p = CreateMessageParams {
+                    system: None,
+                    messages: turns,
+                    ..p
+                };
+                (session.conv_uuid, p)
+            }
+            Resume::Fresh { stale } => {
+                if let Some(stale) = stale {
+                    self.discard_session(stale);
+                }
+                (self.create_conversation(&org_uuid, &p).await?, p)
+            }
+        };
+
+        // generate the request body
+        // check if the request is empty
+        let mut body = self.transform_request(p).ok_or(ClewdrError::BadRequest {
+            msg: "Request body is empty",
+        })?;
+
+        // check images
+        let images = body.images.drain(..).collect::<Vec<_>>();
+
+        // upload images
+        let files = self.upload_images(images).await;
+        body.files = files;
+
+        // send the request
+        print_out_json(&body, "claude_web_clewdr_req.json");
+        let endpoint = self
+            .endpoint
+            .join(&format!(
+                "api/organizations/{}/chat_conversations/{}/completion",
+                org_uuid, conv_uuid
+            ))
+            .expect("Url parse error");
+
+        self.build_request(Method::POST, endpoint)
+            .json(&body)
+            .header(ACCEPT, "text/event-stream")
+            .send()
+            .await
+            .context(WreqSnafu {
+                msg: "Failed to send chat request",
+            })?
+            .check_claude()
+            .await
+    }
+
+    /// Creates a new conversation and configures its thinking mode
+    ///
+    /// # Arguments
+    /// * `org_uuid` - Organization the conversation is created in
+    /// * `p` - The client request, its thinking setting is applied
+    ///
+    /// # Returns
+    /// * `Result<String, ClewdrError>` - UUID of the new conversation
+    async fn create_conversation(
+        &mut self,
+        org_uuid: &str,
+        p: &CreateMessageParams,
+    ) -> Result<String, ClewdrError> {
         let new_uuid = uuid::Uuid::new_v4().to_string();
         let endpoint = self
             .endpoint
@@ -128,8 +290,6 @@ impl ClaudeWebState {
         self.conv_uuid = Some(new_uuid.to_string());
         debug!("New conversation created: {}", new_uuid);
 
-        // preserve original params for possible post-call token accounting
-        self.last_params = Some(p.clone());
         let mut body = json!({});
         // enable thinking mode
         body["settings"]["paprika_mode"] = if p.thinking.is_some() && self.is_pro() {
@@ -150,38 +310,6 @@ impl ClaudeWebState {
             .json(&body)
             .send()
             .await;
-        // generate the request body
-        // check if the request is empty
-        let mut body = self.transform_request(p).ok_or(ClewdrError::BadRequest {
-            msg: "Request body is empty",
-        })?;
-
-        // check images
-        let images = body.images.drain(..).collect::<Vec<_>>();
-
-        // upload images
-        let files = self.upload_images(images).await;
-        body.files = files;
-
-        // send the request
-        print_out_json(&body, "claude_web_clewdr_req.json");
-        let endpoint = self
-            .endpoint
-            .join(&format!(
-                "api/organizations/{}/chat_conversations/{}/completion",
-                org_uuid, new_uuid
-            ))
-            .expect("Url parse error");
-
-        self.build_request(Method::POST, endpoint)
-            .json(&body)
-            .header(ACCEPT, "text/event-stream")
-            .send()
-            .await
-            .context(WreqSnafu {
-                msg: "Failed to send chat request",
-            })?
-            .check_claude()
-            .await
+        Ok(new_uuid)
     }
 }
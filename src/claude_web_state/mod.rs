@@ -1,9 +1,6 @@
-use std::sync::LazyLock;
+use std::sync::{Arc, LazyLock};
 
-use axum::http::{
-    HeaderValue,
-    header::COOKIE,
-};
+use axum::http::{HeaderValue, header::COOKIE};
 use snafu::ResultExt;
 use tracing::{debug, error, warn};
 use url::Url;
@@ -14,10 +11,15 @@ use wreq::{
 use wreq_util::Emulation;
 
 use crate::{
-    config::{CLAUDE_ENDPOINT, CLEWDR_CONFIG, CookieStatus, Reason},
+    config::{CLAUDE_ENDPOINT, CLEWDR_CONFIG, ClewdrCookie, CookieStatus, Reason},
     error::{ClewdrError, WreqSnafu},
     middleware::claude::ClaudeApiFormat,
-    services::cookie_actor::CookieActorHandle,
+    services::{
+        conversations::{self, ReplyHash, WebSession},
+        cookie_actor::CookieActorHandle,
+        demo,
+        queue::CookiePermit,
+    },
     types::claude::{CreateMessageParams, Usage},
 };
 
@@ -45,6 +47,18 @@ pub struct ClaudeWebState {
     pub usage: Usage,
     // keep the last request params for potential post-call token accounting
     pub last_params: Option<CreateMessageParams>,
+    // smoke checks are not counted in usage stats
+    pub smoke: bool,
+    // request slot on the cookie, released once the response is finished
+    pub permit: Option<Arc<CookiePermit>>,
+    // waits ahead of fresh requests when the client retried
+    pub retry_boost: u32,
+    // client session whose conversation is reused, see `conversation_reuse`
+    pub session: Option<String>,
+    // cookies the request failed on, passed over by later attempts
+    pub failed_cookies: Vec<ClewdrCookie>,
+    // hash of the reply, kept with the session to check the next request against
+    pub reply: ReplyHash,
 }
 
 impl ClaudeWebState {
@@ -65,6 +79,12 @@ impl ClaudeWebState {
             key: None,
             usage: Usage::default(),
             last_params: None,
+            smoke: false,
+            permit: None,
+            retry_boost: 0,
+            session: None,
+            failed_cookies: Vec::new(),
+            reply: ReplyHash::default(),
         }
     }
 
@@ -121,7 +141,35 @@ impl ClaudeWebState {
     /// Requests a new cookie from the cookie manager
     /// Updates the internal state with the new cookie and proxy configuration
     pub async fn request_cookie(&mut self) -> Result<CookieStatus, ClewdrError> {
-        let res = self.cookie_actor_handle.request(None).await?;
+        demo::ensure_live(&CLEWDR_CONFIG.load())?;
+        let limit = CLEWDR_CONFIG.load().web_cookie_concurrency;
+        // a session stays on the cookie holding its conversation
+        let hash = self.session.as_deref().map(conversations::session_hash);
+        let (res, permit) = self
+            .cookie_actor_handle
+            .acquire(hash, limit, self.retry_boost, &self.failed_cookies)
+            .await?;
+        self.permit = Some(Arc::new(permit));
+        self.use_cookie(&res)?;
+        Ok(res)
+    }
+
+    /// Checks a cookie against Claude.ai without taking it from the cookie manager
+    ///
+    /// # Returns
+    /// * `Result<(), ClewdrError>` - Ok if the cookie can be used, or the reason it cannot
+    pub async fn validate_cookie(
+        cookie_actor_handle: CookieActorHandle,
+        cookie: &CookieStatus,
+    ) -> Result<(), ClewdrError> {
+        demo::ensure_live(&CLEWDR_CONFIG.load())?;
+        let mut state = Self::new(cookie_actor_handle);
+        state.use_cookie(cookie)?;
+        state.bootstrap().await
+    }
+
+    /// Sends the next requests with `res`, through a fresh client
+    fn use_cookie(&mut self, res: &CookieStatus) -> Result<(), ClewdrError> {
         self.cookie = Some(res.to_owned());
         // Always pull latest proxy/endpoint before building the client
         self.proxy = CLEWDR_CONFIG.load().wreq_proxy.to_owned();
@@ -136,7 +184,7 @@ impl ClaudeWebState {
             msg: "Failed to build client with new cookie",
         })?;
         self.cookie_header_value = HeaderValue::from_str(res.cookie.to_string().as_str())?;
-        Ok(res)
+        Ok(())
     }
 
     /// Returns the current cookie to the cookie manager
@@ -165,16 +213,19 @@ impl ClaudeWebState {
     }
 
     pub async fn persist_usage_totals(&mut self, input: u64, output: u64) {
-        if input == 0 && output == 0 {
+        if self.smoke || (input == 0 && output == 0) {
             return;
         }
         if let Some(cookie) = self.cookie.as_mut() {
-            let family = self
+            let model = self
                 .last_params
                 .as_ref()
-                .map(|p| Self::classify_model(&p.model))
-                .unwrap_or(crate::config::ModelFamily::Other);
-            cookie.add_and_bucket_usage(input, output, family);
+                .map(|p| p.model.as_str())
+                .unwrap_or_default();
+            let family = Self::classify_model(model);
+            let tokens = crate::config::BilledTokens::uncached(input, output);
+            let cost = crate::config::cost_now(model, tokens, true);
+            cookie.add_and_bucket_usage(input, output, family, cost);
             let cloned = cookie.clone();
             if let Err(err) = self.cookie_actor_handle.return_cookie(cloned, None).await {
                 warn!("Failed to persist usage statistics: {}", err);
@@ -211,4 +262,44 @@ impl ClaudeWebState {
             });
         Ok(())
     }
+
+    /// Keeps the conversation for the next request of the session
+    ///
+    /// # Arguments
+    /// * `p` - The full request that was just answered
+    ///
+    /// # Returns
+    /// * `bool` - Whether the conversation was kept, otherwise it should be cleaned
+    pub fn keep_session(&self, p: &CreateMessageParams) -> bool {
+        let (Some(key), Some(cookie), Some(org_uuid), Some(conv_uuid)) = (
+            self.session.to_owned(),
+            self.cookie.to_owned(),
+            self.org_uuid.to_owned(),
+            self.conv_uuid.to_owned(),
+        ) else {
+            return false;
+        };
+        let session = WebSession::new(cookie, org_uuid, conv_uuid, p, self.reply.to_owned());
+        conversations::remember(key, session);
+        true
+    }
+
+    /// Deletes the conversation a session no longer continues, in the background
+    ///
+    /// The conversation may belong to another cookie than the current one, so
+    /// it is deleted through a client of its own.
+    fn discard_session(&self, session: WebSession) {
+        let mut state = Self::new(self.cookie_actor_handle.to_owned());
+        if let Err(e) = state.use_cookie(&session.cookie) {
+            warn!("Failed to delete stale conversation: {}", e);
+            return;
+        }
+        state.org_uuid = Some(session.org_uuid);
+        state.conv_uuid = Some(session.conv_uuid);
+        tokio::spawn(async move {
+            if let Err(e) = state.clean_chat().await {
+                warn!("Failed to delete stale conversation: {}", e);
+            }
+        });
+    }
 }
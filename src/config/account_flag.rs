@@ -0,0 +1,218 @@
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+use crate::error::{ClaudeErrorBody, ClewdrError};
+
+/// Rest of a restriction whose end upstream did not tell
+const DEFAULT_RESTRICTION_SECS: i64 = 24 * 60 * 60;
+/// Rest of a banned cookie after its first ban response, doubled on each further one
+const BAN_BACKOFF_SECS: i64 = 60 * 60;
+/// Doublings of the ban rest, capping it at 64 hours
+const MAX_BAN_BACKOFF_DOUBLINGS: u32 = 6;
+
+/// Phrases of a suspended account, checked on 403 responses
+const BAN_PHRASES: [&str; 4] = ["banned", "suspended", "has been disabled", "terminated"];
+/// Phrases of a restricted account, checked on permission errors
+const RESTRICTION_PHRASES: [&str; 2] = ["restricted", "unavailable"];
+/// Phrases claude.ai sends to flagged accounts that still get answers
+const WARNING_PHRASES: [&str; 2] = ["capacity constraints", "unusual activity"];
+
+/// Standing of a claude.ai account, as told by its upstream errors
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum AccountStanding {
+    /// Still selected
+    Warning,
+    /// Rested until the restriction ends, like a rate-limited cookie
+    Restricted,
+    /// Kept with the invalid cookies for review, or rested and retried under a ban limit
+    Banned,
+}
+
+/// Latest standing an upstream error gave a cookie
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct AccountFlag {
+    pub standing: AccountStanding,
+    /// Upstream error message as received
+    pub message: String,
+    /// Unix timestamp of the error
+    pub seen_at: i64,
+    /// End of a restriction, when upstream told it
+    #[serde(default)]
+    pub until: Option<i64>,
+    /// Ban responses in a row, 0 for other standings
+    #[serde(default)]
+    pub strikes: u32,
+}
+
+impl AccountFlag {
+    /// Classifies an upstream error of claude.ai
+    ///
+    /// # Arguments
+    /// * `e` - Error of the failed attempt
+    /// * `now` - Current unix timestamp
+    ///
+    /// # Returns
+    /// * `Option<AccountFlag>` - The standing it tells, `None` for errors unrelated to the account
+    pub fn classify(e: &ClewdrError, now: i64) -> Option<Self> {
+        let ClewdrError::ClaudeHttpError { code, inner } = e else {
+            return None;
+        };
+        Self::from_body(*code, inner, now)
+    }
+
+    fn from_body(code: StatusCode, body: &ClaudeErrorBody, now: i64) -> Option<Self> {
+        let message = match body.message.as_str() {
+            Some(text) => text.to_string(),
+            None => body.message.to_string(),
+        };
+        let lower = message.to_ascii_lowercase();
+        let says = |phrases: &[&str]| phrases.iter().any(|p| lower.contains(p));
+        let permission = code == StatusCode::FORBIDDEN || body.r#type == "permission_error";
+        let standing = if code == StatusCode::FORBIDDEN && says(&BAN_PHRASES) {
+            AccountStanding::Banned
+        } else if permission && says(&RESTRICTION_PHRASES) {
+            AccountStanding::Restricted
+        } else if says(&WARNING_PHRASES) {
+            AccountStanding::Warning
+        } else {
+            return None;
+        };
+        Some(Self {
+            standing,
+            message,
+            seen_at: now,
+            until: body.message["resetsAt"].as_i64(),
+            strikes: (standing == AccountStanding::Banned).into(),
+        })
+    }
+
+    /// When a restricted cookie may be selected again
+    pub fn restricted_until(&self) -> i64 {
+        self.until
+            .unwrap_or(self.seen_at + DEFAULT_RESTRICTION_SECS)
+    }
+
+    /// When a banned cookie, short of its last strike, may be selected again
+    pub fn ban_backoff_until(&self) -> i64 {
+        let doublings = self
+            .strikes
+            .saturating_sub(1)
+            .min(MAX_BAN_BACKOFF_DOUBLINGS);
+        self.seen_at + (BAN_BACKOFF_SECS << doublings)
+    }
+
+    /// Counts this ban response after the previous flag of the cookie
+    pub fn after(mut self, previous: Option<&AccountFlag>) -> Self {
+        if self.standing == AccountStanding::Banned
+            && let Some(previous) = previous
+            && previous.standing == AccountStanding::Banned
+        {
+            self.strikes = previous.strikes + 1;
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ClaudeError;
+
+    /// Error bodies as claude.ai sent them, with their status and expected standing
+    const FIXTURES: [(u16, &str, Option<AccountStanding>); 9] = [
+        (
+            403,
+            r#"{"type":"error","error":{"type":"permission_error","message":"Your account has been disabled after an automatic review of your recent activities. Please take a look at our Terms of Service and Acceptable Use Policy."}}"#,
+            Some(AccountStanding::Banned),
+        ),
+        (
+            403,
+            r#"{"type":"error","error":{"type":"permission_error","message":"This account has been suspended for violating our Usage Policy."}}"#,
+            Some(AccountStanding::Banned),
+        ),
+        (
+            403,
+            r#"{"type":"error","error":{"type":"permission_error","message":"{\"type\":\"account_restricted\",\"resetsAt\":1767225600,\"message\":\"Your account is temporarily restricted.\"}"}}"#,
+            Some(AccountStanding::Restricted),
+        ),
+        (
+            400,
+            r#"{"type":"error","error":{"type":"permission_error","message":"Claude is currently unavailable for this account."}}"#,
+            Some(AccountStanding::Restricted),
+        ),
+        (
+            529,
+            r#"{"type":"error","error":{"type":"overloaded_error","message":"Due to unexpected capacity constraints, Claude is unable to respond to your message. Please try again soon."}}"#,
+            Some(AccountStanding::Warning),
+        ),
+        (
+            503,
+            r#"{"type":"error","error":{"type":"api_error","message":"Service Unavailable"}}"#,
+            None,
+        ),
+        (
+            403,
+            r#"{"type":"error","error":{"type":"permission_error","message":"Request not allowed"}}"#,
+            None,
+        ),
+        (
+            400,
+            r#"{"type":"error","error":{"type":"invalid_request_error","message":"prompt is too long: 215000 tokens > 200000 maximum"}}"#,
+            None,
+        ),
+        (
+            529,
+            r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#,
+            None,
+        ),
+    ];
+
+    fn classify(code: u16, fixture: &str) -> Option<AccountFlag> {
+        let body = serde_json::from_str::<ClaudeError>(fixture).unwrap().error;
+        let e = ClewdrError::ClaudeHttpError {
+            code: StatusCode::from_u16(code).unwrap(),
+            inner: body,
+        };
+        AccountFlag::classify(&e, 1_000)
+    }
+
+    #[test]
+    fn classifies_captured_errors() {
+        for (code, fixture, expected) in FIXTURES {
+            let flag = classify(code, fixture);
+            assert_eq!(flag.as_ref().map(|f| f.standing), expected, "{fixture}");
+        }
+        assert_eq!(AccountFlag::classify(&ClewdrError::TooManyRetries, 0), None);
+    }
+
+    #[test]
+    fn keeps_message_and_counts_strikes() {
+        let restricted = classify(FIXTURES[2].0, FIXTURES[2].1).unwrap();
+        assert!(restricted.message.contains("temporarily restricted"));
+        assert_eq!(restricted.restricted_until(), 1767225600);
+        let unavailable = classify(FIXTURES[3].0, FIXTURES[3].1).unwrap();
+        assert_eq!(
+            unavailable.restricted_until(),
+            1_000 + DEFAULT_RESTRICTION_SECS
+        );
+
+        let ban = classify(FIXTURES[0].0, FIXTURES[0].1).unwrap();
+        assert!(ban.message.starts_with("Your account has been disabled"));
+        assert_eq!(ban.strikes, 1);
+        let second = ban.to_owned().after(Some(&ban));
+        assert_eq!(second.strikes, 2);
+        assert_eq!(ban.to_owned().after(Some(&restricted)).strikes, 1);
+        assert_eq!(restricted.to_owned().after(Some(&ban)).strikes, 0);
+
+        assert_eq!(ban.ban_backoff_until(), 1_000 + BAN_BACKOFF_SECS);
+        assert_eq!(second.ban_backoff_until(), 1_000 + 2 * BAN_BACKOFF_SECS);
+        let many = AccountFlag {
+            strikes: 40,
+            ..second
+        };
+        assert_eq!(many.ban_backoff_until(), 1_000 + 64 * BAN_BACKOFF_SECS);
+    }
+}
@@ -0,0 +1,78 @@
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+use super::default_key_enabled;
+
+/// Proxy endpoints a client key can be limited to
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum KeyEndpoint {
+    /// `/v1`, served by claude.ai cookies
+    ClaudeWeb,
+    /// `/code/v1`, served by Claude Code tokens
+    ClaudeCode,
+}
+
+impl KeyEndpoint {
+    /// Endpoint a request path belongs to
+    pub fn of_path(path: &str) -> Self {
+        if path.starts_with("/code/") {
+            Self::ClaudeCode
+        } else {
+            Self::ClaudeWeb
+        }
+    }
+}
+
+/// Who a proxy request authenticated as
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientAuth {
+    /// The shared `password`
+    Password,
+    /// A valid proxy key sent with the `admin_password` in `x-clewdr-admin`
+    Admin,
+    /// One of `api_keys`, by name
+    Key(String),
+}
+
+impl ClientAuth {
+    /// Whether the request carried the admin password
+    pub fn is_admin(&self) -> bool {
+        matches!(self, Self::Admin)
+    }
+
+    /// Stable name of the client, for keeping state of different clients apart
+    pub fn identity(&self) -> String {
+        match self {
+            Self::Password => "password".into(),
+            Self::Admin => "admin".into(),
+            Self::Key(name) => format!("key:{name}"),
+        }
+    }
+}
+
+/// A named key for one consumer of the proxy, next to the shared password
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ApiKey {
+    pub name: String,
+    pub key: String,
+    #[serde(default = "default_key_enabled")]
+    pub enabled: bool,
+    /// Endpoints the key may use, every endpoint when empty
+    #[serde(default)]
+    pub allowed_endpoints: Vec<KeyEndpoint>,
+}
+
+impl ApiKey {
+    /// Whether the key may be used on `endpoint`
+    pub fn allows(&self, endpoint: KeyEndpoint) -> bool {
+        self.allowed_endpoints.is_empty() || self.allowed_endpoints.contains(&endpoint)
+    }
+
+    /// Start of the key, enough to tell keys apart in listings
+    pub fn ellipse(&self) -> String {
+        let start = self.key.chars().take(12).collect::<String>();
+        format!("{start}...")
+    }
+}
@@ -1,5 +1,5 @@
 use std::{
-    collections::HashSet,
+    collections::{BTreeMap, HashSet},
     fmt::{Debug, Display},
     net::{IpAddr, SocketAddr},
 };
@@ -15,7 +15,7 @@ use http::uri::Authority;
 use passwords::PasswordGenerator;
 use serde::{Deserialize, Serialize};
 use tokio::spawn;
-use tracing::error;
+use tracing::{error, warn};
 use url::Url;
 use wreq::Proxy;
 
@@ -23,10 +23,20 @@ use super::{CONFIG_PATH, ENDPOINT_URL};
 use crate::{
     Args,
     config::{
-        CC_CLIENT_ID, CookieStatus, UselessCookie, default_check_update, default_ip,
-        default_max_retries, default_port, default_skip_cool_down, default_use_real_roles,
+        ApiKey, CC_CLIENT_ID, CONFIG_VERSION, ClientAuth, ConversationReuse, CookieStatus,
+        HeaderPassthrough, KeyEndpoint, LanguagePolicy, ModelPrice, PromptPreset, RateLimitConfig,
+        RedactionRules, ResponseCacheConfig, SloConfig, TokenRates, TypographyConfig,
+        UpstreamRetry, UselessCookie, default_account_cache_ttl_secs, default_check_update,
+        default_code_cookie_concurrency, default_config_version, default_demo_error_rate,
+        default_drain_timeout_secs, default_ip, default_log_download_max_mb, default_max_queued,
+        default_max_retries, default_max_retry_boost, default_model_list_ttl_secs, default_port,
+        default_queue_timeout_ms, default_readiness_requires_cookie, default_retry_window_secs,
+        default_skip_cool_down, default_sse_keep_alive_secs, default_token_expiry_skew_secs,
+        default_transcript_max_mb, default_use_real_roles, default_web_cookie_concurrency,
+        read_config_file, validate_pricing,
     },
     error::ClewdrError,
+    services::{config_watch, demo, language, storage},
     utils::enabled,
 };
 
@@ -36,6 +46,12 @@ use crate::{
 /// # Returns
 /// A random password string
 fn generate_password() -> String {
+    println!("{}", "Generating random password......".green());
+    random_password()
+}
+
+/// Creates a random 64-character password without announcing it
+pub fn random_password() -> String {
     let pg = PasswordGenerator {
         length: 64,
         numbers: true,
@@ -46,14 +62,108 @@ fn generate_password() -> String {
         exclude_similar_characters: true,
         strict: true,
     };
-
-    println!("{}", "Generating random password......".green());
     pg.generate_one().unwrap()
 }
 
+/// Format of log lines written to stdout and the log file
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// Human readable lines
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+/// Rotation and retention of the log file, 0 disables a limit
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct LogRotation {
+    /// Rotate once `clewdr.log` grows past this many MiB
+    pub max_mb: u64,
+    /// Rotate once `clewdr.log` is this many hours old
+    pub max_age_hours: u64,
+    /// Total MiB of rotated files kept, the oldest are removed first
+    pub retain_mb: u64,
+    /// Rotated files older than this many days are removed
+    pub retain_days: u64,
+    /// Compress rotated files with zstd
+    pub compress: bool,
+}
+
+impl Default for LogRotation {
+    fn default() -> Self {
+        Self {
+            max_mb: 10,
+            max_age_hours: 24,
+            retain_mb: 200,
+            retain_days: 14,
+            compress: true,
+        }
+    }
+}
+
+/// Where the cookie pool is persisted
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageBackend {
+    /// Part of the config file
+    #[default]
+    File,
+    /// `clewdr.db` next to the config file
+    Sqlite,
+}
+
+/// How the next cookie is picked among those with room for another request
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CookieStrategy {
+    /// Take turns, the cookie used longest ago goes first
+    #[default]
+    RoundRobin,
+    /// The cookie dispatched for the fewest requests since startup
+    LeastUsed,
+    /// Any cookie, picked at random
+    Random,
+}
+
+/// Who may ask for request reports with `x-clewdr-report`
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportAccess {
+    /// Never produce reports
+    Off,
+    /// Only requests authenticated with the admin password
+    #[default]
+    Admin,
+    /// Any authenticated request
+    All,
+}
+
+/// JSON pointers of the config fields holding secrets, `*` stands for any list item
+///
+/// Redacted exports and `GET /api/config` replace their values. A new field
+/// holding a secret must be listed here, names are never guessed from.
+pub const SECRET_FIELDS: [&str; 9] = [
+    "/password",
+    "/admin_password",
+    "/api_keys/*/key",
+    "/proxy",
+    "/redaction/secrets",
+    "/cookie_array/*/cookie",
+    "/cookie_array/*/token/access_token",
+    "/cookie_array/*/token/refresh_token",
+    "/wasted_cookie/*/cookie",
+];
+
 /// A struct representing the configuration of the application
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ClewdrConfig {
+    // format of the file, older files are migrated on load
+    #[serde(default = "default_config_version")]
+    pub config_version: u32,
+
     // key configurations
     #[serde(default)]
     pub cookie_array: HashSet<CookieStatus>,
@@ -74,7 +184,27 @@ pub struct ClewdrConfig {
     #[serde(default)]
     pub no_fs: bool,
     #[serde(default)]
+    pub storage: StorageBackend,
+    #[serde(default = "default_drain_timeout_secs")]
+    pub drain_timeout_secs: u64,
+    #[serde(default)]
     pub log_to_file: bool,
+    #[serde(default)]
+    pub log_format: LogFormat,
+    #[serde(default)]
+    pub log_rotation: LogRotation,
+    #[serde(default = "default_log_download_max_mb")]
+    pub log_download_max_mb: u64,
+    #[serde(default)]
+    pub record_transcripts: bool,
+    #[serde(default = "default_transcript_max_mb")]
+    pub transcript_max_mb: u64,
+    #[serde(default)]
+    pub redaction: RedactionRules,
+    #[serde(default)]
+    pub demo: bool,
+    #[serde(default = "default_demo_error_rate")]
+    pub demo_error_rate: f64,
 
     // Network settings, can hot reload
     #[serde(default)]
@@ -82,6 +212,8 @@ pub struct ClewdrConfig {
     #[serde(default)]
     admin_password: String,
     #[serde(default)]
+    pub api_keys: Vec<ApiKey>,
+    #[serde(default)]
     pub proxy: Option<String>,
     #[serde(default)]
     pub rproxy: Option<Url>,
@@ -90,13 +222,49 @@ pub struct ClewdrConfig {
     #[serde(default = "default_max_retries")]
     pub max_retries: usize,
     #[serde(default)]
+    pub upstream_retry: UpstreamRetry,
+    #[serde(default)]
+    pub response_cache: ResponseCacheConfig,
+    #[serde(default)]
     pub preserve_chats: bool,
     #[serde(default)]
+    pub conversation_reuse: ConversationReuse,
+    #[serde(default)]
     pub web_search: bool,
     #[serde(default)]
     pub enable_web_count_tokens: bool,
     #[serde(default)]
     pub sanitize_messages: bool,
+    // client model names mapped to upstream model ids
+    #[serde(default)]
+    pub model_aliases: BTreeMap<String, String>,
+    // model used for names that are neither claude models nor aliases
+    #[serde(default)]
+    pub default_model: Option<String>,
+    // thinking budget for requests without `thinking` to models that support it
+    #[serde(default)]
+    pub default_thinking_budget: Option<u64>,
+    #[serde(default)]
+    pub request_reports: ReportAccess,
+    #[serde(default)]
+    pub header_passthrough: HeaderPassthrough,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    // seconds of upstream silence before a streamed response is pinged, 0 disables
+    #[serde(default = "default_sse_keep_alive_secs")]
+    pub sse_keep_alive_secs: u64,
+    // seconds account metadata of a cookie is reused, 0 fetches it on every use
+    #[serde(default = "default_account_cache_ttl_secs")]
+    pub account_cache_ttl_secs: u64,
+    // seconds the upstream model list is reused, 0 lists the built-in models
+    #[serde(default = "default_model_list_ttl_secs")]
+    pub model_list_ttl_secs: u64,
+    // seconds before expiry a Claude Code token is refreshed
+    #[serde(default = "default_token_expiry_skew_secs")]
+    pub token_expiry_skew_secs: u64,
+    // whether /readyz fails while no cookie is usable
+    #[serde(default = "default_readiness_requires_cookie")]
+    pub readiness_requires_cookie: bool,
 
     // Cookie settings, can hot reload
     #[serde(default)]
@@ -111,6 +279,31 @@ pub struct ClewdrConfig {
     pub skip_rate_limit: bool,
     #[serde(default)]
     pub skip_normal_pro: bool,
+    // ban responses in a row before a banned cookie is deleted, banned cookies rest and
+    // are retried until then; 0 keeps them with the invalid cookies for review
+    #[serde(default)]
+    pub ban_auto_remove_after: u32,
+    #[serde(default)]
+    pub cookie_strategy: CookieStrategy,
+    // name the serving cookie, by hash, in `x-clewdr-account`
+    #[serde(default)]
+    pub debug_account_header: bool,
+    // requests served by one cookie at once, 0 means unlimited
+    #[serde(default = "default_web_cookie_concurrency")]
+    pub web_cookie_concurrency: usize,
+    #[serde(default = "default_code_cookie_concurrency")]
+    pub code_cookie_concurrency: usize,
+    #[serde(default = "default_queue_timeout_ms")]
+    pub queue_timeout_ms: u64,
+    // requests waiting for a busy cookie, 0 means unbounded
+    #[serde(default = "default_max_queued")]
+    pub max_queued: usize,
+    // a body seen again within this many seconds is a client retry
+    #[serde(default = "default_retry_window_secs")]
+    pub retry_window_secs: u64,
+    // queue priority levels a retry can gain, 0 disables the boost
+    #[serde(default = "default_max_retry_boost")]
+    pub max_retry_boost: u32,
 
     // Prompt configurations, can hot reload
     #[serde(default = "default_use_real_roles")]
@@ -121,6 +314,31 @@ pub struct ClewdrConfig {
     pub custom_a: Option<String>,
     #[serde(default)]
     pub custom_prompt: String,
+    #[serde(default)]
+    pub prompt_preset: PromptPreset,
+
+    // Response post-processing, can hot reload
+    #[serde(default)]
+    pub typography: TypographyConfig,
+    #[serde(default)]
+    pub language_policy: LanguagePolicy,
+    // drop thinking blocks from responses
+    #[serde(default)]
+    pub strip_thinking: bool,
+
+    // Service level objectives, can hot reload
+    #[serde(default)]
+    pub slo: Vec<SloConfig>,
+
+    // Spend tracking, can hot reload
+    #[serde(default)]
+    pub pricing: Vec<ModelPrice>,
+    // rates of models missing from the pricing table
+    #[serde(default)]
+    pub default_price: TokenRates,
+    // claude.ai is not billed per token, price its requests anyway
+    #[serde(default)]
+    pub price_web_requests: bool,
 
     // Claude Code settings, can hot reload
     #[serde(default)]
@@ -138,37 +356,80 @@ pub struct ClewdrConfig {
 impl Default for ClewdrConfig {
     fn default() -> Self {
         Self {
+            config_version: CONFIG_VERSION,
             max_retries: default_max_retries(),
+            upstream_retry: UpstreamRetry::default(),
+            response_cache: ResponseCacheConfig::default(),
             check_update: default_check_update(),
             auto_update: false,
             cookie_array: HashSet::new(),
             wasted_cookie: HashSet::new(),
             password: String::new(),
             admin_password: String::new(),
+            api_keys: vec![],
             proxy: None,
             ip: default_ip(),
             port: default_port(),
             rproxy: None,
             use_real_roles: default_use_real_roles(),
             custom_prompt: String::new(),
+            prompt_preset: PromptPreset::default(),
             custom_h: None,
             custom_a: None,
+            typography: TypographyConfig::default(),
+            language_policy: LanguagePolicy::default(),
+            strip_thinking: false,
+            header_passthrough: HeaderPassthrough::default(),
+            rate_limit: RateLimitConfig::default(),
+            slo: Vec::new(),
+            pricing: Vec::new(),
+            default_price: TokenRates::default(),
+            price_web_requests: false,
             wreq_proxy: None,
             preserve_chats: false,
+            conversation_reuse: ConversationReuse::default(),
             web_search: false,
             enable_web_count_tokens: false,
             sanitize_messages: false,
+            model_aliases: BTreeMap::new(),
+            default_model: None,
+            default_thinking_budget: None,
+            request_reports: ReportAccess::default(),
+            sse_keep_alive_secs: default_sse_keep_alive_secs(),
+            account_cache_ttl_secs: default_account_cache_ttl_secs(),
+            model_list_ttl_secs: default_model_list_ttl_secs(),
+            token_expiry_skew_secs: default_token_expiry_skew_secs(),
+            readiness_requires_cookie: default_readiness_requires_cookie(),
             skip_first_warning: false,
             skip_second_warning: false,
             skip_restricted: false,
             skip_non_pro: false,
             skip_rate_limit: default_skip_cool_down(),
             skip_normal_pro: false,
+            ban_auto_remove_after: 0,
+            cookie_strategy: CookieStrategy::default(),
+            debug_account_header: false,
+            web_cookie_concurrency: default_web_cookie_concurrency(),
+            code_cookie_concurrency: default_code_cookie_concurrency(),
+            queue_timeout_ms: default_queue_timeout_ms(),
+            max_queued: default_max_queued(),
+            retry_window_secs: default_retry_window_secs(),
+            max_retry_boost: default_max_retry_boost(),
             claude_code_client_id: None,
             custom_system: None,
             claude_code_telemetry: false,
             no_fs: false,
+            storage: StorageBackend::default(),
+            drain_timeout_secs: default_drain_timeout_secs(),
             log_to_file: false,
+            log_format: LogFormat::default(),
+            log_rotation: LogRotation::default(),
+            log_download_max_mb: default_log_download_max_mb(),
+            record_transcripts: false,
+            transcript_max_mb: default_transcript_max_mb(),
+            redaction: RedactionRules::default(),
+            demo: false,
+            demo_error_rate: default_demo_error_rate(),
         }
     }
 }
@@ -228,6 +489,13 @@ impl Display for ClewdrConfig {
             "Web count_tokens: {}",
             enabled(self.enable_web_count_tokens)
         )?;
+        if self.demo {
+            writeln!(
+                f,
+                "{}",
+                "Demo mode: synthetic responses, no upstream requests".yellow()
+            )?;
+        }
         Ok(())
     }
 }
@@ -241,6 +509,40 @@ impl ClewdrConfig {
         key == self.admin_password
     }
 
+    /// Checks a proxy key against the shared password and `api_keys`
+    ///
+    /// # Arguments
+    /// * `key` - Key sent by the client
+    /// * `endpoint` - Endpoint the request is for
+    ///
+    /// # Returns
+    /// * `Result<ClientAuth, ClewdrError>` - Who the key belongs to, or why it was rejected
+    pub fn client_auth(&self, key: &str, endpoint: KeyEndpoint) -> Result<ClientAuth, ClewdrError> {
+        if self.user_auth(key) {
+            return Ok(ClientAuth::Password);
+        }
+        let Some(found) = self.api_keys.iter().find(|k| k.key == key) else {
+            return Err(ClewdrError::InvalidAuth);
+        };
+        if !found.enabled {
+            return Err(ClewdrError::KeyDisabled {
+                name: found.name.to_owned(),
+            });
+        }
+        if !found.allows(endpoint) {
+            return Err(ClewdrError::KeyNotAllowed {
+                name: found.name.to_owned(),
+                endpoint,
+            });
+        }
+        Ok(ClientAuth::Key(found.name.to_owned()))
+    }
+
+    /// Replaces the admin password
+    pub fn set_admin_password(&mut self, password: String) {
+        self.admin_password = password;
+    }
+
     pub fn cc_client_id(&self) -> String {
         self.claude_code_client_id
             .as_deref()
@@ -252,18 +554,30 @@ impl ClewdrConfig {
     /// Combines settings from config.toml, clewdr.toml, and environment variables
     /// Also loads cookies from a file if specified
     ///
+    /// A file written by an older version is migrated, its original backed up
+    /// before the migrated config is saved over it. A file from a newer
+    /// version exits the process rather than load partially and be overwritten.
+    ///
     /// # Returns
     /// * Config instance
     pub fn new() -> Self {
-        // Load config from TOML then override with environment variables.
-        // Use double underscore "__" to map nested keys.
-        let mut config: ClewdrConfig = Figment::from(Toml::file(CONFIG_PATH.as_path()))
-            .admerge(Env::prefixed("CLEWDR_").split("__"))
+        let (file, migrated) = read_config_file(CONFIG_PATH.as_path()).unwrap_or_else(|e| {
+            // logging is not set up yet
+            eprintln!(
+                "{}",
+                format!("Failed to load config {}: {}", CONFIG_PATH.display(), e).red()
+            );
+            std::process::exit(1);
+        });
+        let mut config: ClewdrConfig = Self::figment(&file)
             .extract_lossy()
             .inspect_err(|e| {
                 error!("Failed to load config: {}", e);
             })
             .unwrap_or_default();
+        if Args::try_parse().is_ok_and(|a| a.demo) {
+            config.demo = true;
+        }
         if let Some(ref f) = Args::try_parse().ok().and_then(|a| a.file) {
             // load cookies from file
             if f.exists() {
@@ -279,8 +593,18 @@ impl ClewdrConfig {
                 error!("Cookie file not found: {}", f.display());
             }
         }
-        let config = config.validate();
-        if !config.no_fs {
+        let mut config = config.validate();
+        if config.demo {
+            // the real pool is never loaded, the fake one is never saved
+            (config.cookie_array, config.wasted_cookie) = demo::demo_pool();
+        }
+        // a migrated file is only rewritten once its original is backed up
+        if !config.no_fs
+            && !config.demo
+            && migrated
+                .as_ref()
+                .is_none_or(|m| m.keep_original(CONFIG_PATH.as_path()))
+        {
             let config_clone = config.to_owned();
             spawn(async move {
                 config_clone.save().await.unwrap_or_else(|e| {
@@ -291,6 +615,43 @@ impl ClewdrConfig {
         config
     }
 
+    /// Config file contents with the environment variables on top
+    ///
+    /// # Arguments
+    /// * `file` - TOML of the config file, already migrated
+    pub fn figment(file: &str) -> Figment {
+        // Load config from TOML then override with environment variables.
+        // Use double underscore "__" to map nested keys.
+        Figment::from(Toml::string(file)).admerge(Env::prefixed("CLEWDR_").split("__"))
+    }
+
+    /// Carries over what a config reloaded from file cannot change at runtime
+    ///
+    /// The cookie pool belongs to the cookie actor, and an emptied password
+    /// keeps its value rather than being replaced by a random one. The listen
+    /// address and the storage backend are only read at startup, new values
+    /// are kept for the next one.
+    ///
+    /// # Arguments
+    /// * `old` - The live config
+    pub fn reloaded(mut self, old: &ClewdrConfig) -> Self {
+        if self.address() != old.address() || self.storage != old.storage {
+            warn!(
+                "Listen address and storage changes in the config file take effect after a restart"
+            );
+        }
+        self.demo = old.demo;
+        self.cookie_array = old.cookie_array.to_owned();
+        self.wasted_cookie = old.wasted_cookie.to_owned();
+        if self.password.trim().is_empty() {
+            self.password = old.password.to_owned();
+        }
+        if self.admin_password.trim().is_empty() {
+            self.admin_password = old.admin_password.to_owned();
+        }
+        self
+    }
+
     /// Gets the API endpoint for the Claude service
     /// Returns the reverse proxy URL if configured, otherwise the default endpoint
     ///
@@ -303,6 +664,18 @@ impl ClewdrConfig {
         ENDPOINT_URL.to_owned()
     }
 
+    /// Returns a client builder routed through the configured proxy
+    ///
+    /// For clients that do not act as a cookie, which build their own
+    /// emulation and cookie store on top of the proxy.
+    pub fn http_client_builder(&self) -> wreq::ClientBuilder {
+        let builder = wreq::Client::builder();
+        match self.wreq_proxy.to_owned() {
+            Some(proxy) => builder.proxy(proxy),
+            None => builder,
+        }
+    }
+
     /// address of proxy
     pub fn address(&self) -> SocketAddr {
         SocketAddr::new(self.ip, self.port)
@@ -310,7 +683,8 @@ impl ClewdrConfig {
 
     /// Save the configuration to a file
     pub async fn save(&self) -> Result<(), ClewdrError> {
-        if self.no_fs {
+        // demo mode keeps every change in memory
+        if self.no_fs || self.demo {
             return Ok(());
         }
         if let Some(parent) = CONFIG_PATH.parent()
@@ -318,11 +692,33 @@ impl ClewdrConfig {
         {
             tokio::fs::create_dir_all(parent).await?;
         }
-        Ok(tokio::fs::write(CONFIG_PATH.as_path(), toml::ser::to_string_pretty(self)?).await?)
+        // write a sibling file and rename it over, a crash never leaves a truncated config
+        let tmp = CONFIG_PATH.with_extension(format!("{}.tmp", uuid::Uuid::new_v4()));
+        let text = self.file_text(!storage::pool_in_database())?;
+        // the file watcher must not mistake this write for an edit
+        config_watch::remember_saved(&text);
+        tokio::fs::write(&tmp, text).await?;
+        Ok(tokio::fs::rename(&tmp, CONFIG_PATH.as_path()).await?)
+    }
+
+    /// Renders the configuration as saved to the config file
+    ///
+    /// # Arguments
+    /// * `with_pool` - Whether `cookie_array` and `wasted_cookie` are written, not
+    ///   when another store holds the pool and a copy in the file would go stale
+    pub fn file_text(&self, with_pool: bool) -> Result<String, ClewdrError> {
+        if with_pool {
+            return Ok(toml::ser::to_string_pretty(self)?);
+        }
+        let mut config = self.to_owned();
+        config.cookie_array.clear();
+        config.wasted_cookie.clear();
+        Ok(toml::ser::to_string_pretty(&config)?)
     }
 
     /// Validate the configuration
     pub fn validate(mut self) -> Self {
+        self.config_version = CONFIG_VERSION;
         if self.password.trim().is_empty() {
             self.password = generate_password();
         }
@@ -330,6 +726,46 @@ impl ClewdrConfig {
             self.admin_password = generate_password();
         }
         self.cookie_array = self.cookie_array.into_iter().map(|x| x.reset()).collect();
+        self.demo_error_rate = self.demo_error_rate.clamp(0.0, 1.0);
+        for slo in self.slo.iter_mut() {
+            slo.target = slo.target.clamp(0.0, 1.0);
+        }
+        self.pricing = validate_pricing(std::mem::take(&mut self.pricing));
+        let mut names = HashSet::new();
+        let mut keys = HashSet::new();
+        let password = self.password.to_owned();
+        self.api_keys.retain(|k| {
+            let valid = !k.name.trim().is_empty()
+                && !k.key.trim().is_empty()
+                && k.key != password
+                && names.insert(k.name.to_owned())
+                && keys.insert(k.key.to_owned());
+            if !valid {
+                error!("API key {} is empty or not unique, ignored", k.name);
+            }
+            valid
+        });
+        if let Some(t) = self.prompt_preset.force_temperature {
+            self.prompt_preset.force_temperature = Some(t.clamp(0.0, 1.0));
+        }
+        if let Some([min, max]) = self.prompt_preset.temperature_range
+            && !(0.0 <= min && min <= max && max <= 1.0)
+        {
+            error!(
+                "Invalid temperature range [{}, {}], prompt preset ignores it",
+                min, max
+            );
+            self.prompt_preset.temperature_range = None;
+        }
+        if let Some(required) = self.language_policy.required.as_deref()
+            && !language::is_supported(required)
+        {
+            error!(
+                "Language {} cannot be detected, language policy ignores it",
+                required
+            );
+            self.language_policy.required = None;
+        }
         self.wreq_proxy = self.proxy.to_owned().and_then(|p| {
             Proxy::all(p)
                 .inspect_err(|e| {
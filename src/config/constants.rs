@@ -8,7 +8,10 @@ use arc_swap::ArcSwap;
 use clap::Parser;
 use url::Url;
 
-use crate::{Args, config::ClewdrConfig};
+use crate::{
+    Args,
+    config::{CONFIG_VERSION, ClewdrConfig},
+};
 
 pub const CONFIG_NAME: &str = "clewdr.toml";
 pub const CLAUDE_ENDPOINT: &str = "https://api.anthropic.com/";
@@ -19,6 +22,28 @@ pub const CC_CLIENT_ID: &str = "9d1c250a-e61b-44d9-88ed-5944d1962f5e";
 pub const CC_TOKEN_URL: &str = "https://api.anthropic.com/v1/oauth/token";
 pub const CC_REDIRECT_URI: &str = "https://console.anthropic.com/oauth/code/callback";
 pub const CLAUDE_CODE_VERSION: &str = "2.1.76";
+/// Header marking requests sent by `clewdr smoke`, kept out of usage stats
+pub const SMOKE_HEADER: &str = "x-clewdr-smoke";
+/// Request header naming a client session whose claude.ai conversation is reused
+pub const SESSION_HEADER: &str = "x-clewdr-session";
+/// Header opting a request into a feature usage report, `1` inline or `header`
+pub const REPORT_HEADER: &str = "x-clewdr-report";
+/// Response header carrying the id of the request report
+pub const REPORT_ID_HEADER: &str = "x-clewdr-report-id";
+/// Response header marking every response of a demo mode instance
+pub const DEMO_HEADER: &str = "x-clewdr-demo";
+/// Response header listing what the prompt preset changed in the request
+pub const TRANSFORMED_HEADER: &str = "x-clewdr-transformed";
+/// Response header naming the cookie that served a request, see `debug_account_header`
+pub const ACCOUNT_HEADER: &str = "x-clewdr-account";
+/// Response header telling whether a response came from the response cache
+pub const CACHE_HEADER: &str = "x-clewdr-cache";
+/// Request header carrying the admin password next to a proxy key, for admin-only features
+pub const ADMIN_HEADER: &str = "x-clewdr-admin";
+/// Request header repeating the admin password to reveal secrets of `/api/config`
+pub const REAUTH_HEADER: &str = "x-clewdr-reauth";
+/// Prefix of forwarded upstream headers whose name clewdr already uses
+pub const UPSTREAM_HEADER_PREFIX: &str = "x-upstream-";
 pub const CLAUDE_CODE_USER_AGENT: &str = "claude-code/2.1.76";
 pub const CLAUDE_CODE_BILLING_SALT: &str = "59cf53e54c78";
 
@@ -124,6 +149,22 @@ pub const fn default_use_real_roles() -> bool {
     true
 }
 
+/// Default setting for requiring a usable cookie to report ready
+///
+/// # Returns
+/// * `bool` - The default value of true
+pub const fn default_readiness_requires_cookie() -> bool {
+    true
+}
+
+/// Default state of a new client API key
+///
+/// # Returns
+/// * `bool` - The default value of true
+pub const fn default_key_enabled() -> bool {
+    true
+}
+
 /// Default setting for checking updates on startup
 ///
 /// # Returns
@@ -139,5 +180,172 @@ pub const fn default_skip_cool_down() -> bool {
     true
 }
 
+/// Default format version of a config that does not name one
+///
+/// Files without it are migrated before they are deserialized, so this only
+/// applies to configs from the environment or the API.
+///
+/// # Returns
+/// * `u32` - The version this build writes
+pub const fn default_config_version() -> u32 {
+    CONFIG_VERSION
+}
+
+/// Default size cap of the transcript store in megabytes
+///
+/// # Returns
+/// * `u64` - The default value of 200
+pub const fn default_transcript_max_mb() -> u64 {
+    200
+}
+
+/// Default cap of uncompressed log downloads in megabytes
+///
+/// # Returns
+/// * `u64` - The default value of 100
+pub const fn default_log_download_max_mb() -> u64 {
+    100
+}
+
+/// Default seconds before expiry a Claude Code token is refreshed
+///
+/// # Returns
+/// * `u64` - The default value of 300
+pub const fn default_token_expiry_skew_secs() -> u64 {
+    300
+}
+
+/// Default seconds running requests get to finish once shutdown starts
+///
+/// # Returns
+/// * `u64` - The default value of 30
+pub const fn default_drain_timeout_secs() -> u64 {
+    30
+}
+
+/// Default share of demo mode responses that fail with a simulated error
+///
+/// # Returns
+/// * `f64` - The default value of 0.05
+pub const fn default_demo_error_rate() -> f64 {
+    0.05
+}
+
+/// Default number of requests a claude.ai cookie serves at once
+///
+/// # Returns
+/// * `usize` - The default value of 1
+pub const fn default_web_cookie_concurrency() -> usize {
+    1
+}
+
+/// Default number of requests a Claude Code token serves at once
+///
+/// # Returns
+/// * `usize` - The default value of 4
+pub const fn default_code_cookie_concurrency() -> usize {
+    4
+}
+
+/// Default time a request waits for a busy cookie, in milliseconds
+///
+/// # Returns
+/// * `u64` - The default value of 30000
+pub const fn default_queue_timeout_ms() -> u64 {
+    30_000
+}
+
+/// Default seconds of upstream silence before a streamed response is pinged
+///
+/// # Returns
+/// * `u64` - The default value of 15
+pub const fn default_sse_keep_alive_secs() -> u64 {
+    15
+}
+
+/// Default seconds account metadata of a cookie is reused before it is refetched
+///
+/// # Returns
+/// * `u64` - The default value of 600
+pub const fn default_account_cache_ttl_secs() -> u64 {
+    600
+}
+
+/// Default seconds the upstream model list is served before it is refetched
+///
+/// # Returns
+/// * `u64` - The default value of 3600
+pub const fn default_model_list_ttl_secs() -> u64 {
+    3600
+}
+
+/// Default number of requests allowed to wait for a busy cookie
+///
+/// # Returns
+/// * `usize` - The default value of 64
+pub const fn default_max_queued() -> usize {
+    64
+}
+
+/// Default window in seconds in which a repeated request body is a retry
+///
+/// # Returns
+/// * `u64` - The default value of 120
+pub const fn default_retry_window_secs() -> u64 {
+    120
+}
+
+/// Default number of queue priority levels a retried request can gain
+///
+/// # Returns
+/// * `u32` - The default value of 3
+pub const fn default_max_retry_boost() -> u32 {
+    3
+}
+
+/// Default success target of a service level objective
+///
+/// # Returns
+/// * `f64` - The default value of 0.99
+pub const fn default_slo_target() -> f64 {
+    0.99
+}
+
+/// Default latency bound of a service level objective in seconds
+///
+/// # Returns
+/// * `u64` - The default value of 60
+pub const fn default_slo_latency_secs() -> u64 {
+    60
+}
+
+/// Default 1h burn rate that fires a fast-burn alert
+///
+/// # Returns
+/// * `f64` - The default value of 14.4
+pub const fn default_slo_fast_burn() -> f64 {
+    14.4
+}
+
+/// Default 24h burn rate that fires a slow-burn alert
+///
+/// # Returns
+/// * `f64` - The default value of 3.0
+pub const fn default_slo_slow_burn() -> f64 {
+    3.0
+}
+
+/// Default upstream headers forwarded from the Claude Code backend
+///
+/// # Returns
+/// * `Vec<String>` - Rate-limit headers and the upstream request id
+pub fn default_passthrough_headers() -> Vec<String> {
+    vec![
+        "anthropic-ratelimit-*".to_string(),
+        "request-id".to_string(),
+        "retry-after".to_string(),
+    ]
+}
+
 /// Default cookie value for testing purposes
 pub const PLACEHOLDER_COOKIE: &str = "sk-ant-REDACTED";
@@ -0,0 +1,21 @@
+use serde::{Deserialize, Serialize};
+
+/// Reuse of claude.ai conversations across requests of one client session
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct ConversationReuse {
+    pub enabled: bool,
+    /// Seconds a session is kept after its last request
+    pub ttl_secs: u64,
+    pub max_sessions: u64,
+}
+
+impl Default for ConversationReuse {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ttl_secs: 30 * 60,
+            max_sessions: 1000,
+        }
+    }
+}
@@ -12,7 +12,7 @@ use snafu::{GenerateImplicitData, Location};
 use tracing::info;
 
 use crate::{
-    config::{PLACEHOLDER_COOKIE, TokenInfo},
+    config::{AccountFlag, AccountStanding, PLACEHOLDER_COOKIE, TokenInfo},
     error::ClewdrError,
 };
 
@@ -49,6 +49,10 @@ pub struct UsageBreakdown {
     pub opus_input_tokens: u64,
     #[serde(default)]
     pub opus_output_tokens: u64,
+
+    /// Estimated cost in USD, see `pricing`
+    #[serde(default)]
+    pub cost_usd: f64,
 }
 
 /// A struct representing a cookie
@@ -90,6 +94,12 @@ pub struct CookieStatus {
     pub supports_claude_1m_opus: Option<bool>,
     #[serde(default)]
     pub count_tokens_allowed: Option<bool>,
+    /// Set when the refresh token was rejected, cleared once re-authorization succeeds
+    #[serde(default)]
+    pub needs_reauth: bool,
+    /// Warning or restriction told by the latest classified upstream error
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flag: Option<AccountFlag>,
 
     // New: Per-period usage breakdown
     #[serde(default)]
@@ -173,6 +183,8 @@ impl CookieStatus {
             supports_claude_1m_sonnet: Some(true),
             supports_claude_1m_opus: Some(true),
             count_tokens_allowed: None,
+            needs_reauth: false,
+            flag: None,
 
             session_usage: UsageBreakdown::default(),
             weekly_usage: UsageBreakdown::default(),
@@ -207,6 +219,10 @@ impl CookieStatus {
                 weekly_usage: UsageBreakdown::default(),
                 weekly_sonnet_usage: UsageBreakdown::default(),
                 weekly_opus_usage: UsageBreakdown::default(),
+                // the restriction is over, a warning stays
+                flag: self
+                    .flag
+                    .filter(|f| f.standing != AccountStanding::Restricted),
                 ..self
             };
         }
@@ -263,10 +279,25 @@ impl CookieStatus {
         self.weekly_opus_resets_at = ts;
     }
 
-    pub fn add_and_bucket_usage(&mut self, input: u64, output: u64, family: ModelFamily) {
+    pub fn add_and_bucket_usage(
+        &mut self,
+        input: u64,
+        output: u64,
+        family: ModelFamily,
+        cost: f64,
+    ) {
         if input == 0 && output == 0 {
             return;
         }
+        // cost follows the tokens into every bucket they count towards
+        self.session_usage.cost_usd += cost;
+        self.weekly_usage.cost_usd += cost;
+        self.lifetime_usage.cost_usd += cost;
+        match family {
+            ModelFamily::Sonnet => self.weekly_sonnet_usage.cost_usd += cost,
+            ModelFamily::Opus => self.weekly_opus_usage.cost_usd += cost,
+            ModelFamily::Other => {}
+        }
         // Legacy totals/windows removed; only bucketed aggregation remains
 
         // session bucket (total + per family)
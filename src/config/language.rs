@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+
+/// What to do with the detected language of a response
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LanguageMode {
+    /// Responses are not inspected
+    #[default]
+    Off,
+    /// Add an `x-clewdr-language` header with the detected language
+    Annotate,
+    /// Annotate and log a warning when the required language is not met
+    Warn,
+    /// Annotate and retry once with a stronger instruction on a mismatch
+    Enforce,
+}
+
+/// Language required of non-streamed responses
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct LanguagePolicy {
+    pub mode: LanguageMode,
+    /// ISO 639-1 code responses must be written in, only annotated when unset
+    pub required: Option<String>,
+    /// Characters of prose inspected, from the start of the response
+    pub prefix_chars: usize,
+}
+
+impl Default for LanguagePolicy {
+    fn default() -> Self {
+        Self {
+            mode: LanguageMode::Off,
+            required: None,
+            prefix_chars: 1000,
+        }
+    }
+}
@@ -0,0 +1,261 @@
+//! Upgrades of config files written by older versions
+//!
+//! Every change to the persisted format that serde defaults cannot absorb,
+//! such as a renamed field, gets a migration here and bumps
+//! [`CONFIG_VERSION`]. Files without `config_version` predate versioning and
+//! count as version 0.
+
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use chrono::Local;
+use toml::{Table, Value};
+use tracing::{error, info};
+
+use crate::error::ClewdrError;
+
+type Migration = fn(&mut Table);
+
+/// Migrations in order, the one at index `i` upgrades version `i` to `i + 1`
+const MIGRATIONS: [Migration; 2] = [rename_skip_cool_down, cookie_strings_to_array];
+
+/// Version of the config format this build writes
+pub const CONFIG_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// Version 1: `skip_cool_down` became `skip_rate_limit`
+fn rename_skip_cool_down(doc: &mut Table) {
+    if let Some(skip) = doc.remove("skip_cool_down") {
+        doc.entry("skip_rate_limit").or_insert(skip);
+    }
+}
+
+/// Version 2: a lone `cookie` string and bare strings in `cookie_array`
+/// became `cookie_array` entries
+fn cookie_strings_to_array(doc: &mut Table) {
+    let mut cookies = match doc.remove("cookie_array") {
+        Some(Value::Array(cookies)) => cookies,
+        _ => vec![],
+    };
+    match doc.remove("cookie") {
+        Some(Value::String(cookie)) => cookies.push(Value::String(cookie)),
+        Some(Value::Array(more)) => cookies.extend(more),
+        _ => {}
+    }
+    let cookies = cookies
+        .into_iter()
+        .filter_map(|cookie| match cookie {
+            Value::String(cookie) if cookie.trim().is_empty() => None,
+            Value::String(cookie) => Some(Value::Table(Table::from_iter([(
+                "cookie".to_string(),
+                Value::String(cookie),
+            )]))),
+            entry => Some(entry),
+        })
+        .collect::<Vec<_>>();
+    if !cookies.is_empty() {
+        doc.insert("cookie_array".into(), Value::Array(cookies));
+    }
+}
+
+/// Upgrades a config document to [`CONFIG_VERSION`]
+///
+/// # Returns
+/// * `Result<Option<u32>, ClewdrError>` - Version the document was migrated from, `None` when it was current,
+///   an error when it is newer than this build
+pub fn migrate(doc: &mut Table) -> Result<Option<u32>, ClewdrError> {
+    let version = match doc.get("config_version") {
+        None => 0,
+        Some(version) => version
+            .as_integer()
+            .and_then(|v| u32::try_from(v).ok())
+            .filter(|v| *v <= CONFIG_VERSION)
+            .ok_or_else(|| ClewdrError::UnsupportedConfigVersion {
+                version: version.to_string(),
+            })?,
+    };
+    if version == CONFIG_VERSION {
+        return Ok(None);
+    }
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(doc);
+    }
+    doc.insert(
+        "config_version".into(),
+        Value::Integer(CONFIG_VERSION.into()),
+    );
+    Ok(Some(version))
+}
+
+/// A config file upgraded while it was read, whose original is not yet backed up
+pub struct Migrated {
+    /// Version of the original
+    pub from: u32,
+    original: String,
+}
+
+impl Migrated {
+    /// Writes the original file next to `path`, named after its version and the time
+    ///
+    /// # Returns
+    /// * `Result<PathBuf, ClewdrError>` - Path of the backup
+    pub fn backup(&self, path: &Path) -> Result<PathBuf, ClewdrError> {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let backup = path.with_file_name(format!(
+            "{}.v{}-{}.bak",
+            name,
+            self.from,
+            Local::now().format("%Y%m%d-%H%M%S")
+        ));
+        std::fs::write(&backup, &self.original)?;
+        Ok(backup)
+    }
+
+    /// Backs the original up next to `path`, logging the outcome
+    ///
+    /// # Returns
+    /// * `bool` - Whether the backup was written, the file must not be rewritten otherwise
+    pub fn keep_original(&self, path: &Path) -> bool {
+        match self.backup(path) {
+            Ok(backup) => {
+                info!(
+                    "Config migrated from version {} to {}, original kept at {}",
+                    self.from,
+                    CONFIG_VERSION,
+                    backup.display()
+                );
+                true
+            }
+            Err(e) => {
+                error!("Failed to back up config, file left unchanged: {}", e);
+                false
+            }
+        }
+    }
+}
+
+/// Reads the config file at `path`, upgraded to [`CONFIG_VERSION`]
+///
+/// A missing file reads as empty. A file that is not valid TOML is returned as
+/// it is, for the config loader to report.
+///
+/// # Returns
+/// * `Result<(String, Option<Migrated>), ClewdrError>` - TOML to load, and the original when it was migrated
+pub fn read_config_file(path: &Path) -> Result<(String, Option<Migrated>), ClewdrError> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok((String::new(), None)),
+        Err(e) => return Err(e.into()),
+    };
+    let Ok(mut doc) = toml::from_str::<Table>(&text) else {
+        return Ok((text, None));
+    };
+    match migrate(&mut doc)? {
+        None => Ok((text, None)),
+        Some(from) => Ok((
+            toml::to_string(&doc)?,
+            Some(Migrated {
+                from,
+                original: text,
+            }),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ClewdrConfig;
+
+    const V0: &str = include_str!("fixtures/config_v0.toml");
+    const V1: &str = include_str!("fixtures/config_v1.toml");
+
+    fn migrated(fixture: &str) -> (Table, Option<u32>) {
+        let mut doc = toml::from_str::<Table>(fixture).unwrap();
+        let from = migrate(&mut doc).unwrap();
+        (doc, from)
+    }
+
+    #[test]
+    fn skip_cool_down_becomes_skip_rate_limit() {
+        let (doc, from) = migrated(V0);
+        assert_eq!(from, Some(0));
+        assert_eq!(
+            doc["config_version"].as_integer(),
+            Some(CONFIG_VERSION.into())
+        );
+        assert!(!doc.contains_key("skip_cool_down"));
+        assert_eq!(doc["skip_rate_limit"].as_bool(), Some(false));
+
+        let config = doc.try_into::<ClewdrConfig>().unwrap();
+        assert!(!config.skip_rate_limit);
+        assert!(config.skip_non_pro);
+        assert_eq!(config.max_retries, 5);
+        assert_eq!(config.cookie_array.len(), 1);
+
+        // an explicit new name wins over the old one
+        let mut doc =
+            toml::from_str::<Table>("skip_cool_down = false\nskip_rate_limit = true").unwrap();
+        rename_skip_cool_down(&mut doc);
+        assert_eq!(doc.get("skip_rate_limit"), Some(&Value::Boolean(true)));
+    }
+
+    #[test]
+    fn cookie_strings_become_entries() {
+        let (doc, from) = migrated(V1);
+        assert_eq!(from, Some(1));
+        assert!(!doc.contains_key("cookie"));
+        let cookies = doc["cookie_array"].as_array().unwrap();
+        assert_eq!(cookies.len(), 3);
+        assert!(cookies.iter().all(|c| c["cookie"].is_str()));
+        assert!(cookies[0]["cookie"].as_str().unwrap().ends_with("000003AA"));
+        assert_eq!(cookies[1]["reset_time"].as_integer(), Some(1767225600));
+        assert!(cookies[2]["cookie"].as_str().unwrap().ends_with("000002AA"));
+
+        let config = doc.try_into::<ClewdrConfig>().unwrap();
+        assert_eq!(config.cookie_array.len(), 3);
+        assert!(config.skip_rate_limit);
+    }
+
+    #[test]
+    fn current_files_are_left_alone_and_newer_ones_fail() {
+        let (mut doc, _) = migrated(V0);
+        let current = doc.to_owned();
+        assert_eq!(migrate(&mut doc).unwrap(), None);
+        assert_eq!(doc, current);
+
+        for version in [CONFIG_VERSION as i64 + 1, -1] {
+            doc.insert("config_version".into(), Value::Integer(version));
+            let err = migrate(&mut doc).unwrap_err();
+            assert!(matches!(err, ClewdrError::UnsupportedConfigVersion { .. }));
+            assert!(err.to_string().contains(&version.to_string()));
+        }
+    }
+
+    #[test]
+    fn migrated_files_are_backed_up() {
+        let dir = std::env::temp_dir().join(format!("clewdr-migration-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("clewdr.toml");
+        assert_eq!(read_config_file(&path).unwrap().0, "");
+
+        std::fs::write(&path, V0).unwrap();
+        let (text, migrated) = read_config_file(&path).unwrap();
+        let migrated = migrated.unwrap();
+        assert!(text.contains("skip_rate_limit = false"));
+        let backup = migrated.backup(&path).unwrap();
+        let name = backup.file_name().unwrap().to_string_lossy().into_owned();
+        assert!(name.starts_with("clewdr.toml.v0-"), "{name}");
+        assert_eq!(std::fs::read_to_string(&backup).unwrap(), V0);
+
+        std::fs::write(&path, &text).unwrap();
+        let (again, migrated) = read_config_file(&path).unwrap();
+        assert_eq!(again, text);
+        assert!(migrated.is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,12 +1,48 @@
 // Re-export all items from submodules
+mod account_flag;
+mod api_key;
 mod clewdr_config;
 mod constants;
+mod conversation_reuse;
 mod cookie;
+mod language;
+mod migration;
+mod models;
+mod passthrough;
+mod preset;
+mod pricing;
+mod rate_limit;
 mod reason;
+mod redaction;
+mod response_cache;
+mod slo;
+#[cfg(test)]
+mod testing;
 mod token;
+mod typography;
+mod upstream_retry;
+mod validation;
 
+pub use account_flag::*;
+pub use api_key::*;
 pub use clewdr_config::*;
 pub use constants::*;
+pub use conversation_reuse::*;
 pub use cookie::*;
+pub use language::*;
+pub use migration::*;
+pub use models::*;
+pub use passthrough::*;
+pub use preset::*;
+pub use pricing::*;
+pub use rate_limit::*;
 pub use reason::*;
+pub use redaction::*;
+pub use response_cache::*;
+pub use slo::*;
+#[cfg(test)]
+pub use testing::*;
 pub use token::*;
+pub use typography::*;
+pub use upstream_retry::*;
+pub use validation::*;
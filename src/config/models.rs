@@ -0,0 +1,194 @@
+use std::collections::BTreeMap;
+
+use crate::{config::ClewdrConfig, error::ClewdrError};
+
+/// Models listed by `/v1/models`
+pub const MODEL_LIST: [&str; 26] = [
+    "claude-3-7-sonnet-20250219",
+    "claude-3-7-sonnet-20250219-thinking",
+    "claude-sonnet-4-20250514",
+    "claude-sonnet-4-20250514-thinking",
+    "claude-sonnet-4-20250514-1M",
+    "claude-sonnet-4-20250514-1M-thinking",
+    "claude-sonnet-4-5-20250929",
+    "claude-sonnet-4-5-20250929-thinking",
+    "claude-sonnet-4-5-20250929-1M",
+    "claude-sonnet-4-5-20250929-1M-thinking",
+    "claude-sonnet-4-6",
+    "claude-sonnet-4-6-thinking",
+    "claude-sonnet-4-6-1M",
+    "claude-sonnet-4-6-1M-thinking",
+    "claude-opus-4-20250514",
+    "claude-opus-4-20250514-thinking",
+    "claude-opus-4-1-20250805",
+    "claude-opus-4-1-20250805-thinking",
+    "claude-opus-4-5-20251101",
+    "claude-opus-4-5-20251101-thinking",
+    "claude-opus-4-5",
+    "claude-opus-4-5-thinking",
+    "claude-opus-4-6",
+    "claude-opus-4-6-thinking",
+    "claude-opus-4-6-1M",
+    "claude-opus-4-6-1M-thinking",
+];
+
+/// Resolves a requested model through the aliases
+///
+/// Names are only checked once aliases are configured, so instances without
+/// any keep forwarding every model as before. Any `claude-` name counts as a
+/// model, as upstream offers models [`MODEL_LIST`] does not know yet.
+///
+/// # Arguments
+/// * `aliases` - Alias to model id
+/// * `default_model` - Model used for names that are not models
+/// * `model` - Model named by the client
+///
+/// # Returns
+/// * `Ok(Some(model))` - Model to send upstream instead
+/// * `Ok(None)` - The name is sent as it is
+/// * `Err(ClewdrError::UnknownModel)` - Unknown name and no default
+pub fn resolve_model(
+    aliases: &BTreeMap<String, String>,
+    default_model: Option<&str>,
+    model: &str,
+) -> Result<Option<String>, ClewdrError> {
+    if let Some(target) = aliases.get(model) {
+        return Ok(Some(target.to_owned()));
+    }
+    let known = model.starts_with("claude-") || aliases.values().any(|m| m == model);
+    match default_model {
+        _ if known => Ok(None),
+        Some(default) => Ok(Some(default.to_string())),
+        None if aliases.is_empty() => Ok(None),
+        None => Err(ClewdrError::UnknownModel {
+            model: model.to_string(),
+            accepted: accepted_models(aliases),
+        }),
+    }
+}
+
+/// Listed models followed by the aliases
+pub fn accepted_models(aliases: &BTreeMap<String, String>) -> Vec<String> {
+    with_aliases(MODEL_LIST.map(str::to_string).to_vec(), aliases)
+}
+
+/// Models followed by the aliases that are not models themselves
+pub fn with_aliases(mut models: Vec<String>, aliases: &BTreeMap<String, String>) -> Vec<String> {
+    let extra = aliases
+        .keys()
+        .filter(|a| !models.contains(a))
+        .cloned()
+        .collect::<Vec<_>>();
+    models.extend(extra);
+    models
+}
+
+/// Models to list for the ids the Anthropic API offers
+///
+/// Each id is followed by its `-thinking` variant, and the `-1M` variants in
+/// [`MODEL_LIST`] are kept for the ids still offered.
+pub fn expand_upstream_models(ids: &[String]) -> Vec<String> {
+    let mut models = ids
+        .iter()
+        .flat_map(|id| [id.to_owned(), format!("{id}-thinking")])
+        .collect::<Vec<_>>();
+    for model in MODEL_LIST {
+        let base = model.trim_end_matches("-thinking").trim_end_matches("-1M");
+        if ids.iter().any(|id| id == base) && !models.iter().any(|m| m == model) {
+            models.push(model.to_string());
+        }
+    }
+    models
+}
+
+/// Whether a model accepts extended thinking
+///
+/// Claude 3 models before 3.7 do not, every later model does.
+pub fn supports_thinking(model: &str) -> bool {
+    model.starts_with("claude-")
+        && (!model.starts_with("claude-3-") || model.starts_with("claude-3-7-"))
+}
+
+impl ClewdrConfig {
+    /// Resolves a requested model through `model_aliases` and `default_model`
+    pub fn resolve_model(&self, model: &str) -> Result<Option<String>, ClewdrError> {
+        resolve_model(&self.model_aliases, self.default_model.as_deref(), model)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aliases_and_default() {
+        let aliases = BTreeMap::from([
+            ("gpt-4o".to_string(), "claude-sonnet-4-6".to_string()),
+            ("fast".to_string(), "claude-haiku-4-5".to_string()),
+        ]);
+        let resolve = |model, default| resolve_model(&aliases, default, model);
+        assert_eq!(
+            resolve("gpt-4o", None).unwrap().as_deref(),
+            Some("claude-sonnet-4-6")
+        );
+        // models, listed or not, and alias targets pass as they are
+        assert_eq!(resolve("claude-opus-4-6-thinking", None).unwrap(), None);
+        assert_eq!(resolve("claude-haiku-4-5", None).unwrap(), None);
+        assert_eq!(resolve("claude-3-opus-latest", None).unwrap(), None);
+        assert_eq!(
+            resolve("gpt-4.1", Some("claude-opus-4-6"))
+                .unwrap()
+                .as_deref(),
+            Some("claude-opus-4-6")
+        );
+        let Err(ClewdrError::UnknownModel { accepted, .. }) = resolve("gpt-4.1", None) else {
+            panic!("unknown model accepted");
+        };
+        assert!(accepted.contains(&"fast".to_string()));
+        assert_eq!(accepted.len(), MODEL_LIST.len() + 2);
+    }
+
+    #[test]
+    fn upstream_ids_get_variants() {
+        let ids = [
+            "claude-sonnet-4-6".to_string(),
+            "claude-haiku-5".to_string(),
+        ];
+        assert_eq!(
+            expand_upstream_models(&ids),
+            [
+                "claude-sonnet-4-6",
+                "claude-sonnet-4-6-thinking",
+                "claude-haiku-5",
+                "claude-haiku-5-thinking",
+                "claude-sonnet-4-6-1M",
+                "claude-sonnet-4-6-1M-thinking",
+            ]
+        );
+        let aliases = BTreeMap::from([
+            ("fast".to_string(), "claude-haiku-5".to_string()),
+            ("claude-haiku-5".to_string(), "claude-haiku-5".to_string()),
+        ]);
+        assert_eq!(with_aliases(ids.to_vec(), &aliases).len(), 3);
+    }
+
+    #[test]
+    fn thinking_models() {
+        for model in MODEL_LIST {
+            assert!(supports_thinking(model), "{model}");
+        }
+        assert!(supports_thinking("claude-haiku-4-5"));
+        assert!(!supports_thinking("claude-3-5-haiku-20241022"));
+        assert!(!supports_thinking("claude-3-opus-latest"));
+        assert!(!supports_thinking("gpt-4o"));
+    }
+
+    #[test]
+    fn no_aliases_forward_everything() {
+        let none = BTreeMap::new();
+        assert_eq!(
+            resolve_model(&none, None, "claude-haiku-4-5").unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+
+use super::default_passthrough_headers;
+
+/// Upstream response headers forwarded to clients
+///
+/// Patterns are header names, a trailing `*` matches every header with that
+/// prefix. Matching ignores case.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct HeaderPassthrough {
+    /// Forwarded from the Claude Code backend
+    #[serde(default = "default_passthrough_headers")]
+    pub claude_code: Vec<String>,
+    /// Forwarded from the claude.ai backend
+    #[serde(default)]
+    pub claude_web: Vec<String>,
+    /// Reduced set for requests not made with the admin password, unset forwards everything above
+    #[serde(default)]
+    pub user: Option<Vec<String>>,
+    /// Attach rate-limit headers derived from the cookie pool to claude.ai responses
+    #[serde(default)]
+    pub synthesize_web: bool,
+}
+
+impl Default for HeaderPassthrough {
+    fn default() -> Self {
+        Self {
+            claude_code: default_passthrough_headers(),
+            claude_web: Vec::new(),
+            user: None,
+            synthesize_web: false,
+        }
+    }
+}
+
+/// Whether a header name matches any of the patterns
+pub fn header_matches(patterns: &[String], name: &str) -> bool {
+    patterns.iter().any(|p| match p.strip_suffix('*') {
+        Some(prefix) => name
+            .get(..prefix.len())
+            .is_some_and(|n| n.eq_ignore_ascii_case(prefix)),
+        None => p.eq_ignore_ascii_case(name),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_match_names_and_prefixes() {
+        let patterns = default_passthrough_headers();
+        assert!(header_matches(
+            &patterns,
+            "anthropic-ratelimit-tokens-remaining"
+        ));
+        assert!(header_matches(&patterns, "Request-Id"));
+        assert!(!header_matches(&patterns, "request-id-extra"));
+        assert!(!header_matches(&patterns, "set-cookie"));
+        assert!(!header_matches(&[], "request-id"));
+    }
+}
@@ -0,0 +1,24 @@
+use serde::{Deserialize, Serialize};
+
+/// Instance wide prompt policy applied to incoming requests
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct PromptPreset {
+    /// Text placed before the system prompt of every request
+    #[serde(default)]
+    pub system_prefix: Option<String>,
+    /// Text placed after the system prompt of every request
+    #[serde(default)]
+    pub system_suffix: Option<String>,
+    /// Stop sequences added to the ones the client sent
+    #[serde(default)]
+    pub extra_stop_sequences: Vec<String>,
+    /// Temperature replacing whatever the client sent
+    #[serde(default)]
+    pub force_temperature: Option<f32>,
+    /// Inclusive `[min, max]` the temperature is clamped to
+    #[serde(default)]
+    pub temperature_range: Option<[f32; 2]>,
+    /// Also apply the preset to `/code/v1`, only `/v1` is transformed otherwise
+    #[serde(default)]
+    pub apply_to_code: bool,
+}
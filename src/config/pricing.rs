@@ -0,0 +1,265 @@
+use std::{
+    collections::HashSet,
+    sync::{LazyLock, Mutex, PoisonError},
+};
+
+use chrono::{DateTime, NaiveDate};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serde_with::{DisplayFromStr, serde_as};
+use tracing::{error, warn};
+
+use crate::config::{CLEWDR_CONFIG, ClewdrConfig};
+
+/// Models already warned about missing from the pricing table
+static UNPRICED: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(Default::default);
+
+/// Prices in USD per million tokens
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(default)]
+pub struct TokenRates {
+    pub input: f64,
+    pub output: f64,
+    pub cache_read: f64,
+    pub cache_write: f64,
+}
+
+/// Tokens of one request, split the way they are billed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BilledTokens {
+    /// Uncached input tokens
+    pub input: u64,
+    pub output: u64,
+    pub cache_read: u64,
+    pub cache_write: u64,
+}
+
+impl BilledTokens {
+    /// Tokens without any cached input, as counted for claude.ai
+    pub fn uncached(input: u64, output: u64) -> Self {
+        Self {
+            input,
+            output,
+            ..Default::default()
+        }
+    }
+
+    /// Tokens of an Anthropic `usage` object, missing fields count as zero
+    pub fn from_usage(usage: &Value) -> Self {
+        let field = |name: &str| usage[name].as_u64().unwrap_or_default();
+        Self {
+            input: field("input_tokens"),
+            output: field("output_tokens"),
+            cache_read: field("cache_read_input_tokens"),
+            cache_write: field("cache_creation_input_tokens"),
+        }
+    }
+}
+
+impl TokenRates {
+    /// Cost of `tokens` in USD
+    pub fn cost(&self, tokens: BilledTokens) -> f64 {
+        (tokens.input as f64 * self.input
+            + tokens.output as f64 * self.output
+            + tokens.cache_read as f64 * self.cache_read
+            + tokens.cache_write as f64 * self.cache_write)
+            / 1_000_000.0
+    }
+}
+
+/// Rates of the models whose name starts with `model`, over a range of days
+#[serde_as]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ModelPrice {
+    pub model: String,
+    #[serde(flatten)]
+    pub rates: TokenRates,
+    /// First day the rates apply, applies since ever when unset
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default)]
+    pub from: Option<NaiveDate>,
+    /// First day the rates no longer apply, applies for good when unset
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default)]
+    pub until: Option<NaiveDate>,
+}
+
+impl ModelPrice {
+    fn applies_on(&self, day: NaiveDate) -> bool {
+        self.from.is_none_or(|from| from <= day) && self.until.is_none_or(|until| day < until)
+    }
+
+    pub(super) fn overlaps(&self, other: &ModelPrice) -> bool {
+        self.model == other.model && self.from.max(other.from) < min_until(self.until, other.until)
+    }
+}
+
+/// Earlier of two range ends, an unset end being open
+fn min_until(a: Option<NaiveDate>, b: Option<NaiveDate>) -> Option<NaiveDate> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+    .or(Some(NaiveDate::MAX))
+}
+
+/// Drops empty date ranges and ranges overlapping an earlier entry of the same model
+pub fn validate_pricing(prices: Vec<ModelPrice>) -> Vec<ModelPrice> {
+    let mut kept: Vec<ModelPrice> = Vec::with_capacity(prices.len());
+    for price in prices {
+        if let (Some(from), Some(until)) = (price.from, price.until)
+            && from >= until
+        {
+            error!("Pricing for {} ends before it starts, ignored", price.model);
+            continue;
+        }
+        if kept.iter().any(|k| k.overlaps(&price)) {
+            error!(
+                "Pricing for {} overlaps an earlier date range, ignored",
+                price.model
+            );
+            continue;
+        }
+        kept.push(price);
+    }
+    kept
+}
+
+/// Rates of `model` on `day`, the longest matching model prefix wins
+pub fn rates_for<'a>(
+    prices: &'a [ModelPrice],
+    model: &str,
+    day: NaiveDate,
+) -> Option<&'a TokenRates> {
+    prices
+        .iter()
+        .filter(|p| model.starts_with(&p.model) && p.applies_on(day))
+        .max_by_key(|p| p.model.len())
+        .map(|p| &p.rates)
+}
+
+impl ClewdrConfig {
+    /// Estimated cost in USD of a request, zero for unpriced web requests
+    ///
+    /// # Arguments
+    /// * `model` - Model the request was sent to
+    /// * `tokens` - Billed tokens of the request
+    /// * `web` - Whether claude.ai served the request
+    /// * `timestamp` - When the request was made, in unix seconds
+    pub fn estimate_cost(
+        &self,
+        model: &str,
+        tokens: BilledTokens,
+        web: bool,
+        timestamp: i64,
+    ) -> f64 {
+        if web && !self.price_web_requests {
+            return 0.0;
+        }
+        let day = DateTime::from_timestamp(timestamp, 0)
+            .unwrap_or_default()
+            .date_naive();
+        let rates = rates_for(&self.pricing, model, day).unwrap_or_else(|| {
+            let mut unpriced = UNPRICED.lock().unwrap_or_else(PoisonError::into_inner);
+            if !self.pricing.is_empty() && unpriced.insert(model.to_string()) {
+                warn!("No price for {}, using default_price", model);
+            }
+            &self.default_price
+        });
+        rates.cost(tokens)
+    }
+}
+
+/// Estimated cost in USD of tokens used just now, as counted per cookie
+///
+/// # Arguments
+/// * `model` - Model the request was sent to
+/// * `tokens` - Billed tokens, cache reads and writes priced at their own rates
+/// * `web` - Whether claude.ai served the request
+pub fn cost_now(model: &str, tokens: BilledTokens, web: bool) -> f64 {
+    let now = chrono::Utc::now().timestamp();
+    CLEWDR_CONFIG.load().estimate_cost(model, tokens, web, now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    fn price(model: &str, input: f64, from: Option<&str>, until: Option<&str>) -> ModelPrice {
+        ModelPrice {
+            model: model.to_string(),
+            rates: TokenRates {
+                input,
+                output: input * 5.0,
+                cache_read: input / 10.0,
+                cache_write: input * 1.25,
+            },
+            from: from.map(day),
+            until: until.map(day),
+        }
+    }
+
+    #[test]
+    fn price_changes_at_the_boundary() {
+        let prices = vec![
+            price("claude-opus-4", 15.0, None, Some("2025-11-24")),
+            price("claude-opus-4", 5.0, Some("2025-11-24"), None),
+            price("claude-opus-4-1", 15.0, None, None),
+        ];
+        let tokens = BilledTokens {
+            input: 1_000_000,
+            output: 100_000,
+            cache_read: 1_000_000,
+            cache_write: 0,
+        };
+        let cost = |model, on| rates_for(&prices, model, day(on)).unwrap().cost(tokens);
+        assert_eq!(cost("claude-opus-4-5", "2025-11-23"), 15.0 + 7.5 + 1.5);
+        assert_eq!(cost("claude-opus-4-5", "2025-11-24"), 5.0 + 2.5 + 0.5);
+        // the longer prefix wins whatever the date
+        assert_eq!(cost("claude-opus-4-1-20250805", "2026-01-01"), 24.0);
+        assert!(rates_for(&prices, "claude-sonnet-4", day("2026-01-01")).is_none());
+    }
+
+    #[test]
+    fn overlapping_ranges_are_dropped() {
+        let prices = validate_pricing(vec![
+            price("claude-sonnet-4", 3.0, None, Some("2026-01-01")),
+            price("claude-sonnet-4", 2.0, Some("2025-06-01"), None),
+            price("claude-sonnet-4", 1.0, Some("2026-01-01"), None),
+            price("claude-haiku", 1.0, Some("2026-01-01"), Some("2025-01-01")),
+            price("claude-haiku", 0.8, None, None),
+        ]);
+        let kept = prices.iter().map(|p| p.rates.input).collect::<Vec<_>>();
+        assert_eq!(kept, [3.0, 1.0, 0.8]);
+    }
+
+    #[test]
+    fn cached_input_is_read_from_usage() {
+        let usage = serde_json::json!({
+            "input_tokens": 10,
+            "output_tokens": 20,
+            "cache_read_input_tokens": 3000,
+            "cache_creation_input_tokens": 400,
+        });
+        let tokens = BilledTokens::from_usage(&usage);
+        assert_eq!(
+            tokens,
+            BilledTokens {
+                input: 10,
+                output: 20,
+                cache_read: 3000,
+                cache_write: 400,
+            }
+        );
+        let rates = price("claude-opus-4", 10.0, None, None).rates;
+        assert!(rates.cost(tokens) > rates.cost(BilledTokens::uncached(10, 20)));
+        assert_eq!(
+            BilledTokens::from_usage(&Value::Null),
+            BilledTokens::default()
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use std::net::IpAddr;
+
+use serde::{Deserialize, Serialize};
+
+/// Token bucket of one client
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct BucketLimit {
+    /// Requests a client can make at once
+    pub burst: u32,
+    /// Requests regained per minute
+    pub per_minute: u32,
+}
+
+/// Per IP limits on the message and auth endpoints, for instances open to the internet
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    /// Requests carrying a valid password or API key
+    pub authenticated: BucketLimit,
+    /// Requests without one, scanners among them
+    pub unauthenticated: BucketLimit,
+    /// Proxies whose `X-Forwarded-For` is trusted, as addresses or CIDR ranges
+    pub trusted_proxies: Vec<String>,
+    /// Rejections within `violation_window_secs` that block the IP, 0 never blocks
+    pub block_after: u32,
+    pub violation_window_secs: u64,
+    /// Seconds every request of a blocked IP is rejected
+    pub block_secs: u64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            authenticated: BucketLimit {
+                burst: 60,
+                per_minute: 120,
+            },
+            unauthenticated: BucketLimit {
+                burst: 10,
+                per_minute: 10,
+            },
+            trusted_proxies: Vec::new(),
+            block_after: 20,
+            violation_window_secs: 60,
+            block_secs: 900,
+        }
+    }
+}
+
+/// Address and prefix length of `entry`, an address or a CIDR range
+fn parse_range(entry: &str) -> Option<(IpAddr, u32)> {
+    let entry = entry.trim();
+    let (addr, prefix) = match entry.split_once('/') {
+        Some((addr, prefix)) => (addr, Some(prefix.parse::<u32>().ok()?)),
+        None => (entry, None),
+    };
+    let addr = addr.parse::<IpAddr>().ok()?.to_canonical();
+    let bits = if addr.is_ipv4() { 32 } else { 128 };
+    match prefix {
+        Some(prefix) if prefix > bits => None,
+        prefix => Some((addr, prefix.unwrap_or(bits))),
+    }
+}
+
+/// Whether `entry` is an address or a CIDR range
+pub fn is_ip_range(entry: &str) -> bool {
+    parse_range(entry).is_some()
+}
+
+/// Whether `ip` is `entry`, an address or a CIDR range
+fn in_range(entry: &str, ip: IpAddr) -> bool {
+    let Some((range, prefix)) = parse_range(entry) else {
+        return false;
+    };
+    match (range, ip.to_canonical()) {
+        (IpAddr::V4(range), IpAddr::V4(ip)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+            u32::from(range) & mask == u32::from(ip) & mask
+        }
+        (IpAddr::V6(range), IpAddr::V6(ip)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+            u128::from(range) & mask == u128::from(ip) & mask
+        }
+        _ => false,
+    }
+}
+
+impl RateLimitConfig {
+    /// Whether requests from `ip` may name the client in `X-Forwarded-For`
+    pub fn trusts(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|entry| in_range(entry, ip))
+    }
+
+    /// Address a request is limited by
+    ///
+    /// `X-Forwarded-For` is read from the right, past every trusted proxy, so
+    /// a client cannot pick its address by sending the header itself.
+    ///
+    /// # Arguments
+    /// * `peer` - Address of the connection
+    /// * `forwarded_for` - `X-Forwarded-For` header of the request
+    ///
+    /// # Returns
+    /// * `IpAddr` - The nearest address that is not a trusted proxy
+    pub fn client_ip(&self, peer: IpAddr, forwarded_for: Option<&str>) -> IpAddr {
+        let mut client = peer.to_canonical();
+        let Some(forwarded_for) = forwarded_for else {
+            return client;
+        };
+        for hop in forwarded_for.rsplit(',') {
+            if !self.trusts(client) {
+                break;
+            }
+            match hop.trim().parse::<IpAddr>() {
+                Ok(ip) => client = ip.to_canonical(),
+                Err(_) => break,
+            }
+        }
+        client
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn ranges_match() {
+        assert!(in_range("10.0.0.0/8", ip("10.20.30.40")));
+        assert!(!in_range("10.0.0.0/8", ip("11.0.0.1")));
+        assert!(in_range("192.168.1.7", ip("192.168.1.7")));
+        assert!(in_range("0.0.0.0/0", ip("8.8.8.8")));
+        assert!(in_range("fd00::/8", ip("fd12::1")));
+        assert!(in_range("127.0.0.1", ip("::ffff:127.0.0.1")));
+        assert!(!in_range("fd00::/8", ip("10.0.0.1")));
+        assert!(!in_range("not an address", ip("10.0.0.1")));
+        assert!(!in_range("10.0.0.0/33", ip("10.0.0.1")));
+        assert!(is_ip_range(" fd00::/128 "));
+        assert!(!is_ip_range("10.0.0.0/x"));
+    }
+
+    #[test]
+    fn forwarded_for_only_from_trusted_proxies() {
+        let mut config = RateLimitConfig::default();
+        let header = Some("1.1.1.1, 2.2.2.2, 10.0.0.5");
+        // untrusted peers are the client whatever they send
+        assert_eq!(config.client_ip(ip("10.0.0.9"), header), ip("10.0.0.9"));
+
+        config.trusted_proxies = vec!["10.0.0.0/8".into()];
+        assert_eq!(config.client_ip(ip("10.0.0.9"), header), ip("2.2.2.2"));
+        assert_eq!(config.client_ip(ip("10.0.0.9"), None), ip("10.0.0.9"));
+        assert_eq!(
+            config.client_ip(ip("10.0.0.9"), Some("garbage")),
+            ip("10.0.0.9")
+        );
+        assert_eq!(config.client_ip(ip("3.3.3.3"), header), ip("3.3.3.3"));
+    }
+}
@@ -6,7 +6,7 @@ use std::{
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use super::CookieStatus;
+use super::{AccountFlag, CookieStatus};
 use crate::config::ClewdrCookie;
 
 /// Reason why a cookie is considered useless
@@ -50,6 +50,9 @@ impl Display for Reason {
 pub struct UselessCookie {
     pub cookie: ClewdrCookie,
     pub reason: Reason,
+    /// Ban told by an upstream error, with its message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flag: Option<AccountFlag>,
 }
 
 impl PartialEq<CookieStatus> for UselessCookie {
@@ -82,6 +85,10 @@ impl UselessCookie {
     /// # Returns
     /// A new UselessCookie instance
     pub fn new(cookie: ClewdrCookie, reason: Reason) -> Self {
-        Self { cookie, reason }
+        Self {
+            cookie,
+            reason,
+            flag: None,
+        }
     }
 }
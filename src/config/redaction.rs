@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+
+/// Rules applied when a transcript is redacted for sharing
+///
+/// Roles, block types, token counts, timing, model and sampling settings are
+/// always kept, only content is replaced.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct RedactionRules {
+    /// Replace email addresses
+    pub emails: bool,
+    /// Replace names introduced in the text, like "my name is Jane Doe"
+    pub names: bool,
+    /// Names always replaced, wherever they appear
+    pub known_names: Vec<String>,
+    /// Strings that must never survive, like API keys or hostnames
+    pub secrets: Vec<String>,
+    /// Replace every user message body with a placeholder of the same shape
+    pub placeholder_user_messages: bool,
+    /// Replace tool results with a digest of their content
+    pub digest_tool_results: bool,
+}
+
+impl Default for RedactionRules {
+    fn default() -> Self {
+        Self {
+            emails: true,
+            names: true,
+            known_names: Vec::new(),
+            secrets: Vec::new(),
+            placeholder_user_messages: false,
+            digest_tool_results: true,
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+
+use crate::types::claude::CreateMessageParams;
+
+/// Cache of non-streaming responses to identical requests
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct ResponseCacheConfig {
+    pub enabled: bool,
+    /// Seconds a response is served from the cache
+    pub ttl_secs: u64,
+    pub max_entries: u64,
+    /// Also cache requests sampled with a temperature above 0, their answers vary
+    pub cache_nondeterministic: bool,
+}
+
+impl Default for ResponseCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ttl_secs: 300,
+            max_entries: 1000,
+            cache_nondeterministic: false,
+        }
+    }
+}
+
+impl ResponseCacheConfig {
+    /// Whether the response to a request may be cached
+    ///
+    /// A missing temperature samples at Claude's default of 1, so only an
+    /// explicit 0 counts as deterministic.
+    ///
+    /// # Arguments
+    /// * `params` - Request as sent upstream
+    pub fn caches(&self, params: &CreateMessageParams) -> bool {
+        self.enabled
+            && self.max_entries > 0
+            && !params.stream.unwrap_or_default()
+            && (self.cache_nondeterministic || params.temperature.is_some_and(|t| t <= 0.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caches_only_deterministic_non_streaming_requests() {
+        let config = ResponseCacheConfig {
+            enabled: true,
+            ..Default::default()
+        };
+        let mut params = CreateMessageParams {
+            temperature: Some(0.0),
+            ..Default::default()
+        };
+        assert!(config.caches(&params));
+        assert!(!ResponseCacheConfig::default().caches(&params));
+        params.stream = Some(true);
+        assert!(!config.caches(&params));
+        params.stream = None;
+        params.temperature = None;
+        assert!(!config.caches(&params));
+        let nondeterministic = ResponseCacheConfig {
+            cache_nondeterministic: true,
+            ..config
+        };
+        assert!(nondeterministic.caches(&params));
+    }
+}
@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    default_slo_fast_burn, default_slo_latency_secs, default_slo_slow_burn, default_slo_target,
+};
+
+/// Proxy endpoint a service level objective can be scoped to
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum SloEndpoint {
+    ClaudeWeb,
+    ClaudeCode,
+}
+
+/// A service level objective over proxied requests
+///
+/// A request is good when it reaches a non-error terminal state, i.e. the
+/// response body was fully delivered, within `latency_secs`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SloConfig {
+    pub name: String,
+    /// Fraction of good requests, e.g. `0.99`
+    #[serde(default = "default_slo_target")]
+    pub target: f64,
+    #[serde(default = "default_slo_latency_secs")]
+    pub latency_secs: u64,
+    /// Endpoints covered by this objective, empty covers every endpoint
+    #[serde(default)]
+    pub endpoints: Vec<SloEndpoint>,
+    /// Burn rate over the last hour that fires a fast-burn alert
+    #[serde(default = "default_slo_fast_burn")]
+    pub fast_burn: f64,
+    /// Burn rate over the last day that fires a slow-burn alert
+    #[serde(default = "default_slo_slow_burn")]
+    pub slow_burn: f64,
+}
+
+impl SloConfig {
+    /// Whether requests to `endpoint` count towards this objective
+    pub fn covers(&self, endpoint: SloEndpoint) -> bool {
+        self.endpoints.is_empty() || self.endpoints.contains(&endpoint)
+    }
+
+    /// Allowed fraction of bad requests
+    pub fn error_budget(&self) -> f64 {
+        (1.0 - self.target).max(f64::EPSILON)
+    }
+}
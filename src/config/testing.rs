@@ -0,0 +1,35 @@
+//! Config shared by tests that go through [`CLEWDR_CONFIG`]
+
+use std::sync::{Arc, Once};
+
+use super::{CLEWDR_CONFIG, ClewdrConfig};
+
+pub const TEST_PASSWORD: &str = "test-password";
+pub const TEST_ADMIN_PASSWORD: &str = "test-admin-password";
+/// Key of the `test` entry of `api_keys`
+pub const TEST_KEY: &str = "test-key";
+
+static INSTALLED: Once = Once::new();
+
+/// Installs the config of tests reading the global config, once per test binary
+///
+/// Tests must only change it through fields they own, every other test sees
+/// the same values.
+pub fn install_test_config() {
+    INSTALLED.call_once(|| {
+        let config = toml::from_str::<ClewdrConfig>(&format!(
+            r#"
+            password = "{TEST_PASSWORD}"
+            admin_password = "{TEST_ADMIN_PASSWORD}"
+            no_fs = true
+            check_update = false
+
+            [[api_keys]]
+            name = "test"
+            key = "{TEST_KEY}"
+            "#
+        ))
+        .expect("test config parses");
+        CLEWDR_CONFIG.store(Arc::new(config));
+    });
+}
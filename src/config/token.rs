@@ -6,6 +6,8 @@ use serde::{Deserialize, Serialize};
 use serde_with::{DurationSeconds, TimestampSecondsWithFrac, serde_as};
 use tracing::debug;
 
+use super::CLEWDR_CONFIG;
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 
 pub struct Organization {
@@ -43,8 +45,58 @@ impl TokenInfo {
         }
     }
 
+    /// Whether the token is within `token_expiry_skew_secs` of its expiry
     pub fn is_expired(&self) -> bool {
+        let skew = Duration::from_secs(CLEWDR_CONFIG.load().token_expiry_skew_secs);
+        self.expires_within(skew, Utc::now())
+    }
+
+    /// Whether the token expires within `skew` of `now`
+    ///
+    /// The skew is capped at half the lifetime of the token, a longer one
+    /// would make a token due for refresh as soon as it is issued.
+    ///
+    /// # Arguments
+    /// * `skew` - How long before expiry the token counts as expired
+    /// * `now` - Current time
+    pub fn expires_within(&self, skew: Duration, now: DateTime<Utc>) -> bool {
         debug!("Expires at: {}", self.expires_at.to_rfc3339());
-        Utc::now() >= self.expires_at - Duration::from_secs(60 * 5) // 5 minutes
+        now >= self.expires_at - skew.min(self.expires_in / 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(lifetime: Duration, issued: DateTime<Utc>) -> TokenInfo {
+        TokenInfo {
+            access_token: "access".to_string(),
+            expires_in: lifetime,
+            organization: Organization {
+                uuid: "org".to_string(),
+            },
+            refresh_token: "refresh".to_string(),
+            expires_at: issued + lifetime,
+        }
+    }
+
+    #[test]
+    fn skew_is_capped_at_half_the_lifetime() {
+        let issued = Utc::now();
+        let hour = token(Duration::from_secs(60 * 60), issued);
+        let skew = Duration::from_secs(5 * 60);
+        assert!(!hour.expires_within(skew, issued));
+        assert!(!hour.expires_within(skew, issued + Duration::from_secs(54 * 60)));
+        assert!(hour.expires_within(skew, issued + Duration::from_secs(55 * 60)));
+
+        // a just refreshed token is never due, however long the skew
+        let short = token(Duration::from_secs(60), issued);
+        for skew in [skew, Duration::from_secs(60), Duration::MAX] {
+            assert!(!short.expires_within(skew, issued));
+            assert!(!short.expires_within(skew, issued + Duration::from_secs(29)));
+            assert!(short.expires_within(skew, issued + Duration::from_secs(30)));
+        }
+        assert!(!short.expires_within(Duration::from_secs(20), issued + Duration::from_secs(30)));
     }
 }
@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+
+/// Quote style applied to prose outside of code spans and fences
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum QuoteStyle {
+    /// Leave quotes untouched
+    #[default]
+    Preserve,
+    /// Replace curly quotes with `"` and `'`
+    Straight,
+    /// Replace `"` and `'` with curly quotes based on the preceding character
+    Smart,
+}
+
+/// What to do with newlines at the very end of a text block
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FinalNewline {
+    /// Leave trailing newlines untouched
+    #[default]
+    Preserve,
+    /// Append a newline to non-empty blocks that do not end with one
+    Ensure,
+    /// Remove all trailing newlines
+    Strip,
+}
+
+/// Typography normalization applied to assistant text, every toggle defaults off
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TypographyConfig {
+    /// Strip spaces and tabs before line breaks and at the end of the block
+    #[serde(default)]
+    pub strip_trailing_whitespace: bool,
+    #[serde(default)]
+    pub quote_style: QuoteStyle,
+    /// Replace `...` with `…`
+    #[serde(default)]
+    pub normalize_ellipsis: bool,
+    /// Replace `--` with `—` and remove the spaces around em dashes
+    #[serde(default)]
+    pub normalize_dashes: bool,
+    #[serde(default)]
+    pub final_newline: FinalNewline,
+}
+
+impl TypographyConfig {
+    /// Whether any transformation is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.strip_trailing_whitespace
+            || self.quote_style != QuoteStyle::Preserve
+            || self.normalize_ellipsis
+            || self.normalize_dashes
+            || self.final_newline != FinalNewline::Preserve
+    }
+}
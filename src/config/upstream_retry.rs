@@ -0,0 +1,133 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::ClewdrError;
+
+/// Longest wait between two attempts, whatever `backoff_ms` grows to
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Retries of transient claude.ai failures, made before anything reached the client
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct UpstreamRetry {
+    /// Retries after the first attempt, 0 disables them
+    pub max_retries: u32,
+    /// Upstream statuses worth another attempt, connection resets always are
+    pub statuses: Vec<u16>,
+    /// Wait before the first retry, doubled for each further one and jittered
+    pub backoff_ms: u64,
+}
+
+impl Default for UpstreamRetry {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            statuses: vec![502, 503, 529],
+            backoff_ms: 1000,
+        }
+    }
+}
+
+/// Which cookie the next attempt is made with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failover {
+    /// The service as a whole is struggling, another account would not help
+    SameCookie,
+    /// The failure may be tied to the account, try the next cookie
+    NextCookie,
+}
+
+impl UpstreamRetry {
+    /// Whether an upstream error is worth another attempt, and with which cookie
+    ///
+    /// # Arguments
+    /// * `e` - Error of the failed attempt
+    ///
+    /// # Returns
+    /// * `Option<Failover>` - How to retry, `None` if the error is final
+    pub fn classify(&self, e: &ClewdrError) -> Option<Failover> {
+        match e {
+            ClewdrError::ClaudeHttpError { code, inner }
+                if self.statuses.contains(&code.as_u16()) =>
+            {
+                if code.as_u16() == 529 || inner.r#type == "overloaded_error" {
+                    Some(Failover::SameCookie)
+                } else {
+                    Some(Failover::NextCookie)
+                }
+            }
+            ClewdrError::WreqError { source, .. }
+                if source.is_connection_reset() || source.is_connect() =>
+            {
+                Some(Failover::SameCookie)
+            }
+            _ => None,
+        }
+    }
+
+    /// Wait before a retry, between half and one and a half of the nominal backoff
+    ///
+    /// # Arguments
+    /// * `retry` - Number of the retry, starting at 1
+    /// * `jitter` - Random value in `[0, 1)`
+    pub fn backoff(&self, retry: u32, jitter: f64) -> Duration {
+        let nominal = Duration::from_millis(self.backoff_ms)
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+            .min(MAX_BACKOFF);
+        nominal.mul_f64(0.5 + jitter.clamp(0.0, 1.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::StatusCode;
+    use serde_json::json;
+
+    use super::*;
+    use crate::error::ClaudeErrorBody;
+
+    fn upstream(code: u16, r#type: &str) -> ClewdrError {
+        ClewdrError::ClaudeHttpError {
+            code: StatusCode::from_u16(code).unwrap(),
+            inner: ClaudeErrorBody {
+                message: json!("failed"),
+                r#type: r#type.into(),
+                code: Some(code),
+            },
+        }
+    }
+
+    #[test]
+    fn overload_keeps_the_cookie() {
+        let policy = UpstreamRetry::default();
+        assert_eq!(
+            policy.classify(&upstream(529, "overloaded_error")),
+            Some(Failover::SameCookie)
+        );
+        assert_eq!(
+            policy.classify(&upstream(503, "overloaded_error")),
+            Some(Failover::SameCookie)
+        );
+        assert_eq!(
+            policy.classify(&upstream(502, "api_error")),
+            Some(Failover::NextCookie)
+        );
+        // not in the configured statuses
+        assert_eq!(policy.classify(&upstream(500, "api_error")), None);
+        assert_eq!(
+            policy.classify(&upstream(400, "invalid_request_error")),
+            None
+        );
+        assert_eq!(policy.classify(&ClewdrError::TooManyRetries), None);
+    }
+
+    #[test]
+    fn backoff_doubles_within_jitter() {
+        let policy = UpstreamRetry::default();
+        assert_eq!(policy.backoff(1, 0.5), Duration::from_millis(1000));
+        assert_eq!(policy.backoff(2, 0.5), Duration::from_millis(2000));
+        assert_eq!(policy.backoff(2, 0.0), Duration::from_millis(1000));
+        assert_eq!(policy.backoff(40, 0.5), MAX_BACKOFF);
+    }
+}
@@ -0,0 +1,334 @@
+use std::collections::HashSet;
+
+use serde::Serialize;
+use wreq::Proxy;
+
+use crate::{
+    config::{ClewdrConfig, is_ip_range},
+    services::language,
+};
+
+/// A config value an update is rejected for
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    /// JSON pointer to the value
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+fn is_fraction(value: f64) -> bool {
+    (0.0..=1.0).contains(&value)
+}
+
+impl ClewdrConfig {
+    /// Checks the values deserializing alone cannot, and how fields fit together
+    ///
+    /// Config files keep loading with such values repaired by
+    /// [`ClewdrConfig::validate`], updates through the API are rejected for them.
+    ///
+    /// # Returns
+    /// * `Vec<FieldError>` - Every invalid value, empty when the config is valid
+    pub fn check(&self) -> Vec<FieldError> {
+        let mut errors = vec![];
+        let mut error = |field: String, message: &str| errors.push(FieldError::new(field, message));
+
+        let mut names = HashSet::new();
+        let mut keys = HashSet::new();
+        for (i, k) in self.api_keys.iter().enumerate() {
+            if k.name.trim().is_empty() {
+                error(format!("/api_keys/{i}/name"), "must not be empty");
+            } else if !names.insert(k.name.as_str()) {
+                error(
+                    format!("/api_keys/{i}/name"),
+                    "is already used by another key",
+                );
+            }
+            if k.key.trim().is_empty() {
+                error(format!("/api_keys/{i}/key"), "must not be empty");
+            } else if self.user_auth(&k.key) {
+                error(format!("/api_keys/{i}/key"), "must differ from password");
+            } else if !keys.insert(k.key.as_str()) {
+                error(
+                    format!("/api_keys/{i}/key"),
+                    "is already used by another key",
+                );
+            }
+        }
+
+        for (i, price) in self.pricing.iter().enumerate() {
+            if let (Some(from), Some(until)) = (price.from, price.until)
+                && from >= until
+            {
+                error(format!("/pricing/{i}/until"), "must be after from");
+            } else if self.pricing[..i].iter().any(|p| p.overlaps(price)) {
+                error(
+                    format!("/pricing/{i}"),
+                    "overlaps an earlier date range of the model",
+                );
+            }
+        }
+        for (i, slo) in self.slo.iter().enumerate() {
+            if !is_fraction(slo.target) {
+                error(format!("/slo/{i}/target"), "must be between 0 and 1");
+            }
+            if slo.latency_secs == 0 {
+                error(format!("/slo/{i}/latency_secs"), "must be positive");
+            }
+        }
+        if !is_fraction(self.demo_error_rate) {
+            error("/demo_error_rate".into(), "must be between 0 and 1");
+        }
+
+        let preset = &self.prompt_preset;
+        if preset
+            .force_temperature
+            .is_some_and(|t| !is_fraction(t.into()))
+        {
+            error(
+                "/prompt_preset/force_temperature".into(),
+                "must be between 0 and 1",
+            );
+        }
+        if let Some([min, max]) = preset.temperature_range
+            && !(0.0 <= min && min <= max && max <= 1.0)
+        {
+            error(
+                "/prompt_preset/temperature_range".into(),
+                "must be a [min, max] range within 0 and 1",
+            );
+        }
+        if let Some(required) = self.language_policy.required.as_deref()
+            && !language::is_supported(required)
+        {
+            error(
+                "/language_policy/required".into(),
+                "is not a language that can be detected",
+            );
+        }
+        if let Some(proxy) = self.proxy.as_deref()
+            && Proxy::all(proxy).is_err()
+        {
+            error("/proxy".into(), "is not a valid proxy URL");
+        }
+        if self
+            .default_model
+            .as_deref()
+            .is_some_and(|m| m.trim().is_empty())
+        {
+            error("/default_model".into(), "must not be empty when set");
+        }
+        for (alias, target) in &self.model_aliases {
+            if target.trim().is_empty() {
+                let alias = alias.replace('~', "~0").replace('/', "~1");
+                error(format!("/model_aliases/{alias}"), "must name a model");
+            }
+        }
+        for (i, status) in self.upstream_retry.statuses.iter().enumerate() {
+            if !(100..=599).contains(status) {
+                error(
+                    format!("/upstream_retry/statuses/{i}"),
+                    "is not an HTTP status",
+                );
+            }
+        }
+
+        // durations that only make sense while their feature is on
+        if self.response_cache.enabled {
+            if self.response_cache.ttl_secs == 0 {
+                error("/response_cache/ttl_secs".into(), "must be positive");
+            }
+            if self.response_cache.max_entries == 0 {
+                error("/response_cache/max_entries".into(), "must be positive");
+            }
+        }
+        if self.conversation_reuse.enabled {
+            if self.conversation_reuse.ttl_secs == 0 {
+                error("/conversation_reuse/ttl_secs".into(), "must be positive");
+            }
+            if self.conversation_reuse.max_sessions == 0 {
+                error(
+                    "/conversation_reuse/max_sessions".into(),
+                    "must be positive",
+                );
+            }
+        }
+        let rate_limit = &self.rate_limit;
+        if rate_limit.block_after > 0 {
+            if rate_limit.violation_window_secs == 0 {
+                error(
+                    "/rate_limit/violation_window_secs".into(),
+                    "must be positive while block_after is set",
+                );
+            }
+            if rate_limit.block_secs == 0 {
+                error(
+                    "/rate_limit/block_secs".into(),
+                    "must be positive while block_after is set",
+                );
+            }
+        }
+        for (i, entry) in rate_limit.trusted_proxies.iter().enumerate() {
+            if !is_ip_range(entry) {
+                error(
+                    format!("/rate_limit/trusted_proxies/{i}"),
+                    "is not an IP address or CIDR range",
+                );
+            }
+        }
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ApiKey, ModelPrice, SloConfig};
+
+    fn key(name: &str, key: &str) -> ApiKey {
+        ApiKey {
+            name: name.into(),
+            key: key.into(),
+            enabled: true,
+            allowed_endpoints: vec![],
+        }
+    }
+
+    fn price(model: &str, from: &str, until: &str) -> ModelPrice {
+        toml::from_str(&format!(
+            r#"
+            model = "{model}"
+            input = 1.0
+            output = 2.0
+            from = "{from}"
+            until = "{until}"
+            "#
+        ))
+        .unwrap()
+    }
+
+    fn config() -> ClewdrConfig {
+        toml::from_str(r#"password = "client-secret""#).unwrap()
+    }
+
+    #[test]
+    fn valid_config_passes() {
+        assert_eq!(config().check(), vec![]);
+    }
+
+    #[test]
+    fn each_invariant_names_its_field() {
+        let cases: [(fn(&mut ClewdrConfig), &[&str]); 13] = [
+            (
+                |c| c.api_keys = vec![key("a", "k1"), key("a", "k2"), key(" ", "k3")],
+                &["/api_keys/1/name", "/api_keys/2/name"],
+            ),
+            (
+                |c| c.api_keys = vec![key("a", "k1"), key("b", "k1"), key("c", "client-secret")],
+                &["/api_keys/1/key", "/api_keys/2/key"],
+            ),
+            (
+                |c| {
+                    c.pricing = vec![
+                        price("claude-opus-4", "2025-01-01", "2025-06-01"),
+                        price("claude-opus-4", "2025-03-01", "2025-09-01"),
+                        price("claude-sonnet-4", "2025-06-01", "2025-01-01"),
+                    ]
+                },
+                &["/pricing/1", "/pricing/2/until"],
+            ),
+            (
+                |c| {
+                    let mut slo = toml::from_str::<SloConfig>(r#"name = "chat""#).unwrap();
+                    slo.target = 1.5;
+                    slo.latency_secs = 0;
+                    c.slo = vec![slo];
+                },
+                &["/slo/0/target", "/slo/0/latency_secs"],
+            ),
+            (|c| c.demo_error_rate = -0.1, &["/demo_error_rate"]),
+            (
+                |c| {
+                    c.prompt_preset.force_temperature = Some(2.0);
+                    c.prompt_preset.temperature_range = Some([0.8, 0.2]);
+                },
+                &[
+                    "/prompt_preset/force_temperature",
+                    "/prompt_preset/temperature_range",
+                ],
+            ),
+            (
+                |c| c.language_policy.required = Some("tlh".into()),
+                &["/language_policy/required"],
+            ),
+            (|c| c.proxy = Some("not a proxy".into()), &["/proxy"]),
+            (
+                |c| {
+                    c.default_model = Some(" ".into());
+                    c.model_aliases.insert("fast".into(), String::new());
+                },
+                &["/default_model", "/model_aliases/fast"],
+            ),
+            (
+                |c| c.upstream_retry.statuses = vec![503, 42],
+                &["/upstream_retry/statuses/1"],
+            ),
+            (
+                |c| {
+                    c.response_cache.enabled = true;
+                    c.response_cache.ttl_secs = 0;
+                    c.response_cache.max_entries = 0;
+                },
+                &["/response_cache/ttl_secs", "/response_cache/max_entries"],
+            ),
+            (
+                |c| {
+                    c.conversation_reuse.enabled = true;
+                    c.conversation_reuse.ttl_secs = 0;
+                    c.conversation_reuse.max_sessions = 0;
+                },
+                &[
+                    "/conversation_reuse/ttl_secs",
+                    "/conversation_reuse/max_sessions",
+                ],
+            ),
+            (
+                |c| {
+                    c.rate_limit.violation_window_secs = 0;
+                    c.rate_limit.block_secs = 0;
+                    c.rate_limit.trusted_proxies = vec!["10.0.0.0/8".into(), "10.0.0.0/40".into()];
+                },
+                &[
+                    "/rate_limit/violation_window_secs",
+                    "/rate_limit/block_secs",
+                    "/rate_limit/trusted_proxies/1",
+                ],
+            ),
+        ];
+        for (change, expected) in cases {
+            let mut config = config();
+            change(&mut config);
+            let fields = config
+                .check()
+                .into_iter()
+                .map(|e| e.field)
+                .collect::<Vec<_>>();
+            assert_eq!(fields, expected);
+        }
+
+        // zero durations of disabled features are left alone
+        let mut config = config();
+        config.response_cache.ttl_secs = 0;
+        config.rate_limit.block_after = 0;
+        config.rate_limit.block_secs = 0;
+        assert_eq!(config.check(), vec![]);
+    }
+}
@@ -16,7 +16,10 @@ use tokio::sync::oneshot;
 use tracing::{debug, error};
 use wreq::{Response, StatusCode, header::InvalidHeaderValue};
 
-use crate::{config::Reason, types::claude::Message};
+use crate::{
+    config::{CONFIG_VERSION, KeyEndpoint, Reason},
+    types::claude::Message,
+};
 
 #[derive(Debug, IntoStaticStr, snafu::Snafu)]
 #[snafu(visibility(pub(crate)))]
@@ -68,6 +71,9 @@ pub enum ClewdrError {
     #[snafu(display("JSON error: {}", source))]
     #[snafu(context(false))]
     JsonError { source: serde_json::Error },
+    #[snafu(display("SQLite error: {}", source))]
+    #[snafu(context(false))]
+    SqliteError { source: rusqlite::Error },
     #[snafu(transparent)]
     PathRejection { source: PathRejection },
     #[snafu(transparent)]
@@ -86,6 +92,11 @@ pub enum ClewdrError {
     InvalidHeaderValue { source: InvalidHeaderValue },
     #[snafu(display("Bad request: {}", msg))]
     BadRequest { msg: &'static str },
+    #[snafu(display("Unknown model {}, accepted models: {}", model, accepted.join(", ")))]
+    UnknownModel {
+        model: String,
+        accepted: Vec<String>,
+    },
     #[snafu(display("Retries exceeded"))]
     TooManyRetries,
     #[snafu(display("EventSource error: {}", source))]
@@ -113,6 +124,20 @@ pub enum ClewdrError {
     CookieDispatchError { source: oneshot::error::RecvError },
     #[snafu(display("No cookie available"))]
     NoCookieAvailable,
+    #[snafu(display("All cookies are busy"))]
+    CookiesBusy { capacity: usize },
+    #[snafu(display("Server is shutting down"))]
+    ShuttingDown { retry_after_secs: u64 },
+    #[snafu(display("Too many requests, retry in {}s", retry_after_secs))]
+    RateLimited {
+        retry_after_secs: u64,
+        blocked: bool,
+    },
+    #[snafu(display("No free cookie, {} requests queued", queue_depth))]
+    QueueTimeout {
+        queue_depth: usize,
+        estimated_wait_ms: u64,
+    },
     #[snafu(display("Invalid Cookie: {}", reason))]
     #[snafu(context(false))]
     InvalidCookie {
@@ -124,6 +149,12 @@ pub enum ClewdrError {
     TomlDeError { source: toml::de::Error },
     #[snafu(transparent)]
     TomlSeError { source: toml::ser::Error },
+    #[snafu(display(
+        "Config version {} is not supported, this build reads versions 0 to {}",
+        version,
+        CONFIG_VERSION
+    ))]
+    UnsupportedConfigVersion { version: String },
     #[snafu(transparent)]
     JsonRejection { source: JsonRejection },
     #[snafu(display("Rquest error: {}, source: {}", msg, source))]
@@ -143,6 +174,12 @@ pub enum ClewdrError {
         code: StatusCode,
         inner: ClaudeErrorBody,
     },
+    #[snafu(display("Upstream still failing after {} attempts", attempts))]
+    RetriesExhausted {
+        attempts: u32,
+        upstream_status: Option<StatusCode>,
+        inner: ClaudeErrorBody,
+    },
     #[snafu(display("Unexpected None: {}", msg))]
     UnexpectedNone { msg: &'static str },
     #[snafu(display("IO error: {}", source))]
@@ -158,6 +195,12 @@ pub enum ClewdrError {
     TimestampError { timestamp: i64 },
     #[snafu(display("Key/Password Invalid"))]
     InvalidAuth,
+    #[snafu(display("No key or password provided"))]
+    MissingAuth,
+    #[snafu(display("API key {} is disabled", name))]
+    KeyDisabled { name: String },
+    #[snafu(display("API key {} may not use {}", name, endpoint))]
+    KeyNotAllowed { name: String, endpoint: KeyEndpoint },
     #[snafu(whatever, display("{}: {}", message, source.as_ref().map_or_else(|| "Unknown error".into(), |e| e.to_string())))]
     Whatever {
         message: String,
@@ -166,6 +209,38 @@ pub enum ClewdrError {
     },
 }
 
+impl ClewdrError {
+    /// Reports an upstream failure along with the attempts made before giving up
+    ///
+    /// # Arguments
+    /// * `attempts` - Attempts made, including the failed last one
+    ///
+    /// # Returns
+    /// * `ClewdrError` - `RetriesExhausted` for upstream errors after a retry, `self` otherwise
+    pub fn after_attempts(self, attempts: u32) -> Self {
+        if attempts < 2 {
+            return self;
+        }
+        match self {
+            ClewdrError::ClaudeHttpError { code, inner } => ClewdrError::RetriesExhausted {
+                attempts,
+                upstream_status: Some(code),
+                inner,
+            },
+            ClewdrError::WreqError { .. } => ClewdrError::RetriesExhausted {
+                attempts,
+                upstream_status: None,
+                inner: ClaudeErrorBody {
+                    message: json!(self.to_string()),
+                    r#type: <&str>::from(&self).into(),
+                    code: Some(StatusCode::BAD_GATEWAY.as_u16()),
+                },
+            },
+            e => e,
+        }
+    }
+}
+
 impl IntoResponse for ClewdrError {
     fn into_response(self) -> axum::response::Response {
         let (status, msg) = match self {
@@ -190,6 +265,72 @@ impl IntoResponse for ClewdrError {
             ClewdrError::ClaudeHttpError { code, inner } => {
                 return (code, Json(ClaudeError { error: inner })).into_response();
             }
+            ClewdrError::RetriesExhausted {
+                attempts,
+                upstream_status,
+                ref inner,
+            } => {
+                let status = upstream_status.unwrap_or(StatusCode::BAD_GATEWAY);
+                let mut error = json!(inner);
+                error["attempts"] = json!(attempts);
+                error["upstream_status"] = json!(upstream_status.map(|s| s.as_u16()));
+                return (status, Json(json!({ "error": error }))).into_response();
+            }
+            ClewdrError::ShuttingDown { retry_after_secs } => {
+                return (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    [(http::header::RETRY_AFTER, retry_after_secs.to_string())],
+                    Json(json!({
+                        "error": {
+                            "message": self.to_string(),
+                            "type": <&str>::from(&self),
+                            "code": StatusCode::SERVICE_UNAVAILABLE.as_u16(),
+                        }
+                    })),
+                )
+                    .into_response();
+            }
+            ClewdrError::RateLimited {
+                retry_after_secs,
+                blocked,
+            } => {
+                return (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    [(http::header::RETRY_AFTER, retry_after_secs.to_string())],
+                    Json(json!({
+                        "error": {
+                            "message": self.to_string(),
+                            "type": <&str>::from(&self),
+                            "code": StatusCode::TOO_MANY_REQUESTS.as_u16(),
+                            "blocked": blocked,
+                        }
+                    })),
+                )
+                    .into_response();
+            }
+            ClewdrError::QueueTimeout {
+                queue_depth,
+                estimated_wait_ms,
+            } => {
+                let retry_after = estimated_wait_ms.div_ceil(1000).max(1);
+                return (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    [(http::header::RETRY_AFTER, retry_after.to_string())],
+                    Json(json!({
+                        "error": {
+                            "message": self.to_string(),
+                            "type": <&str>::from(&self),
+                            "code": StatusCode::TOO_MANY_REQUESTS.as_u16(),
+                            "queue_depth": queue_depth,
+                            "estimated_wait_ms": estimated_wait_ms,
+                        }
+                    })),
+                )
+                    .into_response();
+            }
+            ClewdrError::CookiesBusy { .. } => {
+                (StatusCode::TOO_MANY_REQUESTS, json!(self.to_string()))
+            }
             ClewdrError::TestMessage => {
                 return (
                     StatusCode::OK,
@@ -205,8 +346,14 @@ impl IntoResponse for ClewdrError {
             ClewdrError::TooManyRetries => (StatusCode::GATEWAY_TIMEOUT, json!(self.to_string())),
             ClewdrError::InvalidCookie { .. } => (StatusCode::BAD_REQUEST, json!(self.to_string())),
             ClewdrError::PathNotFound { .. } => (StatusCode::NOT_FOUND, json!(self.to_string())),
-            ClewdrError::InvalidAuth => (StatusCode::UNAUTHORIZED, json!(self.to_string())),
+            ClewdrError::InvalidAuth
+            | ClewdrError::MissingAuth
+            | ClewdrError::KeyDisabled { .. } => {
+                (StatusCode::UNAUTHORIZED, json!(self.to_string()))
+            }
+            ClewdrError::KeyNotAllowed { .. } => (StatusCode::FORBIDDEN, json!(self.to_string())),
             ClewdrError::BadRequest { .. } => (StatusCode::BAD_REQUEST, json!(self.to_string())),
+            ClewdrError::UnknownModel { .. } => (StatusCode::BAD_REQUEST, json!(self.to_string())),
             ClewdrError::InvalidHeaderValue { .. } => {
                 (StatusCode::BAD_REQUEST, json!(self.to_string()))
             }
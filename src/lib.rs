@@ -1,6 +1,6 @@
-use std::{path::PathBuf, sync::LazyLock};
+use std::{path::PathBuf, sync::LazyLock, time::Instant};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use colored::Colorize;
 
 use crate::config::CLEWDR_CONFIG;
@@ -20,6 +20,18 @@ pub mod utils;
 pub const IS_DEBUG: bool = cfg!(debug_assertions);
 pub static IS_DEV: LazyLock<bool> = LazyLock::new(|| std::env::var("CARGO_MANIFEST_DIR").is_ok());
 
+/// When the process started, forced first thing in `main`
+pub static STARTED_AT: LazyLock<Instant> = LazyLock::new(Instant::now);
+
+/// Commit the binary was built from, unset outside a git checkout
+pub const GIT_COMMIT: Option<&str> = option_env!("CLEWDR_GIT_COMMIT");
+/// Unix timestamp of the build
+pub const BUILD_TIMESTAMP: &str = env!("CLEWDR_BUILD_TIMESTAMP");
+/// Output of `rustc --version` for the compiler used
+pub const RUSTC_VERSION: &str = env!("CLEWDR_RUSTC_VERSION");
+/// Target triple the binary was built for
+pub const TARGET: &str = env!("CLEWDR_TARGET");
+
 pub static VERSION_INFO: LazyLock<String> = LazyLock::new(|| {
     format!(
         "v{} by {}\n| profile: {}\n| mode: {}\n| no_fs: {}",
@@ -84,4 +96,17 @@ pub struct Args {
     #[arg(short, long)]
     /// Alternative log directory
     pub log_dir: Option<PathBuf>,
+    #[arg(long)]
+    /// Serve synthetic responses and a fake cookie pool, never contact Claude
+    pub demo: bool,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Run end-to-end smoke checks against a running instance
+    Smoke(services::smoke::SmokeArgs),
+    /// Score a backend of a running instance against the conformance suite
+    Conformance(services::conformance::ConformanceArgs),
 }
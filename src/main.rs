@@ -1,19 +1,23 @@
+use clap::Parser;
 use clewdr::{
-    self, FIG, IS_DEBUG,
-    config::{CLEWDR_CONFIG, CONFIG_PATH, LOG_DIR},
+    self, Args, Command, FIG, IS_DEBUG,
+    config::{CLEWDR_CONFIG, CONFIG_PATH, LOG_DIR, LogFormat},
     error::ClewdrError,
+    services::shutdown,
+    utils::{RotatingFile, RotatingWriter, log_layer},
     version_info_colored,
 };
 use colored::Colorize;
 #[cfg(feature = "mimalloc")]
 use mimalloc::MiMalloc;
-use std::io::IsTerminal;
+use std::{
+    io::IsTerminal,
+    net::SocketAddr,
+    sync::{Arc, LazyLock, OnceLock},
+};
 use tracing::Subscriber;
 use tracing_subscriber::{
-    Layer, Registry,
-    fmt::{self, time::ChronoLocal},
-    layer::SubscriberExt,
-    registry::LookupSpan,
+    Layer, Registry, fmt::time::ChronoLocal, layer::SubscriberExt, registry::LookupSpan,
 };
 
 #[cfg(feature = "mimalloc")]
@@ -51,6 +55,7 @@ where
 /// Result indicating success or failure of the application execution
 #[tokio::main]
 async fn main() -> Result<(), ClewdrError> {
+    LazyLock::force(&clewdr::STARTED_AT);
     // Ensure a crypto provider is installed before rustls usage (yup-oauth2 / hyper-rustls).
     #[cfg(target_os = "android")]
     rustls::crypto::ring::default_provider()
@@ -72,7 +77,22 @@ async fn main() -> Result<(), ClewdrError> {
     let stdout_is_tty = std::io::stdout().is_terminal();
     colored::control::set_override(stdout_is_tty);
 
+    // subcommands run against another instance, skip config and server setup
+    let passed = match Args::parse().command {
+        Some(Command::Smoke(args)) => Some(clewdr::services::smoke::run(args).await?),
+        Some(Command::Conformance(args)) => Some(clewdr::services::conformance::run(args).await?),
+        None => None,
+    };
+    if let Some(passed) = passed {
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+
     // set up logging time format
+    let log_format = CLEWDR_CONFIG.load().log_format;
+    // keep color codes out of JSON messages
+    if log_format == LogFormat::Json {
+        colored::control::set_override(false);
+    }
     let timer = ChronoLocal::new("%H:%M:%S%.3f".to_string());
     // set up logging
     let filter = if IS_DEBUG {
@@ -83,26 +103,26 @@ async fn main() -> Result<(), ClewdrError> {
     let env_filter = tracing_subscriber::EnvFilter::builder()
         .with_default_directive(filter.into())
         .from_env_lossy();
-    let subscriber = Registry::default().with(
-        fmt::Layer::default()
-            .with_writer(std::io::stdout)
-            .with_timer(timer.to_owned())
-            .with_ansi(stdout_is_tty)
-            .with_filter(env_filter),
-    );
+    let subscriber = Registry::default()
+        .with(log_layer(log_format, std::io::stdout, timer, stdout_is_tty).with_filter(env_filter));
     let _guard = if !CLEWDR_CONFIG.load().no_fs && CLEWDR_CONFIG.load().log_to_file {
         std::fs::create_dir_all(LOG_DIR.as_path()).expect("Failed to create log directory");
-        let file_appender = tracing_appender::rolling::daily(LOG_DIR.as_path(), "clewdr.log");
-        let (file_writer, guard) = tracing_appender::non_blocking(file_appender);
+        let log_file = RotatingFile::open(
+            LOG_DIR.as_path(),
+            "clewdr",
+            CLEWDR_CONFIG.load().log_rotation.to_owned(),
+        )
+        .expect("Failed to open log file");
+        // block instead of dropping lines when the writer falls behind
+        let (file_writer, guard) = tracing_appender::non_blocking::NonBlockingBuilder::default()
+            .lossy(false)
+            .finish(RotatingWriter(log_file));
         let filter = tracing_subscriber::EnvFilter::builder()
             .with_default_directive(filter.into())
             .from_env_lossy();
+        // no ANSI colors, and full dates so downloads can select lines by time
         let subscriber = subscriber.with(
-            fmt::Layer::default()
-                .with_writer(file_writer)
-                .with_timer(timer)
-                .with_ansi(false) // disable ANSI colors for file logging
-                .with_filter(filter),
+            log_layer(log_format, file_writer, ChronoLocal::rfc_3339(), false).with_filter(filter),
         );
         setup_subscriber(subscriber);
         Some(guard)
@@ -126,25 +146,54 @@ async fn main() -> Result<(), ClewdrError> {
     println!("Config dir: {}", CONFIG_PATH.display().to_string().blue());
     println!("{}", *CLEWDR_CONFIG);
 
-    // Initialize Claude Code telemetry emulation
+    // Initialize Claude Code telemetry emulation, never in demo mode
     clewdr::claude_code_state::telemetry::init_telemetry(
-        CLEWDR_CONFIG.load().claude_code_telemetry,
+        CLEWDR_CONFIG.load().claude_code_telemetry && !CLEWDR_CONFIG.load().demo,
     );
 
-    // build axum router
-    // create a TCP listener
+    clewdr::services::resources::init_resource_sampling();
+
+    // serve right away, probes and /api/startup answer while the subsystems start
     let addr = CLEWDR_CONFIG.load().address();
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    let router = clewdr::router::RouterBuilder::new()
-        .await
-        .with_default_setup()
-        .build();
-    // serve the application
-    Ok(axum::serve(listener, router)
-        .with_graceful_shutdown(async {
-            tokio::signal::ctrl_c()
-                .await
-                .expect("Failed to install Ctrl-C handler");
-        })
-        .await?)
+    let app = Arc::new(OnceLock::new());
+    // serve the application until a shutdown signal, then let running requests drain
+    // the peer address is what the rate limiter counts requests by
+    let serve = axum::serve(
+        listener,
+        clewdr::router::serve_when_ready(app.to_owned())
+            .into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown::signal())
+    .into_future();
+    tokio::pin!(serve);
+
+    let startup = &clewdr::services::startup::STARTUP;
+    let start = async {
+        let (_, router) = tokio::join!(
+            // restore SLO state and start periodic snapshots
+            startup.track("slo", clewdr::services::slo::init_slo()),
+            // load the cookie pool and build axum router
+            startup.track("router", async {
+                let builder = clewdr::router::RouterBuilder::new()
+                    .await?
+                    .with_default_setup();
+                Ok::<_, ClewdrError>((builder.cookie_actor_handle(), builder.build()))
+            }),
+        );
+        // a failed SLO restore shows in /readyz, without a router nothing can be served
+        let (cookies, router) = router?;
+        _ = app.set(router);
+        Ok::<_, ClewdrError>(cookies)
+    };
+    let cookies = tokio::select! {
+        result = &mut serve => return Ok(result?),
+        cookies = start => cookies?,
+    };
+    tokio::select! {
+        result = serve => result?,
+        _ = shutdown::drain_deadline() => {}
+    }
+    shutdown::flush(&cookies).await;
+    Ok(())
 }
@@ -2,7 +2,34 @@ use axum::extract::FromRequestParts;
 use axum_auth::AuthBearer;
 use tracing::warn;
 
-use crate::{config::CLEWDR_CONFIG, error::ClewdrError};
+use crate::{
+    config::{ADMIN_HEADER, CLEWDR_CONFIG, ClientAuth, KeyEndpoint},
+    error::ClewdrError,
+    services::key_usage,
+};
+
+/// Checks a proxy key for the endpoint of the request and remembers who sent it
+///
+/// The [`ClientAuth`] is left in the request extensions for later layers. It
+/// is [`ClientAuth::Admin`] when [`ADMIN_HEADER`] carries the admin password,
+/// which is not a proxy key by itself.
+fn admit(parts: &mut axum::http::request::Parts, key: &str) -> Result<(), ClewdrError> {
+    let endpoint = KeyEndpoint::of_path(parts.uri.path());
+    let config = CLEWDR_CONFIG.load();
+    let mut auth = config.client_auth(key, endpoint)?;
+    if let ClientAuth::Key(name) = &auth {
+        key_usage::record(name);
+    }
+    if let Some(admin) = parts.headers.get(ADMIN_HEADER) {
+        if admin.to_str().is_ok_and(|admin| config.admin_auth(admin)) {
+            auth = ClientAuth::Admin;
+        } else {
+            warn!("Invalid admin key in {}", ADMIN_HEADER);
+        }
+    }
+    parts.extensions.insert(auth);
+    Ok(())
+}
 
 /// Middleware guard that ensures requests have valid admin authentication
 ///
@@ -69,11 +96,8 @@ where
     ) -> Result<Self, Self::Rejection> {
         let AuthBearer(key) = AuthBearer::from_request_parts(parts, &())
             .await
-            .map_err(|_| ClewdrError::InvalidAuth)?;
-        if !CLEWDR_CONFIG.load().user_auth(&key) {
-            warn!("Invalid Bearer key: {}", key);
-            return Err(ClewdrError::InvalidAuth);
-        }
+            .map_err(|_| ClewdrError::MissingAuth)?;
+        admit(parts, &key).inspect_err(|e| warn!("Rejected Bearer key: {}", e))?;
         Ok(Self)
     }
 }
@@ -93,21 +117,28 @@ where
         parts: &mut axum::http::request::Parts,
         _: &S,
     ) -> Result<Self, Self::Rejection> {
-        // Try X-API-Key first
-        if let Some(key) = parts.headers.get("x-api-key").and_then(|v| v.to_str().ok())
-            && CLEWDR_CONFIG.load().user_auth(key)
-        {
-            return Ok(Self);
-        }
-
-        // Fall back to Bearer token
-        if let Ok(AuthBearer(key)) = AuthBearer::from_request_parts(parts, &()).await
-            && CLEWDR_CONFIG.load().user_auth(&key)
-        {
-            return Ok(Self);
+        // Try X-API-Key first, then fall back to Bearer token
+        let api_key = parts
+            .headers
+            .get("x-api-key")
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let bearer = AuthBearer::from_request_parts(parts, &())
+            .await
+            .ok()
+            .map(|AuthBearer(key)| key);
+        let mut rejection = None;
+        for key in api_key.into_iter().chain(bearer) {
+            match admit(parts, &key) {
+                Ok(()) => return Ok(Self),
+                // report the first key that was sent
+                Err(e) => {
+                    rejection.get_or_insert(e);
+                }
+            }
         }
 
         warn!("No valid authentication found (tried x-api-key and Bearer)");
-        Err(ClewdrError::InvalidAuth)
+        Err(rejection.unwrap_or(ClewdrError::MissingAuth))
     }
 }
@@ -1,15 +1,29 @@
 mod claude2oai;
+mod passthrough;
+mod preset;
+mod report;
 mod request;
 mod response;
 mod stop_sequences;
+mod thinking;
+mod typography;
 
 pub(crate) use claude2oai::*;
+use http::HeaderMap;
+pub use passthrough::*;
+pub use preset::*;
+pub use report::*;
 pub use request::*;
 pub use response::*;
 pub use stop_sequences::*;
 use strum::Display;
+pub use thinking::*;
+pub use typography::*;
 
-use crate::types::claude::Usage;
+use crate::{
+    services::{response_cache::CacheStatus, retry::RetryInfo},
+    types::claude::Usage,
+};
 
 /// Represents the format of the API response
 ///
@@ -74,10 +88,95 @@ impl ClaudeContext {
         }
     }
 
+    pub fn is_smoke(&self) -> bool {
+        match self {
+            ClaudeContext::Web(ctx) => ctx.smoke,
+            ClaudeContext::Code(ctx) => ctx.smoke,
+        }
+    }
+
+    pub fn report(&self) -> Option<&RequestReport> {
+        match self {
+            ClaudeContext::Web(ctx) => ctx.report.as_ref(),
+            ClaudeContext::Code(ctx) => ctx.report.as_ref(),
+        }
+    }
+
     pub fn anthropic_beta(&self) -> Option<&str> {
         match self {
             ClaudeContext::Web(_) => None,
             ClaudeContext::Code(ctx) => ctx.anthropic_beta.as_deref(),
         }
     }
+
+    pub fn is_admin(&self) -> bool {
+        match self {
+            ClaudeContext::Web(ctx) => ctx.admin,
+            ClaudeContext::Code(ctx) => ctx.admin,
+        }
+    }
+
+    pub fn upstream_headers(&self) -> Option<&HeaderMap> {
+        match self {
+            ClaudeContext::Web(ctx) => ctx.upstream_headers.as_ref(),
+            ClaudeContext::Code(ctx) => ctx.upstream_headers.as_ref(),
+        }
+    }
+
+    pub fn set_upstream_headers(&mut self, headers: Option<HeaderMap>) {
+        match self {
+            ClaudeContext::Web(ctx) => ctx.upstream_headers = headers,
+            ClaudeContext::Code(ctx) => ctx.upstream_headers = headers,
+        }
+    }
+
+    pub fn account(&self) -> Option<&str> {
+        match self {
+            ClaudeContext::Web(ctx) => ctx.account.as_deref(),
+            ClaudeContext::Code(ctx) => ctx.account.as_deref(),
+        }
+    }
+
+    pub fn set_account(&mut self, account: Option<String>) {
+        match self {
+            ClaudeContext::Web(ctx) => ctx.account = account,
+            ClaudeContext::Code(ctx) => ctx.account = account,
+        }
+    }
+
+    pub fn cache(&self) -> Option<CacheStatus> {
+        match self {
+            ClaudeContext::Web(ctx) => ctx.cache,
+            ClaudeContext::Code(ctx) => ctx.cache,
+        }
+    }
+
+    pub fn set_cache(&mut self, cache: CacheStatus) {
+        match self {
+            ClaudeContext::Web(ctx) => ctx.cache = Some(cache),
+            ClaudeContext::Code(ctx) => ctx.cache = Some(cache),
+        }
+    }
+
+    /// Session whose claude.ai conversation is reused, web requests only
+    pub fn session(&self) -> Option<&str> {
+        match self {
+            ClaudeContext::Web(ctx) => ctx.session.as_deref(),
+            ClaudeContext::Code(_) => None,
+        }
+    }
+
+    pub fn transformed(&self) -> &[&'static str] {
+        match self {
+            ClaudeContext::Web(ctx) => &ctx.transformed,
+            ClaudeContext::Code(ctx) => &ctx.transformed,
+        }
+    }
+
+    pub fn retry(&self) -> RetryInfo {
+        match self {
+            ClaudeContext::Web(ctx) => ctx.retry,
+            ClaudeContext::Code(ctx) => ctx.retry,
+        }
+    }
 }
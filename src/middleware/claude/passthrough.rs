@@ -0,0 +1,262 @@
+//! Forwarding of upstream response headers
+//!
+//! Backends keep the upstream headers matching their `header_passthrough`
+//! list as [`UpstreamHeaders`] on the response. Providers move them into the
+//! [`ClaudeContext`], which survives the response transforms, and the
+//! outermost layer applies role gating and renames collisions.
+
+use std::collections::HashSet;
+
+use axum::response::Response;
+use http::{HeaderMap, HeaderName, HeaderValue};
+
+use crate::{
+    config::{
+        ACCOUNT_HEADER, CACHE_HEADER, CLEWDR_CONFIG, CookieStatus, HeaderPassthrough,
+        UPSTREAM_HEADER_PREFIX, header_matches,
+    },
+    middleware::claude::ClaudeContext,
+    services::{cookie_actor::CookieStatusInfo, transcript::hash_cookie},
+};
+
+/// Headers describing the body or the connection, never taken from upstream
+const RESERVED: [&str; 5] = [
+    "connection",
+    "content-encoding",
+    "content-length",
+    "content-type",
+    "transfer-encoding",
+];
+
+/// Upstream headers a backend selected for forwarding
+#[derive(Debug, Clone, Default)]
+pub struct UpstreamHeaders(pub HeaderMap);
+
+impl UpstreamHeaders {
+    /// Keeps the headers matching `patterns`
+    pub fn select(headers: &HeaderMap, patterns: &[String]) -> Self {
+        let mut selected = HeaderMap::new();
+        for (name, value) in headers {
+            if header_matches(patterns, name.as_str()) {
+                selected.append(name, value.to_owned());
+            }
+        }
+        Self(selected)
+    }
+
+    /// Attaches the headers to a backend response
+    pub fn attach(self, mut resp: Response) -> Response {
+        if !self.0.is_empty() {
+            resp.extensions_mut().insert(self);
+        }
+        resp
+    }
+}
+
+/// Hash of the cookie that served a backend response
+#[derive(Debug, Clone)]
+pub struct ServedBy(pub String);
+
+/// Names the cookie that served a response, when `debug_account_header` is on
+pub fn mark_served_by(mut resp: Response, cookie: Option<&CookieStatus>) -> Response {
+    if CLEWDR_CONFIG.load().debug_account_header
+        && let Some(cookie) = cookie
+    {
+        resp.extensions_mut().insert(ServedBy(hash_cookie(cookie)));
+    }
+    resp
+}
+
+/// Copies upstream headers onto a response
+///
+/// # Arguments
+/// * `upstream` - Headers selected by the backend
+/// * `target` - Headers of the response sent to the client
+/// * `admin` - Whether the request used the admin password
+/// * `config` - Passthrough policy, `user` narrows what non-admin requests get
+pub fn apply_passthrough(
+    upstream: &HeaderMap,
+    target: &mut HeaderMap,
+    admin: bool,
+    config: &HeaderPassthrough,
+) {
+    let own = target.keys().cloned().collect::<HashSet<_>>();
+    for (name, value) in upstream {
+        if !admin
+            && let Some(user) = config.user.as_deref()
+            && !header_matches(user, name.as_str())
+        {
+            continue;
+        }
+        let collides = own.contains(name)
+            || RESERVED.contains(&name.as_str())
+            || name.as_str().starts_with("x-clewdr-");
+        if !collides {
+            target.append(name, value.to_owned());
+        } else if let Ok(renamed) =
+            HeaderName::try_from(format!("{UPSTREAM_HEADER_PREFIX}{}", name.as_str()))
+        {
+            target.append(renamed, value.to_owned());
+        }
+    }
+}
+
+/// Rate-limit headers derived from the cookie pool, for backends without real ones
+///
+/// # Arguments
+/// * `status` - Current cookie pool
+/// * `limit` - Requests one cookie serves at once, 0 means unlimited
+pub fn synthesize_rate_limits(status: &CookieStatusInfo, limit: usize) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let allowed = if status.valid.is_empty() {
+        "rejected"
+    } else {
+        "allowed"
+    };
+    headers.insert(
+        "anthropic-ratelimit-unified-status",
+        HeaderValue::from_static(allowed),
+    );
+    if limit > 0 {
+        let capacity = status.valid.len() * limit;
+        let busy = status
+            .valid
+            .iter()
+            .map(|c| status.in_flight.get(&c.cookie).copied().unwrap_or_default())
+            .map(|n| n.min(limit))
+            .sum::<usize>();
+        headers.insert("anthropic-ratelimit-requests-limit", capacity.into());
+        headers.insert(
+            "anthropic-ratelimit-requests-remaining",
+            (capacity - busy).into(),
+        );
+    }
+    // the pool grows again when the first cooling down cookie resets
+    if let Some(reset) = status.exhausted.iter().filter_map(|c| c.reset_time).min() {
+        headers.insert("anthropic-ratelimit-unified-reset", reset.into());
+    }
+    headers
+}
+
+/// Forwards the upstream headers selected by the backend, and the serving cookie
+///
+/// Must wrap every layer that rebuilds the response, otherwise the forwarded
+/// headers are dropped again.
+pub async fn forward_upstream_headers(mut resp: Response) -> Response {
+    let Some(cx) = resp.extensions().get::<ClaudeContext>() else {
+        return resp;
+    };
+    let account = cx.account().and_then(|a| HeaderValue::from_str(a).ok());
+    let upstream = cx.upstream_headers().cloned();
+    let admin = cx.is_admin();
+    let cache = cx.cache();
+    if let Some(account) = account {
+        resp.headers_mut().insert(ACCOUNT_HEADER, account);
+    }
+    if let Some(cache) = cache {
+        resp.headers_mut()
+            .insert(CACHE_HEADER, HeaderValue::from_static(cache.into()));
+    }
+    if let Some(upstream) = upstream {
+        apply_passthrough(
+            &upstream,
+            resp.headers_mut(),
+            admin,
+            &CLEWDR_CONFIG.load().header_passthrough,
+        );
+    }
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::config::CookieStatus;
+
+    fn upstream() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("anthropic-ratelimit-tokens-remaining", 1200.into());
+        headers.insert("request-id", HeaderValue::from_static("req_1"));
+        headers.insert("content-type", HeaderValue::from_static("text/plain"));
+        headers.insert("set-cookie", HeaderValue::from_static("secret"));
+        headers
+    }
+
+    fn cookie(i: usize, reset_time: Option<i64>) -> CookieStatus {
+        let body = format!("{:0<86}", format!("headers-test-{i:02}-"));
+        let raw = format!("sk-ant-sid01-{body}-{i:06}AA");
+        let mut cookie = CookieStatus::new(&raw, None).unwrap();
+        cookie.reset_time = reset_time;
+        cookie
+    }
+
+    #[test]
+    fn forwards_whitelist_and_renames_collisions() {
+        let config = HeaderPassthrough {
+            claude_code: vec![
+                "anthropic-ratelimit-*".into(),
+                "request-id".into(),
+                "content-type".into(),
+            ],
+            ..Default::default()
+        };
+        let selected = UpstreamHeaders::select(&upstream(), &config.claude_code).0;
+        assert!(!selected.contains_key("set-cookie"));
+
+        let mut target = HeaderMap::new();
+        target.insert("content-type", HeaderValue::from_static("application/json"));
+        target.insert("request-id", HeaderValue::from_static("clewdr"));
+        apply_passthrough(&selected, &mut target, false, &config);
+        assert_eq!(target["anthropic-ratelimit-tokens-remaining"], "1200");
+        assert_eq!(target["content-type"], "application/json");
+        assert_eq!(target["x-upstream-content-type"], "text/plain");
+        assert_eq!(target["request-id"], "clewdr");
+        assert_eq!(target["x-upstream-request-id"], "req_1");
+    }
+
+    #[test]
+    fn users_get_the_reduced_set() {
+        let config = HeaderPassthrough {
+            user: Some(vec!["request-id".into()]),
+            ..Default::default()
+        };
+        let selected = UpstreamHeaders::select(&upstream(), &config.claude_code).0;
+
+        let mut user = HeaderMap::new();
+        apply_passthrough(&selected, &mut user, false, &config);
+        assert_eq!(user.len(), 1);
+        assert_eq!(user["request-id"], "req_1");
+
+        let mut admin = HeaderMap::new();
+        apply_passthrough(&selected, &mut admin, true, &config);
+        assert_eq!(admin.len(), 2);
+    }
+
+    #[test]
+    fn synthesizes_from_pool_state() {
+        let busy = cookie(1, None);
+        let status = CookieStatusInfo {
+            valid: vec![busy.clone(), cookie(2, None)],
+            exhausted: vec![cookie(3, Some(2_000)), cookie(4, Some(1_000))],
+            invalid: Vec::new(),
+            in_flight: HashMap::from([(busy.cookie, 1)]),
+        };
+        let headers = synthesize_rate_limits(&status, 2);
+        assert_eq!(headers["anthropic-ratelimit-unified-status"], "allowed");
+        assert_eq!(headers["anthropic-ratelimit-requests-limit"], "4");
+        assert_eq!(headers["anthropic-ratelimit-requests-remaining"], "3");
+        assert_eq!(headers["anthropic-ratelimit-unified-reset"], "1000");
+
+        let empty = CookieStatusInfo {
+            valid: Vec::new(),
+            exhausted: Vec::new(),
+            invalid: Vec::new(),
+            in_flight: HashMap::new(),
+        };
+        let headers = synthesize_rate_limits(&empty, 0);
+        assert_eq!(headers["anthropic-ratelimit-unified-status"], "rejected");
+        assert!(!headers.contains_key("anthropic-ratelimit-requests-remaining"));
+    }
+}
@@ -0,0 +1,205 @@
+//! Prompt preset applied to request bodies before they are sent upstream
+//!
+//! What a preset changed is kept in the [`ClaudeContext`] and reported in the
+//! `x-clewdr-transformed` response header by the outermost layer.
+
+use axum::response::Response;
+use http::HeaderValue;
+use serde_json::{Value, json};
+
+use crate::{
+    config::{PromptPreset, TRANSFORMED_HEADER},
+    middleware::claude::ClaudeContext,
+    types::claude::{ContentBlock, CreateMessageParams},
+};
+
+fn non_blank(text: Option<&String>) -> Option<&str> {
+    text.map(String::as_str).filter(|t| !t.trim().is_empty())
+}
+
+/// Wraps the system prompt in the preset prefix and suffix
+///
+/// A string prompt is concatenated, an array of blocks gets a text block
+/// inserted at either end so cache control on the client's blocks stays put.
+fn wrap_system(body: &mut CreateMessageParams, prefix: Option<&str>, suffix: Option<&str>) -> bool {
+    if prefix.is_none() && suffix.is_none() {
+        return false;
+    }
+    match body.system.take() {
+        Some(Value::Array(mut blocks)) => {
+            if let Some(prefix) = prefix {
+                blocks.insert(0, json!(ContentBlock::text(prefix)));
+            }
+            if let Some(suffix) = suffix {
+                blocks.push(json!(ContentBlock::text(suffix)));
+            }
+            body.system = Some(Value::Array(blocks));
+        }
+        Some(Value::String(text)) => {
+            let parts = [prefix, Some(text.as_str()), suffix];
+            let joined = parts
+                .into_iter()
+                .flatten()
+                .filter(|t| !t.trim().is_empty())
+                .collect::<Vec<_>>()
+                .join("\n\n");
+            body.system = Some(Value::String(joined));
+        }
+        Some(Value::Null) | None => {
+            let joined = [prefix, suffix]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+                .join("\n\n");
+            body.system = Some(Value::String(joined));
+        }
+        // a shape upstream would reject anyway, leave it to fail there
+        Some(other) => {
+            body.system = Some(other);
+            return false;
+        }
+    }
+    true
+}
+
+/// Applies a prompt preset to a request body
+///
+/// # Arguments
+/// * `body` - Parsed request body
+/// * `preset` - Configured prompt preset
+///
+/// # Returns
+/// * `Vec<&'static str>` - Parts of the request that changed: `system`, `stop`, `temperature`
+pub fn apply_preset(body: &mut CreateMessageParams, preset: &PromptPreset) -> Vec<&'static str> {
+    let mut changed = vec![];
+    let prefix = non_blank(preset.system_prefix.as_ref());
+    let suffix = non_blank(preset.system_suffix.as_ref());
+    if wrap_system(body, prefix, suffix) {
+        changed.push("system");
+    }
+
+    let extra = preset
+        .extra_stop_sequences
+        .iter()
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>();
+    if !extra.is_empty() {
+        let stops = body.stop_sequences.get_or_insert_default();
+        let before = stops.len();
+        for stop in extra {
+            if !stops.contains(stop) {
+                stops.push(stop.to_owned());
+            }
+        }
+        if stops.len() > before {
+            changed.push("stop");
+        }
+    }
+
+    let original = body.temperature;
+    if let Some(forced) = preset.force_temperature {
+        body.temperature = Some(forced);
+    }
+    if let Some([min, max]) = preset.temperature_range {
+        body.temperature = body.temperature.map(|t| t.clamp(min, max));
+    }
+    if body.temperature != original {
+        changed.push("temperature");
+    }
+    changed
+}
+
+/// Reports the parts of the request changed by the prompt preset
+pub async fn mark_transformed(mut resp: Response) -> Response {
+    let Some(cx) = resp.extensions().get::<ClaudeContext>() else {
+        return resp;
+    };
+    let transformed = cx.transformed();
+    if transformed.is_empty() {
+        return resp;
+    }
+    if let Ok(value) = HeaderValue::from_str(&transformed.join(",")) {
+        resp.headers_mut().insert(TRANSFORMED_HEADER, value);
+    }
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::claude::{Message, Role};
+
+    fn body(system: Option<Value>) -> CreateMessageParams {
+        CreateMessageParams {
+            messages: vec![Message::new_text(Role::User, "hey")],
+            model: "claude-sonnet-4-5".to_string(),
+            system,
+            ..Default::default()
+        }
+    }
+
+    fn preset() -> PromptPreset {
+        PromptPreset {
+            system_prefix: Some("Be brief.".into()),
+            system_suffix: Some("Stay safe.".into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn wraps_string_and_block_systems() {
+        let mut string = body(Some(json!("You are a cat.")));
+        assert_eq!(apply_preset(&mut string, &preset()), ["system"]);
+        assert_eq!(
+            string.system.unwrap(),
+            "Be brief.\n\nYou are a cat.\n\nStay safe."
+        );
+
+        let cached = json!({
+            "type": "text",
+            "text": "You are a cat.",
+            "cache_control": {"type": "ephemeral"},
+        });
+        let mut blocks = body(Some(json!([cached])));
+        apply_preset(&mut blocks, &preset());
+        let system = blocks.system.unwrap();
+        let system = system.as_array().unwrap();
+        assert_eq!(system.len(), 3);
+        assert_eq!(system[0]["text"], "Be brief.");
+        assert_eq!(system[1], cached);
+        assert_eq!(system[2]["text"], "Stay safe.");
+
+        let mut empty = body(None);
+        apply_preset(&mut empty, &preset());
+        assert_eq!(empty.system.unwrap(), "Be brief.\n\nStay safe.");
+    }
+
+    #[test]
+    fn adds_stops_and_clamps_temperature() {
+        let preset = PromptPreset {
+            extra_stop_sequences: vec!["\n\nHuman:".into(), "END".into()],
+            temperature_range: Some([0.2, 0.8]),
+            ..Default::default()
+        };
+        let mut request = body(None);
+        request.stop_sequences = Some(vec!["END".into()]);
+        request.temperature = Some(1.0);
+        assert_eq!(apply_preset(&mut request, &preset), ["stop", "temperature"]);
+        assert_eq!(request.stop_sequences.unwrap(), ["END", "\n\nHuman:"]);
+        assert_eq!(request.temperature, Some(0.8));
+        assert!(request.system.is_none());
+
+        // nothing to change, nothing reported
+        let mut request = body(None);
+        request.stop_sequences = Some(vec!["END".into(), "\n\nHuman:".into()]);
+        assert!(apply_preset(&mut request, &preset).is_empty());
+
+        let forced = PromptPreset {
+            force_temperature: Some(0.5),
+            ..Default::default()
+        };
+        let mut request = body(None);
+        assert_eq!(apply_preset(&mut request, &forced), ["temperature"]);
+        assert_eq!(request.temperature, Some(0.5));
+    }
+}
@@ -0,0 +1,543 @@
+use std::{
+    sync::{Arc, LazyLock},
+    time::Duration,
+};
+
+use async_stream::try_stream;
+use axum::{
+    Json,
+    response::{IntoResponse, Response, sse::Event},
+};
+use eventsource_stream::{Event as SourceEvent, Eventsource};
+use futures::Stream;
+use http::{HeaderMap, HeaderValue, header::CONTENT_TYPE};
+use moka::sync::Cache;
+use serde::Serialize;
+use serde_json::Value;
+
+use super::response::parse_response;
+use crate::{
+    config::{
+        BilledTokens, CLEWDR_CONFIG, ClewdrConfig, ClientAuth, REPORT_HEADER, REPORT_ID_HEADER,
+        ReportAccess,
+    },
+    middleware::claude::{ClaudeApiFormat, ClaudeContext},
+    services::resources::{FnReporter, ResourceUsage, register_reporter},
+    types::claude::CreateMessageParams,
+    utils::sse_response,
+};
+
+type EventResult<T> = Result<T, eventsource_stream::EventStreamError<axum::Error>>;
+
+/// Content type of non-stream responses carrying an inline report
+const INLINE_REPORT_CONTENT_TYPE: &str = "application/json; profile=clewdr-report";
+
+/// Reports delivered by header, swept an hour after they were written
+static REPORTS: LazyLock<Cache<String, RequestReport>> = LazyLock::new(|| {
+    register_reporter(FnReporter::new("request_reports", || {
+        let entries = REPORTS.entry_count();
+        ResourceUsage {
+            // shallow estimate, strings are small and bounded
+            bytes: Some(entries * size_of::<RequestReport>() as u64),
+            entries,
+        }
+    }));
+    Cache::builder()
+        .max_capacity(10_000)
+        .time_to_live(Duration::from_secs(60 * 60))
+        .build()
+});
+
+/// How the client wants to receive the report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportDelivery {
+    /// Extra `clewdr_report` field or trailing `clewdr_report` SSE event
+    Inline,
+    /// Untouched body, report id in `x-clewdr-report-id` for `GET /api/reports/{id}`
+    Header,
+}
+
+impl ReportDelivery {
+    /// Reads `x-clewdr-report`, returns `None` when absent or not permitted
+    ///
+    /// # Arguments
+    /// * `headers` - Headers of the request
+    /// * `auth` - Who the auth layer admitted the request as
+    /// * `config` - Config deciding who may ask
+    pub fn requested(
+        headers: &HeaderMap,
+        auth: Option<&ClientAuth>,
+        config: &ClewdrConfig,
+    ) -> Option<Self> {
+        let delivery = match headers.get(REPORT_HEADER)?.to_str().ok()?.trim() {
+            "1" | "inline" => Self::Inline,
+            "header" => Self::Header,
+            _ => return None,
+        };
+        let permitted = match config.request_reports {
+            ReportAccess::Off => false,
+            ReportAccess::All => true,
+            ReportAccess::Admin => auth.is_some_and(ClientAuth::is_admin),
+        };
+        permitted.then_some(delivery)
+    }
+}
+
+/// What clewdr did with a request, for client developers
+#[derive(Debug, Serialize, Clone)]
+pub struct RequestReport {
+    pub request_id: String,
+    #[serde(skip)]
+    delivery: ReportDelivery,
+    /// Backend that served the request, `claude_web` or `claude_code`
+    pub backend: &'static str,
+    pub api_format: String,
+    /// Model sent upstream, after normalization
+    pub model: String,
+    pub stream: bool,
+    /// Preprocessing rules that rewrote the request
+    pub preprocessing: Vec<&'static str>,
+    /// Response post-processing applied on the way back
+    pub post_processing: Vec<&'static str>,
+    /// Whether the system prompt carries cache control blocks
+    pub prompt_cache: bool,
+    pub input_tokens_estimate: u32,
+    pub output_tokens: Option<u32>,
+    /// Estimated cost in USD from the reported usage, see `pricing`
+    pub estimated_cost_usd: Option<f64>,
+    #[serde(skip)]
+    billed: Option<BilledTokens>,
+    /// Identity of the client that made the request, the only one besides the admin to fetch it
+    #[serde(skip)]
+    owner: String,
+}
+
+impl RequestReport {
+    pub(super) fn new(
+        delivery: ReportDelivery,
+        backend: &'static str,
+        api_format: ClaudeApiFormat,
+        body: &CreateMessageParams,
+        preprocessing: Vec<&'static str>,
+        input_tokens: u32,
+        owner: Option<&ClientAuth>,
+    ) -> Self {
+        Self {
+            request_id: uuid::Uuid::new_v4().simple().to_string(),
+            delivery,
+            backend,
+            api_format: api_format.to_string(),
+            model: body.model.to_owned(),
+            stream: body.stream.unwrap_or_default(),
+            preprocessing,
+            post_processing: vec![],
+            prompt_cache: false,
+            input_tokens_estimate: input_tokens,
+            output_tokens: None,
+            estimated_cost_usd: None,
+            billed: None,
+            owner: owner.map(ClientAuth::identity).unwrap_or_default(),
+        }
+    }
+
+    pub(super) fn with_prompt_cache(mut self, prompt_cache: bool) -> Self {
+        self.prompt_cache = prompt_cache;
+        self
+    }
+
+    /// Records the last usage seen in a response body or stream event
+    fn note_usage(&mut self, value: &Value) {
+        let usage = &value["usage"];
+        let usage = if usage.is_object() {
+            usage
+        } else {
+            // Claude `message_start` nests usage in the message
+            &value["message"]["usage"]
+        };
+        if !usage.is_object() {
+            return;
+        }
+        let billed = self.billed.get_or_insert_default();
+        let field = |name: &str| usage[name].as_u64();
+        if let Some(tokens) = field("input_tokens").or_else(|| field("prompt_tokens")) {
+            billed.input = tokens;
+        }
+        if let Some(tokens) = field("cache_read_input_tokens") {
+            billed.cache_read = tokens;
+        }
+        if let Some(tokens) = field("cache_creation_input_tokens") {
+            billed.cache_write = tokens;
+        }
+        if let Some(tokens) = field("output_tokens").or_else(|| field("completion_tokens")) {
+            billed.output = tokens;
+            self.output_tokens = Some(tokens as u32);
+        }
+    }
+
+    /// Prices the usage seen so far
+    fn price(&mut self, config: &ClewdrConfig) {
+        let web = self.backend == "claude_web";
+        let now = chrono::Utc::now().timestamp();
+        self.estimated_cost_usd = self
+            .billed
+            .map(|tokens| config.estimate_cost(&self.model, tokens, web, now));
+    }
+}
+
+/// Looks up a report delivered by header
+///
+/// # Arguments
+/// * `id` - Report id
+/// * `caller` - Client asking, only the client that made the request and the admin get the report
+pub fn get_report(id: &str, caller: &ClientAuth) -> Option<RequestReport> {
+    REPORTS
+        .get(id)
+        .filter(|report| caller.is_admin() || report.owner == caller.identity())
+}
+
+/// Attaches the request report to the response when the client asked for it
+///
+/// Must be the outermost Claude response layer, so the report sees the final
+/// body and inline fields are not dropped by later transforms.
+pub async fn attach_report(resp: Response) -> Response {
+    let Some(cx) = resp.extensions().get::<ClaudeContext>() else {
+        return resp;
+    };
+    let Some(mut report) = cx.report().cloned() else {
+        return resp;
+    };
+    let config = CLEWDR_CONFIG.load();
+    if !cx.stop_sequences().is_empty() {
+        report.post_processing.push("stop_sequences");
+    }
+    if config.typography.is_enabled() {
+        report.post_processing.push("typography");
+    }
+    if cx.api_format() == ClaudeApiFormat::OpenAI {
+        report.post_processing.push("openai_transform");
+    }
+    deliver(resp, report, Arc::clone(&config)).await
+}
+
+async fn deliver(resp: Response, mut report: RequestReport, config: Arc<ClewdrConfig>) -> Response {
+    let id = HeaderValue::from_str(&report.request_id).expect("uuid is a valid header value");
+    // outer layers still read the request context
+    let extensions = resp.extensions().clone();
+    let is_event_stream = resp
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("text/event-stream"));
+    let mut resp = if is_event_stream {
+        let stream = resp.into_body().into_data_stream().eventsource();
+        sse_response(report_stream(report, stream, config))
+    } else {
+        let status = resp.status();
+        let mut value = match parse_response::<Value>(resp).await {
+            Ok(value) => value,
+            Err(resp) => return resp,
+        };
+        report.note_usage(&value);
+        report.price(&config);
+        let mut resp = match report.delivery {
+            ReportDelivery::Inline => {
+                if let Some(obj) = value.as_object_mut() {
+                    obj.insert(
+                        "clewdr_report".to_string(),
+                        serde_json::to_value(&report).unwrap_or_default(),
+                    );
+                }
+                let mut resp = Json(value).into_response();
+                resp.headers_mut().insert(
+                    CONTENT_TYPE,
+                    HeaderValue::from_static(INLINE_REPORT_CONTENT_TYPE),
+                );
+                resp
+            }
+            ReportDelivery::Header => {
+                REPORTS.insert(report.request_id.to_owned(), report);
+                Json(value).into_response()
+            }
+        };
+        *resp.status_mut() = status;
+        resp
+    };
+    resp.headers_mut().insert(REPORT_ID_HEADER, id);
+    *resp.extensions_mut() = extensions;
+    resp
+}
+
+fn report_stream(
+    mut report: RequestReport,
+    stream: impl Stream<Item = EventResult<SourceEvent>>,
+    config: Arc<ClewdrConfig>,
+) -> impl Stream<Item = EventResult<Event>> {
+    try_stream!({
+        for await event in stream {
+            let SourceEvent {
+                data,
+                id,
+                event,
+                retry,
+            } = event?;
+            if let Ok(value) = serde_json::from_str::<Value>(&data) {
+                report.note_usage(&value);
+            }
+            let event = Event::default().event(event).id(id).data(data);
+            let event = if let Some(retry) = retry {
+                event.retry(retry)
+            } else {
+                event
+            };
+            yield event;
+        }
+        report.price(&config);
+        match report.delivery {
+            ReportDelivery::Inline => {
+                yield Event::default()
+                    .event("clewdr_report")
+                    .json_data(&report)
+                    .unwrap();
+            }
+            ReportDelivery::Header => {
+                REPORTS.insert(report.request_id.to_owned(), report);
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Router,
+        body::{self, Body},
+        extract::Request,
+        middleware::from_extractor,
+        routing::post,
+    };
+    use http::header::AUTHORIZATION;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{
+        config::{ADMIN_HEADER, TEST_ADMIN_PASSWORD, TEST_KEY, TEST_PASSWORD, install_test_config},
+        middleware::RequireFlexibleAuth,
+    };
+
+    fn config(access: &str) -> ClewdrConfig {
+        toml::from_str(&format!("request_reports = \"{access}\"")).unwrap()
+    }
+
+    fn headers(mode: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(REPORT_HEADER, HeaderValue::from_str(mode).unwrap());
+        headers
+    }
+
+    fn priced() -> Arc<ClewdrConfig> {
+        let config =
+            toml::from_str("[[pricing]]\nmodel = \"claude-sonnet-4\"\ninput = 3.0\noutput = 15.0")
+                .unwrap();
+        Arc::new(config)
+    }
+
+    fn report(delivery: ReportDelivery) -> RequestReport {
+        let body = CreateMessageParams {
+            model: "claude-sonnet-4-5".to_string(),
+            ..Default::default()
+        };
+        RequestReport::new(
+            delivery,
+            "claude_code",
+            ClaudeApiFormat::Claude,
+            &body,
+            vec!["billing_header"],
+            5,
+            Some(&ClientAuth::Password),
+        )
+    }
+
+    #[test]
+    fn role_gating() {
+        let user = Some(&ClientAuth::Password);
+        let admin = Some(&ClientAuth::Admin);
+        let by_admin = config("admin");
+        assert_eq!(
+            ReportDelivery::requested(&headers("1"), admin, &by_admin),
+            Some(ReportDelivery::Inline)
+        );
+        assert_eq!(
+            ReportDelivery::requested(&headers("1"), user, &by_admin),
+            None
+        );
+        assert_eq!(
+            ReportDelivery::requested(&headers("1"), None, &by_admin),
+            None
+        );
+
+        let all = config("all");
+        assert_eq!(
+            ReportDelivery::requested(&headers("header"), user, &all),
+            Some(ReportDelivery::Header)
+        );
+        assert_eq!(ReportDelivery::requested(&headers("0"), user, &all), None);
+        assert_eq!(
+            ReportDelivery::requested(&HeaderMap::new(), user, &all),
+            None
+        );
+
+        let off = config("off");
+        assert_eq!(ReportDelivery::requested(&headers("1"), admin, &off), None);
+    }
+
+    /// Delivery granted to a request with `key` and `admin`, as admitted by the proxy auth layer
+    async fn requested_through_auth(
+        key: &str,
+        admin: Option<&str>,
+        bearer: bool,
+    ) -> Option<String> {
+        install_test_config();
+        let router = Router::new()
+            .route(
+                "/v1/messages",
+                post(|req: Request| async move {
+                    let delivery = ReportDelivery::requested(
+                        req.headers(),
+                        req.extensions().get::<ClientAuth>(),
+                        &CLEWDR_CONFIG.load(),
+                    );
+                    format!("{delivery:?}")
+                }),
+            )
+            .layer(from_extractor::<RequireFlexibleAuth>());
+        let mut req = http::Request::post("/v1/messages").header(REPORT_HEADER, "1");
+        if let Some(admin) = admin {
+            req = req.header(ADMIN_HEADER, admin);
+        }
+        req = if bearer {
+            req.header(AUTHORIZATION, format!("Bearer {key}"))
+        } else {
+            req.header("x-api-key", key)
+        };
+        let resp = router
+            .oneshot(req.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        resp.status().is_success().then_some(body_text(resp).await)
+    }
+
+    #[tokio::test]
+    async fn admin_reports_through_auth_layer() {
+        // request_reports defaults to admin
+        let inline = Some(format!("{:?}", Some(ReportDelivery::Inline)));
+        let denied = Some(format!("{:?}", None::<ReportDelivery>));
+        let admin = Some(TEST_ADMIN_PASSWORD);
+        assert_eq!(
+            requested_through_auth(TEST_PASSWORD, admin, false).await,
+            inline
+        );
+        assert_eq!(requested_through_auth(TEST_KEY, admin, true).await, inline);
+        assert_eq!(
+            requested_through_auth(TEST_PASSWORD, None, false).await,
+            denied
+        );
+        assert_eq!(requested_through_auth(TEST_KEY, None, true).await, denied);
+        assert_eq!(
+            requested_through_auth(TEST_PASSWORD, Some("wrong"), false).await,
+            denied
+        );
+        // the admin password is no proxy key by itself
+        assert_eq!(
+            requested_through_auth(TEST_ADMIN_PASSWORD, None, false).await,
+            None
+        );
+        assert_eq!(requested_through_auth("wrong", admin, false).await, None);
+    }
+
+    fn message_response() -> Response {
+        Json(serde_json::json!({
+            "id": "msg_1",
+            "type": "message",
+            "role": "assistant",
+            "content": [{"type": "text", "text": "hi"}],
+            "usage": {"input_tokens": 5, "output_tokens": 7},
+        }))
+        .into_response()
+    }
+
+    fn stream_response() -> Response {
+        let raw = "event: message_start\n\
+            data: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":5,\"output_tokens\":1}}}\n\n\
+            event: message_delta\n\
+            data: {\"type\":\"message_delta\",\"delta\":{},\"usage\":{\"output_tokens\":9}}\n\n\
+            event: message_stop\n\
+            data: {\"type\":\"message_stop\"}\n\n";
+        Response::builder()
+            .header(CONTENT_TYPE, "text/event-stream")
+            .body(Body::from(raw))
+            .unwrap()
+    }
+
+    async fn body_text(resp: Response) -> String {
+        let bytes = body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    fn report_id(resp: &Response) -> String {
+        resp.headers()[REPORT_ID_HEADER]
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn inline_non_stream() {
+        let resp = deliver(message_response(), report(ReportDelivery::Inline), priced()).await;
+        assert_eq!(resp.headers()[CONTENT_TYPE], INLINE_REPORT_CONTENT_TYPE);
+        let id = report_id(&resp);
+        let value: Value = serde_json::from_str(&body_text(resp).await).unwrap();
+        assert_eq!(value["content"][0]["text"], "hi");
+        let report = &value["clewdr_report"];
+        assert_eq!(report["request_id"], id.as_str());
+        assert_eq!(report["backend"], "claude_code");
+        assert_eq!(report["output_tokens"], 7);
+        assert_eq!(report["estimated_cost_usd"], (5.0 * 3.0 + 7.0 * 15.0) / 1e6);
+        assert_eq!(report["preprocessing"][0], "billing_header");
+        assert!(get_report(&id, &ClientAuth::Password).is_none());
+    }
+
+    #[tokio::test]
+    async fn inline_stream_appends_event() {
+        let resp = deliver(stream_response(), report(ReportDelivery::Inline), priced()).await;
+        let text = body_text(resp).await;
+        let (before, after) = text.split_once("event: clewdr_report").unwrap();
+        assert!(before.contains("message_stop"));
+        let data = after.trim().strip_prefix("data:").unwrap().trim();
+        let report: Value = serde_json::from_str(data).unwrap();
+        assert_eq!(report["output_tokens"], 9);
+        assert_eq!(report["estimated_cost_usd"], (5.0 * 3.0 + 9.0 * 15.0) / 1e6);
+        assert_eq!(report["stream"], false);
+    }
+
+    #[tokio::test]
+    async fn header_delivery_leaves_body_untouched() {
+        let resp = deliver(message_response(), report(ReportDelivery::Header), priced()).await;
+        assert_eq!(resp.headers()[CONTENT_TYPE], "application/json");
+        let id = report_id(&resp);
+        let value: Value = serde_json::from_str(&body_text(resp).await).unwrap();
+        assert!(value.get("clewdr_report").is_none());
+        let fetched = |caller: ClientAuth| get_report(&id, &caller).map(|r| r.output_tokens);
+        assert_eq!(fetched(ClientAuth::Password), Some(Some(7)));
+        assert_eq!(fetched(ClientAuth::Admin), Some(Some(7)));
+        // other clients cannot read it
+        assert_eq!(fetched(ClientAuth::Key("test".into())), None);
+
+        let resp = deliver(stream_response(), report(ReportDelivery::Header), priced()).await;
+        let id = report_id(&resp);
+        // the report is stored once the stream has been consumed
+        let text = body_text(resp).await;
+        assert!(!text.contains("clewdr_report"));
+        let report = get_report(&id, &ClientAuth::Password).unwrap();
+        assert_eq!(report.output_tokens, Some(9));
+    }
+}
@@ -15,9 +15,19 @@ use serde_json::{Value, json};
 use sha2::{Digest, Sha256};
 
 use crate::{
-    config::{CLAUDE_CODE_BILLING_SALT, CLAUDE_CODE_VERSION, CLEWDR_CONFIG},
+    config::{
+        CLAUDE_CODE_BILLING_SALT, CLAUDE_CODE_VERSION, CLEWDR_CONFIG, ClientAuth, SESSION_HEADER,
+        SMOKE_HEADER, supports_thinking,
+    },
     error::ClewdrError,
-    middleware::claude::{ClaudeApiFormat, ClaudeContext},
+    middleware::claude::{
+        ClaudeApiFormat, ClaudeContext, ReportDelivery, RequestReport, apply_preset,
+    },
+    services::{
+        conversations,
+        response_cache::CacheStatus,
+        retry::{RetryClaim, RetryInfo},
+    },
     types::{
         claude::{
             ContentBlock, CreateMessageParams, Message, MessageContent, Role, Thinking, Usage,
@@ -57,6 +67,24 @@ pub struct ClaudeWebContext {
     pub(super) stop_sequences: Vec<String>,
     /// User information about input and output tokens
     pub(super) usage: Usage,
+    /// Whether the request is a smoke check that stays out of usage stats
+    pub(super) smoke: bool,
+    /// Feature usage report requested by the client
+    pub(super) report: Option<RequestReport>,
+    /// Whether the request was made with the admin password
+    pub(super) admin: bool,
+    /// Upstream headers selected for forwarding, filled in from the response
+    pub(super) upstream_headers: Option<HeaderMap>,
+    /// Whether the client retried an earlier request
+    pub(super) retry: RetryInfo,
+    /// Parts of the request changed by the prompt preset
+    pub(super) transformed: Vec<&'static str>,
+    /// Hash of the cookie that served the request, filled in with `debug_account_header`
+    pub(super) account: Option<String>,
+    /// Whether the response cache answered, `None` when the request is not cacheable
+    pub(super) cache: Option<CacheStatus>,
+    /// Session named by `x-clewdr-session`, set while conversation reuse is enabled
+    pub(super) session: Option<String>,
 }
 
 /// Predefined test message in Claude format for connection testing
@@ -70,7 +98,8 @@ static TEST_MESSAGE_CLAUDE: LazyLock<Message> =
 /// Predefined test message in OpenAI format for connection testing
 static TEST_MESSAGE_OAI: LazyLock<Message> = LazyLock::new(|| Message::new_text(Role::User, "Hi"));
 
-struct NormalizeRequest(CreateMessageParams, ClaudeApiFormat);
+/// Normalized body, its format and the preprocessing rules that rewrote it
+struct NormalizeRequest(CreateMessageParams, ClaudeApiFormat, Vec<&'static str>);
 
 const CLAUDE_CODE_ENTRYPOINT_ENV: &str = "CLAUDE_CODE_ENTRYPOINT";
 
@@ -161,6 +190,35 @@ fn drop_empty_system(body: &mut CreateMessageParams) {
     body.system = (!is_empty).then_some(system);
 }
 
+/// Smallest thinking budget the API accepts
+const MIN_THINKING_BUDGET: u64 = 1024;
+
+/// Enables thinking with the configured default budget
+///
+/// Requests that set `thinking`, or sampling options thinking does not allow,
+/// are left as they are. The budget is capped below `max_tokens`.
+///
+/// # Returns
+/// * `bool` - Whether thinking was enabled
+fn apply_default_thinking(body: &mut CreateMessageParams, budget: Option<u64>) -> bool {
+    let Some(budget) = budget else {
+        return false;
+    };
+    if body.thinking.is_some()
+        || !supports_thinking(&body.model)
+        || body.temperature.is_some_and(|t| t != 1.0)
+        || body.top_k.is_some()
+    {
+        return false;
+    }
+    let budget = budget.min(u64::from(body.max_tokens).saturating_sub(1));
+    if budget < MIN_THINKING_BUDGET {
+        return false;
+    }
+    body.thinking = Some(Thinking::new(budget));
+    true
+}
+
 fn strip_ephemeral_scope_from_system(system: &mut Value) {
     let Some(items) = system.as_array_mut() else {
         return;
@@ -269,19 +327,41 @@ where
             }
             ClaudeApiFormat::Claude => Json::<CreateMessageParams>::from_request(req, &()).await?,
         };
+        let mut rules = vec![];
+        if let Some(model) = CLEWDR_CONFIG.load().resolve_model(&body.model)? {
+            body.model = model;
+            rules.push("model_alias");
+        }
         if CLEWDR_CONFIG.load().sanitize_messages {
             // Trim whitespace and drop empty assistant turns when enabled.
             body.messages = sanitize_messages(body.messages);
+            rules.push("sanitize_messages");
         }
         if body.model.ends_with("-thinking") {
             body.model = body.model.trim_end_matches("-thinking").to_string();
             body.thinking.get_or_insert(Thinking::new(4096));
+            rules.push("thinking_model_suffix");
         }
+        if apply_default_thinking(&mut body, CLEWDR_CONFIG.load().default_thinking_budget) {
+            rules.push("default_thinking");
+        }
+        let had_system = body.system.is_some();
         drop_empty_system(&mut body);
-        Ok(Self(body, format))
+        if had_system && body.system.is_none() {
+            rules.push("drop_empty_system");
+        }
+        Ok(Self(body, format, rules))
     }
 }
 
+/// Whether a request is a smoke check, only honoured for the admin
+///
+/// Smoke checks stay out of usage stats and SLOs, any other client sending
+/// the header is counted as usual.
+fn is_smoke_check(headers: &HeaderMap, admin: bool) -> bool {
+    admin && headers.contains_key(SMOKE_HEADER)
+}
+
 impl<S> FromRequest<S> for ClaudeWebPreprocess
 where
     S: Send + Sync,
@@ -289,7 +369,28 @@ where
     type Rejection = ClewdrError;
 
     async fn from_request(req: Request, _: &S) -> Result<Self, Self::Rejection> {
-        let NormalizeRequest(body, format) = NormalizeRequest::from_request(req, &()).await?;
+        let auth = req.extensions().get::<ClientAuth>().cloned();
+        let report = ReportDelivery::requested(req.headers(), auth.as_ref(), &CLEWDR_CONFIG.load());
+        let admin = auth.as_ref().is_some_and(ClientAuth::is_admin);
+        let smoke = is_smoke_check(req.headers(), admin);
+        let claim = RetryClaim::from_headers(req.headers(), auth.as_ref());
+        let session = CLEWDR_CONFIG
+            .load()
+            .conversation_reuse
+            .enabled
+            .then(|| req.headers().get(SESSION_HEADER)?.to_str().ok())
+            .flatten()
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                let client = auth.as_ref().map(ClientAuth::identity).unwrap_or_default();
+                conversations::session_key(&client, s)
+            });
+        let NormalizeRequest(mut body, format, mut rules) =
+            NormalizeRequest::from_request(req, &()).await?;
+        let transformed = apply_preset(&mut body, &CLEWDR_CONFIG.load().prompt_preset);
+        if !transformed.is_empty() {
+            rules.push("prompt_preset");
+        }
 
         // Check for test messages and respond appropriately
         if !body.stream.unwrap_or_default()
@@ -299,11 +400,28 @@ where
             // Respond with a test message
             return Err(ClewdrError::TestMessage);
         }
+        // smoke checks repeat on purpose
+        let retry = if smoke {
+            RetryInfo::default()
+        } else {
+            claim.observe(&body)
+        };
 
         // Determine streaming status and API format
         let stream = body.stream.unwrap_or_default();
 
         let input_tokens = body.count_tokens();
+        let report = report.map(|delivery| {
+            RequestReport::new(
+                delivery,
+                "claude_web",
+                format,
+                &body,
+                rules,
+                input_tokens,
+                auth.as_ref(),
+            )
+        });
         let info = ClaudeWebContext {
             stream,
             api_format: format,
@@ -312,6 +430,15 @@ where
                 input_tokens,
                 output_tokens: 0, // Placeholder for output token count
             },
+            smoke,
+            report,
+            admin,
+            upstream_headers: None,
+            retry,
+            transformed,
+            account: None,
+            cache: None,
+            session,
         };
 
         Ok(Self(body, ClaudeContext::Web(info)))
@@ -330,6 +457,22 @@ pub struct ClaudeCodeContext {
     pub(super) anthropic_beta: Option<String>,
     // Usage information for the request
     pub(super) usage: Usage,
+    /// Whether the request is a smoke check that stays out of usage stats
+    pub(super) smoke: bool,
+    /// Feature usage report requested by the client
+    pub(super) report: Option<RequestReport>,
+    /// Whether the request was made with the admin password
+    pub(super) admin: bool,
+    /// Upstream headers selected for forwarding, filled in from the response
+    pub(super) upstream_headers: Option<HeaderMap>,
+    /// Whether the client retried an earlier request
+    pub(super) retry: RetryInfo,
+    /// Parts of the request changed by the prompt preset
+    pub(super) transformed: Vec<&'static str>,
+    /// Hash of the cookie that served the request, filled in with `debug_account_header`
+    pub(super) account: Option<String>,
+    /// Whether the response cache answered, `None` when the request is not cacheable
+    pub(super) cache: Option<CacheStatus>,
 }
 
 pub struct ClaudeCodePreprocess(pub CreateMessageParams, pub ClaudeContext);
@@ -342,10 +485,26 @@ where
 
     async fn from_request(req: Request, _: &S) -> Result<Self, Self::Rejection> {
         let anthropic_beta = extract_anthropic_beta_header(req.headers());
-        let NormalizeRequest(mut body, format) = NormalizeRequest::from_request(req, &()).await?;
+        let auth = req.extensions().get::<ClientAuth>().cloned();
+        let report = ReportDelivery::requested(req.headers(), auth.as_ref(), &CLEWDR_CONFIG.load());
+        let admin = auth.as_ref().is_some_and(ClientAuth::is_admin);
+        let smoke = is_smoke_check(req.headers(), admin);
+        let claim = RetryClaim::from_headers(req.headers(), auth.as_ref());
+        let NormalizeRequest(mut body, format, mut rules) =
+            NormalizeRequest::from_request(req, &()).await?;
+        let preset = CLEWDR_CONFIG.load().prompt_preset.to_owned();
+        let transformed = if preset.apply_to_code {
+            apply_preset(&mut body, &preset)
+        } else {
+            vec![]
+        };
+        if !transformed.is_empty() {
+            rules.push("prompt_preset");
+        }
         // Handle thinking mode by modifying the model name
-        if body.temperature.is_some() {
+        if body.temperature.is_some() && body.top_p.is_some() {
             body.top_p = None; // temperature and top_p cannot be used together in Opus-4.x
+            rules.push("drop_top_p");
         }
 
         // Check for test messages and respond appropriately
@@ -356,6 +515,12 @@ where
             // Respond with a test message
             return Err(ClewdrError::TestMessage);
         }
+        // smoke checks repeat on purpose
+        let retry = if smoke {
+            RetryInfo::default()
+        } else {
+            claim.observe(&body)
+        };
 
         // Determine streaming status and API format
         let stream = body.stream.unwrap_or_default();
@@ -370,8 +535,10 @@ where
             .filter(|s| !s.trim().is_empty())
         {
             system_prefixes.push(ContentBlock::text(custom_system));
+            rules.push("custom_system");
         }
         prepend_system_blocks(&mut body, system_prefixes);
+        rules.push("billing_header");
 
         if let Some(system) = body.system.as_mut() {
             strip_ephemeral_scope_from_system(system);
@@ -395,6 +562,18 @@ where
         });
 
         let input_tokens = body.count_tokens();
+        let report = report.map(|delivery| {
+            RequestReport::new(
+                delivery,
+                "claude_code",
+                format,
+                &body,
+                rules,
+                input_tokens,
+                auth.as_ref(),
+            )
+            .with_prompt_cache(system_prompt_hash.is_some())
+        });
 
         let info = ClaudeCodeContext {
             stream,
@@ -405,6 +584,14 @@ where
                 input_tokens,
                 output_tokens: 0, // Placeholder for output token count
             },
+            smoke,
+            report,
+            admin,
+            upstream_headers: None,
+            retry,
+            transformed,
+            account: None,
+            cache: None,
         };
 
         Ok(Self(body, ClaudeContext::Code(info)))
@@ -413,7 +600,83 @@ where
 
 #[cfg(test)]
 mod tests {
+    use axum::body::Body;
+    use http::header::CONTENT_TYPE;
+
     use super::*;
+    use crate::{
+        config::{ADMIN_HEADER, TEST_ADMIN_PASSWORD, install_test_config},
+        types::claude::RequiredMessageParams,
+    };
+
+    #[tokio::test]
+    async fn admin_and_smoke_come_from_client_auth() {
+        install_test_config();
+        // whether the request counts as admin, and as a smoke check
+        let roles = async |auth: Option<ClientAuth>| {
+            let body = json!({
+                "model": "claude-sonnet-4-5",
+                "max_tokens": 16,
+                "messages": [{ "role": "user", "content": "hi" }],
+            });
+            let mut req = http::Request::post("/code/v1/messages")
+                .header(CONTENT_TYPE, "application/json")
+                // a header alone does not make the request an admin one
+                .header(ADMIN_HEADER, TEST_ADMIN_PASSWORD)
+                .header(SMOKE_HEADER, "1")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            if let Some(auth) = auth {
+                req.extensions_mut().insert(auth);
+            }
+            let ClaudeCodePreprocess(_, cx) =
+                ClaudeCodePreprocess::from_request(req, &()).await.unwrap();
+            (cx.is_admin(), cx.is_smoke())
+        };
+        assert_eq!(roles(Some(ClientAuth::Admin)).await, (true, true));
+        assert_eq!(roles(Some(ClientAuth::Password)).await, (false, false));
+        assert_eq!(
+            roles(Some(ClientAuth::Key("test".into()))).await,
+            (false, false)
+        );
+        assert_eq!(roles(None).await, (false, false));
+    }
+
+    #[test]
+    fn default_thinking_only_fills_gaps() {
+        let params = |model: &str, max_tokens| {
+            CreateMessageParams::new(RequiredMessageParams {
+                model: model.to_string(),
+                messages: vec![Message::new_text(Role::User, "hey")],
+                max_tokens,
+            })
+        };
+        let budget = |body: &CreateMessageParams| match body.thinking {
+            Some(Thinking::Enabled { budget_tokens }) => Some(budget_tokens),
+            _ => None,
+        };
+
+        let mut body = params("claude-sonnet-4-6", 8192);
+        assert!(!apply_default_thinking(&mut body, None));
+        assert!(apply_default_thinking(&mut body, Some(4096)));
+        assert_eq!(budget(&body), Some(4096));
+
+        // capped below max_tokens, skipped when that leaves too little
+        let mut body = params("claude-sonnet-4-6", 2048);
+        assert!(apply_default_thinking(&mut body, Some(4096)));
+        assert_eq!(budget(&body), Some(2047));
+        let mut body = params("claude-sonnet-4-6", 1024);
+        assert!(!apply_default_thinking(&mut body, Some(4096)));
+
+        let mut body = params("claude-sonnet-4-6", 8192);
+        body.thinking = Some(Thinking::Disabled);
+        assert!(!apply_default_thinking(&mut body, Some(4096)));
+        let mut body = params("claude-sonnet-4-6", 8192);
+        body.temperature = Some(0.7);
+        assert!(!apply_default_thinking(&mut body, Some(4096)));
+        let mut body = params("claude-3-5-haiku-20241022", 8192);
+        assert!(!apply_default_thinking(&mut body, Some(4096)));
+    }
 
     #[test]
     fn claude_code_billing_header_matches_2176_rule() {
@@ -1,7 +1,7 @@
 use axum::{
     Json,
     body::{self, Body},
-    response::{IntoResponse, Response, Sse},
+    response::{IntoResponse, Response},
 };
 use eventsource_stream::Eventsource;
 use futures::TryStreamExt;
@@ -12,9 +12,10 @@ use super::{ClaudeApiFormat, transform_stream};
 use crate::{
     middleware::claude::{ClaudeContext, transforms_json},
     types::claude::{CreateMessageResponse, StreamEvent},
+    utils::sse_response,
 };
 
-async fn parse_response<T>(resp: Response) -> Result<T, Response>
+pub(super) async fn parse_response<T>(resp: Response) -> Result<T, Response>
 where
     T: serde::de::DeserializeOwned,
 {
@@ -52,27 +53,29 @@ where
 ///
 /// The original or transformed response as appropriate
 pub async fn to_oai(resp: Response) -> impl IntoResponse {
-    let Some(cx) = resp.extensions().get::<ClaudeContext>() else {
+    let Some(cx) = resp.extensions().get::<ClaudeContext>().cloned() else {
         return resp;
     };
     if ClaudeApiFormat::Claude == cx.api_format() {
         return resp;
     }
-    if !cx.is_stream() {
+    let mut resp = if !cx.is_stream() {
         match parse_response::<CreateMessageResponse>(resp).await {
-            Ok(response) => return Json(transforms_json(response)).into_response(),
+            Ok(response) => Json(transforms_json(response)).into_response(),
             Err(resp) => return resp,
         }
-    }
-    let stream = resp.into_body().into_data_stream().eventsource();
-    let stream = transform_stream(stream);
-    Sse::new(stream)
-        .keep_alive(Default::default())
-        .into_response()
+    } else {
+        let stream = resp.into_body().into_data_stream().eventsource();
+        let stream = transform_stream(stream);
+        sse_response(stream)
+    };
+    // outer layers still read the request context
+    resp.extensions_mut().insert(cx);
+    resp
 }
 
 pub async fn add_usage_info(resp: Response) -> impl IntoResponse {
-    let Some(cx) = resp.extensions().get::<ClaudeContext>() else {
+    let Some(cx) = resp.extensions().get::<ClaudeContext>().cloned() else {
         return resp;
     };
     let (mut usage, stream) = (cx.usage().to_owned(), cx.is_stream());
@@ -84,7 +87,9 @@ pub async fn add_usage_info(resp: Response) -> impl IntoResponse {
         let output_tokens = response.count_tokens();
         usage.output_tokens = output_tokens;
         response.usage = Some(usage);
-        return Json(response).into_response();
+        let mut resp = Json(response).into_response();
+        resp.extensions_mut().insert(cx);
+        return resp;
     }
     let stream = resp
         .into_body()
@@ -122,9 +127,9 @@ pub async fn add_usage_info(resp: Response) -> impl IntoResponse {
             }
         });
 
-    Sse::new(stream)
-        .keep_alive(Default::default())
-        .into_response()
+    let mut resp = sse_response(stream);
+    resp.extensions_mut().insert(cx);
+    resp
 }
 
 pub async fn check_overloaded(mut resp: Response) -> Response {
@@ -1,11 +1,12 @@
 use async_stream::try_stream;
-use axum::response::{IntoResponse, Response, Sse, sse::Event};
+use axum::response::{Response, sse::Event};
 use eventsource_stream::{Event as SourceEvent, Eventsource};
 use futures::Stream;
 
 use crate::{
     middleware::claude::ClaudeContext,
     types::claude::{ContentBlockDelta, MessageDeltaContent, StopReason, StreamEvent},
+    utils::sse_response,
 };
 
 type EventResult<T> = Result<T, eventsource_stream::EventStreamError<axum::Error>>;
@@ -95,9 +96,7 @@ pub async fn apply_stop_sequences(resp: Response) -> Response {
 
     let stream = resp.into_body().into_data_stream().eventsource();
     let stream = stop_stream(f.stop_sequences().to_owned(), stream);
-    let mut resp = Sse::new(stream)
-        .keep_alive(Default::default())
-        .into_response();
+    let mut resp = sse_response(stream);
 
     resp.extensions_mut().insert(f);
     resp
@@ -0,0 +1,210 @@
+//! Removal of thinking blocks from responses, enabled with `strip_thinking`
+//!
+//! Streamed blocks are numbered by `index`, so dropping a thinking block would
+//! leave a gap the client may choke on. The blocks after a dropped one are
+//! renumbered to keep the indices contiguous.
+
+use async_stream::try_stream;
+use axum::{
+    Json,
+    response::{IntoResponse, Response, sse::Event},
+};
+use eventsource_stream::{Event as SourceEvent, Eventsource};
+use futures::Stream;
+use http::header::CONTENT_TYPE;
+use serde_json::Value;
+
+use super::parse_response;
+use crate::{
+    config::CLEWDR_CONFIG,
+    middleware::claude::ClaudeContext,
+    types::claude::{ContentBlock, CreateMessageResponse},
+    utils::sse_response,
+};
+
+type EventResult<T> = Result<T, eventsource_stream::EventStreamError<axum::Error>>;
+
+fn is_thinking(block: &Value) -> bool {
+    matches!(
+        block["type"].as_str(),
+        Some("thinking" | "redacted_thinking")
+    )
+}
+
+/// Drops the events of thinking blocks from a stream
+#[derive(Default)]
+struct ThinkingFilter {
+    /// Indices of the thinking blocks started so far
+    dropped: Vec<usize>,
+}
+
+impl ThinkingFilter {
+    /// Filters the data of one event
+    ///
+    /// # Arguments
+    /// * `data` - Data of the event as received
+    ///
+    /// # Returns
+    /// * `Option<String>` - Data to forward, renumbered if needed, `None` to drop the event
+    fn filter(&mut self, data: &str) -> Option<String> {
+        let Ok(mut value) = serde_json::from_str::<Value>(data) else {
+            return Some(data.to_string());
+        };
+        let Some(index) = value.get("index").and_then(Value::as_u64) else {
+            return Some(data.to_string());
+        };
+        let index = index as usize;
+        if value["type"] == "content_block_start" && is_thinking(&value["content_block"]) {
+            self.dropped.push(index);
+            return None;
+        }
+        // deltas and stop of a dropped block
+        if self.dropped.contains(&index) {
+            return None;
+        }
+        let shift = self.dropped.iter().filter(|&&i| i < index).count();
+        if shift == 0 {
+            return Some(data.to_string());
+        }
+        value["index"] = (index - shift).into();
+        Some(value.to_string())
+    }
+}
+
+fn strip_thinking_stream(
+    stream: impl Stream<Item = EventResult<SourceEvent>>,
+) -> impl Stream<Item = EventResult<Event>> {
+    try_stream!({
+        let mut filter = ThinkingFilter::default();
+        for await event in stream {
+            let eventsource_stream::Event {
+                data,
+                id,
+                event,
+                retry,
+            } = event?;
+            let Some(data) = filter.filter(&data) else {
+                continue;
+            };
+            let event = Event::default().event(event).id(id).data(data);
+            if let Some(retry) = retry {
+                yield event.retry(retry);
+            } else {
+                yield event;
+            }
+        }
+    })
+}
+
+/// Removes thinking and redacted thinking blocks when `strip_thinking` is set
+///
+/// Streaming responses lose every event of those blocks, non-streaming
+/// responses lose the blocks themselves. Responses pass through untouched
+/// when the option is off.
+pub async fn apply_strip_thinking(resp: Response) -> Response {
+    if !CLEWDR_CONFIG.load().strip_thinking || !resp.status().is_success() {
+        return resp;
+    }
+    let Some(cx) = resp.extensions().get::<ClaudeContext>().cloned() else {
+        return resp;
+    };
+
+    let mut resp = if cx.is_stream() {
+        if resp
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| !v.contains("text/event-stream"))
+        {
+            return resp;
+        }
+        let stream = resp.into_body().into_data_stream().eventsource();
+        sse_response(strip_thinking_stream(stream))
+    } else {
+        let mut response = match parse_response::<CreateMessageResponse>(resp).await {
+            Ok(response) => response,
+            Err(resp) => return resp,
+        };
+        response.content.retain(|block| {
+            !matches!(
+                block,
+                ContentBlock::Thinking { .. } | ContentBlock::RedactedThinking { .. }
+            )
+        });
+        Json(response).into_response()
+    };
+
+    resp.extensions_mut().insert(cx);
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn renumbers_blocks_after_thinking() {
+        let events = [
+            json!({"type": "message_start", "message": {"id": "msg_1", "content": []}}),
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "thinking", "thinking": "", "signature": ""}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "thinking_delta", "thinking": "Let me see"}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "signature_delta", "signature": "sig"}}),
+            json!({"type": "content_block_stop", "index": 0}),
+            json!({"type": "content_block_start", "index": 1, "content_block": {"type": "text", "text": ""}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "text_delta", "text": "Hi"}}),
+            json!({"type": "content_block_stop", "index": 1}),
+            json!({"type": "content_block_start", "index": 2, "content_block": {"type": "redacted_thinking", "data": "xyz"}}),
+            json!({"type": "content_block_stop", "index": 2}),
+            json!({"type": "content_block_start", "index": 3, "content_block": {"type": "tool_use", "id": "t1", "name": "f", "input": {}}}),
+            json!({"type": "content_block_delta", "index": 3, "delta": {"type": "input_json_delta", "partial_json": "{}"}}),
+            json!({"type": "content_block_stop", "index": 3}),
+            json!({"type": "message_delta", "delta": {"stop_reason": "tool_use"}}),
+            json!({"type": "message_stop"}),
+        ];
+        let mut filter = ThinkingFilter::default();
+        let kept = events
+            .iter()
+            .filter_map(|e| filter.filter(&e.to_string()))
+            .map(|d| serde_json::from_str::<Value>(&d).unwrap())
+            .collect::<Vec<_>>();
+
+        let types = kept
+            .iter()
+            .map(|e| e["type"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            [
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop",
+            ]
+        );
+        let indices = kept
+            .iter()
+            .filter_map(|e| e["index"].as_u64())
+            .collect::<Vec<_>>();
+        assert_eq!(indices, [0, 0, 0, 1, 1, 1]);
+        assert_eq!(kept[4]["content_block"]["type"], "tool_use");
+        assert_eq!(kept[2]["delta"]["text"], "Hi");
+    }
+
+    #[test]
+    fn passes_other_data_through() {
+        let mut filter = ThinkingFilter::default();
+        assert_eq!(filter.filter("not json").as_deref(), Some("not json"));
+        let ping = r#"{"type":"ping"}"#;
+        assert_eq!(filter.filter(ping).as_deref(), Some(ping));
+        // blocks before any thinking keep their exact bytes
+        let text = r#"{"type":"content_block_stop","index":0}"#;
+        assert_eq!(filter.filter(text).as_deref(), Some(text));
+    }
+}
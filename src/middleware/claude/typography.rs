@@ -0,0 +1,653 @@
+//! Typography normalization for assistant text
+//!
+//! Every transform is a char-level state machine, so feeding a text block in
+//! any chunking yields exactly the same output as feeding it whole. Characters
+//! whose meaning depends on what follows (spaces, runs of dots or hyphens,
+//! trailing newlines) are held back until the next character decides them.
+
+use std::collections::HashMap;
+
+use async_stream::try_stream;
+use axum::{
+    Json,
+    response::{IntoResponse, Response, sse::Event},
+};
+use eventsource_stream::{Event as SourceEvent, Eventsource};
+use futures::Stream;
+use http::header::CONTENT_TYPE;
+
+use super::parse_response;
+use crate::{
+    config::{CLEWDR_CONFIG, FinalNewline, QuoteStyle, TypographyConfig},
+    middleware::claude::ClaudeContext,
+    types::claude::{ContentBlock, ContentBlockDelta, CreateMessageResponse, StreamEvent},
+    utils::sse_response,
+};
+
+type EventResult<T> = Result<T, eventsource_stream::EventStreamError<axum::Error>>;
+
+const EM_DASH: char = '—';
+const ELLIPSIS: char = '…';
+
+fn is_blank(c: char) -> bool {
+    c == ' ' || c == '\t'
+}
+
+fn is_line_break(c: char) -> bool {
+    c == '\n' || c == '\r'
+}
+
+/// Tracks whether text is inside an inline code span or a fenced code block
+///
+/// A run of backticks opens code, and a run of the same length closes it.
+#[derive(Default)]
+struct CodeTracker {
+    open: Option<usize>,
+    run: usize,
+}
+
+impl CodeTracker {
+    /// Feeds one character and returns whether it must be left untouched
+    fn feed(&mut self, c: char) -> bool {
+        if c == '`' {
+            self.run += 1;
+            return true;
+        }
+        if self.run > 0 {
+            match self.open {
+                None => self.open = Some(self.run),
+                Some(n) if n == self.run => self.open = None,
+                Some(_) => (),
+            }
+            self.run = 0;
+        }
+        self.open.is_some()
+    }
+}
+
+/// Quote, ellipsis and dash normalization for prose
+struct Prose {
+    config: TypographyConfig,
+    /// Spaces held back in case an em dash follows
+    held_ws: String,
+    hyphens: usize,
+    dots: usize,
+    prev: Option<char>,
+    line_start: bool,
+    /// Set right after an em dash, drops the spaces that follow it
+    skip_ws: bool,
+}
+
+impl Prose {
+    fn new(config: TypographyConfig) -> Self {
+        Self {
+            config,
+            held_ws: String::new(),
+            hyphens: 0,
+            dots: 0,
+            prev: None,
+            line_start: true,
+            skip_ws: false,
+        }
+    }
+
+    fn feed(&mut self, c: char, in_code: bool, out: &mut String) {
+        if in_code {
+            self.resolve(Some(c), out);
+            self.emit(c, out);
+            return;
+        }
+        match c {
+            '-' if self.config.normalize_dashes => {
+                self.flush_dots(out);
+                self.hyphens += 1;
+            }
+            '.' if self.config.normalize_ellipsis => {
+                self.flush_hyphens(Some(c), out);
+                self.flush_ws(out);
+                self.dots += 1;
+            }
+            c if is_blank(c) && self.config.normalize_dashes => {
+                self.resolve(Some(c), out);
+                if !self.skip_ws {
+                    self.held_ws.push(c);
+                }
+            }
+            EM_DASH if self.config.normalize_dashes => {
+                self.resolve(Some(c), out);
+                self.emit_em_dash(out);
+            }
+            c => {
+                self.resolve(Some(c), out);
+                let c = self.convert_quote(c);
+                self.emit(c, out);
+            }
+        }
+    }
+
+    fn finish(&mut self, out: &mut String) {
+        self.resolve(None, out);
+        self.flush_ws(out);
+    }
+
+    /// Settles pending hyphens and dots now that `next` is known
+    fn resolve(&mut self, next: Option<char>, out: &mut String) {
+        self.flush_hyphens(next, out);
+        self.flush_dots(out);
+    }
+
+    fn flush_hyphens(&mut self, next: Option<char>, out: &mut String) {
+        if self.hyphens == 0 {
+            return;
+        }
+        let run = std::mem::take(&mut self.hyphens);
+        let prev = self.held_ws.chars().last().or(self.prev);
+        // `a -- b` and `a--b` are dashes, `--flag`, `<!--` and `-->` are not
+        let spaced = prev.is_some_and(is_blank) && next.is_none_or(char::is_whitespace);
+        let closed =
+            prev.is_some_and(char::is_alphanumeric) && next.is_some_and(char::is_alphanumeric);
+        if run == 2 && (spaced || closed) {
+            self.emit_em_dash(out);
+        } else {
+            for _ in 0..run {
+                self.emit('-', out);
+            }
+        }
+    }
+
+    fn flush_dots(&mut self, out: &mut String) {
+        if self.dots == 0 {
+            return;
+        }
+        let run = std::mem::take(&mut self.dots);
+        if run == 3 {
+            self.emit(ELLIPSIS, out);
+        } else {
+            for _ in 0..run {
+                self.emit('.', out);
+            }
+        }
+    }
+
+    fn flush_ws(&mut self, out: &mut String) {
+        if let Some(last) = self.held_ws.chars().last() {
+            out.push_str(&self.held_ws);
+            self.prev = Some(last);
+            self.held_ws.clear();
+        }
+    }
+
+    fn emit_em_dash(&mut self, out: &mut String) {
+        // keep indentation, drop the spaces between a word and the dash
+        if self.line_start {
+            self.flush_ws(out);
+        } else {
+            self.held_ws.clear();
+        }
+        out.push(EM_DASH);
+        self.prev = Some(EM_DASH);
+        self.line_start = false;
+        self.skip_ws = true;
+    }
+
+    fn emit(&mut self, c: char, out: &mut String) {
+        self.flush_ws(out);
+        out.push(c);
+        self.line_start = c == '\n' || (self.line_start && is_blank(c));
+        self.skip_ws = false;
+        self.prev = Some(c);
+    }
+
+    fn convert_quote(&self, c: char) -> char {
+        match self.config.quote_style {
+            QuoteStyle::Preserve => c,
+            QuoteStyle::Straight => match c {
+                '“' | '”' | '„' => '"',
+                '‘' | '’' | '‚' => '\'',
+                c => c,
+            },
+            QuoteStyle::Smart => {
+                let prev = self.held_ws.chars().last().or(self.prev);
+                let opening = prev.is_none_or(|p| {
+                    p.is_whitespace() || matches!(p, '(' | '[' | '{' | '<' | '“' | '‘' | '—' | '–')
+                });
+                match (c, opening) {
+                    ('"', true) => '“',
+                    ('"', false) => '”',
+                    ('\'', true) => '‘',
+                    ('\'', false) => '’',
+                    (c, _) => c,
+                }
+            }
+        }
+    }
+}
+
+/// Strips spaces and tabs before line breaks and at the end of the block
+#[derive(Default)]
+struct TrailingWhitespace {
+    held: String,
+}
+
+impl TrailingWhitespace {
+    fn feed(&mut self, c: char, out: &mut String) {
+        if is_blank(c) {
+            self.held.push(c);
+            return;
+        }
+        if is_line_break(c) {
+            self.held.clear();
+        } else {
+            out.push_str(&self.held);
+            self.held.clear();
+        }
+        out.push(c);
+    }
+}
+
+/// Applies the final newline policy to the end of the block
+struct FinalNewlineStage {
+    policy: FinalNewline,
+    held: String,
+    any: bool,
+}
+
+impl FinalNewlineStage {
+    fn feed(&mut self, c: char, out: &mut String) {
+        if is_line_break(c) {
+            self.held.push(c);
+            return;
+        }
+        out.push_str(&self.held);
+        self.held.clear();
+        out.push(c);
+        self.any = true;
+    }
+
+    fn finish(&mut self, out: &mut String) {
+        let held = std::mem::take(&mut self.held);
+        match self.policy {
+            FinalNewline::Preserve => out.push_str(&held),
+            FinalNewline::Strip => (),
+            FinalNewline::Ensure if held.is_empty() && self.any => out.push('\n'),
+            FinalNewline::Ensure => out.push_str(&held),
+        }
+    }
+}
+
+/// Rolling typography filter over a single text block
+///
+/// The transformation is idempotent, so already normalized text such as a
+/// cached or replayed response passes through unchanged.
+pub struct TypographyFilter {
+    code: CodeTracker,
+    prose: Prose,
+    trailing: Option<TrailingWhitespace>,
+    final_newline: FinalNewlineStage,
+}
+
+impl TypographyFilter {
+    pub fn new(config: TypographyConfig) -> Self {
+        Self {
+            code: CodeTracker::default(),
+            prose: Prose::new(config),
+            trailing: config
+                .strip_trailing_whitespace
+                .then(TrailingWhitespace::default),
+            final_newline: FinalNewlineStage {
+                policy: config.final_newline,
+                held: String::new(),
+                any: false,
+            },
+        }
+    }
+
+    /// Transforms a whole text block at once
+    pub fn apply(config: TypographyConfig, text: &str) -> String {
+        let mut filter = Self::new(config);
+        let mut out = filter.push(text);
+        out.push_str(&filter.finish());
+        out
+    }
+
+    /// Feeds the next chunk and returns the text that is safe to emit
+    pub fn push(&mut self, chunk: &str) -> String {
+        let mut prose = String::with_capacity(chunk.len());
+        for c in chunk.chars() {
+            let in_code = self.code.feed(c);
+            self.prose.feed(c, in_code, &mut prose);
+        }
+        let mut out = String::with_capacity(prose.len());
+        self.pipe(&prose, &mut out);
+        out
+    }
+
+    /// Flushes everything still held back at the end of the block
+    pub fn finish(mut self) -> String {
+        let mut prose = String::new();
+        self.prose.finish(&mut prose);
+        let mut out = String::new();
+        self.pipe(&prose, &mut out);
+        if let Some(trailing) = self.trailing.as_mut() {
+            // held spaces at the end of the block are trailing whitespace
+            trailing.held.clear();
+        }
+        self.final_newline.finish(&mut out);
+        out
+    }
+
+    fn pipe(&mut self, text: &str, out: &mut String) {
+        for c in text.chars() {
+            match self.trailing.as_mut() {
+                Some(trailing) => {
+                    let mut buf = String::new();
+                    trailing.feed(c, &mut buf);
+                    for c in buf.chars() {
+                        self.final_newline.feed(c, out);
+                    }
+                }
+                None => self.final_newline.feed(c, out),
+            }
+        }
+    }
+}
+
+fn text_delta_event(name: &str, index: usize, text: String) -> Event {
+    Event::default()
+        .event(name)
+        .json_data(StreamEvent::ContentBlockDelta {
+            index,
+            delta: ContentBlockDelta::TextDelta { text },
+        })
+        .unwrap()
+}
+
+fn typography_stream(
+    config: TypographyConfig,
+    stream: impl Stream<Item = EventResult<SourceEvent>>,
+) -> impl Stream<Item = EventResult<Event>> {
+    try_stream!({
+        let mut filters: HashMap<usize, TypographyFilter> = HashMap::new();
+        for await event in stream {
+            let eventsource_stream::Event {
+                data,
+                id,
+                event,
+                retry,
+            } = event?;
+            let passthrough = Event::default().event(&event).id(&id).data(&data);
+            let passthrough = if let Some(retry) = retry {
+                passthrough.retry(retry)
+            } else {
+                passthrough
+            };
+            let Ok(parsed) = serde_json::from_str::<StreamEvent>(&data) else {
+                yield passthrough;
+                continue;
+            };
+            match parsed {
+                StreamEvent::ContentBlockStart {
+                    index,
+                    content_block: ContentBlock::Text { .. },
+                } => {
+                    filters.insert(index, TypographyFilter::new(config));
+                    yield passthrough;
+                }
+                StreamEvent::ContentBlockDelta {
+                    index,
+                    delta: ContentBlockDelta::TextDelta { text },
+                } => {
+                    let filter = filters
+                        .entry(index)
+                        .or_insert_with(|| TypographyFilter::new(config));
+                    let text = filter.push(&text);
+                    if !text.is_empty() {
+                        yield text_delta_event(&event, index, text);
+                    }
+                }
+                StreamEvent::ContentBlockStop { index } => {
+                    if let Some(filter) = filters.remove(&index) {
+                        let tail = filter.finish();
+                        if !tail.is_empty() {
+                            yield text_delta_event("content_block_delta", index, tail);
+                        }
+                    }
+                    yield passthrough;
+                }
+                _ => yield passthrough,
+            }
+        }
+        // upstream ended without closing some blocks, flush what is left
+        for (index, filter) in filters.drain() {
+            let tail = filter.finish();
+            if !tail.is_empty() {
+                yield text_delta_event("content_block_delta", index, tail);
+            }
+        }
+    })
+}
+
+/// Applies the configured typography normalization to assistant text
+///
+/// Streaming responses are filtered per text block, non-streaming responses
+/// have every text block transformed in place. Responses pass through
+/// untouched when no toggle is enabled.
+pub async fn apply_typography(resp: Response) -> Response {
+    let config = CLEWDR_CONFIG.load().typography;
+    if !config.is_enabled() || !resp.status().is_success() {
+        return resp;
+    }
+    let Some(cx) = resp.extensions().get::<ClaudeContext>().cloned() else {
+        return resp;
+    };
+
+    let mut resp = if cx.is_stream() {
+        if resp
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| !v.contains("text/event-stream"))
+        {
+            return resp;
+        }
+        let stream = resp.into_body().into_data_stream().eventsource();
+        sse_response(typography_stream(config, stream))
+    } else {
+        let mut response = match parse_response::<CreateMessageResponse>(resp).await {
+            Ok(response) => response,
+            Err(resp) => return resp,
+        };
+        for block in response.content.iter_mut() {
+            if let ContentBlock::Text { text, .. } = block {
+                *text = TypographyFilter::apply(config, text);
+            }
+        }
+        Json(response).into_response()
+    };
+
+    resp.extensions_mut().insert(cx);
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURES: &[&str] = &[
+        "Hello world  \nNext line\t\t\nEnd   ",
+        "She said \"hi\" and 'left'... then -- suddenly -- returned.",
+        "Curly “quotes” and ‘single’ ones — with  —  spacing.",
+        "Code: `let x = \"a\"...b;` and\n```rust\nlet s = 'c'; // -- ... \"q\"  \n```\nafter \"fence\"",
+        "<!-- comment --> and --flag and a--b and ---\n- list item\n  -- indented",
+        "Trailing newlines\n\n\n",
+        "....  ..  . ...\n'90s \"(quoted)\" [\"x\"]",
+        "",
+        "\n\n",
+        "mixed\r\nline  \r\nendings -- ok",
+    ];
+
+    fn all_configs() -> Vec<TypographyConfig> {
+        let mut configs = vec![];
+        for strip in [false, true] {
+            for quote_style in [
+                QuoteStyle::Preserve,
+                QuoteStyle::Straight,
+                QuoteStyle::Smart,
+            ] {
+                for ellipsis in [false, true] {
+                    for dashes in [false, true] {
+                        for final_newline in [
+                            FinalNewline::Preserve,
+                            FinalNewline::Ensure,
+                            FinalNewline::Strip,
+                        ] {
+                            configs.push(TypographyConfig {
+                                strip_trailing_whitespace: strip,
+                                quote_style,
+                                normalize_ellipsis: ellipsis,
+                                normalize_dashes: dashes,
+                                final_newline,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        configs
+    }
+
+    /// Small deterministic xorshift generator, enough to pick chunk sizes
+    struct Rng(u64);
+
+    impl Rng {
+        fn next_u64(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+    }
+
+    fn rechunk(text: &str, rng: &mut Rng) -> Vec<String> {
+        let chars = text.chars().collect::<Vec<_>>();
+        let mut chunks = vec![];
+        let mut i = 0;
+        while i < chars.len() {
+            let len = 1 + (rng.next_u64() % 6) as usize;
+            let end = (i + len).min(chars.len());
+            chunks.push(chars[i..end].iter().collect());
+            i = end;
+        }
+        chunks
+    }
+
+    #[test]
+    fn disabled_config_is_identity() {
+        let config = TypographyConfig::default();
+        assert!(!config.is_enabled());
+        for fixture in FIXTURES {
+            assert_eq!(TypographyFilter::apply(config, fixture), *fixture);
+        }
+    }
+
+    #[test]
+    fn strips_trailing_whitespace_across_chunks() {
+        let config = TypographyConfig {
+            strip_trailing_whitespace: true,
+            ..Default::default()
+        };
+        let mut filter = TypographyFilter::new(config);
+        let mut out = filter.push("line one  ");
+        out.push_str(&filter.push("  \nline two "));
+        out.push_str(&filter.push("\t"));
+        out.push_str(&filter.finish());
+        assert_eq!(out, "line one\nline two");
+    }
+
+    #[test]
+    fn smart_quotes_skip_code() {
+        let config = TypographyConfig {
+            quote_style: QuoteStyle::Smart,
+            ..Default::default()
+        };
+        let out = TypographyFilter::apply(
+            config,
+            "She said \"don't\" and `\"raw\"`\n```\n'x'\n```\n('y')",
+        );
+        assert_eq!(out, "She said “don’t” and `\"raw\"`\n```\n'x'\n```\n(‘y’)");
+    }
+
+    #[test]
+    fn straight_quotes() {
+        let config = TypographyConfig {
+            quote_style: QuoteStyle::Straight,
+            ..Default::default()
+        };
+        let out = TypographyFilter::apply(config, "“a” ‘b’ `“c”`");
+        assert_eq!(out, "\"a\" 'b' `“c”`");
+    }
+
+    #[test]
+    fn ellipsis_and_dashes() {
+        let config = TypographyConfig {
+            normalize_ellipsis: true,
+            normalize_dashes: true,
+            ..Default::default()
+        };
+        let out = TypographyFilter::apply(
+            config,
+            "wait... a -- b, c--d, e — f, --flag, <!-- x -->, ---, ....\n  -- item",
+        );
+        assert_eq!(
+            out,
+            "wait… a—b, c—d, e—f, --flag, <!-- x -->, ---, ....\n  —item"
+        );
+    }
+
+    #[test]
+    fn final_newline_policies() {
+        let ensure = TypographyConfig {
+            final_newline: FinalNewline::Ensure,
+            ..Default::default()
+        };
+        let strip = TypographyConfig {
+            final_newline: FinalNewline::Strip,
+            ..Default::default()
+        };
+        assert_eq!(TypographyFilter::apply(ensure, "a"), "a\n");
+        assert_eq!(TypographyFilter::apply(ensure, "a\n\n"), "a\n\n");
+        assert_eq!(TypographyFilter::apply(ensure, ""), "");
+        assert_eq!(TypographyFilter::apply(strip, "a\n\n"), "a");
+        assert_eq!(TypographyFilter::apply(strip, "a\nb"), "a\nb");
+    }
+
+    #[test]
+    fn transforms_are_idempotent() {
+        for config in all_configs() {
+            for fixture in FIXTURES {
+                let once = TypographyFilter::apply(config, fixture);
+                let twice = TypographyFilter::apply(config, &once);
+                assert_eq!(once, twice, "config {config:?} fixture {fixture:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn random_rechunking_matches_whole_text() {
+        let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+        for config in all_configs() {
+            for fixture in FIXTURES {
+                let expected = TypographyFilter::apply(config, fixture);
+                for _ in 0..20 {
+                    let mut filter = TypographyFilter::new(config);
+                    let mut out = String::new();
+                    for chunk in rechunk(fixture, &mut rng) {
+                        out.push_str(&filter.push(&chunk));
+                    }
+                    out.push_str(&filter.finish());
+                    assert_eq!(out, expected, "config {config:?} fixture {fixture:?}");
+                }
+            }
+        }
+    }
+}
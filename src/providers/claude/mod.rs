@@ -8,9 +8,13 @@ use super::LLMProvider;
 use crate::{
     claude_code_state::ClaudeCodeState,
     claude_web_state::ClaudeWebState,
+    config::{CLEWDR_CONFIG, SloEndpoint},
     error::ClewdrError,
-    middleware::claude::{ClaudeApiFormat, ClaudeContext},
-    services::cookie_actor::CookieActorHandle,
+    middleware::claude::{ClaudeApiFormat, ClaudeContext, ServedBy, UpstreamHeaders},
+    services::{
+        cookie_actor::CookieActorHandle, demo, language, retry, slo::SloTimer,
+        transcript::TranscriptRecorder,
+    },
     types::claude::CreateMessageParams,
     utils::{enabled, print_out_json},
 };
@@ -108,9 +112,12 @@ impl LLMProvider for ClaudeWebProvider {
         state.api_format = request.context.api_format();
         state.stream = stream;
         state.usage = request.context.usage().to_owned();
+        state.smoke = request.context.is_smoke();
+        state.retry_boost = request.context.retry().boost;
+        state.session = request.context.session().map(str::to_string);
         let ClaudeInvocation {
             params,
-            context,
+            mut context,
             operation,
         } = request;
         if !matches!(operation, ClaudeOperation::Messages) {
@@ -131,8 +138,40 @@ impl LLMProvider for ClaudeWebProvider {
             format_display
         );
         print_out_json(&params, "claude_web_client_req.json");
+        let config = CLEWDR_CONFIG.load();
+        if config.demo {
+            let response = demo::respond(&params, stream, config.demo_error_rate)?;
+            return Ok(ClaudeProviderResponse { context, response });
+        }
         let stopwatch = Instant::now();
-        let response = state.try_chat(params).await?;
+        // smoke checks stay out of the objectives
+        let slo = SloTimer::start(SloEndpoint::ClaudeWeb).filter(|_| !context.is_smoke());
+        let recorder = TranscriptRecorder::start("claude_web", &params);
+        let policed = language::polices(&config, stream, context.is_smoke())
+            .then(|| (state.clone(), params.clone()));
+        let result = state.try_chat(params).await;
+        retry::record_outcome(context.retry(), result.is_ok());
+        let result = match recorder {
+            Some(recorder) => recorder.finish(state.cookie.as_ref(), result),
+            None => result,
+        };
+        let mut response = match slo {
+            Some(slo) => slo.finish(result),
+            None => result,
+        }?;
+        context.set_upstream_headers(
+            response
+                .extensions_mut()
+                .remove::<UpstreamHeaders>()
+                .map(|h| h.0),
+        );
+        context.set_account(response.extensions_mut().remove::<ServedBy>().map(|s| s.0));
+        if let Some((mut retry_state, params)) = policed {
+            response = language::apply_policy(params, response, |params| async move {
+                retry_state.try_chat(params).await
+            })
+            .await;
+        }
         let elapsed = stopwatch.elapsed();
         info!(
             "[FIN] elapsed: {}s",
@@ -165,9 +204,11 @@ impl LLMProvider for ClaudeCodeProvider {
         state.system_prompt_hash = request.context.system_prompt_hash();
         state.anthropic_beta_header = request.context.anthropic_beta().map(str::to_string);
         state.usage = request.context.usage().to_owned();
+        state.smoke = request.context.is_smoke();
+        state.retry_boost = request.context.retry().boost;
         let ClaudeInvocation {
             params,
-            context,
+            mut context,
             operation,
         } = request;
         match operation {
@@ -184,8 +225,40 @@ impl LLMProvider for ClaudeCodeProvider {
                     format_display
                 );
                 print_out_json(&params, "claude_code_client_req.json");
+                let config = CLEWDR_CONFIG.load();
+                if config.demo {
+                    let response = demo::respond(&params, state.stream, config.demo_error_rate)?;
+                    return Ok(ClaudeProviderResponse { context, response });
+                }
                 let stopwatch = Instant::now();
-                let response = state.try_chat(params).await?;
+                // smoke checks stay out of the objectives
+                let slo = SloTimer::start(SloEndpoint::ClaudeCode).filter(|_| !context.is_smoke());
+                let recorder = TranscriptRecorder::start("claude_code", &params);
+                let policed = language::polices(&config, state.stream, context.is_smoke())
+                    .then(|| (state.clone(), params.clone()));
+                let result = state.try_chat(params).await;
+                retry::record_outcome(context.retry(), result.is_ok());
+                let result = match recorder {
+                    Some(recorder) => recorder.finish(state.cookie.as_ref(), result),
+                    None => result,
+                };
+                let mut response = match slo {
+                    Some(slo) => slo.finish(result),
+                    None => result,
+                }?;
+                context.set_upstream_headers(
+                    response
+                        .extensions_mut()
+                        .remove::<UpstreamHeaders>()
+                        .map(|h| h.0),
+                );
+                context.set_account(response.extensions_mut().remove::<ServedBy>().map(|s| s.0));
+                if let Some((mut retry_state, params)) = policed {
+                    response = language::apply_policy(params, response, |params| async move {
+                        retry_state.try_chat(params).await
+                    })
+                    .await;
+                }
                 let elapsed = stopwatch.elapsed();
                 info!(
                     "[FIN] elapsed: {}s",
@@ -199,6 +272,10 @@ impl LLMProvider for ClaudeCodeProvider {
                     params.messages.len().to_string().green(),
                     params.model.green()
                 );
+                if CLEWDR_CONFIG.load().demo {
+                    let response = demo::count_tokens(&params);
+                    return Ok(ClaudeProviderResponse { context, response });
+                }
                 let stopwatch = Instant::now();
                 let response = state.try_count_tokens(params, context.is_web()).await?;
                 let elapsed = stopwatch.elapsed();
@@ -1,21 +1,28 @@
+use std::sync::{Arc, OnceLock};
+
 use axum::{
     Router,
-    extract::DefaultBodyLimit,
-    http::Method,
-    middleware::{from_extractor, map_response},
+    extract::{DefaultBodyLimit, Request},
+    http::{Method, StatusCode},
+    middleware::{from_extractor, from_fn, map_response},
     routing::{delete, get, post},
 };
-use tower::ServiceBuilder;
+use snafu::{GenerateImplicitData, Location};
+use tower::{ServiceBuilder, ServiceExt, service_fn};
 use tower_http::{compression::CompressionLayer, cors::CorsLayer};
 
 use crate::{
     api::*,
+    error::ClewdrError,
     middleware::{
         RequireAdminAuth, RequireBearerAuth, RequireFlexibleAuth,
-        claude::{add_usage_info, apply_stop_sequences, check_overloaded, to_oai},
+        claude::{
+            add_usage_info, apply_stop_sequences, apply_strip_thinking, apply_typography,
+            attach_report, check_overloaded, forward_upstream_headers, mark_transformed, to_oai,
+        },
     },
     providers::claude::ClaudeProviders,
-    services::cookie_actor::CookieActorHandle,
+    services::{cookie_actor::CookieActorHandle, demo, shutdown, throttle},
 };
 
 /// RouterBuilder for the application
@@ -27,20 +34,27 @@ pub struct RouterBuilder {
 
 impl RouterBuilder {
     /// Creates a blank RouterBuilder instance
-    /// Initializes the router with the provided application state
+    /// Starts the cookie actor and the services that share it
     ///
-    /// # Arguments
-    /// * `state` - The application state containing client information
-    pub async fn new() -> Self {
-        let cookie_handle = CookieActorHandle::start()
-            .await
-            .expect("Failed to start CookieActor");
+    /// # Returns
+    /// * `Result<Self, ClewdrError>` - Error when the cookie actor fails to start
+    pub async fn new() -> Result<Self, ClewdrError> {
+        let cookie_handle =
+            CookieActorHandle::start()
+                .await
+                .map_err(|e| ClewdrError::RactorError {
+                    loc: Location::generate(),
+                    msg: format!("Failed to start CookieActor: {e}"),
+                })?;
+        crate::services::token_refresh::init_token_refresh(cookie_handle.clone());
+        crate::services::model_list::init_model_list(cookie_handle.clone());
+        crate::services::config_watch::init_config_watch(cookie_handle.clone());
         let claude_providers = crate::providers::claude::build_providers(cookie_handle.clone());
-        RouterBuilder {
+        Ok(RouterBuilder {
             claude_providers,
             cookie_actor_handle: cookie_handle,
             inner: Router::new(),
-        }
+        })
     }
 
     /// Creates a new RouterBuilder instance
@@ -49,11 +63,21 @@ impl RouterBuilder {
         self.route_claude_code_endpoints()
             .route_claude_web_endpoints()
             .route_admin_endpoints()
+            .route_report_endpoints()
+            .route_health_endpoints()
             .route_claude_web_oai_endpoints()
             .route_claude_code_oai_endpoints()
             .setup_static_serving()
             .with_tower_trace()
+            .with_rate_limit()
             .with_cors()
+            .with_demo_watermark()
+            .with_drain_gate()
+    }
+
+    /// Handle of the cookie actor the routes share
+    pub fn cookie_actor_handle(&self) -> CookieActorHandle {
+        self.cookie_actor_handle.to_owned()
     }
 
     /// Sets up routes for v1 endpoints
@@ -64,8 +88,13 @@ impl RouterBuilder {
                 ServiceBuilder::new()
                     .layer(from_extractor::<RequireFlexibleAuth>())
                     .layer(CompressionLayer::new())
+                    .layer(map_response(forward_upstream_headers))
+                    .layer(map_response(mark_transformed))
+                    .layer(map_response(attach_report))
                     .layer(map_response(add_usage_info))
+                    .layer(map_response(apply_typography))
                     .layer(map_response(apply_stop_sequences))
+                    .layer(map_response(apply_strip_thinking))
                     .layer(map_response(check_overloaded)),
             )
             .with_state(self.claude_providers.web());
@@ -84,7 +113,12 @@ impl RouterBuilder {
             .layer(
                 ServiceBuilder::new()
                     .layer(from_extractor::<RequireFlexibleAuth>())
-                    .layer(CompressionLayer::new()),
+                    .layer(CompressionLayer::new())
+                    .layer(map_response(forward_upstream_headers))
+                    .layer(map_response(mark_transformed))
+                    .layer(map_response(attach_report))
+                    .layer(map_response(apply_typography))
+                    .layer(map_response(apply_strip_thinking)),
             )
             .with_state(self.claude_providers.code());
         self.inner = self.inner.merge(router);
@@ -95,16 +129,45 @@ impl RouterBuilder {
     fn route_admin_endpoints(mut self) -> Self {
         let cookie_router = Router::new()
             .route("/cookies", get(api_get_cookies))
+            .route("/usage", get(api_get_usage))
             .route(
                 "/cookie",
                 delete(api_delete_cookie)
                     .post(api_post_cookie)
                     .put(api_put_cookie),
             )
+            .route("/cookies/batch", post(api_post_cookie_batch))
+            .route("/cookies/delete", post(api_post_cookie_delete))
+            .route("/config/import", post(api_import_config))
             .with_state(self.cookie_actor_handle.to_owned());
         let admin_router = Router::new()
             .route("/auth", get(api_auth))
-            .route("/config", get(api_get_config).post(api_post_config));
+            .route("/auth/rotate", post(api_rotate_admin))
+            .route("/keys", get(api_get_keys).post(api_post_key))
+            .route("/keys/{name}", delete(api_delete_key))
+            .route("/cache", get(api_get_cache).delete(api_delete_cache))
+            .route(
+                "/config",
+                get(api_get_config)
+                    .post(api_post_config)
+                    .patch(api_patch_config),
+            )
+            .route("/config/export", get(api_export_config))
+            .route("/slo", get(api_get_slo))
+            .route("/conformance", get(api_get_conformance))
+            .route("/audit", get(api_get_audit))
+            .route("/ratelimit/offenders", get(api_get_offenders))
+            .route("/ratelimit/offenders/{ip}", delete(api_delete_offender))
+            .route("/resources", get(api_get_resources))
+            .route("/language", get(api_get_language))
+            .route("/logs/download", get(api_download_logs))
+            .route("/startup", get(api_get_startup))
+            .route(
+                "/transcripts",
+                get(api_get_transcripts).delete(api_delete_transcripts),
+            )
+            .route("/transcripts/{id}", get(api_get_transcript))
+            .route("/transcripts/{id}/redact", post(api_redact_transcript));
         let router = Router::new()
             .nest(
                 "/api",
@@ -117,6 +180,25 @@ impl RouterBuilder {
         self
     }
 
+    /// Sets up routes for request report retrieval
+    fn route_report_endpoints(mut self) -> Self {
+        let router = Router::new()
+            .route("/api/reports/{id}", get(api_get_report))
+            .layer(from_extractor::<RequireFlexibleAuth>());
+        self.inner = self.inner.merge(router);
+        self
+    }
+
+    /// Sets up unauthenticated probes for container orchestration
+    fn route_health_endpoints(mut self) -> Self {
+        let router = Router::new()
+            .route("/healthz", get(api_healthz))
+            .route("/readyz", get(api_readyz))
+            .with_state(self.cookie_actor_handle.to_owned());
+        self.inner = self.inner.merge(router);
+        self
+    }
+
     /// Sets up routes for OpenAI compatible endpoints
     fn route_claude_web_oai_endpoints(mut self) -> Self {
         let router = Router::new()
@@ -126,8 +208,13 @@ impl RouterBuilder {
                 ServiceBuilder::new()
                     .layer(from_extractor::<RequireBearerAuth>())
                     .layer(CompressionLayer::new())
+                    .layer(map_response(forward_upstream_headers))
+                    .layer(map_response(mark_transformed))
+                    .layer(map_response(attach_report))
                     .layer(map_response(to_oai))
+                    .layer(map_response(apply_typography))
                     .layer(map_response(apply_stop_sequences))
+                    .layer(map_response(apply_strip_thinking))
                     .layer(map_response(check_overloaded)),
             )
             .with_state(self.claude_providers.web());
@@ -144,7 +231,12 @@ impl RouterBuilder {
                 ServiceBuilder::new()
                     .layer(from_extractor::<RequireBearerAuth>())
                     .layer(CompressionLayer::new())
-                    .layer(map_response(to_oai)),
+                    .layer(map_response(forward_upstream_headers))
+                    .layer(map_response(mark_transformed))
+                    .layer(map_response(attach_report))
+                    .layer(map_response(to_oai))
+                    .layer(map_response(apply_typography))
+                    .layer(map_response(apply_strip_thinking)),
             )
             .with_state(self.claude_providers.code());
         self.inner = self.inner.merge(router);
@@ -180,7 +272,13 @@ impl RouterBuilder {
 
         let cors = CorsLayer::new()
             .allow_origin(tower_http::cors::Any)
-            .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+            .allow_methods([
+                Method::GET,
+                Method::POST,
+                Method::PUT,
+                Method::PATCH,
+                Method::DELETE,
+            ])
             .allow_headers([
                 AUTHORIZATION,
                 CONTENT_TYPE,
@@ -200,9 +298,84 @@ impl RouterBuilder {
         self
     }
 
+    /// Limits clients of the message and auth endpoints by IP
+    fn with_rate_limit(mut self) -> Self {
+        self.inner = self.inner.layer(from_fn(throttle::limit_clients));
+        self
+    }
+
+    /// Marks every response of a demo instance with the demo header
+    fn with_demo_watermark(mut self) -> Self {
+        self.inner = self.inner.layer(map_response(demo::watermark));
+        self
+    }
+
+    /// Turns new requests away with 503 once shutdown has started
+    fn with_drain_gate(mut self) -> Self {
+        self.inner = self.inner.layer(from_fn(shutdown::reject_while_draining));
+        self
+    }
+
     /// Returns the configured router
     /// Finalizes the router configuration for use with axum
     pub fn build(self) -> Router {
         self.inner.layer(DefaultBodyLimit::max(32 * 1024 * 1024))
     }
 }
+
+/// Router to serve while the subsystems start
+///
+/// Until `app` is set only the probes and `/api/startup` answer, every other
+/// request gets 503. `/api/startup` needs the admin password, as in the full
+/// router. Once `app` is set, every request goes to `app`.
+///
+/// # Arguments
+/// * `app` - The full router, set when startup finished
+pub fn serve_when_ready(app: Arc<OnceLock<Router>>) -> Router {
+    let starting = Router::new()
+        .route("/healthz", get(api_healthz))
+        .route("/readyz", get(api_readyz_starting))
+        .route(
+            "/api/startup",
+            get(api_get_startup).route_layer(from_extractor::<RequireAdminAuth>()),
+        )
+        .fallback(|| async { (StatusCode::SERVICE_UNAVAILABLE, "clewdr is starting") });
+    Router::new().fallback_service(service_fn(move |req: Request| {
+        app.get().unwrap_or(&starting).to_owned().oneshot(req)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::header::AUTHORIZATION};
+
+    use super::*;
+    use crate::config::{TEST_ADMIN_PASSWORD, TEST_PASSWORD, install_test_config};
+
+    async fn startup_status(token: Option<&str>) -> StatusCode {
+        let mut req = Request::get("/api/startup");
+        if let Some(token) = token {
+            req = req.header(AUTHORIZATION, format!("Bearer {token}"));
+        }
+        let router = serve_when_ready(Arc::new(OnceLock::new()));
+        let res = router
+            .oneshot(req.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        res.status()
+    }
+
+    #[tokio::test]
+    async fn startup_report_needs_admin_while_starting() {
+        install_test_config();
+        assert_eq!(startup_status(None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            startup_status(Some(TEST_PASSWORD)).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            startup_status(Some(TEST_ADMIN_PASSWORD)).await,
+            StatusCode::OK
+        );
+    }
+}
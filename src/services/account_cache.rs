@@ -0,0 +1,437 @@
+//! Read-through cache of claude.ai account metadata per cookie
+//!
+//! Both backends read the organizations of a cookie through this cache
+//! instead of bootstrapping on every request. Entries live for
+//! `account_cache_ttl_secs`, concurrent misses for one cookie share a single
+//! upstream fetch, and a cookie returned as invalid drops its entry. The last
+//! known entries are kept in `account_cache.json` next to the config file, so
+//! a restart does not refetch every cookie at once.
+
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{
+        Arc, LazyLock, Mutex, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{error, warn};
+
+use crate::{
+    config::{CLEWDR_CONFIG, CONFIG_PATH, CookieStatus, Reason},
+    error::ClewdrError,
+    services::{
+        resources::{FnReporter, ResourceUsage, register_reporter},
+        transcript::hash_cookie,
+    },
+};
+
+static CACHE_PATH: LazyLock<PathBuf> = LazyLock::new(|| {
+    CONFIG_PATH
+        .parent()
+        .map(|p| p.join("account_cache.json"))
+        .unwrap_or_else(|| PathBuf::from("account_cache.json"))
+});
+
+static ACCOUNT_CACHE: LazyLock<AccountCache> = LazyLock::new(|| {
+    register_reporter(FnReporter::new("account_cache", || {
+        let entries = ACCOUNT_CACHE.entries();
+        ResourceUsage {
+            bytes: Some(entries.to_string().len() as u64),
+            entries: entries.as_object().map_or(0, |e| e.len() as u64),
+        }
+    }));
+    if CLEWDR_CONFIG.load().no_fs {
+        return AccountCache::default();
+    }
+    match std::fs::read_to_string(CACHE_PATH.as_path()) {
+        Ok(text) => AccountCache::from_json(&text).unwrap_or_else(|e| {
+            warn!("Failed to parse account cache: {}", e);
+            AccountCache::default()
+        }),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => AccountCache::default(),
+        Err(e) => {
+            warn!("Failed to read account cache: {}", e);
+            AccountCache::default()
+        }
+    }
+});
+
+/// Serializes saves so an older snapshot never overwrites a newer one
+static WRITE_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// What claude.ai knows about the account behind a cookie
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccountMetadata {
+    /// Organization chats are sent to, the chat capable one with most capabilities
+    pub org_uuid: String,
+    /// First chat capable organization of the memberships, authorized for OAuth
+    pub membership_org_uuid: String,
+    pub email: String,
+    /// Capabilities of the membership organization
+    pub capabilities: Vec<String>,
+    /// Chat organization as returned upstream, its flags are checked on every use
+    pub organization: Value,
+    /// When the metadata was fetched, in unix seconds
+    pub verified_at: i64,
+}
+
+impl AccountMetadata {
+    /// Builds the metadata from the `api/bootstrap` and `api/organizations` responses
+    pub fn from_responses(
+        bootstrap: &Value,
+        organizations: &Value,
+        now: i64,
+    ) -> Result<Self, ClewdrError> {
+        if bootstrap["account"].is_null() {
+            return Err(Reason::Null.into());
+        }
+        let has_chat = |org: &Value| {
+            org["capabilities"]
+                .as_array()
+                .is_some_and(|c| c.iter().any(|c| c.as_str() == Some("chat")))
+        };
+        let membership_org = bootstrap["account"]["memberships"]
+            .as_array()
+            .ok_or(ClewdrError::UnexpectedNone {
+                msg: "Failed to get memberships from bootstrap",
+            })?
+            .iter()
+            .map(|m| &m["organization"])
+            .find(|org| org.is_object() && has_chat(org))
+            .ok_or(ClewdrError::UnexpectedNone {
+                msg: "Failed to find a valid organization in bootstrap",
+            })?;
+        let organization = organizations
+            .as_array()
+            .and_then(|a| {
+                a.iter().filter(|org| has_chat(org)).max_by_key(|org| {
+                    org["capabilities"]
+                        .as_array()
+                        .map(|c| c.len())
+                        .unwrap_or_default()
+                })
+            })
+            .ok_or(ClewdrError::UnexpectedNone {
+                msg: "Failed to find a valid organization in response",
+            })?;
+        let uuid = |org: &Value| {
+            org["uuid"]
+                .as_str()
+                .map(str::to_string)
+                .ok_or(ClewdrError::UnexpectedNone {
+                    msg: "Failed to find UUID in organization response",
+                })
+        };
+        Ok(Self {
+            org_uuid: uuid(organization)?,
+            membership_org_uuid: uuid(membership_org)?,
+            email: bootstrap["account"]["email_address"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            capabilities: membership_org["capabilities"]
+                .as_array()
+                .map(|a| {
+                    a.iter()
+                        .filter_map(|c| c.as_str())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            organization: organization.to_owned(),
+            verified_at: now,
+        })
+    }
+
+    /// Whether the membership organization has a paid plan
+    pub fn is_pro(&self) -> bool {
+        self.capabilities.iter().any(|c| {
+            c.contains("pro")
+                || c.contains("enterprise")
+                || c.contains("raven")
+                || c.contains("max")
+        })
+    }
+
+    fn is_fresh(&self, ttl_secs: u64, now: i64) -> bool {
+        now.saturating_sub(self.verified_at) < ttl_secs as i64
+    }
+}
+
+/// Lookups served since startup and the state of the entries
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountCacheStats {
+    pub entries: usize,
+    /// Entries past the TTL, refetched on their next use
+    pub stale: usize,
+    pub hits: u64,
+    pub misses: u64,
+    /// Misses served by a fetch another request had started
+    pub coalesced: u64,
+    pub invalidations: u64,
+    pub hit_rate: Option<f64>,
+}
+
+#[derive(Default)]
+struct AccountCache {
+    entries: Mutex<HashMap<String, AccountMetadata>>,
+    /// One lock per cookie being fetched, so concurrent misses share the fetch
+    fetching: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    coalesced: AtomicU64,
+    invalidations: AtomicU64,
+}
+
+impl AccountCache {
+    fn from_json(text: &str) -> Result<Self, serde_json::Error> {
+        Ok(Self {
+            entries: Mutex::new(serde_json::from_str(text)?),
+            ..Default::default()
+        })
+    }
+
+    fn entries(&self) -> Value {
+        let entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        serde_json::to_value(&*entries).unwrap_or_default()
+    }
+
+    fn fresh(&self, key: &str, ttl_secs: u64, now: i64) -> Option<AccountMetadata> {
+        self.entries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(key)
+            .filter(|m| m.is_fresh(ttl_secs, now))
+            .cloned()
+    }
+
+    /// Returns the metadata of `key`, fetching it once when missing or stale
+    ///
+    /// # Returns
+    /// * The metadata, and whether this call fetched it
+    async fn get_or_fetch<F>(
+        &self,
+        key: &str,
+        ttl_secs: u64,
+        now: i64,
+        fetch: impl FnOnce() -> F,
+    ) -> Result<(AccountMetadata, bool), ClewdrError>
+    where
+        F: Future<Output = Result<AccountMetadata, ClewdrError>>,
+    {
+        if let Some(metadata) = self.fresh(key, ttl_secs, now) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok((metadata, false));
+        }
+        let lock = self
+            .fetching
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(key.to_string())
+            .or_default()
+            .to_owned();
+        let _guard = lock.lock().await;
+        // another request may have fetched it while this one waited
+        if let Some(metadata) = self.fresh(key, ttl_secs, now) {
+            self.coalesced.fetch_add(1, Ordering::Relaxed);
+            return Ok((metadata, false));
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let result = fetch().await;
+        if let Ok(metadata) = &result {
+            self.entries
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(key.to_string(), metadata.to_owned());
+        }
+        self.fetching
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(key);
+        result.map(|metadata| (metadata, true))
+    }
+
+    fn invalidate(&self, key: &str) -> bool {
+        let removed = self
+            .entries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(key)
+            .is_some();
+        if removed {
+            self.invalidations.fetch_add(1, Ordering::Relaxed);
+        }
+        removed
+    }
+
+    fn stats(&self, ttl_secs: u64, now: i64) -> AccountCacheStats {
+        let entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        let load = |a: &AtomicU64| a.load(Ordering::Relaxed);
+        let hits = load(&self.hits) + load(&self.coalesced);
+        let lookups = hits + load(&self.misses);
+        AccountCacheStats {
+            entries: entries.len(),
+            stale: entries
+                .values()
+                .filter(|m| !m.is_fresh(ttl_secs, now))
+                .count(),
+            hits: load(&self.hits),
+            misses: load(&self.misses),
+            coalesced: load(&self.coalesced),
+            invalidations: load(&self.invalidations),
+            hit_rate: (lookups > 0).then(|| hits as f64 / lookups as f64),
+        }
+    }
+}
+
+fn save() {
+    if CLEWDR_CONFIG.load().no_fs {
+        return;
+    }
+    tokio::spawn(async {
+        let _guard = WRITE_LOCK.lock().await;
+        let text = ACCOUNT_CACHE.entries().to_string();
+        let tmp = CACHE_PATH.with_extension("json.tmp");
+        let result = async {
+            tokio::fs::write(&tmp, text).await?;
+            tokio::fs::rename(&tmp, CACHE_PATH.as_path()).await
+        };
+        if let Err(e) = result.await {
+            error!("Failed to save account cache: {}", e);
+        }
+    });
+}
+
+/// Returns the account metadata of a cookie, fetching it on a miss
+///
+/// # Arguments
+/// * `cookie` - Cookie the metadata belongs to
+/// * `fetch` - Fetches the metadata upstream, called at most once per miss
+pub async fn account_metadata<F>(
+    cookie: &CookieStatus,
+    fetch: impl FnOnce() -> F,
+) -> Result<AccountMetadata, ClewdrError>
+where
+    F: Future<Output = Result<AccountMetadata, ClewdrError>>,
+{
+    let ttl_secs = CLEWDR_CONFIG.load().account_cache_ttl_secs;
+    if ttl_secs == 0 {
+        return fetch().await;
+    }
+    let now = chrono::Utc::now().timestamp();
+    let (metadata, fetched) = ACCOUNT_CACHE
+        .get_or_fetch(&hash_cookie(cookie), ttl_secs, now, fetch)
+        .await?;
+    if fetched {
+        save();
+    }
+    Ok(metadata)
+}
+
+/// Drops the cached metadata of a cookie, after upstream rejected it
+pub fn invalidate(cookie: &CookieStatus) {
+    if ACCOUNT_CACHE.invalidate(&hash_cookie(cookie)) {
+        save();
+    }
+}
+
+/// Returns the cache statistics since startup
+pub fn stats() -> AccountCacheStats {
+    let ttl_secs = CLEWDR_CONFIG.load().account_cache_ttl_secs;
+    ACCOUNT_CACHE.stats(ttl_secs, chrono::Utc::now().timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn metadata(now: i64) -> AccountMetadata {
+        let bootstrap = json!({
+            "account": {
+                "email_address": "user@example.com",
+                "memberships": [
+                    { "organization": { "uuid": "api-org", "capabilities": ["api"] } },
+                    { "organization": { "uuid": "member-org", "capabilities": ["chat", "claude_pro"] } },
+                ],
+            },
+        });
+        let organizations = json!([
+            { "uuid": "small-org", "capabilities": ["chat"] },
+            { "uuid": "chat-org", "capabilities": ["chat", "claude_pro"] },
+        ]);
+        AccountMetadata::from_responses(&bootstrap, &organizations, now).unwrap()
+    }
+
+    #[test]
+    fn picks_chat_organizations() {
+        let metadata = metadata(0);
+        assert_eq!(metadata.org_uuid, "chat-org");
+        assert_eq!(metadata.membership_org_uuid, "member-org");
+        assert!(metadata.is_pro());
+        let missing = AccountMetadata::from_responses(&json!({ "account": null }), &json!([]), 0);
+        assert!(matches!(
+            missing,
+            Err(ClewdrError::InvalidCookie {
+                reason: Reason::Null
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn concurrent_misses_fetch_once() {
+        let cache = AccountCache::default();
+        let fetches = AtomicU64::new(0);
+        let lookups = (0..8).map(|_| {
+            cache.get_or_fetch("cookie", 60, 100, || async {
+                fetches.fetch_add(1, Ordering::Relaxed);
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                Ok(metadata(100))
+            })
+        });
+        let results = futures::future::join_all(lookups).await;
+        assert!(
+            results
+                .iter()
+                .all(|r| r.as_ref().unwrap().0.org_uuid == "chat-org")
+        );
+        assert_eq!(fetches.load(Ordering::Relaxed), 1);
+        let stats = cache.stats(60, 100);
+        assert_eq!((stats.misses, stats.coalesced), (1, 7));
+    }
+
+    #[tokio::test]
+    async fn expires_and_invalidates() {
+        let cache = AccountCache::default();
+        let lookup =
+            |now| cache.get_or_fetch("cookie", 60, now, move || async move { Ok(metadata(now)) });
+        assert!(lookup(0).await.unwrap().1);
+        assert!(!lookup(59).await.unwrap().1);
+        assert_eq!(cache.stats(60, 60).stale, 1);
+        // past the TTL the entry is refetched
+        assert!(lookup(60).await.unwrap().1);
+        // a rejected cookie is fetched again on its next use
+        assert!(cache.invalidate("cookie"));
+        assert!(lookup(61).await.unwrap().1);
+        assert_eq!(cache.stats(60, 61).invalidations, 1);
+    }
+
+    #[test]
+    fn persists_last_known_entries() {
+        let cache = AccountCache::default();
+        cache
+            .entries
+            .lock()
+            .unwrap()
+            .insert("cookie".to_string(), metadata(7));
+        let restored = AccountCache::from_json(&cache.entries().to_string()).unwrap();
+        assert_eq!(restored.fresh("cookie", 60, 10), Some(metadata(7)));
+    }
+}
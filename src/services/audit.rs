@@ -0,0 +1,300 @@
+//! Audit trail of admin actions
+//!
+//! Every mutating admin endpoint records what was done, failed attempts
+//! included. Entries name what changed but never hold values, so secrets
+//! stay out of the trail. They are kept in `audit.jsonl` next to the config
+//! file, the last [`MAX_ENTRIES`] are served. With `no_fs` they only live in
+//! memory.
+
+use std::{
+    collections::VecDeque,
+    path::PathBuf,
+    sync::{LazyLock, Mutex, PoisonError},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use strum::Display;
+use tokio::io::AsyncWriteExt;
+use tracing::{error, warn};
+
+use crate::config::{CLEWDR_CONFIG, CONFIG_PATH, ClewdrConfig};
+
+/// Entries kept, the file is compacted once it holds twice as many
+pub const MAX_ENTRIES: usize = 1000;
+/// Actor of requests authenticated with the admin password
+pub const TOKEN_ACTOR: &str = "token auth";
+
+static AUDIT_PATH: LazyLock<PathBuf> = LazyLock::new(|| {
+    CONFIG_PATH
+        .parent()
+        .map(|p| p.join("audit.jsonl"))
+        .unwrap_or_else(|| PathBuf::from("audit.jsonl"))
+});
+
+static AUDIT_LOG: LazyLock<Mutex<AuditLog>> = LazyLock::new(|| {
+    if CLEWDR_CONFIG.load().no_fs {
+        return Default::default();
+    }
+    match std::fs::read_to_string(AUDIT_PATH.as_path()) {
+        Ok(text) => Mutex::new(AuditLog::from_lines(&text)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Default::default(),
+        Err(e) => {
+            warn!("Failed to read audit log: {}", e);
+            Default::default()
+        }
+    }
+});
+
+/// Serializes file writes so appends and compactions never interleave
+static WRITE_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Kind of admin action
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum AuditAction {
+    ConfigUpdate,
+    ConfigImport,
+    ConfigReveal,
+    CookieAdd,
+    CookieUpdate,
+    CookieDelete,
+    TranscriptPurge,
+    AdminRotate,
+    ApiKeyCreate,
+    ApiKeyRevoke,
+    CacheFlush,
+    RateLimitReset,
+}
+
+/// One recorded admin action
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Increasing id, the cursor of `before`
+    pub id: u64,
+    /// Unix timestamp in seconds
+    pub timestamp: i64,
+    pub actor: String,
+    pub action: AuditAction,
+    /// What the action touched, names only
+    pub summary: String,
+    /// Failure reason, `None` when the action succeeded
+    pub error: Option<String>,
+}
+
+#[derive(Default)]
+struct AuditLog {
+    entries: VecDeque<AuditEntry>,
+    next_id: u64,
+    /// Lines in the file, compacted past twice [`MAX_ENTRIES`]
+    file_lines: usize,
+}
+
+impl AuditLog {
+    /// Restores the log from the lines of `audit.jsonl`, skipping broken ones
+    fn from_lines(text: &str) -> Self {
+        let mut log = Self::default();
+        for line in text.lines().filter(|l| !l.trim().is_empty()) {
+            log.file_lines += 1;
+            let Ok(entry) = serde_json::from_str::<AuditEntry>(line) else {
+                continue;
+            };
+            log.next_id = log.next_id.max(entry.id + 1);
+            log.keep(entry);
+        }
+        log
+    }
+
+    fn keep(&mut self, entry: AuditEntry) {
+        if self.entries.len() == MAX_ENTRIES {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    fn push(
+        &mut self,
+        action: AuditAction,
+        summary: String,
+        error: Option<String>,
+        now: i64,
+    ) -> AuditEntry {
+        let entry = AuditEntry {
+            id: self.next_id,
+            timestamp: now,
+            actor: TOKEN_ACTOR.to_string(),
+            action,
+            summary,
+            error,
+        };
+        self.next_id += 1;
+        self.keep(entry.to_owned());
+        entry
+    }
+
+    /// Newest entries first, only those older than `before` when given
+    fn page(&self, limit: usize, before: Option<u64>) -> Vec<AuditEntry> {
+        self.entries
+            .iter()
+            .rev()
+            .filter(|e| before.is_none_or(|b| e.id < b))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    /// Counts an appended line, returns the compacted file when it is due
+    fn appended(&mut self) -> Option<String> {
+        self.file_lines += 1;
+        if self.file_lines < 2 * MAX_ENTRIES {
+            return None;
+        }
+        self.file_lines = self.entries.len();
+        Some(self.lines())
+    }
+
+    fn lines(&self) -> String {
+        self.entries
+            .iter()
+            .filter_map(|e| serde_json::to_string(e).ok())
+            .map(|l| l + "\n")
+            .collect()
+    }
+}
+
+/// Names of the top level config fields that differ, cookies left out
+pub fn config_changes(old: &ClewdrConfig, new: &ClewdrConfig) -> String {
+    let (old, new) = (serde_json::json!(old), serde_json::json!(new));
+    let changed = changed_keys(&old, &new)
+        .into_iter()
+        .filter(|k| k != "cookie_array" && k != "wasted_cookie")
+        .collect::<Vec<_>>();
+    if changed.is_empty() {
+        "no changes".to_string()
+    } else {
+        format!("changed: {}", changed.join(", "))
+    }
+}
+
+fn changed_keys(old: &Value, new: &Value) -> Vec<String> {
+    let (Some(old), Some(new)) = (old.as_object(), new.as_object()) else {
+        return vec![];
+    };
+    let mut keys = old
+        .keys()
+        .chain(new.keys())
+        .filter(|k| old.get(*k) != new.get(*k))
+        .cloned()
+        .collect::<Vec<_>>();
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+/// Records an admin action, failed ones with their reason
+///
+/// # Arguments
+/// * `action` - What was attempted
+/// * `summary` - What it touched, never secret values
+/// * `error` - Failure reason, `None` on success
+pub async fn record(action: AuditAction, summary: String, error: Option<String>) {
+    let now = chrono::Utc::now().timestamp();
+    // hold the write lock first so the file keeps the order of the ids
+    let _guard = WRITE_LOCK.lock().await;
+    let (entry, compacted) = {
+        let mut log = AUDIT_LOG.lock().unwrap_or_else(PoisonError::into_inner);
+        let entry = log.push(action, summary, error, now);
+        let compacted = log.appended();
+        (entry, compacted)
+    };
+    if CLEWDR_CONFIG.load().no_fs {
+        return;
+    }
+    let result = match compacted {
+        Some(lines) => rewrite(lines).await,
+        None => append(&entry).await,
+    };
+    if let Err(e) = result {
+        error!("Failed to write audit log: {}", e);
+    }
+}
+
+async fn append(entry: &AuditEntry) -> std::io::Result<()> {
+    let mut line = serde_json::to_vec(entry)?;
+    line.push(b'\n');
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(AUDIT_PATH.as_path())
+        .await?;
+    file.write_all(&line).await
+}
+
+async fn rewrite(lines: String) -> std::io::Result<()> {
+    let tmp = AUDIT_PATH.with_extension("jsonl.tmp");
+    tokio::fs::write(&tmp, lines).await?;
+    tokio::fs::rename(&tmp, AUDIT_PATH.as_path()).await
+}
+
+/// Returns recorded actions, newest first
+///
+/// # Arguments
+/// * `limit` - Most entries returned
+/// * `before` - Only entries with a smaller id, for paging
+pub fn entries(limit: usize, before: Option<u64>) -> Vec<AuditEntry> {
+    AUDIT_LOG
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .page(limit, before)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn pages_newest_first_and_caps() {
+        let mut log = AuditLog::default();
+        for i in 0..MAX_ENTRIES + 5 {
+            let error = (i % 2 == 1).then(|| "invalid".to_string());
+            log.push(AuditAction::CookieAdd, format!("cookie {i}"), error, 0);
+        }
+        assert_eq!(log.entries.len(), MAX_ENTRIES);
+        let ids = |page: Vec<AuditEntry>| page.iter().map(|e| e.id).collect::<Vec<_>>();
+        let total = MAX_ENTRIES as u64 + 5;
+        assert_eq!(ids(log.page(2, None)), [total - 1, total - 2]);
+        assert_eq!(ids(log.page(2, Some(10))), [9, 8]);
+        // evicted entries are gone
+        assert!(log.page(10, Some(5)).is_empty());
+    }
+
+    #[test]
+    fn restores_and_compacts_the_file() {
+        let mut log = AuditLog::default();
+        log.push(AuditAction::ConfigUpdate, "changed: port".into(), None, 1);
+        let text = format!("{}not json\n", log.lines());
+        let mut restored = AuditLog::from_lines(&text);
+        assert_eq!(restored.entries.len(), 1);
+        assert_eq!(restored.file_lines, 2);
+        let next = restored.push(AuditAction::CookieDelete, String::new(), None, 2);
+        assert_eq!(next.id, 1);
+
+        restored.file_lines = 2 * MAX_ENTRIES - 1;
+        let compacted = restored.appended().unwrap();
+        assert_eq!(compacted.lines().count(), 2);
+        assert_eq!(restored.file_lines, 2);
+    }
+
+    #[test]
+    fn changes_name_keys_only() {
+        let old = json!({ "port": 8484, "password": "old", "max_retries": 5 });
+        let new = json!({ "port": 8484, "password": "new", "proxy": "socks5://x" });
+        assert_eq!(
+            changed_keys(&old, &new),
+            ["max_retries", "password", "proxy"]
+        );
+    }
+}
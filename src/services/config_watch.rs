@@ -0,0 +1,296 @@
+//! Reload of the config file when it is edited on disk
+//!
+//! The file is polled rather than watched: its modification time and size
+//! are checked every [`POLL_INTERVAL`], and a change is only read once the
+//! file has stayed the same for a whole interval, so a file still being
+//! written is not read halfway. Contents that fail to parse or hold values a
+//! config update would be rejected for leave the live config as it was.
+//!
+//! clewdr saves the file itself on every config and cookie change. Those
+//! contents are remembered by [`remember_saved`] and never reloaded, or a
+//! save racing a newer change could roll it back. For the same reason the
+//! cookies in the file are compared with the ones it held when it was last
+//! read, not with the live pool, which can have changes not saved yet.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    hash::{DefaultHasher, Hash, Hasher},
+    path::Path,
+    sync::{LazyLock, Mutex, PoisonError},
+    time::{Duration, SystemTime},
+};
+
+use tracing::{debug, info, warn};
+
+use crate::{
+    config::{
+        CLEWDR_CONFIG, CONFIG_PATH, ClewdrConfig, CookieStatus, StorageBackend, read_config_file,
+    },
+    services::{audit::config_changes, cookie_actor::CookieActorHandle},
+};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// Own saves remembered, enough for the saves a burst of cookie changes makes
+const REMEMBERED_SAVES: usize = 16;
+
+/// Digests of the latest contents clewdr wrote to the config file
+static SAVED: LazyLock<Mutex<VecDeque<u64>>> =
+    LazyLock::new(|| Mutex::new(VecDeque::with_capacity(REMEMBERED_SAVES)));
+
+fn digest(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Remembers contents about to be written to the config file, so they are not reloaded
+pub fn remember_saved(text: &str) {
+    let mut saved = SAVED.lock().unwrap_or_else(PoisonError::into_inner);
+    if saved.len() == REMEMBERED_SAVES {
+        saved.pop_front();
+    }
+    saved.push_back(digest(text));
+}
+
+fn is_own_save(text: &str) -> bool {
+    SAVED
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .contains(&digest(text))
+}
+
+/// Modification time and size of a file, `None` while it does not exist
+type Stamp = Option<(SystemTime, u64)>;
+
+async fn stamp(path: &Path) -> Stamp {
+    let meta = tokio::fs::metadata(path).await.ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
+
+/// Tells when a changed file has settled
+struct Debounce {
+    /// Stamp of the previous poll
+    last: Stamp,
+    /// Stamp the file had when it was last read
+    read: Stamp,
+}
+
+impl Debounce {
+    fn new(stamp: Stamp) -> Self {
+        Self {
+            last: stamp,
+            read: stamp,
+        }
+    }
+
+    /// Whether the file is due for reading, given its stamp at this poll
+    fn due(&mut self, stamp: Stamp) -> bool {
+        if stamp != self.last {
+            // still changing, or just changed
+            self.last = stamp;
+            return false;
+        }
+        if stamp == self.read || stamp.is_none() {
+            return false;
+        }
+        self.read = stamp;
+        true
+    }
+}
+
+/// Cookies to make the pool, when the file has other cookies than it had when last read
+///
+/// # Arguments
+/// * `storage` - Backend the cookie actor was started with, only the file one reconciles
+/// * `file` - Cookies in the file now
+/// * `read` - Cookies in the file when it was last read, becomes `file`
+fn changed_cookies(
+    storage: StorageBackend,
+    file: &HashSet<CookieStatus>,
+    read: &mut HashSet<CookieStatus>,
+) -> Option<HashSet<CookieStatus>> {
+    // cookies compare by their value, usage and reset times are not looked at
+    let changed = file != read;
+    *read = file.to_owned();
+    (storage == StorageBackend::File && changed).then(|| file.to_owned())
+}
+
+/// Reads the config file and swaps it in when it was edited
+///
+/// The reloaded config is checked like a config update and validated like one
+/// loaded at startup. With the cookie pool kept in the file, cookies added to
+/// or removed from `cookie_array` since the last read replace the pool of the
+/// cookie actor.
+///
+/// # Arguments
+/// * `handle` - Handle of the cookie actor
+/// * `storage` - Backend the cookie actor was started with
+/// * `read` - Cookies the file held when it was last read or saved, updated on reload
+async fn reload(
+    handle: &CookieActorHandle,
+    storage: StorageBackend,
+    read: &mut HashSet<CookieStatus>,
+) {
+    let path = CONFIG_PATH.as_path();
+    let (text, migrated) = match read_config_file(path) {
+        Ok(file) => file,
+        Err(e) => {
+            warn!("Config file changed but was not reloaded: {}", e);
+            return;
+        }
+    };
+    let own_save = migrated.is_none() && is_own_save(&text);
+    if let Some(migrated) = &migrated
+        && !migrated.keep_original(path)
+    {
+        return;
+    }
+    let file = match ClewdrConfig::figment(&text).extract_lossy::<ClewdrConfig>() {
+        Ok(file) => file,
+        Err(e) => {
+            warn!(
+                "Config file changed but does not parse, not reloaded: {}",
+                e
+            );
+            return;
+        }
+    };
+
+    if own_save {
+        *read = file.cookie_array;
+        return;
+    }
+    let errors = file.to_owned().reloaded(&CLEWDR_CONFIG.load()).check();
+    if !errors.is_empty() {
+        let errors = errors
+            .iter()
+            .map(|e| format!("{} {}", e.field, e.message))
+            .collect::<Vec<_>>();
+        warn!(
+            "Config file changed but has invalid values, not reloaded: {}",
+            errors.join(", ")
+        );
+        return;
+    }
+
+    let cookies = changed_cookies(storage, &file.cookie_array, read);
+    let mut summary = String::new();
+    CLEWDR_CONFIG.rcu(|old| {
+        let new = file.to_owned().reloaded(old).validate();
+        summary = config_changes(old, &new);
+        new
+    });
+    if summary == "no changes" {
+        debug!("Config file reloaded, no changes");
+    } else {
+        info!("Config file reloaded, {}", summary);
+    }
+    if let Some(cookies) = cookies {
+        match handle.replace_pool(cookies).await {
+            Ok((added, removed)) => {
+                info!("Config file reloaded, cookies +{} -{}", added, removed)
+            }
+            Err(e) => warn!("Failed to reload cookies from the config file: {}", e),
+        }
+    }
+}
+
+/// Starts polling the config file and reloading it when it is edited
+///
+/// Nothing is watched with `no_fs` or in demo mode, neither reads the file
+/// after startup.
+///
+/// # Arguments
+/// * `handle` - Handle of the cookie actor, for cookies edited in the file
+pub fn init_config_watch(handle: CookieActorHandle) {
+    let config = CLEWDR_CONFIG.load();
+    if config.no_fs || config.demo {
+        return;
+    }
+    let storage = config.storage;
+    // loaded from the file at startup
+    let mut read = config.cookie_array.to_owned();
+    tokio::spawn(async move {
+        let path = CONFIG_PATH.as_path();
+        let mut debounce = Debounce::new(stamp(path).await);
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            if debounce.due(stamp(path).await) {
+                reload(&handle, storage, &mut read).await;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: u64, len: u64) -> Stamp {
+        Some((SystemTime::UNIX_EPOCH + Duration::from_secs(secs), len))
+    }
+
+    #[test]
+    fn reads_once_the_file_settles() {
+        let mut debounce = Debounce::new(at(1, 100));
+        assert!(!debounce.due(at(1, 100)));
+        // a write in progress, then a finished one
+        assert!(!debounce.due(at(2, 40)));
+        assert!(!debounce.due(at(3, 120)));
+        assert!(debounce.due(at(3, 120)));
+        assert!(!debounce.due(at(3, 120)));
+        // deleted and recreated as it was
+        assert!(!debounce.due(None));
+        assert!(!debounce.due(None));
+        assert!(!debounce.due(at(3, 120)));
+        assert!(!debounce.due(at(3, 120)));
+    }
+
+    fn cookie(i: usize) -> CookieStatus {
+        let body = format!("{:0<86}", format!("watch-test-{i:02}-"));
+        CookieStatus::new(&format!("sk-ant-sid01-{body}-{i:06}AA"), None).unwrap()
+    }
+
+    #[test]
+    fn pool_follows_cookie_edits_only() {
+        let mut read = HashSet::from([cookie(1), cookie(2)]);
+        // an unrelated edit, with usage recorded on a cookie since the last read
+        let mut used = cookie(1);
+        used.reset_time = Some(1767225600);
+        let file = HashSet::from([used, cookie(2)]);
+        assert_eq!(
+            changed_cookies(StorageBackend::File, &file, &mut read),
+            None
+        );
+
+        let file = HashSet::from([cookie(2), cookie(3)]);
+        assert_eq!(
+            changed_cookies(StorageBackend::File, &file, &mut read),
+            Some(file.to_owned())
+        );
+        assert_eq!(read, file);
+        assert_eq!(
+            changed_cookies(StorageBackend::File, &file, &mut read),
+            None
+        );
+
+        let file = HashSet::from([cookie(4)]);
+        assert_eq!(
+            changed_cookies(StorageBackend::Sqlite, &file, &mut read),
+            None
+        );
+        assert_eq!(read, file);
+    }
+
+    #[test]
+    fn own_saves_are_recognized() {
+        remember_saved("port = 8484\n");
+        assert!(is_own_save("port = 8484\n"));
+        assert!(!is_own_save("port = 8485\n"));
+        for i in 0..REMEMBERED_SAVES {
+            remember_saved(&format!("max_retries = {i}\n"));
+        }
+        assert!(!is_own_save("port = 8484\n"));
+    }
+}
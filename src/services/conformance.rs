@@ -0,0 +1,716 @@
+//! Backend conformance suite
+//!
+//! Standard scenarios run against a live instance through one backend, each
+//! scoring the invariants clients rely on. Some invariants are not met by the
+//! backend itself but by ClewdR's adaptation layer, the report marks those.
+//! Pointed at a `--demo` instance the suite needs no credentials. Reports are
+//! saved next to the config file and served by `/api/conformance`.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        LazyLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use clap::ValueEnum;
+use colored::Colorize;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use snafu::ResultExt;
+use strum::Display;
+use tracing::warn;
+use url::Url;
+use wreq::Client;
+
+use crate::{
+    config::{ADMIN_HEADER, CONFIG_PATH, DEMO_HEADER, SMOKE_HEADER},
+    error::{ClewdrError, WreqSnafu},
+    services::smoke::{
+        SMOKE_MODEL, check_error_envelope, check_message_response, check_stream_events, read_json,
+        stream_events,
+    },
+};
+
+const SHORT_PROMPT: &str = "Reply with the single word: pong";
+const LONG_PROMPT: &str = "Write about 150 words on the history of the telescope.";
+const COUNT_PROMPT: &str = "Count from one to ten in words, separated by spaces.";
+const TOOL_PROMPT: &str = "What is the weather in Paris?";
+const STOP_SEQUENCE: &str = "e";
+const TOOL_NAME: &str = "get_weather";
+/// Words of the oversized prompt, past a 200k token context window
+const OVERSIZED_WORDS: usize = 250_000;
+/// Deltas a long stream must at least be split into
+const MIN_DELTAS: usize = 5;
+
+/// Directory of the saved reports, next to the config file
+static REPORT_DIR: LazyLock<PathBuf> = LazyLock::new(|| {
+    CONFIG_PATH
+        .parent()
+        .map(|p| p.join("conformance"))
+        .unwrap_or_else(|| PathBuf::from("conformance"))
+});
+
+/// Backend the suite runs against
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ConformanceBackend {
+    /// claude.ai cookies, `/v1/messages`
+    Web,
+    /// Claude Code tokens, `/code/v1/messages`
+    Code,
+}
+
+impl ConformanceBackend {
+    fn messages_path(self) -> &'static str {
+        match self {
+            ConformanceBackend::Web => "v1/messages",
+            ConformanceBackend::Code => "code/v1/messages",
+        }
+    }
+
+    /// Whether ClewdR provides the invariant on top of the backend
+    fn adapted(self, invariant: &str) -> bool {
+        match self {
+            // claude.ai reports no token usage and knows no stop sequences
+            ConformanceBackend::Web => matches!(
+                invariant,
+                "usage_reported" | "stream_usage" | "stop_honored" | "stop_reason_reported"
+            ),
+            ConformanceBackend::Code => false,
+        }
+    }
+}
+
+/// Options of the `conformance` subcommand
+#[derive(clap::Args, Debug, Clone)]
+pub struct ConformanceArgs {
+    /// Base URL of the running instance, e.g. http://127.0.0.1:8484
+    #[arg(long)]
+    pub url: Url,
+    /// API password for the completion endpoints
+    #[arg(long)]
+    pub key: String,
+    /// Admin password, enables the checks reading the cookie pool and keeps
+    /// the scenarios out of usage stats, sent next to `key` on completions
+    #[arg(long)]
+    pub admin_key: Option<String>,
+    /// Backend to run the scenarios against
+    #[arg(long, value_enum)]
+    pub backend: ConformanceBackend,
+    /// Model used by the scenarios
+    #[arg(long, default_value = SMOKE_MODEL)]
+    pub model: String,
+    /// Timeout of each request in seconds
+    #[arg(long, default_value_t = 120)]
+    pub timeout: u64,
+    /// Where to save the report, defaults to the report read by the dashboard
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+    /// Print the report without saving it
+    #[arg(long)]
+    pub no_save: bool,
+}
+
+/// Outcome of one invariant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Pass,
+    Fail,
+    /// Not run, missing the access it needs
+    Skip,
+}
+
+/// One invariant checked by a scenario
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InvariantResult {
+    pub scenario: String,
+    pub invariant: String,
+    pub verdict: Verdict,
+    /// Observed value on success, failure or skip reason otherwise
+    pub detail: String,
+    /// Met by ClewdR's adaptation layer rather than by the backend
+    pub adapted: bool,
+}
+
+/// Scored result of a conformance run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConformanceReport {
+    pub backend: ConformanceBackend,
+    pub model: String,
+    /// Whether the instance answered in demo mode
+    pub demo: bool,
+    /// Unix timestamp of the end of the run
+    pub finished_at: i64,
+    pub passed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    /// Share of the invariants run that passed
+    pub score: f64,
+    pub invariants: Vec<InvariantResult>,
+}
+
+impl ConformanceReport {
+    /// Default location of the report of a backend
+    pub fn path(backend: ConformanceBackend) -> PathBuf {
+        REPORT_DIR.join(format!("{backend}.json"))
+    }
+
+    pub async fn save(&self, path: &Path) -> Result<(), ClewdrError> {
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        Ok(tokio::fs::write(path, serde_json::to_vec_pretty(self)?).await?)
+    }
+}
+
+/// Reads the last saved report of every backend
+pub async fn load_reports() -> Vec<ConformanceReport> {
+    let mut reports = vec![];
+    for backend in ConformanceBackend::value_variants() {
+        let path = ConformanceReport::path(*backend);
+        let bytes = match tokio::fs::read(&path).await {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => {
+                warn!(
+                    "Failed to read conformance report {}: {}",
+                    path.display(),
+                    e
+                );
+                continue;
+            }
+        };
+        match serde_json::from_slice(&bytes) {
+            Ok(report) => reports.push(report),
+            Err(e) => warn!("Invalid conformance report {}: {}", path.display(), e),
+        }
+    }
+    reports
+}
+
+/// Runs the scenarios against a live instance
+pub struct ConformanceRunner {
+    client: Client,
+    args: ConformanceArgs,
+    demo: AtomicBool,
+    results: Vec<InvariantResult>,
+}
+
+impl ConformanceRunner {
+    pub fn new(args: ConformanceArgs) -> Result<Self, ClewdrError> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(args.timeout))
+            .build()
+            .context(WreqSnafu {
+                msg: "Failed to create HTTP client",
+            })?;
+        Ok(Self {
+            client,
+            args,
+            demo: AtomicBool::new(false),
+            results: vec![],
+        })
+    }
+
+    /// Runs every scenario in order and scores the invariants
+    pub async fn run(mut self) -> ConformanceReport {
+        self.simple_completion().await;
+        self.long_streaming().await;
+        self.stop_sequence().await;
+        let tool_use = self.forced_tool_choice().await;
+        self.tool_round_trip(tool_use).await;
+        self.oversized_prompt().await;
+        self.mid_stream_cancel().await;
+
+        let count = |verdict| self.results.iter().filter(|r| r.verdict == verdict).count();
+        let (passed, failed, skipped) = (
+            count(Verdict::Pass),
+            count(Verdict::Fail),
+            count(Verdict::Skip),
+        );
+        ConformanceReport {
+            backend: self.args.backend,
+            model: self.args.model,
+            demo: self.demo.into_inner(),
+            finished_at: chrono::Utc::now().timestamp(),
+            passed,
+            failed,
+            skipped,
+            score: passed as f64 / (passed + failed).max(1) as f64,
+            invariants: self.results,
+        }
+    }
+
+    fn record(&mut self, scenario: &str, invariant: &str, result: Result<String, String>) {
+        let (verdict, detail) = match result {
+            Ok(detail) => (Verdict::Pass, detail),
+            Err(reason) => (Verdict::Fail, reason),
+        };
+        self.push(scenario, invariant, verdict, detail);
+    }
+
+    fn push(&mut self, scenario: &str, invariant: &str, verdict: Verdict, detail: String) {
+        self.results.push(InvariantResult {
+            scenario: scenario.to_string(),
+            invariant: invariant.to_string(),
+            verdict,
+            detail,
+            adapted: self.args.backend.adapted(invariant),
+        });
+    }
+
+    fn message_body(&self, prompt: &str, max_tokens: u32, stream: bool) -> Value {
+        json!({
+            "model": self.args.model,
+            "max_tokens": max_tokens,
+            "stream": stream,
+            "messages": [{ "role": "user", "content": prompt }],
+        })
+    }
+
+    fn tools() -> Value {
+        json!([{
+            "name": TOOL_NAME,
+            "description": "Current weather of a city",
+            "input_schema": {
+                "type": "object",
+                "properties": { "city": { "type": "string" } },
+                "required": ["city"],
+            },
+        }])
+    }
+
+    async fn send(&self, body: &Value) -> Result<wreq::Response, String> {
+        let url = self
+            .args
+            .url
+            .join(self.args.backend.messages_path())
+            .map_err(|e| e.to_string())?;
+        let mut req = self
+            .client
+            .post(url.as_str())
+            .header("x-api-key", &self.args.key)
+            .header(SMOKE_HEADER, "1")
+            .json(body);
+        // the smoke header is only honoured for the admin
+        if let Some(admin_key) = self.args.admin_key.as_deref() {
+            req = req.header(ADMIN_HEADER, admin_key);
+        }
+        let resp = req.send().await.map_err(|e| e.to_string())?;
+        if resp.headers().contains_key(DEMO_HEADER) {
+            self.demo.store(true, Ordering::Relaxed);
+        }
+        Ok(resp)
+    }
+
+    /// Sends a non-streaming request, the reply must succeed
+    async fn message(&self, body: &Value) -> Result<Value, String> {
+        let (status, body) = read_json(self.send(body).await?).await?;
+        if !status.is_success() {
+            return Err(format!("status {status}: {body}"));
+        }
+        Ok(body)
+    }
+
+    /// Sends a streaming request and reads the whole stream
+    async fn stream(&self, body: &Value) -> Result<String, String> {
+        let resp = self.send(body).await?;
+        let status = resp.status();
+        let text = resp.text().await.map_err(|e| e.to_string())?;
+        if !status.is_success() {
+            return Err(format!("status {status}: {text}"));
+        }
+        Ok(text)
+    }
+
+    async fn simple_completion(&mut self) {
+        const SCENARIO: &str = "simple_completion";
+        let reply = self
+            .message(&self.message_body(SHORT_PROMPT, 16, false))
+            .await;
+        let shape = reply.as_ref().map_err(String::to_owned);
+        self.record(
+            SCENARIO,
+            "message_shape",
+            shape.and_then(check_message_response),
+        );
+        let usage = reply.as_ref().map_err(String::to_owned).and_then(|body| {
+            let usage = &body["usage"];
+            match (
+                usage["input_tokens"].as_u64(),
+                usage["output_tokens"].as_u64(),
+            ) {
+                (Some(i), Some(o)) if i > 0 && o > 0 => Ok(format!("{i} in, {o} out")),
+                _ => Err(format!("missing usage: {usage}")),
+            }
+        });
+        self.record(SCENARIO, "usage_reported", usage);
+        let stop_reason = reply.and_then(|body| {
+            body["stop_reason"]
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| "missing stop_reason".to_string())
+        });
+        self.record(SCENARIO, "stop_reason_set", stop_reason);
+    }
+
+    async fn long_streaming(&mut self) {
+        const SCENARIO: &str = "long_streaming";
+        let text = self
+            .stream(&self.message_body(LONG_PROMPT, 256, true))
+            .await;
+        let events = text
+            .as_ref()
+            .map(|t| stream_events(t))
+            .map_err(String::to_owned);
+        self.record(
+            SCENARIO,
+            "event_order",
+            text.as_deref()
+                .map_err(String::to_owned)
+                .and_then(check_stream_events),
+        );
+        let deltas = events.as_ref().map_err(String::to_owned).and_then(|e| {
+            let n = e
+                .iter()
+                .filter(|e| e["type"] == "content_block_delta")
+                .count();
+            if n >= MIN_DELTAS {
+                Ok(format!("{n} deltas"))
+            } else {
+                Err(format!("only {n} deltas"))
+            }
+        });
+        self.record(SCENARIO, "multiple_deltas", deltas);
+        let usage = events.and_then(|e| {
+            let usage = &final_delta(&e)["usage"];
+            match usage["output_tokens"].as_u64() {
+                Some(n) if n > 0 => Ok(format!("{n} output tokens")),
+                _ => Err(format!("missing usage in message_delta: {usage}")),
+            }
+        });
+        self.record(SCENARIO, "stream_usage", usage);
+    }
+
+    async fn stop_sequence(&mut self) {
+        const SCENARIO: &str = "stop_sequence";
+        let mut body = self.message_body(COUNT_PROMPT, 64, true);
+        body["stop_sequences"] = json!([STOP_SEQUENCE]);
+        let events = self.stream(&body).await.map(|t| stream_events(&t));
+        let honored = events.as_ref().map_err(String::to_owned).and_then(|e| {
+            let text = streamed_text(e);
+            if text.contains(STOP_SEQUENCE) {
+                Err(format!("output goes past the stop sequence: {text:?}"))
+            } else {
+                Ok(format!("{} chars before the stop", text.len()))
+            }
+        });
+        self.record(SCENARIO, "stop_honored", honored);
+        let reported = events.and_then(|e| {
+            let delta = &final_delta(&e)["delta"];
+            if delta["stop_reason"] == "stop_sequence" && delta["stop_sequence"] == STOP_SEQUENCE {
+                Ok("stop_sequence".to_string())
+            } else {
+                Err(format!("final delta: {delta}"))
+            }
+        });
+        self.record(SCENARIO, "stop_reason_reported", reported);
+    }
+
+    /// Returns the tool call for the round trip, when the backend made one
+    async fn forced_tool_choice(&mut self) -> Option<Value> {
+        const SCENARIO: &str = "forced_tool_choice";
+        let mut body = self.message_body(TOOL_PROMPT, 128, false);
+        body["tools"] = Self::tools();
+        body["tool_choice"] = json!({ "type": "tool", "name": TOOL_NAME });
+        let reply = self.message(&body).await;
+        let tool_use = reply.as_ref().ok().and_then(|body| {
+            body["content"]
+                .as_array()?
+                .iter()
+                .find(|b| b["type"] == "tool_use" && b["name"] == TOOL_NAME)
+                .cloned()
+        });
+        let block = match (&reply, &tool_use) {
+            (Err(e), _) => Err(e.to_owned()),
+            (Ok(_), Some(block)) if block["input"].is_object() && block["id"].is_string() => {
+                Ok(block["input"].to_string())
+            }
+            (Ok(body), _) => Err(format!("no {TOOL_NAME} call: {}", body["content"])),
+        };
+        self.record(SCENARIO, "tool_use_block", block);
+        let stop_reason = reply.and_then(|body| match body["stop_reason"].as_str() {
+            Some("tool_use") => Ok("tool_use".to_string()),
+            other => Err(format!("stop_reason {other:?}")),
+        });
+        self.record(SCENARIO, "stop_reason_tool_use", stop_reason);
+        tool_use
+    }
+
+    async fn tool_round_trip(&mut self, tool_use: Option<Value>) {
+        const SCENARIO: &str = "tool_round_trip";
+        // a made up call still exercises the round trip when none was made
+        let tool_use = tool_use.unwrap_or_else(|| {
+            json!({
+                "type": "tool_use",
+                "id": "toolu_conformance",
+                "name": TOOL_NAME,
+                "input": { "city": "Paris" },
+            })
+        });
+        let body = json!({
+            "model": self.args.model,
+            "max_tokens": 128,
+            "tools": Self::tools(),
+            "messages": [
+                { "role": "user", "content": TOOL_PROMPT },
+                { "role": "assistant", "content": [tool_use] },
+                { "role": "user", "content": [{
+                    "type": "tool_result",
+                    "tool_use_id": tool_use["id"],
+                    "content": "18 degrees and sunny",
+                }] },
+            ],
+        });
+        let reply = self.message(&body).await;
+        self.record(
+            SCENARIO,
+            "tool_result_accepted",
+            reply
+                .as_ref()
+                .map_err(String::to_owned)
+                .and_then(check_message_response),
+        );
+        let answer = reply.and_then(|body| {
+            let text = body["content"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|b| b["text"].as_str())
+                .collect::<String>();
+            match body["stop_reason"].as_str() {
+                Some("end_turn") if !text.trim().is_empty() => Ok(format!("{} chars", text.len())),
+                other => Err(format!("stop_reason {other:?}, text {text:?}")),
+            }
+        });
+        self.record(SCENARIO, "answer_after_tool", answer);
+    }
+
+    async fn oversized_prompt(&mut self) {
+        const SCENARIO: &str = "oversized_prompt";
+        let prompt = "lorem ".repeat(OVERSIZED_WORDS);
+        let reply = match self.send(&self.message_body(&prompt, 16, false)).await {
+            Ok(resp) => {
+                let status = resp.status();
+                let text = resp.text().await.map_err(|e| e.to_string());
+                text.map(|t| (status, t))
+            }
+            Err(e) => Err(e),
+        };
+        let rejected = reply.as_ref().map_err(String::to_owned).and_then(|(s, _)| {
+            if s.is_client_error() {
+                Ok(s.to_string())
+            } else {
+                Err(format!("expected 4xx, got {s}"))
+            }
+        });
+        self.record(SCENARIO, "rejected_4xx", rejected);
+        let envelope = reply.and_then(|(status, text)| {
+            let body = serde_json::from_str::<Value>(&text)
+                .map_err(|_| format!("status {status}, not JSON: {text}"))?;
+            check_error_envelope(&body)
+        });
+        self.record(SCENARIO, "error_envelope", envelope);
+    }
+
+    async fn mid_stream_cancel(&mut self) {
+        const SCENARIO: &str = "mid_stream_cancel";
+        let partial = self.read_partial_stream().await;
+        let cancelled = partial.is_ok();
+        self.record(SCENARIO, "partial_stream", partial);
+        let recovered = self
+            .message(&self.message_body(SHORT_PROMPT, 16, false))
+            .await
+            .and_then(|body| check_message_response(&body));
+        self.record(SCENARIO, "recovers_after_cancel", recovered);
+        let Some(admin_key) = self.args.admin_key.to_owned() else {
+            let reason = "needs --admin-key".to_string();
+            self.push(SCENARIO, "slot_released", Verdict::Skip, reason);
+            return;
+        };
+        let released = if cancelled {
+            self.wait_for_idle(&admin_key).await
+        } else {
+            Err("no stream was cancelled".to_string())
+        };
+        self.record(SCENARIO, "slot_released", released);
+    }
+
+    /// Reads a stream up to its first delta, then drops the connection
+    async fn read_partial_stream(&self) -> Result<String, String> {
+        let resp = self
+            .send(&self.message_body(LONG_PROMPT, 512, true))
+            .await?;
+        if !resp.status().is_success() {
+            return Err(format!("status {}", resp.status()));
+        }
+        let mut stream = resp.bytes_stream();
+        let mut received = vec![];
+        while let Some(chunk) = stream.next().await {
+            received.extend_from_slice(&chunk.map_err(|e| e.to_string())?);
+            if String::from_utf8_lossy(&received).contains("content_block_delta") {
+                return Ok(format!("cancelled after {} bytes", received.len()));
+            }
+        }
+        Err("stream ended before the first delta".to_string())
+    }
+
+    /// Waits until no cookie serves a request anymore
+    async fn wait_for_idle(&self, admin_key: &str) -> Result<String, String> {
+        let url = self
+            .args
+            .url
+            .join("api/cookies")
+            .map_err(|e| e.to_string())?;
+        let mut busy = 0;
+        for attempt in 0..10 {
+            if attempt > 0 {
+                tokio::time::sleep(Duration::from_millis(500)).await;
+            }
+            let resp = self
+                .client
+                .get(url.as_str())
+                .bearer_auth(admin_key)
+                .send()
+                .await
+                .map_err(|e| e.to_string())?;
+            let (status, body) = read_json(resp).await?;
+            if !status.is_success() {
+                return Err(format!("status {status}: {body}"));
+            }
+            busy = ["valid", "exhausted"]
+                .iter()
+                .filter_map(|list| body[list].as_array())
+                .flatten()
+                .filter_map(|c| c["in_flight"].as_u64())
+                .sum::<u64>();
+            if busy == 0 {
+                return Ok(format!("idle after {}ms", attempt * 500));
+            }
+        }
+        Err(format!("{busy} requests still in flight"))
+    }
+}
+
+/// Text of all text deltas of a stream
+fn streamed_text(events: &[Value]) -> String {
+    events
+        .iter()
+        .filter(|e| e["type"] == "content_block_delta")
+        .filter_map(|e| e["delta"]["text"].as_str())
+        .collect()
+}
+
+/// Last `message_delta` of a stream, `null` when there is none
+fn final_delta(events: &[Value]) -> &Value {
+    events
+        .iter()
+        .rfind(|e| e["type"] == "message_delta")
+        .unwrap_or(&Value::Null)
+}
+
+/// Runs the conformance suite, prints and saves the report
+///
+/// # Returns
+/// * `Result<bool, ClewdrError>` - Whether every invariant run passed
+pub async fn run(args: ConformanceArgs) -> Result<bool, ClewdrError> {
+    println!(
+        "Conformance of the {} backend at {}",
+        args.backend.to_string().blue(),
+        args.url.to_string().blue()
+    );
+    let output = (!args.no_save).then(|| {
+        args.output
+            .to_owned()
+            .unwrap_or_else(|| ConformanceReport::path(args.backend))
+    });
+    let report = ConformanceRunner::new(args)?.run().await;
+    for result in &report.invariants {
+        let verdict = match result.verdict {
+            Verdict::Pass => "PASS".green(),
+            Verdict::Fail => "FAIL".red(),
+            Verdict::Skip => "SKIP".yellow(),
+        };
+        let adapted = if result.adapted { " (adapted)" } else { "" };
+        println!(
+            "{} {:<18} {:<22} {}{}",
+            verdict, result.scenario, result.invariant, result.detail, adapted
+        );
+    }
+    let summary = format!(
+        "{} passed, {} failed, {} skipped, score {:.0}%",
+        report.passed,
+        report.failed,
+        report.skipped,
+        report.score * 100.0
+    );
+    if report.failed == 0 {
+        println!("{}", summary.green());
+    } else {
+        println!("{}", summary.red());
+    }
+    if let Some(path) = output {
+        report.save(&path).await?;
+        println!("Report saved to {}", path.display().to_string().blue());
+    }
+    Ok(report.failed == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{TEST_ADMIN_PASSWORD, TEST_PASSWORD},
+        services::test_instance::{test_instance, upstream_requests},
+    };
+
+    #[tokio::test]
+    async fn code_backend_meets_every_invariant_through_the_router() {
+        let args = ConformanceArgs {
+            url: test_instance(),
+            key: TEST_PASSWORD.to_string(),
+            admin_key: Some(TEST_ADMIN_PASSWORD.to_string()),
+            backend: ConformanceBackend::Code,
+            model: SMOKE_MODEL.to_string(),
+            timeout: 30,
+            output: None,
+            no_save: true,
+        };
+        let report = ConformanceRunner::new(args).unwrap().run().await;
+        for result in &report.invariants {
+            assert_eq!(result.verdict, Verdict::Pass, "{result:?}");
+        }
+        assert_eq!(report.invariants.len(), 17);
+        assert_eq!(report.score, 1.0);
+        assert!(!report.demo);
+        // the tool result reached the upstream as the client sent it
+        let round_trip = upstream_requests()
+            .iter()
+            .any(|body| body["messages"][2]["content"][0]["type"] == "tool_result");
+        assert!(round_trip);
+    }
+
+    #[test]
+    fn web_marks_adapted_invariants() {
+        let web = ConformanceBackend::Web;
+        assert!(web.adapted("stop_honored"));
+        assert!(!web.adapted("event_order"));
+        assert!(!ConformanceBackend::Code.adapted("stop_honored"));
+    }
+}
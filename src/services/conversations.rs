@@ -0,0 +1,427 @@
+//! Reuse of claude.ai conversations across requests of one client session
+//!
+//! Opt-in with `[conversation_reuse]`. Clients name a session with the
+//! `x-clewdr-session` header, which is kept apart per client. A request whose
+//! messages extend the previous request of its session, with the reply it was
+//! sent unchanged, is appended to the same conversation, on the same cookie,
+//! instead of opening a new one. Any other history starts over in a fresh
+//! conversation and the old one is deleted upstream.
+//!
+//! Sessions are kept in a map built with the `ttl_secs` and `max_sessions`
+//! of the current config. When either changes the map is rebuilt, taking the
+//! sessions of the old one along.
+
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::{Arc, LazyLock, Mutex, OnceLock, PoisonError},
+    time::Duration,
+};
+
+use moka::sync::Cache;
+
+use crate::{
+    config::{CLEWDR_CONFIG, ConversationReuse, CookieStatus},
+    services::resources::{FnReporter, ResourceUsage, register_reporter},
+    types::claude::{ContentBlock, CreateMessageParams, Message, MessageContent, Role},
+};
+
+/// The session map with the limits it was built with, rebuilt when they change
+struct Built {
+    ttl_secs: u64,
+    max_sessions: u64,
+    sessions: Cache<String, WebSession>,
+}
+
+static SESSIONS: LazyLock<Mutex<Option<Built>>> = LazyLock::new(|| {
+    register_reporter(FnReporter::new("web_sessions", || ResourceUsage {
+        bytes: None,
+        entries: sessions().entry_count(),
+    }));
+    Mutex::new(None)
+});
+
+/// Returns the session map matching the current config
+fn sessions() -> Cache<String, WebSession> {
+    let config = CLEWDR_CONFIG.load();
+    let mut built = SESSIONS.lock().unwrap_or_else(PoisonError::into_inner);
+    rebuilt(&mut built, &config.conversation_reuse)
+}
+
+/// Returns the map of `built`, rebuilt first when it has other limits than `reuse`
+///
+/// Sessions of the old map move to the new one, their conversations stay in use.
+fn rebuilt(built: &mut Option<Built>, reuse: &ConversationReuse) -> Cache<String, WebSession> {
+    let ConversationReuse {
+        ttl_secs,
+        max_sessions,
+        ..
+    } = *reuse;
+    if let Some(b) = built.as_ref()
+        && b.ttl_secs == ttl_secs
+        && b.max_sessions == max_sessions
+    {
+        return b.sessions.to_owned();
+    }
+    let sessions = Cache::builder()
+        .max_capacity(max_sessions)
+        .time_to_idle(Duration::from_secs(ttl_secs.max(1)))
+        .build();
+    if let Some(old) = built.take() {
+        for (key, session) in old.sessions.iter() {
+            sessions.insert(key.as_ref().to_owned(), session);
+        }
+    }
+    *built = Some(Built {
+        ttl_secs,
+        max_sessions,
+        sessions: sessions.to_owned(),
+    });
+    sessions
+}
+
+/// The claude.ai conversation of a client session
+#[derive(Debug, Clone)]
+pub struct WebSession {
+    pub cookie: CookieStatus,
+    pub org_uuid: String,
+    pub conv_uuid: String,
+    /// Hash of the model and system prompt the conversation started with
+    prelude: u64,
+    /// Hashes of the messages the conversation holds
+    messages: Vec<u64>,
+    /// The reply the conversation holds after them
+    reply: ReplyHash,
+}
+
+/// Hash of the reply produced for a request, set once it was sent in full
+///
+/// Clones share the hash, so it can be recorded once a streamed reply ends,
+/// after its session was kept.
+#[derive(Debug, Clone, Default)]
+pub struct ReplyHash(Arc<OnceLock<u64>>);
+
+impl ReplyHash {
+    /// Records the text of the reply
+    pub fn record(&self, text: &str) {
+        _ = self.0.set(text_hash(text));
+    }
+
+    /// Whether a message of the client is the reply as it was produced
+    fn matches(&self, message: &Message) -> bool {
+        let text = match &message.content {
+            MessageContent::Text { content } => content.to_owned(),
+            MessageContent::Blocks { content } => content
+                .iter()
+                .filter_map(|b| match b {
+                    ContentBlock::Text { text, .. } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect(),
+        };
+        message.role == Role::Assistant && self.0.get() == Some(&text_hash(&text))
+    }
+}
+
+fn text_hash(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.trim().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// What to do with the conversation of a session
+#[derive(Debug)]
+pub enum Resume {
+    /// Append `turns` to the conversation of `session`
+    Continue {
+        session: WebSession,
+        turns: Vec<Message>,
+    },
+    /// Open a new conversation, `stale` is the one the session had
+    Fresh { stale: Option<WebSession> },
+}
+
+fn hash_of(value: &impl serde::Serialize) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_string(value)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    hasher.finish()
+}
+
+fn prelude(p: &CreateMessageParams) -> u64 {
+    hash_of(&(&p.model, &p.system))
+}
+
+/// Hash the cookie actor keeps the requests of a session on one cookie with
+pub fn session_hash(key: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Session key of a client, so clients naming the same session do not share it
+///
+/// # Arguments
+/// * `client` - Identity of the client, see [`ClientAuth::identity`]
+/// * `session` - Session named in `x-clewdr-session`
+///
+/// [`ClientAuth::identity`]: crate::config::ClientAuth::identity
+pub fn session_key(client: &str, session: &str) -> String {
+    format!("{client}:{session}")
+}
+
+impl WebSession {
+    /// Records the conversation a request was sent to
+    ///
+    /// # Arguments
+    /// * `cookie` - Cookie the conversation belongs to
+    /// * `org_uuid` - Organization of the conversation
+    /// * `conv_uuid` - The conversation
+    /// * `p` - The full request, as the client sent it
+    /// * `reply` - Hash of the reply to the request, recorded once it is sent
+    pub fn new(
+        cookie: CookieStatus,
+        org_uuid: String,
+        conv_uuid: String,
+        p: &CreateMessageParams,
+        reply: ReplyHash,
+    ) -> Self {
+        Self {
+            cookie,
+            org_uuid,
+            conv_uuid,
+            prelude: prelude(p),
+            messages: p.messages.iter().map(hash_of).collect(),
+            reply,
+        }
+    }
+
+    /// Messages of a request that come after what the conversation holds
+    ///
+    /// The first of them must be the assistant's reply as it was produced,
+    /// which the conversation already has. A reply the client edited, or one
+    /// that was cut off, leaves the conversation diverged from the history.
+    ///
+    /// # Returns
+    /// * `Option<Vec<Message>>` - New turns, `None` when the request does not extend the conversation
+    fn new_turns(&self, p: &CreateMessageParams) -> Option<Vec<Message>> {
+        if prelude(p) != self.prelude || p.messages.len() <= self.messages.len() {
+            return None;
+        }
+        let (sent, rest) = p.messages.split_at(self.messages.len());
+        if !sent.iter().map(hash_of).eq(self.messages.iter().copied()) {
+            return None;
+        }
+        let (reply, rest) = rest.split_first()?;
+        if !self.reply.matches(reply) {
+            return None;
+        }
+        (!rest.is_empty()).then(|| rest.to_vec())
+    }
+}
+
+/// Takes the session `key` out of the map and decides how to continue it
+///
+/// A session is only continued by one request at a time, a concurrent
+/// request of the same session opens its own conversation.
+///
+/// # Arguments
+/// * `key` - Session named by the client
+/// * `cookie` - Cookie dispatched for the request
+/// * `org_uuid` - Organization the request is sent to
+/// * `p` - The full request
+pub fn resume(key: &str, cookie: &CookieStatus, org_uuid: &str, p: &CreateMessageParams) -> Resume {
+    let Some(session) = sessions().remove(key) else {
+        return Resume::Fresh { stale: None };
+    };
+    if session.cookie == *cookie
+        && session.org_uuid == org_uuid
+        && let Some(turns) = session.new_turns(p)
+    {
+        Resume::Continue { session, turns }
+    } else {
+        Resume::Fresh {
+            stale: Some(session),
+        }
+    }
+}
+
+/// Keeps the conversation of a session for its next request
+pub fn remember(key: String, session: WebSession) {
+    sessions().insert(key, session);
+}
+
+/// Drops the sessions served by a removed cookie
+pub fn forget_cookie(cookie: &CookieStatus) {
+    let sessions = sessions();
+    for (key, session) in sessions.iter() {
+        if session.cookie == *cookie {
+            sessions.invalidate(key.as_str());
+        }
+    }
+}
+
+/// Number of sessions with a conversation
+pub fn active() -> u64 {
+    let sessions = sessions();
+    sessions.run_pending_tasks();
+    sessions.entry_count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cookie(i: usize) -> CookieStatus {
+        let body = format!("{:0<86}", format!("session-test-{i:02}-"));
+        CookieStatus::new(&format!("sk-ant-sid01-{body}-{i:06}AA"), None).unwrap()
+    }
+
+    fn request(turns: &[(Role, &str)]) -> CreateMessageParams {
+        CreateMessageParams {
+            model: "claude-sonnet-4-5".into(),
+            system: Some("Be brief".into()),
+            messages: turns
+                .iter()
+                .map(|(role, text)| Message::new_text(*role, *text))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn replied(text: &str) -> ReplyHash {
+        let reply = ReplyHash::default();
+        reply.record(text);
+        reply
+    }
+
+    #[test]
+    fn continues_only_extended_histories() {
+        let first = request(&[(Role::User, "hi")]);
+        let session = WebSession::new(
+            cookie(1),
+            "org".into(),
+            "conv".into(),
+            &first,
+            replied("hello"),
+        );
+
+        let next = request(&[
+            (Role::User, "hi"),
+            (Role::Assistant, "hello"),
+            (Role::User, "how are you"),
+        ]);
+        let turns = session.new_turns(&next).unwrap();
+        assert_eq!(turns, vec![Message::new_text(Role::User, "how are you")]);
+
+        // edited history, a changed system prompt and a repeat all start over
+        let edited = request(&[
+            (Role::User, "hey"),
+            (Role::Assistant, "hello"),
+            (Role::User, "?"),
+        ]);
+        assert!(session.new_turns(&edited).is_none());
+        let mut other_system = next.to_owned();
+        other_system.system = Some("Be verbose".into());
+        assert!(session.new_turns(&other_system).is_none());
+        assert!(session.new_turns(&first).is_none());
+
+        // the reply must be the one the conversation holds
+        let edited_reply = request(&[
+            (Role::User, "hi"),
+            (Role::Assistant, "hello there"),
+            (Role::User, "how are you"),
+        ]);
+        assert!(session.new_turns(&edited_reply).is_none());
+        let no_reply = request(&[(Role::User, "hi"), (Role::User, "how are you")]);
+        assert!(session.new_turns(&no_reply).is_none());
+        let cut_off = WebSession::new(
+            cookie(1),
+            "org".into(),
+            "conv".into(),
+            &first,
+            ReplyHash::default(),
+        );
+        assert!(cut_off.new_turns(&next).is_none());
+    }
+
+    #[test]
+    fn sessions_stay_on_their_cookie() {
+        let key = format!("session-{}", uuid::Uuid::new_v4());
+        let first = request(&[(Role::User, "hi")]);
+        let next = request(&[
+            (Role::User, "hi"),
+            (Role::Assistant, "hello"),
+            (Role::User, "?"),
+        ]);
+        let remember_first = || {
+            remember(
+                key.to_owned(),
+                WebSession::new(
+                    cookie(1),
+                    "org".into(),
+                    "conv".into(),
+                    &first,
+                    replied("hello"),
+                ),
+            )
+        };
+
+        remember_first();
+        assert!(matches!(
+            resume(&key, &cookie(1), "org", &next),
+            Resume::Continue { .. }
+        ));
+        // taken out until the request succeeds
+        assert!(matches!(
+            resume(&key, &cookie(1), "org", &next),
+            Resume::Fresh { stale: None }
+        ));
+
+        remember_first();
+        assert!(matches!(
+            resume(&key, &cookie(2), "org", &next),
+            Resume::Fresh { stale: Some(_) }
+        ));
+
+        remember_first();
+        forget_cookie(&cookie(1));
+        assert!(matches!(
+            resume(&key, &cookie(1), "org", &next),
+            Resume::Fresh { stale: None }
+        ));
+    }
+
+    #[test]
+    fn limits_apply_when_changed() {
+        let mut built = None;
+        let mut reuse = ConversationReuse {
+            enabled: true,
+            ..Default::default()
+        };
+        let first = request(&[(Role::User, "hi")]);
+        let sessions = rebuilt(&mut built, &reuse);
+        sessions.insert(
+            "kept".into(),
+            WebSession::new(
+                cookie(1),
+                "org".into(),
+                "conv".into(),
+                &first,
+                replied("hello"),
+            ),
+        );
+        assert!(rebuilt(&mut built, &reuse).contains_key("kept"));
+
+        reuse.max_sessions = 5;
+        reuse.ttl_secs = 60;
+        let sessions = rebuilt(&mut built, &reuse);
+        assert_eq!(sessions.policy().max_capacity(), Some(5));
+        assert_eq!(
+            sessions.policy().time_to_idle(),
+            Some(Duration::from_secs(60))
+        );
+        // sessions of the old map carry over
+        assert_eq!(sessions.get("kept").unwrap().conv_uuid, "conv");
+    }
+}
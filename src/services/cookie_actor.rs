@@ -1,4 +1,7 @@
-use std::collections::{HashSet, VecDeque};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    time::Duration,
+};
 
 use chrono::Utc;
 use colored::Colorize;
@@ -8,9 +11,23 @@ use serde::Serialize;
 use snafu::{GenerateImplicitData, Location};
 use tracing::{error, info, warn};
 
+use tokio::{
+    sync::{mpsc, oneshot},
+    time::Instant,
+};
+
 use crate::{
-    config::{CLEWDR_CONFIG, ClewdrConfig, CookieStatus, Reason, UsageBreakdown, UselessCookie},
+    config::{
+        AccountFlag, AccountStanding, CLEWDR_CONFIG, ClewdrConfig, ClewdrCookie, CookieStatus,
+        CookieStrategy, Reason, UsageBreakdown, UselessCookie,
+    },
     error::ClewdrError,
+    services::{
+        account_cache,
+        queue::{self, CAPACITY, CookiePermit, QueueSlot},
+        storage::{StoreWrite, StoredCookies, cookie_store, spawn_writer},
+    },
+    utils::random_unit,
 };
 
 const INTERVAL: u64 = 300;
@@ -22,6 +39,8 @@ pub struct CookieStatusInfo {
     pub valid: Vec<CookieStatus>,
     pub exhausted: Vec<CookieStatus>,
     pub invalid: Vec<UselessCookie>,
+    /// Requests currently served by each cookie
+    pub in_flight: HashMap<ClewdrCookie, usize>,
 }
 
 /// Messages that the CookieActor can handle
@@ -31,16 +50,32 @@ enum CookieActorMessage {
     Return(CookieStatus, Option<Reason>),
     /// Submit a new Cookie
     Submit(CookieStatus),
+    /// Submit several Cookies at once, each with the reason it cannot be used if any
+    SubmitBatch(Vec<(CookieStatus, Option<Reason>)>, RpcReplyPort<Vec<bool>>),
     /// Check for timed out Cookies
     CheckReset,
-    /// Request to get a Cookie
-    Request(Option<u64>, RpcReplyPort<Result<CookieStatus, ClewdrError>>),
+    /// Request to get a Cookie serving fewer than the given number of requests
+    /// and passing over the given Cookies while others have room
+    Request(
+        Option<u64>,
+        usize,
+        Vec<ClewdrCookie>,
+        RpcReplyPort<Result<CookieStatus, ClewdrError>>,
+    ),
+    /// A request finished with its Cookie
+    Release(ClewdrCookie),
     /// Get all Cookie status information
     GetStatus(RpcReplyPort<CookieStatusInfo>),
     /// Delete a Cookie
     Delete(CookieStatus, RpcReplyPort<Result<(), ClewdrError>>),
+    /// Record the standing an upstream error gave a Cookie
+    Flag(CookieStatus, AccountFlag),
+    /// Delete many Cookies, answering whether each one was found
+    DeleteBatch(Vec<CookieStatus>, RpcReplyPort<Vec<bool>>),
     /// Update 1M support flags on an existing cookie
     Update1mSupport(CookieStatus, RpcReplyPort<Result<(), ClewdrError>>),
+    /// Answer once every change so far is in the cookie store
+    Flush(RpcReplyPort<()>),
 }
 
 /// CookieActor state - manages collections of cookies
@@ -50,34 +85,39 @@ struct CookieActorState {
     exhausted: HashSet<CookieStatus>,
     invalid: HashSet<UselessCookie>,
     moka: Cache<u64, CookieStatus>,
+    /// Requests currently served by each cookie
+    in_flight: HashMap<ClewdrCookie, usize>,
+    /// Requests each cookie was dispatched for since startup
+    requests: HashMap<ClewdrCookie, u64>,
+    /// Snapshots queued for the cookie store
+    persist: mpsc::UnboundedSender<StoreWrite>,
 }
 
 /// Cookie actor that handles cookie distribution, collection, and status tracking using Ractor
 struct CookieActor;
 
 impl CookieActor {
-    /// Saves the current state of cookies to the configuration
+    /// Saves the current state of cookies to the configuration and the cookie store
     fn save(state: &CookieActorState) {
-        CLEWDR_CONFIG.rcu(|config| {
-            let mut config = ClewdrConfig::clone(config);
-            config.cookie_array = state
+        let snapshot = StoredCookies {
+            cookies: state
                 .valid
                 .iter()
                 .chain(state.exhausted.iter())
                 .cloned()
-                .collect();
-            config.wasted_cookie = state.invalid.clone();
+                .collect(),
+            invalid: state.invalid.clone(),
+        };
+        CLEWDR_CONFIG.rcu(|config| {
+            let mut config = ClewdrConfig::clone(config);
+            config.cookie_array = snapshot.cookies.clone();
+            config.wasted_cookie = snapshot.invalid.clone();
             config
         });
 
-        // Persist config file/DB config row only（不再全量重写 cookies）
-        tokio::spawn(async move {
-            let result = CLEWDR_CONFIG.load().save().await;
-            match result {
-                Ok(_) => info!("Configuration saved successfully"),
-                Err(e) => error!("Save task panicked: {}", e),
-            }
-        });
+        if state.persist.send(StoreWrite::Save(snapshot)).is_err() {
+            error!("Cookie store writer stopped, cookies not saved");
+        }
     }
 
     /// Logs the current state of cookie collections
@@ -181,34 +221,119 @@ impl CookieActor {
         changed
     }
 
-    /// Dispatches a cookie for use
+    /// Dispatches a cookie serving fewer than `limit` requests, 0 means unlimited
+    ///
+    /// Requests with a known `hash` stay on their cookie, others get one picked
+    /// by `strategy`. Cookies in `exclude` already failed the request, they are
+    /// only dispatched when no other cookie has room, and never by `hash`.
     fn dispatch(
         &self,
         state: &mut CookieActorState,
         hash: Option<u64>,
+        limit: usize,
+        strategy: CookieStrategy,
+        exclude: &[ClewdrCookie],
     ) -> Result<CookieStatus, ClewdrError> {
         Self::reset(state);
+        let has_room = |in_flight: &HashMap<ClewdrCookie, usize>, cookie: &CookieStatus| {
+            limit == 0 || in_flight.get(&cookie.cookie).copied().unwrap_or_default() < limit
+        };
         if let Some(hash) = hash
             && let Some(cookie) = state.moka.get(&hash)
             && let Some(cookie) = state.valid.iter().find(|&c| c == &cookie)
+            && !exclude.contains(&cookie.cookie)
+            && has_room(&state.in_flight, cookie)
         {
+            let cookie = cookie.clone();
             // renew moka cache
             state.moka.insert(hash, cookie.clone());
-            return Ok(cookie.clone());
+            Self::occupy(state, &cookie.cookie);
+            return Ok(cookie);
         }
+        if state.valid.is_empty() {
+            return Err(ClewdrError::NoCookieAvailable);
+        }
+        let mut candidates = state
+            .valid
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| has_room(&state.in_flight, c))
+            .collect::<Vec<_>>();
+        if candidates.iter().any(|(_, c)| !exclude.contains(&c.cookie)) {
+            candidates.retain(|(_, c)| !exclude.contains(&c.cookie));
+        }
+        let mut candidates = candidates.into_iter();
+        let pos = match strategy {
+            CookieStrategy::RoundRobin => candidates.next(),
+            // ties go to the cookie used longest ago
+            CookieStrategy::LeastUsed => {
+                candidates.min_by_key(|(_, c)| Self::requests_of(state, &c.cookie))
+            }
+            CookieStrategy::Random => {
+                let candidates = candidates.collect::<Vec<_>>();
+                let pick = (random_unit() * candidates.len() as f64) as usize;
+                candidates.get(pick).copied()
+            }
+        }
+        .map(|(pos, _)| pos);
+        let Some(pos) = pos else {
+            return Err(ClewdrError::CookiesBusy {
+                capacity: state.valid.len() * limit,
+            });
+        };
+        // move the dispatched cookie to the back, keeping the rotation order
+        state.valid.rotate_left(pos + 1);
         let cookie = state
             .valid
-            .pop_front()
+            .back()
+            .cloned()
             .ok_or(ClewdrError::NoCookieAvailable)?;
-        state.valid.push_back(cookie.clone());
         if let Some(hash) = hash {
             state.moka.insert(hash, cookie.clone());
         }
+        Self::occupy(state, &cookie.cookie);
         Ok(cookie)
     }
 
+    /// Requests a cookie was dispatched for, for [`CookieStrategy::LeastUsed`]
+    ///
+    /// A cookie new to the pool counts as many as the least used one, so it
+    /// is not handed every request until it caught up with the others.
+    fn requests_of(state: &CookieActorState, cookie: &ClewdrCookie) -> u64 {
+        state.requests.get(cookie).copied().unwrap_or_else(|| {
+            state
+                .valid
+                .iter()
+                .filter_map(|c| state.requests.get(&c.cookie))
+                .min()
+                .copied()
+                .unwrap_or_default()
+        })
+    }
+
+    /// Takes one request slot of a cookie and counts the request
+    fn occupy(state: &mut CookieActorState, cookie: &ClewdrCookie) {
+        let requests = Self::requests_of(state, cookie) + 1;
+        state.requests.insert(cookie.to_owned(), requests);
+        *state.in_flight.entry(cookie.to_owned()).or_default() += 1;
+    }
+
+    /// Frees one request slot of a cookie
+    fn release(state: &mut CookieActorState, cookie: &ClewdrCookie) {
+        if let Some(count) = state.in_flight.get_mut(cookie) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                state.in_flight.remove(cookie);
+            }
+        }
+    }
+
     /// Collects a returned cookie and processes it based on the return reason
     fn collect(state: &mut CookieActorState, mut cookie: CookieStatus, reason: Option<Reason>) {
+        // flags are set through `flag` only, the returned copy may predate one
+        if let Some(existing) = state.valid.iter().find(|c| **c == cookie) {
+            cookie.flag = existing.flag.to_owned();
+        }
         let Some(reason) = reason else {
             if let Some(existing) = state.valid.iter_mut().find(|c| **c == cookie) {
                 *existing = cookie;
@@ -283,26 +408,60 @@ impl CookieActor {
         Self::log(state);
     }
 
+    /// Accepts new cookies and saves them in one write
+    ///
+    /// Cookies given a reason are filed the way a returned cookie would be.
+    ///
+    /// # Returns
+    /// * `Vec<bool>` - Whether each cookie was added, false for ones already known
+    fn accept_batch(
+        state: &mut CookieActorState,
+        cookies: Vec<(CookieStatus, Option<Reason>)>,
+    ) -> Vec<bool> {
+        let added = cookies
+            .into_iter()
+            .map(|(mut cookie, reason)| {
+                let known = state.valid.contains(&cookie)
+                    || state.exhausted.contains(&cookie)
+                    || state.invalid.iter().any(|c| *c == cookie);
+                if known {
+                    return false;
+                }
+                match reason {
+                    None | Some(Reason::NormalPro) => state.valid.push_back(cookie),
+                    Some(Reason::TooManyRequest(i) | Reason::Restricted(i)) => {
+                        cookie.reset_time = Some(i);
+                        state.exhausted.insert(cookie);
+                    }
+                    Some(reason) => {
+                        state
+                            .invalid
+                            .insert(UselessCookie::new(cookie.cookie, reason));
+                    }
+                }
+                true
+            })
+            .collect::<Vec<_>>();
+        if added.contains(&true) {
+            Self::save(state);
+            Self::log(state);
+        }
+        added
+    }
+
     /// Creates a report of all cookie statuses
     fn report(state: &CookieActorState) -> CookieStatusInfo {
         CookieStatusInfo {
             valid: state.valid.clone().into(),
             exhausted: state.exhausted.iter().cloned().collect(),
             invalid: state.invalid.iter().cloned().collect(),
+            in_flight: state.in_flight.clone(),
         }
     }
 
     /// Deletes a cookie from all collections
     fn delete(state: &mut CookieActorState, cookie: CookieStatus) -> Result<(), ClewdrError> {
-        let mut found = false;
-        state.valid.retain(|c| {
-            found |= *c == cookie;
-            *c != cookie
-        });
-        let useless = UselessCookie::new(cookie.cookie.clone(), Reason::Null);
-        found |= state.exhausted.remove(&cookie) | state.invalid.remove(&useless);
-
-        if found {
+        if Self::remove(state, &cookie) {
             Self::save(state);
             Self::log(state);
             Ok(())
@@ -313,6 +472,111 @@ impl CookieActor {
         }
     }
 
+    /// Records the standing an upstream error gave a cookie
+    ///
+    /// A warned cookie stays selected and a restricted one rests until the
+    /// restriction ends. Without a ban limit a banned cookie moves to the
+    /// invalid cookies for review. With one it rests with a growing backoff
+    /// and is selected again, so further ban responses can reach the limit,
+    /// and it is deleted with the last one.
+    ///
+    /// # Arguments
+    /// * `ban_limit` - Ban responses in a row that delete a cookie, 0 never deletes
+    fn flag(state: &mut CookieActorState, cookie: CookieStatus, flag: AccountFlag, ban_limit: u32) {
+        let useless = UselessCookie::new(cookie.cookie.clone(), Reason::Banned);
+        let current = state
+            .valid
+            .iter()
+            .chain(state.exhausted.iter())
+            .find(|c| **c == cookie)
+            .cloned();
+        let previous = match &current {
+            Some(current) => current.flag.to_owned(),
+            None => match state.invalid.get(&useless) {
+                Some(invalid) => invalid.flag.to_owned(),
+                // deleted in the meantime
+                None => return,
+            },
+        };
+        let flag = flag.after(previous.as_ref());
+        warn!(
+            "Cookie {} flagged {}: {}",
+            cookie.cookie.ellipse(),
+            flag.standing,
+            flag.message
+        );
+        let mut cookie = current.unwrap_or(cookie);
+        match flag.standing {
+            AccountStanding::Warning => {
+                cookie.flag = Some(flag);
+                if let Some(valid) = state.valid.iter_mut().find(|c| **c == cookie) {
+                    *valid = cookie;
+                } else {
+                    state.exhausted.replace(cookie);
+                }
+            }
+            AccountStanding::Restricted => {
+                Self::remove(state, &cookie);
+                cookie.reset_time = Some(flag.restricted_until());
+                cookie.reset_window_usage();
+                cookie.flag = Some(flag);
+                state.exhausted.insert(cookie);
+            }
+            AccountStanding::Banned => {
+                Self::remove(state, &cookie);
+                if ban_limit == 0 {
+                    state.invalid.insert(UselessCookie {
+                        flag: Some(flag),
+                        ..useless
+                    });
+                } else if flag.strikes >= ban_limit {
+                    info!(
+                        "Cookie {} deleted after {} ban responses",
+                        cookie.cookie.ellipse(),
+                        flag.strikes
+                    );
+                } else {
+                    cookie.reset_time = Some(flag.ban_backoff_until());
+                    cookie.reset_window_usage();
+                    cookie.flag = Some(flag);
+                    state.exhausted.insert(cookie);
+                }
+            }
+        }
+        Self::save(state);
+        Self::log(state);
+    }
+
+    /// Deletes many cookies, saving once for all of them
+    ///
+    /// # Returns
+    /// * `Vec<bool>` - Whether each cookie was found, in order
+    fn delete_batch(state: &mut CookieActorState, cookies: Vec<CookieStatus>) -> Vec<bool> {
+        let found = cookies
+            .iter()
+            .map(|cookie| Self::remove(state, cookie))
+            .collect::<Vec<_>>();
+        if found.contains(&true) {
+            Self::save(state);
+            Self::log(state);
+        }
+        found
+    }
+
+    /// Removes a cookie from every collection, without saving
+    ///
+    /// # Returns
+    /// * `bool` - Whether the cookie was in any of them
+    fn remove(state: &mut CookieActorState, cookie: &CookieStatus) -> bool {
+        let mut found = false;
+        state.valid.retain(|c| {
+            found |= c == cookie;
+            c != cookie
+        });
+        let useless = UselessCookie::new(cookie.cookie.clone(), Reason::Null);
+        found | state.exhausted.remove(cookie) | state.invalid.remove(&useless)
+    }
+
     /// Updates 1M support flags for an existing cookie in valid/exhausted collections
     fn update_1m_support(
         state: &mut CookieActorState,
@@ -369,25 +633,36 @@ impl Actor for CookieActor {
             cookie
         };
 
+        let store = cookie_store(&CLEWDR_CONFIG.load());
+        let stored = store
+            .load()
+            .await
+            .map_err(|e| format!("Failed to load cookies: {e}"))?;
+        // the live config mirrors the store, whichever backend holds the pool
+        CLEWDR_CONFIG.rcu(|config| {
+            let mut config = ClewdrConfig::clone(config);
+            config.cookie_array = stored.cookies.clone();
+            config.wasted_cookie = stored.invalid.clone();
+            config
+        });
+
         let valid = VecDeque::from_iter(
-            CLEWDR_CONFIG
-                .load()
-                .cookie_array
+            stored
+                .cookies
                 .iter()
                 .filter(|c| c.reset_time.is_none())
                 .cloned()
                 .map(normalize_1m_defaults),
         );
         let exhausted = HashSet::from_iter(
-            CLEWDR_CONFIG
-                .load()
-                .cookie_array
+            stored
+                .cookies
                 .iter()
                 .filter(|c| c.reset_time.is_some())
                 .cloned()
                 .map(normalize_1m_defaults),
         );
-        let invalid = HashSet::from_iter(CLEWDR_CONFIG.load().wasted_cookie.iter().cloned());
+        let invalid = stored.invalid;
 
         let moka = Cache::builder()
             .max_capacity(1000)
@@ -399,6 +674,9 @@ impl Actor for CookieActor {
             exhausted,
             invalid,
             moka,
+            in_flight: HashMap::new(),
+            requests: HashMap::new(),
+            persist: spawn_writer(store),
         };
 
         CookieActor::log(&state);
@@ -414,9 +692,17 @@ impl Actor for CookieActor {
         match message {
             CookieActorMessage::Return(cookie, reason) => {
                 Self::collect(state, cookie, reason);
+                // waiters fail fast once no cookie is left
+                CAPACITY.notify_waiters();
             }
             CookieActorMessage::Submit(cookie) => {
                 Self::accept(state, cookie);
+                CAPACITY.notify_waiters();
+            }
+            CookieActorMessage::SubmitBatch(cookies, reply_port) => {
+                let added = Self::accept_batch(state, cookies);
+                CAPACITY.notify_waiters();
+                reply_port.send(added)?;
             }
             CookieActorMessage::CheckReset => {
                 let changed = Self::refresh_usage_windows(state);
@@ -424,10 +710,33 @@ impl Actor for CookieActor {
                     Self::save(state);
                 }
                 Self::reset(state);
+                CAPACITY.notify_waiters();
             }
-            CookieActorMessage::Request(cache_hash, reply_port) => {
-                let result = self.dispatch(state, cache_hash);
-                reply_port.send(result)?;
+            CookieActorMessage::Request(cache_hash, limit, exclude, reply_port) => {
+                let strategy = CLEWDR_CONFIG.load().cookie_strategy;
+                let result = self.dispatch(state, cache_hash, limit, strategy, &exclude);
+                let dispatched = result.as_ref().ok().map(|c| c.cookie.clone());
+                if reply_port.send(result).is_err()
+                    && let Some(cookie) = dispatched
+                {
+                    // the requester is gone and will never release the cookie
+                    Self::release(state, &cookie);
+                }
+            }
+            CookieActorMessage::Release(cookie) => {
+                Self::release(state, &cookie);
+                CAPACITY.notify_waiters();
+            }
+            CookieActorMessage::Flush(reply_port) => {
+                let (done, saved) = oneshot::channel();
+                if state.persist.send(StoreWrite::Flush(done)).is_err() {
+                    error!("Cookie store writer stopped, cookies not saved");
+                }
+                // wait outside the actor, requests keep being served meanwhile
+                tokio::spawn(async move {
+                    let _ = saved.await;
+                    let _ = reply_port.send(());
+                });
             }
             CookieActorMessage::GetStatus(reply_port) => {
                 let changed = Self::refresh_usage_windows(state);
@@ -441,6 +750,14 @@ impl Actor for CookieActor {
                 let result = Self::delete(state, cookie.clone());
                 reply_port.send(result)?;
             }
+            CookieActorMessage::Flag(cookie, flag) => {
+                let ban_limit = CLEWDR_CONFIG.load().ban_auto_remove_after;
+                Self::flag(state, cookie, flag, ban_limit);
+            }
+            CookieActorMessage::DeleteBatch(cookies, reply_port) => {
+                let found = Self::delete_batch(state, cookies);
+                reply_port.send(found)?;
+            }
             CookieActorMessage::Update1mSupport(cookie, reply_port) => {
                 let result = Self::update_1m_support(state, cookie);
                 reply_port.send(result)?;
@@ -494,21 +811,113 @@ impl CookieActorHandle {
     }
 
     /// Request a cookie from the cookie actor
-    pub async fn request(&self, cache_hash: Option<u64>) -> Result<CookieStatus, ClewdrError> {
-        ractor::call!(self.actor_ref, CookieActorMessage::Request, cache_hash).map_err(|e| {
-            ClewdrError::RactorError {
-                loc: Location::generate(),
-                msg: format!("Failed to communicate with CookieActor for request operation: {e}"),
-            }
+    async fn request(
+        &self,
+        cache_hash: Option<u64>,
+        limit: usize,
+        exclude: &[ClewdrCookie],
+    ) -> Result<CookieStatus, ClewdrError> {
+        ractor::call!(
+            self.actor_ref,
+            CookieActorMessage::Request,
+            cache_hash,
+            limit,
+            exclude.to_vec()
+        )
+        .map_err(|e| ClewdrError::RactorError {
+            loc: Location::generate(),
+            msg: format!("Failed to communicate with CookieActor for request operation: {e}"),
         })?
     }
 
+    /// Requests a cookie with spare capacity, waiting while every cookie is busy
+    ///
+    /// # Arguments
+    /// * `cache_hash` - Keeps requests with the same hash on the same cookie
+    /// * `limit` - Requests one cookie serves at once, 0 means unlimited
+    /// * `priority` - Place in the queue, retried requests wait ahead of fresh ones
+    /// * `exclude` - Cookies that failed the request, used only when no other has room
+    ///
+    /// # Returns
+    /// * `Result<(CookieStatus, CookiePermit), ClewdrError>` - The cookie and its
+    ///   request slot, or a queue timeout once `queue_timeout_ms` has passed
+    pub async fn acquire(
+        &self,
+        cache_hash: Option<u64>,
+        limit: usize,
+        priority: u32,
+        exclude: &[ClewdrCookie],
+    ) -> Result<(CookieStatus, CookiePermit), ClewdrError> {
+        let (timeout, max_queued) = {
+            let config = CLEWDR_CONFIG.load();
+            (config.queue_timeout_ms, config.max_queued)
+        };
+        let deadline = Instant::now() + Duration::from_millis(timeout);
+        let queue_timeout = |queue_depth: usize, capacity: usize| {
+            let avg_hold_ms = queue::avg_hold_ms();
+            ClewdrError::QueueTimeout {
+                queue_depth,
+                estimated_wait_ms: queue::estimate_wait_ms(queue_depth, capacity, avg_hold_ms),
+            }
+        };
+        let mut slot: Option<QueueSlot> = None;
+        let mut capacity = 0;
+        loop {
+            // register before asking, a release in between must not be missed
+            let notified = CAPACITY.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            // requests already waiting are served first
+            let turn = match &slot {
+                Some(slot) => slot.is_first(),
+                None => queue::queued() == 0,
+            };
+            if turn {
+                capacity = match self.request(cache_hash, limit, exclude).await {
+                    Ok(cookie) => {
+                        let permit = CookiePermit::new(cookie.cookie.clone(), self.clone());
+                        return Ok((cookie, permit));
+                    }
+                    Err(ClewdrError::CookiesBusy { capacity }) => capacity,
+                    Err(e) => return Err(e),
+                };
+            }
+            if slot.is_none() {
+                slot = Some(
+                    QueueSlot::join(max_queued, priority)
+                        .map_err(|depth| queue_timeout(depth, capacity))?,
+                );
+                // a higher priority may put this request first right away
+                if !turn {
+                    continue;
+                }
+            }
+            if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                return Err(queue_timeout(queue::queued().saturating_sub(1), capacity));
+            }
+        }
+    }
+
+    /// Frees the request slot a permit held on a cookie
+    pub(crate) fn release(&self, cookie: ClewdrCookie) {
+        if let Err(e) = ractor::cast!(self.actor_ref, CookieActorMessage::Release(cookie)) {
+            error!("Failed to release cookie: {}", e);
+        }
+    }
+
     /// Return a cookie to the cookie actor
     pub async fn return_cookie(
         &self,
         cookie: CookieStatus,
         reason: Option<Reason>,
     ) -> Result<(), ClewdrError> {
+        // a cooling down cookie keeps its account, any other reason may not
+        if reason
+            .as_ref()
+            .is_some_and(|r| !matches!(r, Reason::TooManyRequest(_)))
+        {
+            account_cache::invalidate(&cookie);
+        }
         ractor::cast!(self.actor_ref, CookieActorMessage::Return(cookie, reason)).map_err(|e| {
             ClewdrError::RactorError {
                 loc: Location::generate(),
@@ -527,6 +936,22 @@ impl CookieActorHandle {
         })
     }
 
+    /// Submit several cookies, saved in one write
+    ///
+    /// # Returns
+    /// * `Result<Vec<bool>, ClewdrError>` - Whether each cookie was added
+    pub async fn submit_batch(
+        &self,
+        cookies: Vec<(CookieStatus, Option<Reason>)>,
+    ) -> Result<Vec<bool>, ClewdrError> {
+        ractor::call!(self.actor_ref, CookieActorMessage::SubmitBatch, cookies).map_err(|e| {
+            ClewdrError::RactorError {
+                loc: Location::generate(),
+                msg: format!("Failed to communicate with CookieActor for batch submit: {e}"),
+            }
+        })
+    }
+
     /// Get status information about all cookies
     pub async fn get_status(&self) -> Result<CookieStatusInfo, ClewdrError> {
         ractor::call!(self.actor_ref, CookieActorMessage::GetStatus).map_err(|e| {
@@ -539,6 +964,16 @@ impl CookieActorHandle {
         })
     }
 
+    /// Waits until every cookie change made so far is saved
+    pub async fn flush(&self) -> Result<(), ClewdrError> {
+        ractor::call!(self.actor_ref, CookieActorMessage::Flush).map_err(|e| {
+            ClewdrError::RactorError {
+                loc: Location::generate(),
+                msg: format!("Failed to communicate with CookieActor for flush operation: {e}"),
+            }
+        })
+    }
+
     /// Delete a cookie from the cookie actor
     pub async fn delete_cookie(&self, cookie: CookieStatus) -> Result<(), ClewdrError> {
         ractor::call!(self.actor_ref, CookieActorMessage::Delete, cookie).map_err(|e| {
@@ -549,6 +984,66 @@ impl CookieActorHandle {
         })?
     }
 
+    /// Record the standing an upstream error gave a cookie
+    pub async fn flag_cookie(
+        &self,
+        cookie: CookieStatus,
+        flag: AccountFlag,
+    ) -> Result<(), ClewdrError> {
+        if flag.standing != AccountStanding::Warning {
+            account_cache::invalidate(&cookie);
+        }
+        ractor::cast!(self.actor_ref, CookieActorMessage::Flag(cookie, flag)).map_err(|e| {
+            ClewdrError::RactorError {
+                loc: Location::generate(),
+                msg: format!("Failed to communicate with CookieActor for flag operation: {e}"),
+            }
+        })
+    }
+
+    /// Delete many cookies from the cookie actor in one call
+    ///
+    /// # Returns
+    /// * `Result<Vec<bool>, ClewdrError>` - Whether each cookie was found, in order
+    pub async fn delete_cookies(
+        &self,
+        cookies: Vec<CookieStatus>,
+    ) -> Result<Vec<bool>, ClewdrError> {
+        ractor::call!(self.actor_ref, CookieActorMessage::DeleteBatch, cookies).map_err(|e| {
+            ClewdrError::RactorError {
+                loc: Location::generate(),
+                msg: format!("Failed to communicate with CookieActor for delete operation: {e}"),
+            }
+        })
+    }
+
+    /// Makes the cookie actor hold exactly `cookies`, keeping invalid cookies
+    ///
+    /// # Returns
+    /// * `Result<(usize, usize), ClewdrError>` - Number of cookies added and removed
+    pub async fn replace_pool(
+        &self,
+        cookies: HashSet<CookieStatus>,
+    ) -> Result<(usize, usize), ClewdrError> {
+        let status = self.get_status().await?;
+        let current = status
+            .valid
+            .into_iter()
+            .chain(status.exhausted)
+            .collect::<HashSet<_>>();
+        let mut removed = 0;
+        for cookie in current.iter().filter(|c| !cookies.contains(*c)) {
+            self.delete_cookie(cookie.to_owned()).await?;
+            removed += 1;
+        }
+        let mut added = 0;
+        for cookie in cookies.into_iter().filter(|c| !current.contains(c)) {
+            self.submit(cookie).await?;
+            added += 1;
+        }
+        Ok((added, removed))
+    }
+
     /// Update 1M support flags on an existing cookie
     pub async fn update_cookie_1m_support(&self, cookie: CookieStatus) -> Result<(), ClewdrError> {
         ractor::call!(self.actor_ref, CookieActorMessage::Update1mSupport, cookie).map_err(|e| {
@@ -559,3 +1054,260 @@ impl CookieActorHandle {
         })?
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cookie(i: usize) -> CookieStatus {
+        let body = format!("{:0<86}", format!("limit-test-{i:02}-"));
+        CookieStatus::new(&format!("sk-ant-sid01-{body}-{i:06}AA"), None).unwrap()
+    }
+
+    fn state(cookies: usize) -> CookieActorState {
+        CookieActorState {
+            valid: (1..=cookies).map(cookie).collect(),
+            exhausted: HashSet::new(),
+            invalid: HashSet::new(),
+            moka: Cache::new(10),
+            in_flight: HashMap::new(),
+            requests: HashMap::new(),
+            persist: mpsc::unbounded_channel().0,
+        }
+    }
+
+    #[test]
+    fn saturated_cookies_are_skipped() {
+        let mut state = state(2);
+        let first = CookieActor
+            .dispatch(&mut state, None, 1, CookieStrategy::RoundRobin, &[])
+            .unwrap();
+        let second = CookieActor
+            .dispatch(&mut state, None, 1, CookieStrategy::RoundRobin, &[])
+            .unwrap();
+        assert_ne!(first, second);
+        assert!(matches!(
+            CookieActor.dispatch(&mut state, None, 1, CookieStrategy::RoundRobin, &[]),
+            Err(ClewdrError::CookiesBusy { capacity: 2 })
+        ));
+
+        CookieActor::release(&mut state, &first.cookie);
+        assert_eq!(
+            CookieActor
+                .dispatch(&mut state, None, 1, CookieStrategy::RoundRobin, &[])
+                .unwrap(),
+            first
+        );
+        assert_eq!(state.in_flight.values().sum::<usize>(), 2);
+    }
+
+    #[test]
+    fn sticky_cookie_used_until_full() {
+        let mut state = state(2);
+        let sticky = CookieActor
+            .dispatch(&mut state, Some(7), 2, CookieStrategy::RoundRobin, &[])
+            .unwrap();
+        assert_eq!(
+            CookieActor
+                .dispatch(&mut state, Some(7), 2, CookieStrategy::RoundRobin, &[])
+                .unwrap(),
+            sticky
+        );
+        assert_ne!(
+            CookieActor
+                .dispatch(&mut state, Some(7), 2, CookieStrategy::RoundRobin, &[])
+                .unwrap(),
+            sticky
+        );
+        CookieActor::release(&mut state, &sticky.cookie);
+        CookieActor::release(&mut state, &sticky.cookie);
+        assert!(!state.in_flight.contains_key(&sticky.cookie));
+    }
+
+    #[test]
+    fn failed_cookies_are_passed_over() {
+        for strategy in [
+            CookieStrategy::RoundRobin,
+            CookieStrategy::LeastUsed,
+            CookieStrategy::Random,
+        ] {
+            let mut state = state(2);
+            let failed = CookieActor
+                .dispatch(&mut state, Some(7), 0, strategy, &[])
+                .unwrap();
+            // neither the session pin nor the strategy lands on it again
+            let exclude = [failed.cookie.to_owned()];
+            for _ in 0..4 {
+                let next = CookieActor
+                    .dispatch(&mut state, Some(7), 0, strategy, &exclude)
+                    .unwrap();
+                assert_ne!(next, failed);
+            }
+            // the session moved to the other cookie
+            let pinned = CookieActor
+                .dispatch(&mut state, Some(7), 0, strategy, &[])
+                .unwrap();
+            assert_ne!(pinned, failed);
+        }
+
+        // with no other cookie free the failed one is still served
+        let mut state = state(1);
+        let only = CookieActor
+            .dispatch(&mut state, None, 0, CookieStrategy::LeastUsed, &[])
+            .unwrap();
+        let exclude = [only.cookie.to_owned()];
+        assert_eq!(
+            CookieActor
+                .dispatch(&mut state, None, 0, CookieStrategy::LeastUsed, &exclude)
+                .unwrap(),
+            only
+        );
+    }
+
+    /// Requests each cookie served out of `requests` dispatches
+    fn served(
+        state: &mut CookieActorState,
+        strategy: CookieStrategy,
+        requests: usize,
+    ) -> Vec<usize> {
+        let cookies = state.valid.iter().cloned().collect::<Vec<_>>();
+        let mut served = vec![0; cookies.len()];
+        for _ in 0..requests {
+            let cookie = CookieActor.dispatch(state, None, 0, strategy, &[]).unwrap();
+            served[cookies.iter().position(|c| c == &cookie).unwrap()] += 1;
+        }
+        served
+    }
+
+    #[test]
+    fn strategies_spread_requests() {
+        assert_eq!(
+            served(&mut state(3), CookieStrategy::RoundRobin, 9),
+            [3, 3, 3]
+        );
+
+        assert_eq!(
+            served(&mut state(3), CookieStrategy::LeastUsed, 6),
+            [2, 2, 2]
+        );
+        let mut busy = state(3);
+        let (first, third) = (
+            busy.valid[0].cookie.to_owned(),
+            busy.valid[2].cookie.to_owned(),
+        );
+        busy.requests.insert(first, 5);
+        busy.requests.insert(third, 1);
+        // requests count, not tokens, and the second cookie joins level with the third
+        busy.valid[1].session_usage.total_output_tokens = 5000;
+        assert_eq!(served(&mut busy, CookieStrategy::LeastUsed, 6), [0, 3, 3]);
+
+        let random = served(&mut state(3), CookieStrategy::Random, 300);
+        assert!(random.iter().all(|&n| n > 50), "{random:?}");
+    }
+
+    #[test]
+    fn strategies_skip_saturated_cookies() {
+        for strategy in [
+            CookieStrategy::RoundRobin,
+            CookieStrategy::LeastUsed,
+            CookieStrategy::Random,
+        ] {
+            let mut state = state(2);
+            let first = CookieActor
+                .dispatch(&mut state, None, 1, strategy, &[])
+                .unwrap();
+            let second = CookieActor
+                .dispatch(&mut state, None, 1, strategy, &[])
+                .unwrap();
+            assert_ne!(first, second);
+            assert!(
+                CookieActor
+                    .dispatch(&mut state, None, 1, strategy, &[])
+                    .is_err()
+            );
+        }
+    }
+
+    #[test]
+    fn zero_limit_never_saturates() {
+        let mut state = state(1);
+        for _ in 0..10 {
+            CookieActor
+                .dispatch(&mut state, None, 0, CookieStrategy::RoundRobin, &[])
+                .unwrap();
+        }
+        assert_eq!(state.in_flight.values().sum::<usize>(), 10);
+    }
+
+    fn flag(standing: AccountStanding) -> AccountFlag {
+        AccountFlag {
+            standing,
+            message: standing.to_string(),
+            seen_at: Utc::now().timestamp(),
+            until: None,
+            strikes: (standing == AccountStanding::Banned).into(),
+        }
+    }
+
+    #[test]
+    fn flags_move_cookies_by_standing() {
+        let mut state = state(3);
+        CookieActor::flag(&mut state, cookie(1), flag(AccountStanding::Warning), 0);
+        assert_eq!(state.valid.len(), 3);
+        assert!(state.valid.iter().any(|c| c.flag.is_some()));
+
+        CookieActor::flag(&mut state, cookie(2), flag(AccountStanding::Restricted), 0);
+        let rested = state.exhausted.get(&cookie(2)).unwrap();
+        assert!(rested.reset_time.is_some());
+        assert_eq!(
+            rested.flag.as_ref().map(|f| f.standing),
+            Some(AccountStanding::Restricted)
+        );
+
+        CookieActor::flag(&mut state, cookie(3), flag(AccountStanding::Banned), 0);
+        CookieActor::flag(&mut state, cookie(3), flag(AccountStanding::Banned), 0);
+        let banned = state.invalid.iter().next().unwrap();
+        assert_eq!(banned.reason, Reason::Banned);
+        assert_eq!(banned.flag.as_ref().map(|f| f.strikes), Some(2));
+        assert_eq!(state.valid, [cookie(1)]);
+
+        // a deleted cookie is not flagged back in
+        CookieActor::flag(&mut state, cookie(4), flag(AccountStanding::Banned), 0);
+        assert_eq!(state.invalid.len(), 1);
+    }
+
+    #[test]
+    fn ban_limit_is_reached_through_selection() {
+        let mut state = state(2);
+        for strikes in 1..3 {
+            CookieActor::flag(&mut state, cookie(1), flag(AccountStanding::Banned), 3);
+            let rested = state.exhausted.get(&cookie(1)).unwrap().to_owned();
+            assert_eq!(rested.flag.as_ref().map(|f| f.strikes), Some(strikes));
+            assert!(rested.reset_time.unwrap() > Utc::now().timestamp());
+            assert_eq!(state.valid, [cookie(2)]);
+
+            // the backoff ends and the cookie is dispatched again, flag kept
+            let mut rested = state.exhausted.take(&cookie(1)).unwrap();
+            rested.reset_time = Some(0);
+            state.exhausted.insert(rested);
+            CookieActor::reset(&mut state);
+            let back = state.valid.iter().find(|c| **c == cookie(1)).unwrap();
+            assert_eq!(back.flag.as_ref().map(|f| f.strikes), Some(strikes));
+        }
+        CookieActor::flag(&mut state, cookie(1), flag(AccountStanding::Banned), 3);
+        assert_eq!(state.valid, [cookie(2)]);
+        assert!(state.exhausted.is_empty() && state.invalid.is_empty());
+    }
+
+    #[test]
+    fn batch_delete_reports_each_cookie() {
+        let mut state = state(2);
+        let exhausted = cookie(3);
+        state.exhausted.insert(exhausted.to_owned());
+        let found =
+            CookieActor::delete_batch(&mut state, vec![cookie(1), cookie(4), exhausted, cookie(1)]);
+        assert_eq!(found, [true, false, true, false]);
+        assert_eq!(state.valid, [cookie(2)]);
+        assert!(state.exhausted.is_empty());
+    }
+}
@@ -0,0 +1,618 @@
+//! Credential-free demo mode
+//!
+//! Started with `--demo` or `demo = true`, the proxy answers from a
+//! deterministic generator instead of Claude and the admin endpoints work on
+//! a generated cookie pool that only lives in memory. Every response carries
+//! the [`DEMO_HEADER`] watermark.
+//!
+//! The generator behaves like the real API where clients can tell: it stops
+//! at stop sequences, calls a forced tool and rejects prompts over the
+//! context window, so the conformance suite can run against it.
+
+use std::{
+    collections::HashSet,
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use async_stream::stream;
+use axum::{
+    Json,
+    response::{
+        IntoResponse, Response,
+        sse::{Event, Sse},
+    },
+};
+use chrono::Utc;
+use http::{HeaderValue, StatusCode};
+use serde_json::{Value, json};
+
+use crate::{
+    config::{
+        CLEWDR_CONFIG, ClewdrConfig, CookieStatus, DEMO_HEADER, Reason, UsageBreakdown,
+        UselessCookie,
+    },
+    error::{ClaudeErrorBody, ClewdrError},
+    types::claude::{
+        ContentBlock, ContentBlockDelta, CreateMessageParams, CreateMessageResponse,
+        MessageDeltaContent, MessageStartContent, Role, StopReason, StreamEvent, StreamUsage,
+        ToolChoice, Usage,
+    },
+};
+
+/// Vocabulary of the synthetic replies
+const LOREM: &str = "\
+    lorem ipsum dolor sit amet consectetur adipiscing elit sed do eiusmod tempor \
+    incididunt ut labore et dolore magna aliqua enim ad minim veniam quis nostrud \
+    exercitation ullamco laboris nisi aliquip ex ea commodo consequat duis aute \
+    irure in reprehenderit voluptate velit esse cillum fugiat nulla pariatur \
+    excepteur sint";
+
+/// Words per streamed text delta
+const WORDS_PER_DELTA: usize = 3;
+const DELTA_DELAY: Duration = Duration::from_millis(20);
+/// Longest prompt accepted, like the smallest context window upstream
+const CONTEXT_TOKENS: u32 = 200_000;
+
+/// Requests served so far, mixed into the error roll so retries can succeed
+static SERVED: AtomicU64 = AtomicU64::new(0);
+
+/// Small deterministic generator, splitmix64
+struct DemoRng(u64);
+
+impl DemoRng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n.max(1)
+    }
+
+    fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+fn seed_of(value: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Fails when upstream requests are not allowed
+///
+/// Every path that talks to Claude calls this first, so demo mode cannot
+/// reach a real backend whatever else is configured.
+pub fn ensure_live(config: &ClewdrConfig) -> Result<(), ClewdrError> {
+    if config.demo {
+        return Err(ClewdrError::BadRequest {
+            msg: "Upstream requests are disabled in demo mode",
+        });
+    }
+    Ok(())
+}
+
+/// Adds the demo watermark header to every response of a demo instance
+pub async fn watermark(mut resp: Response) -> Response {
+    if CLEWDR_CONFIG.load().demo {
+        resp.headers_mut()
+            .insert(DEMO_HEADER, HeaderValue::from_static("true"));
+    }
+    resp
+}
+
+/// Generates the reply text for a request, the same request gives the same text
+fn synthetic_text(seed: u64, max_tokens: u32) -> String {
+    let vocabulary = LOREM.split_whitespace().collect::<Vec<_>>();
+    let mut rng = DemoRng(seed);
+    // roughly 1.3 tokens per word
+    let limit = (max_tokens as u64 * 3 / 4).max(1);
+    let words = (24 + rng.below(72)).min(limit);
+    let mut text = String::new();
+    let mut sentence = 0;
+    for i in 0..words {
+        let word = vocabulary[rng.below(vocabulary.len() as u64) as usize];
+        if sentence == 0 {
+            if i > 0 {
+                text.push(' ');
+            }
+            let mut chars = word.chars();
+            text.extend(chars.next().map(|c| c.to_ascii_uppercase()));
+            text.push_str(chars.as_str());
+        } else {
+            text.push(' ');
+            text.push_str(word);
+        }
+        sentence += 1;
+        if i + 1 == words || sentence >= 6 + rng.below(7) {
+            text.push('.');
+            sentence = 0;
+        }
+    }
+    text
+}
+
+/// Content of a synthetic reply
+enum Reply {
+    /// Generated text, cut before the first stop sequence it contains
+    Text {
+        text: String,
+        stop_sequence: Option<String>,
+    },
+    /// Call of the tool the request forced
+    ToolUse {
+        id: String,
+        name: String,
+        input: Value,
+    },
+}
+
+impl Reply {
+    fn new(params: &CreateMessageParams, seed: u64) -> Self {
+        if let Some((name, schema)) = forced_tool(params) {
+            return Reply::ToolUse {
+                id: format!("toolu_demo_{}", uuid::Uuid::new_v4().simple()),
+                name,
+                input: synthetic_input(&schema),
+            };
+        }
+        let mut text = synthetic_text(seed, params.max_tokens);
+        let stop = params
+            .stop_sequences
+            .iter()
+            .flatten()
+            .filter(|s| !s.is_empty())
+            .filter_map(|s| text.find(s.as_str()).map(|i| (i, s)))
+            .min_by_key(|(i, _)| *i);
+        let stop_sequence = stop.map(|(i, s)| {
+            text.truncate(i);
+            s.to_owned()
+        });
+        Reply::Text {
+            text,
+            stop_sequence,
+        }
+    }
+
+    fn stop_reason(&self) -> StopReason {
+        match self {
+            Reply::Text {
+                stop_sequence: Some(_),
+                ..
+            } => StopReason::StopSequence,
+            Reply::Text { .. } => StopReason::EndTurn,
+            Reply::ToolUse { .. } => StopReason::ToolUse,
+        }
+    }
+
+    fn stop_sequence(&self) -> Option<String> {
+        match self {
+            Reply::Text { stop_sequence, .. } => stop_sequence.to_owned(),
+            Reply::ToolUse { .. } => None,
+        }
+    }
+
+    fn output_tokens(&self) -> u32 {
+        match self {
+            Reply::Text { text, .. } => (text.split(' ').count() as u32 * 4).div_ceil(3),
+            Reply::ToolUse { input, .. } => (input.to_string().len() as u32).div_ceil(4),
+        }
+    }
+
+    fn content(&self) -> ContentBlock {
+        match self {
+            Reply::Text { text, .. } => ContentBlock::text(text.to_owned()),
+            Reply::ToolUse { id, name, input } => ContentBlock::ToolUse {
+                id: id.to_owned(),
+                name: name.to_owned(),
+                input: input.to_owned(),
+                cache_control: None,
+                caller: None,
+            },
+        }
+    }
+}
+
+/// Name and input schema of the tool the request forces, if any
+fn forced_tool(params: &CreateMessageParams) -> Option<(String, Value)> {
+    let tools = params
+        .tools
+        .iter()
+        .flatten()
+        .filter_map(|t| serde_json::to_value(t).ok())
+        .collect::<Vec<_>>();
+    let name = match params.tool_choice.as_ref()? {
+        ToolChoice::Tool { name, .. } => name.to_owned(),
+        ToolChoice::Any { .. } => tools.first()?["name"].as_str()?.to_string(),
+        _ => return None,
+    };
+    let schema = tools
+        .into_iter()
+        .find(|t| t["name"] == name.as_str())
+        .map(|t| t["input_schema"].to_owned())
+        .unwrap_or_default();
+    Some((name, schema))
+}
+
+/// Fills every property of an input schema with a placeholder of its type
+fn synthetic_input(schema: &Value) -> Value {
+    let Some(properties) = schema["properties"].as_object() else {
+        return json!({});
+    };
+    properties
+        .iter()
+        .map(|(key, property)| {
+            let value = match property["type"].as_str() {
+                Some("string") => json!("lorem"),
+                Some("number" | "integer") => json!(0),
+                Some("boolean") => json!(false),
+                Some("array") => json!([]),
+                Some("object") => json!({}),
+                _ => Value::Null,
+            };
+            (key.to_owned(), value)
+        })
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+/// Builds the event sequence of a streamed reply
+fn synthetic_events(reply: &Reply, model: &str, usage: &Usage) -> Vec<StreamEvent> {
+    let mut events = vec![StreamEvent::MessageStart {
+        message: MessageStartContent {
+            id: format!("msg_demo_{}", uuid::Uuid::new_v4().simple()),
+            type_: "message".into(),
+            role: Role::Assistant,
+            content: vec![],
+            model: model.to_string(),
+            stop_reason: None,
+            stop_sequence: None,
+            usage: Some(Usage {
+                input_tokens: usage.input_tokens,
+                output_tokens: 1,
+            }),
+        },
+    }];
+    match reply {
+        Reply::Text { text, .. } => {
+            events.push(StreamEvent::ContentBlockStart {
+                index: 0,
+                content_block: ContentBlock::text(""),
+            });
+            let words = text.split(' ').collect::<Vec<_>>();
+            for (i, chunk) in words.chunks(WORDS_PER_DELTA).enumerate() {
+                let mut text = chunk.join(" ");
+                if i > 0 {
+                    text.insert(0, ' ');
+                }
+                events.push(StreamEvent::ContentBlockDelta {
+                    index: 0,
+                    delta: ContentBlockDelta::TextDelta { text },
+                });
+            }
+        }
+        Reply::ToolUse { id, name, input } => {
+            // the input arrives as JSON deltas, the start block holds an empty one
+            events.push(StreamEvent::ContentBlockStart {
+                index: 0,
+                content_block: ContentBlock::ToolUse {
+                    id: id.to_owned(),
+                    name: name.to_owned(),
+                    input: json!({}),
+                    cache_control: None,
+                    caller: None,
+                },
+            });
+            events.push(StreamEvent::ContentBlockDelta {
+                index: 0,
+                delta: ContentBlockDelta::InputJsonDelta {
+                    partial_json: input.to_string(),
+                },
+            });
+        }
+    }
+    events.push(StreamEvent::ContentBlockStop { index: 0 });
+    events.push(StreamEvent::MessageDelta {
+        delta: MessageDeltaContent {
+            stop_reason: Some(reply.stop_reason()),
+            stop_sequence: reply.stop_sequence(),
+        },
+        usage: Some(StreamUsage {
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+        }),
+    });
+    events.push(StreamEvent::MessageStop);
+    events
+}
+
+fn sse_event(event: StreamEvent) -> Result<Event, axum::Error> {
+    let kind = serde_json::to_value(&event)
+        .ok()
+        .and_then(|v| v["type"].as_str().map(str::to_string))
+        .unwrap_or_default();
+    Event::default().event(kind).json_data(event)
+}
+
+/// Answers a message request with a synthetic reply
+///
+/// # Arguments
+/// * `params` - The client request
+/// * `stream` - Whether to answer with server-sent events
+/// * `error_rate` - Share of requests failing with a simulated overload
+///
+/// # Returns
+/// * `Result<Response, ClewdrError>` - The reply, or the simulated error
+pub fn respond(
+    params: &CreateMessageParams,
+    stream: bool,
+    error_rate: f64,
+) -> Result<Response, ClewdrError> {
+    let seed = seed_of(&serde_json::to_string(params).unwrap_or_default());
+    let served = SERVED.fetch_add(1, Ordering::Relaxed);
+    if DemoRng(seed ^ served).unit() < error_rate {
+        return Err(ClewdrError::ClaudeHttpError {
+            code: StatusCode::from_u16(529).unwrap_or(StatusCode::SERVICE_UNAVAILABLE),
+            inner: ClaudeErrorBody {
+                message: json!("Overloaded (simulated by demo mode)"),
+                r#type: "overloaded_error".into(),
+                code: Some(529),
+            },
+        });
+    }
+    let input_tokens = params.count_tokens();
+    if input_tokens > CONTEXT_TOKENS {
+        return Err(ClewdrError::ClaudeHttpError {
+            code: StatusCode::BAD_REQUEST,
+            inner: ClaudeErrorBody {
+                message: json!(format!(
+                    "prompt is too long: {input_tokens} tokens > {CONTEXT_TOKENS} maximum"
+                )),
+                r#type: "invalid_request_error".into(),
+                code: Some(400),
+            },
+        });
+    }
+    let reply = Reply::new(params, seed);
+    let usage = Usage {
+        input_tokens,
+        output_tokens: reply.output_tokens(),
+    };
+    if !stream {
+        let mut response =
+            CreateMessageResponse::text(String::new(), params.model.to_owned(), usage);
+        response.id = format!("msg_demo_{}", uuid::Uuid::new_v4().simple());
+        response.content = vec![reply.content()];
+        response.stop_reason = Some(reply.stop_reason());
+        response.stop_sequence = reply.stop_sequence();
+        return Ok(Json(response).into_response());
+    }
+    let events = synthetic_events(&reply, &params.model, &usage);
+    let stream = stream! {
+        for event in events {
+            tokio::time::sleep(DELTA_DELAY).await;
+            yield sse_event(event);
+        }
+    };
+    Ok(Sse::new(stream).into_response())
+}
+
+/// Answers a count_tokens request from the local tokenizer
+pub fn count_tokens(params: &CreateMessageParams) -> Response {
+    Json(json!({ "input_tokens": params.count_tokens() })).into_response()
+}
+
+/// Builds a demo cookie in the real cookie format
+fn demo_cookie(i: usize) -> CookieStatus {
+    let body = format!("{:0<86}", format!("demo-cookie-{i:02}-"));
+    let raw = format!("sk-ant-sid01-{body}-{i:06}AA");
+    CookieStatus::new(&raw, None).expect("demo cookies match the cookie format")
+}
+
+fn demo_usage(rng: &mut DemoRng, scale: u64) -> UsageBreakdown {
+    let sonnet_in = rng.below(scale);
+    let sonnet_out = rng.below(scale / 4);
+    let opus_in = rng.below(scale / 2);
+    let opus_out = rng.below(scale / 8);
+    UsageBreakdown {
+        total_input_tokens: sonnet_in + opus_in,
+        total_output_tokens: sonnet_out + opus_out,
+        sonnet_input_tokens: sonnet_in,
+        sonnet_output_tokens: sonnet_out,
+        opus_input_tokens: opus_in,
+        opus_output_tokens: opus_out,
+        // priced like Sonnet 4.5 and Opus 4.5
+        cost_usd: (sonnet_in * 3 + sonnet_out * 15 + opus_in * 5 + opus_out * 25) as f64
+            / 1_000_000.0,
+    }
+}
+
+/// Generates the fake cookie pool served in demo mode
+///
+/// # Returns
+/// * Valid and cooling down cookies, and invalid cookies with varied reasons
+pub fn demo_pool() -> (HashSet<CookieStatus>, HashSet<UselessCookie>) {
+    let now = Utc::now().timestamp();
+    let mut rng = DemoRng(0x00de_0000);
+    let mut cookies = (0..8)
+        .map(|i| {
+            let mut cookie = demo_cookie(i);
+            cookie.session_usage = demo_usage(&mut rng, 200_000);
+            cookie.weekly_usage = demo_usage(&mut rng, 2_000_000);
+            cookie.lifetime_usage = demo_usage(&mut rng, 50_000_000);
+            cookie.session_resets_at = Some(now + rng.below(5 * 3600) as i64);
+            cookie.weekly_resets_at = Some(now + rng.below(7 * 86400) as i64);
+            cookie
+        })
+        .collect::<Vec<_>>();
+    cookies[2].supports_claude_1m_opus = Some(false);
+    cookies[3].needs_reauth = true;
+    cookies[5].supports_claude_1m_sonnet = Some(false);
+    // cooling down
+    cookies[6].reset_time = Some(now + 2 * 3600);
+    cookies[7].reset_time = Some(now + 30 * 60);
+    let invalid = [
+        Reason::Free,
+        Reason::Banned,
+        Reason::Disabled,
+        Reason::Null,
+        Reason::Restricted(now + 86400),
+    ]
+    .into_iter()
+    .enumerate()
+    .map(|(i, reason)| UselessCookie::new(demo_cookie(8 + i).cookie, reason))
+    .collect();
+    (cookies.into_iter().collect(), invalid)
+}
+
+/// Generates the quota utilization the console would report for a cookie
+pub fn usage_metrics(cookie: &CookieStatus) -> Value {
+    let mut rng = DemoRng(seed_of(&cookie.cookie));
+    let mut window = |secs: u64| {
+        json!({
+            "utilization": rng.below(101),
+            "resets_at": (Utc::now() + Duration::from_secs(rng.below(secs))).to_rfc3339(),
+        })
+    };
+    json!({
+        "five_hour": window(5 * 3600),
+        "seven_day": window(7 * 86400),
+        "seven_day_opus": window(7 * 86400),
+        "seven_day_sonnet": window(7 * 86400),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use eventsource_stream::Eventsource;
+    use futures::StreamExt;
+
+    use super::*;
+
+    fn params(stream: bool) -> CreateMessageParams {
+        serde_json::from_value(json!({
+            "model": "claude-sonnet-4-6",
+            "max_tokens": 1024,
+            "stream": stream,
+            "messages": [{ "role": "user", "content": "Hello there" }],
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn stream_is_valid_sse() {
+        let params = params(true);
+        let resp = respond(&params, true, 0.0).unwrap();
+        let events = resp
+            .into_body()
+            .into_data_stream()
+            .eventsource()
+            .map(|e| e.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+        let parsed = events
+            .iter()
+            .map(|e| {
+                let event = serde_json::from_str::<StreamEvent>(&e.data).unwrap();
+                let kind = serde_json::to_value(&event).unwrap()["type"].to_owned();
+                assert_eq!(kind, e.event.as_str());
+                event
+            })
+            .collect::<Vec<_>>();
+        assert!(matches!(parsed[0], StreamEvent::MessageStart { .. }));
+        assert!(matches!(parsed[1], StreamEvent::ContentBlockStart { .. }));
+        assert!(matches!(parsed.last(), Some(StreamEvent::MessageStop)));
+        assert!(matches!(
+            parsed[parsed.len() - 2],
+            StreamEvent::MessageDelta {
+                usage: Some(StreamUsage { output_tokens, .. }),
+                ..
+            } if output_tokens > 0
+        ));
+        let text = parsed
+            .iter()
+            .filter_map(|e| match e {
+                StreamEvent::ContentBlockDelta {
+                    delta: ContentBlockDelta::TextDelta { text },
+                    ..
+                } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect::<String>();
+        let seed = seed_of(&serde_json::to_string(&params).unwrap());
+        assert_eq!(text, synthetic_text(seed, params.max_tokens));
+    }
+
+    #[test]
+    fn text_is_deterministic() {
+        assert_eq!(synthetic_text(7, 1024), synthetic_text(7, 1024));
+        assert_ne!(synthetic_text(7, 1024), synthetic_text(8, 1024));
+        assert!(synthetic_text(7, 8).split(' ').count() <= 6);
+        assert!(synthetic_text(7, 1024).ends_with('.'));
+    }
+
+    async fn json_reply(params: &CreateMessageParams) -> Value {
+        let resp = respond(params, false, 0.0).unwrap();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn honors_stop_sequences_and_forced_tools() {
+        let mut params = params(false);
+        params.stop_sequences = Some(vec!["e".into()]);
+        let reply = json_reply(&params).await;
+        assert_eq!(reply["stop_reason"], "stop_sequence");
+        assert_eq!(reply["stop_sequence"], "e");
+        assert!(!reply["content"][0]["text"].as_str().unwrap().contains('e'));
+
+        params.tools = serde_json::from_value(json!([{
+            "name": "get_weather",
+            "input_schema": {
+                "type": "object",
+                "properties": { "city": { "type": "string" } },
+            },
+        }]))
+        .unwrap();
+        params.tool_choice = Some(ToolChoice::Any {
+            disable_parallel_tool_use: None,
+        });
+        let reply = json_reply(&params).await;
+        assert_eq!(reply["stop_reason"], "tool_use");
+        assert_eq!(reply["content"][0]["name"], "get_weather");
+        assert_eq!(reply["content"][0]["input"], json!({ "city": "lorem" }));
+    }
+
+    #[test]
+    fn error_rate_bounds() {
+        let params = params(false);
+        assert!(respond(&params, false, 1.0).is_err());
+        assert!(respond(&params, false, 0.0).is_ok());
+    }
+
+    #[test]
+    fn demo_disables_backends() {
+        let demo = toml::from_str::<ClewdrConfig>("demo = true").unwrap();
+        assert!(ensure_live(&demo).is_err());
+        let live = toml::from_str::<ClewdrConfig>("").unwrap();
+        assert!(ensure_live(&live).is_ok());
+    }
+
+    #[test]
+    fn pool_has_every_state() {
+        let (cookies, invalid) = demo_pool();
+        assert!(cookies.iter().any(|c| c.reset_time.is_none()));
+        assert!(cookies.iter().any(|c| c.reset_time.is_some()));
+        assert!(cookies.iter().any(|c| c.needs_reauth));
+        assert_eq!(cookies.len(), 8);
+        assert_eq!(invalid.len(), 5);
+    }
+}
@@ -0,0 +1,60 @@
+//! Requests made with each client API key since startup
+
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex, PoisonError},
+};
+
+use chrono::Utc;
+use serde::Serialize;
+
+static USAGE: LazyLock<Mutex<HashMap<String, KeyUsage>>> = LazyLock::new(Default::default);
+
+/// Use of one client API key
+#[derive(Debug, Clone, Copy, Default, Serialize, PartialEq, Eq)]
+pub struct KeyUsage {
+    /// Authenticated requests
+    pub requests: u64,
+    /// Unix timestamp of the latest request
+    pub last_used: Option<i64>,
+}
+
+/// Counts a request authenticated with the key `name`
+pub fn record(name: &str) {
+    let mut usage = USAGE.lock().unwrap_or_else(PoisonError::into_inner);
+    let entry = usage.entry(name.to_string()).or_default();
+    entry.requests += 1;
+    entry.last_used = Some(Utc::now().timestamp());
+}
+
+/// Use of the key `name`, zero when it was never used
+pub fn usage(name: &str) -> KeyUsage {
+    let usage = USAGE.lock().unwrap_or_else(PoisonError::into_inner);
+    usage.get(name).copied().unwrap_or_default()
+}
+
+/// Drops the counts of a removed key
+pub fn forget(name: &str) {
+    USAGE
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .remove(name);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_per_key() {
+        let name = format!("key-{}", uuid::Uuid::new_v4());
+        assert_eq!(usage(&name), KeyUsage::default());
+        record(&name);
+        record(&name);
+        let used = usage(&name);
+        assert_eq!(used.requests, 2);
+        assert!(used.last_used.is_some());
+        forget(&name);
+        assert_eq!(usage(&name).requests, 0);
+    }
+}
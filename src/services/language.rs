@@ -0,0 +1,548 @@
+//! Language detection of response text and the language policy
+//!
+//! Detection compares the character trigrams of the response with profiles
+//! built from a short embedded sample of each language, so it needs no
+//! network and no model files. Only a bounded prefix of the prose is read,
+//! code spans and fences are left out, and responses that are mostly code are
+//! skipped.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        LazyLock, Mutex, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use axum::{
+    body::{self, Body},
+    response::Response,
+};
+use http::HeaderValue;
+use serde_json::{Value, json};
+use tracing::warn;
+
+use crate::{
+    config::{CLEWDR_CONFIG, ClewdrConfig, LanguageMode, LanguagePolicy},
+    error::ClewdrError,
+    types::claude::{ContentBlock, CreateMessageParams, CreateMessageResponse},
+};
+
+/// Detected language of a response
+pub const LANGUAGE_HEADER: &str = "x-clewdr-language";
+/// Set when the required language was not met even after a retry
+pub const LANGUAGE_WARNING_HEADER: &str = "x-clewdr-language-warning";
+
+/// Fewest letters worth detecting
+const MIN_LETTERS: usize = 20;
+/// Share of the text in code above which a response is not detected
+const MAX_CODE_SHARE: f64 = 0.5;
+
+const SAMPLES: &[(&str, &str)] = &[
+    (
+        "en",
+        "The quick answer is that this depends on what you want to do with it. \
+         If you have the time, we can go through each of the steps together and \
+         I will explain why they are used. There are a few things that you should \
+         know before you start, and most of them are about how the system works \
+         when it is under load. Please let me know which one of these would be \
+         the most helpful for you, and what you have already tried so far.",
+    ),
+    (
+        "de",
+        "Die kurze Antwort ist, dass es davon abhängt, was Sie damit machen \
+         wollen. Wenn Sie Zeit haben, können wir die einzelnen Schritte gemeinsam \
+         durchgehen, und ich erkläre Ihnen, warum sie verwendet werden. Es gibt \
+         einige Dinge, die Sie wissen sollten, bevor Sie anfangen, und die meisten \
+         davon betreffen die Frage, wie das System unter Last funktioniert. Bitte \
+         lassen Sie mich wissen, welche davon für Sie am hilfreichsten wäre und \
+         was Sie bisher schon versucht haben.",
+    ),
+    (
+        "fr",
+        "La réponse courte est que cela dépend de ce que vous voulez en faire. \
+         Si vous avez le temps, nous pouvons parcourir chacune des étapes ensemble \
+         et je vous expliquerai pourquoi elles sont utilisées. Il y a quelques \
+         choses que vous devez savoir avant de commencer, et la plupart concernent \
+         la façon dont le système fonctionne lorsqu'il est sous charge. Dites-moi \
+         laquelle de ces options vous serait la plus utile, et ce que vous avez \
+         déjà essayé jusqu'à présent.",
+    ),
+    (
+        "es",
+        "La respuesta corta es que depende de lo que quieras hacer con ello. Si \
+         tienes tiempo, podemos repasar juntos cada uno de los pasos y te \
+         explicaré por qué se utilizan. Hay algunas cosas que deberías saber antes \
+         de empezar, y la mayoría tienen que ver con cómo funciona el sistema \
+         cuando está bajo carga. Por favor, dime cuál de estas opciones te \
+         resultaría más útil y qué es lo que ya has intentado hasta ahora.",
+    ),
+    (
+        "it",
+        "La risposta breve è che dipende da cosa vuoi farci. Se hai tempo, \
+         possiamo esaminare insieme ciascuno dei passaggi e ti spiegherò perché \
+         vengono utilizzati. Ci sono alcune cose che dovresti sapere prima di \
+         iniziare, e la maggior parte riguarda il modo in cui il sistema funziona \
+         quando è sotto carico. Per favore fammi sapere quale di queste opzioni \
+         ti sarebbe più utile e che cosa hai già provato finora.",
+    ),
+    (
+        "nl",
+        "Het korte antwoord is dat het afhangt van wat je ermee wilt doen. Als je \
+         tijd hebt, kunnen we samen elk van de stappen doorlopen en zal ik \
+         uitleggen waarom ze worden gebruikt. Er zijn een paar dingen die je moet \
+         weten voordat je begint, en de meeste gaan over hoe het systeem werkt \
+         wanneer het onder belasting staat. Laat me alsjeblieft weten welke van \
+         deze het meest nuttig voor je zou zijn en wat je tot nu toe al hebt \
+         geprobeerd.",
+    ),
+];
+
+/// Name of each language in the retry instruction
+const NAMES: &[(&str, &str)] = &[
+    ("en", "English"),
+    ("de", "German"),
+    ("fr", "French"),
+    ("es", "Spanish"),
+    ("it", "Italian"),
+    ("nl", "Dutch"),
+];
+
+/// Whether responses written in `code` can be recognized
+pub fn is_supported(code: &str) -> bool {
+    SAMPLES.iter().any(|(c, _)| c.eq_ignore_ascii_case(code))
+}
+
+type Profile = HashMap<[char; 3], f64>;
+
+static PROFILES: LazyLock<Vec<(&'static str, Profile)>> = LazyLock::new(|| {
+    SAMPLES
+        .iter()
+        .map(|(code, sample)| (*code, profile(sample)))
+        .collect()
+});
+
+static STATS: LazyLock<LanguageStats> = LazyLock::new(Default::default);
+
+/// Outcome of inspecting one response
+#[derive(Debug, Clone, PartialEq)]
+pub enum Detection {
+    /// Mostly code, or too little prose to tell
+    Skipped(&'static str),
+    Detected {
+        language: &'static str,
+        /// Lead of the best profile over the runner-up, between 0 and 1
+        confidence: f64,
+    },
+}
+
+impl Detection {
+    fn language(&self) -> Option<&'static str> {
+        match self {
+            Detection::Detected { language, .. } => Some(language),
+            Detection::Skipped(_) => None,
+        }
+    }
+
+    /// Whether the response breaks the required language, skipped ones never do
+    fn mismatches(&self, required: &str) -> bool {
+        self.language()
+            .is_some_and(|l| !l.eq_ignore_ascii_case(required))
+    }
+}
+
+/// Normalized trigram frequencies of the words of `text`
+fn profile(text: &str) -> Profile {
+    let mut counts = Profile::new();
+    for word in text
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|w| !w.is_empty())
+    {
+        let chars = std::iter::once(' ')
+            .chain(word.chars().flat_map(char::to_lowercase))
+            .chain(std::iter::once(' '))
+            .collect::<Vec<_>>();
+        for w in chars.windows(3) {
+            *counts.entry([w[0], w[1], w[2]]).or_default() += 1.0;
+        }
+    }
+    let norm = counts.values().map(|c| c * c).sum::<f64>().sqrt().max(1.0);
+    counts.values_mut().for_each(|c| *c /= norm);
+    counts
+}
+
+fn similarity(a: &Profile, b: &Profile) -> f64 {
+    a.iter().filter_map(|(k, v)| b.get(k).map(|w| v * w)).sum()
+}
+
+/// Splits prose from code spans and fences, returning the prose and the code share
+fn prose(text: &str) -> (String, f64) {
+    let mut prose = String::new();
+    let mut code = 0usize;
+    let mut in_fence = false;
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+            code += line.len();
+            continue;
+        }
+        if in_fence {
+            code += line.len();
+            continue;
+        }
+        for (i, part) in line.split('`').enumerate() {
+            // odd parts sit between backticks
+            if i % 2 == 1 {
+                code += part.len();
+            } else {
+                prose.push_str(part);
+                prose.push(' ');
+            }
+        }
+        prose.push('\n');
+    }
+    let total = (prose.trim().len() + code).max(1);
+    (prose, code as f64 / total as f64)
+}
+
+/// Detects the language of `text` from its first `prefix_chars` characters of prose
+pub fn detect(text: &str, prefix_chars: usize) -> Detection {
+    let (prose, code_share) = prose(text);
+    if code_share > MAX_CODE_SHARE {
+        return Detection::Skipped("code");
+    }
+    let prefix = prose.chars().take(prefix_chars).collect::<String>();
+    if prefix.chars().filter(|c| c.is_alphabetic()).count() < MIN_LETTERS {
+        return Detection::Skipped("too short");
+    }
+    let target = profile(&prefix);
+    let mut scores = PROFILES
+        .iter()
+        .map(|(code, p)| (*code, similarity(&target, p)))
+        .collect::<Vec<_>>();
+    scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+    let (language, best) = scores[0];
+    let second = scores.get(1).map_or(0.0, |s| s.1);
+    Detection::Detected {
+        language,
+        confidence: if best > 0.0 {
+            (best - second) / best
+        } else {
+            0.0
+        },
+    }
+}
+
+/// Instruction added to the system prompt of a retry
+fn instruction(required: &str) -> String {
+    let name = NAMES
+        .iter()
+        .find(|(code, _)| code.eq_ignore_ascii_case(required))
+        .map_or(required, |(_, name)| name);
+    format!(
+        "You must write your entire reply in {name} only. Do not answer in any other language, \
+         whatever language the conversation or the instructions are written in."
+    )
+}
+
+/// Appends `text` to the system prompt, keeping its string or block form
+fn with_instruction(mut params: CreateMessageParams, text: String) -> CreateMessageParams {
+    params.system = Some(match params.system.take() {
+        Some(Value::String(s)) => Value::String(format!("{s}\n\n{text}")),
+        Some(Value::Array(mut blocks)) => {
+            blocks.push(json!({ "type": "text", "text": text }));
+            Value::Array(blocks)
+        }
+        _ => Value::String(text),
+    });
+    params
+}
+
+/// Detection counts since startup
+#[derive(Default)]
+struct LanguageStats {
+    checked: AtomicU64,
+    skipped: AtomicU64,
+    matched: AtomicU64,
+    mismatched: AtomicU64,
+    retried: AtomicU64,
+    /// Retries that met the required language
+    recovered: AtomicU64,
+    detected: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl LanguageStats {
+    fn record(&self, detection: &Detection, required: Option<&str>) {
+        self.checked.fetch_add(1, Ordering::Relaxed);
+        let Some(language) = detection.language() else {
+            self.skipped.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+        *self
+            .detected
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(language)
+            .or_default() += 1;
+        match required {
+            Some(r) if detection.mismatches(r) => self.mismatched.fetch_add(1, Ordering::Relaxed),
+            Some(_) => self.matched.fetch_add(1, Ordering::Relaxed),
+            None => 0,
+        };
+    }
+}
+
+/// Returns the detection counts since startup
+pub fn stats() -> Value {
+    let load = |a: &AtomicU64| a.load(Ordering::Relaxed);
+    let matched = load(&STATS.matched);
+    let mismatched = load(&STATS.mismatched);
+    json!({
+        "checked": load(&STATS.checked),
+        "skipped": load(&STATS.skipped),
+        "matched": matched,
+        "mismatched": mismatched,
+        "match_rate": (matched + mismatched > 0)
+            .then(|| matched as f64 / (matched + mismatched) as f64),
+        "retried": load(&STATS.retried),
+        "recovered": load(&STATS.recovered),
+        "detected": *STATS.detected.lock().unwrap_or_else(PoisonError::into_inner),
+    })
+}
+
+/// Reads a non-streamed response, the response is rebuilt when it has no text
+async fn read_response(resp: Response) -> Result<(CreateMessageResponse, Response), Response> {
+    let (parts, body) = resp.into_parts();
+    let bytes = body::to_bytes(body, usize::MAX).await.unwrap_or_default();
+    match serde_json::from_slice::<CreateMessageResponse>(&bytes) {
+        Ok(message) => {
+            let resp = Response::from_parts(parts, Body::from(bytes));
+            Ok((message, resp))
+        }
+        Err(_) => Err(Response::from_parts(parts, Body::from(bytes))),
+    }
+}
+
+fn text_of(message: &CreateMessageResponse) -> String {
+    message
+        .content
+        .iter()
+        .filter_map(|b| match b {
+            ContentBlock::Text { text, .. } => Some(text.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn annotate(resp: &mut Response, detection: &Detection) {
+    let value = match detection {
+        Detection::Detected { language, .. } => language,
+        Detection::Skipped(_) => "unknown",
+    };
+    resp.headers_mut()
+        .insert(LANGUAGE_HEADER, HeaderValue::from_static(value));
+}
+
+/// Whether the response of a request goes through the language policy
+///
+/// Streamed responses are left alone, their headers are sent before any text
+/// exists, and smoke checks are never retried.
+pub fn polices(config: &ClewdrConfig, stream: bool, smoke: bool) -> bool {
+    config.language_policy.mode != LanguageMode::Off && !stream && !smoke
+}
+
+/// Applies the language policy to a non-streamed response
+///
+/// # Arguments
+/// * `params` - Request the response answers, reused for the retry
+/// * `resp` - The response in Claude format
+/// * `retry` - Sends the request again, called at most once in enforce mode
+pub async fn apply_policy<F, Fut>(params: CreateMessageParams, resp: Response, retry: F) -> Response
+where
+    F: FnOnce(CreateMessageParams) -> Fut,
+    Fut: Future<Output = Result<Response, ClewdrError>>,
+{
+    let policy = CLEWDR_CONFIG.load().language_policy.to_owned();
+    apply(&policy, params, resp, retry).await
+}
+
+async fn apply<F, Fut>(
+    policy: &LanguagePolicy,
+    params: CreateMessageParams,
+    resp: Response,
+    retry: F,
+) -> Response
+where
+    F: FnOnce(CreateMessageParams) -> Fut,
+    Fut: Future<Output = Result<Response, ClewdrError>>,
+{
+    if policy.mode == LanguageMode::Off || !resp.status().is_success() {
+        return resp;
+    }
+    let (message, mut resp) = match read_response(resp).await {
+        Ok(read) => read,
+        Err(resp) => return resp,
+    };
+    let detection = detect(&text_of(&message), policy.prefix_chars);
+    let required = policy.required.as_deref();
+    STATS.record(&detection, required);
+    annotate(&mut resp, &detection);
+    let Some(required) = required.filter(|r| detection.mismatches(r)) else {
+        return resp;
+    };
+    let detected = detection.language().unwrap_or_default();
+    match policy.mode {
+        LanguageMode::Warn => {
+            warn!("Response written in {} instead of {}", detected, required);
+        }
+        LanguageMode::Enforce => {
+            STATS.retried.fetch_add(1, Ordering::Relaxed);
+            let retried = retry(with_instruction(params, instruction(required))).await;
+            if let Ok(retried) = retried
+                && let Ok((message, mut retried)) = read_response(retried).await
+            {
+                let detection = detect(&text_of(&message), policy.prefix_chars);
+                if !detection.mismatches(required) {
+                    STATS.recovered.fetch_add(1, Ordering::Relaxed);
+                    annotate(&mut retried, &detection);
+                    return retried;
+                }
+            }
+            warn!(
+                "Response written in {} instead of {}, even after a retry",
+                detected, required
+            );
+            resp.headers_mut().insert(
+                LANGUAGE_WARNING_HEADER,
+                HeaderValue::from_static("required language not met"),
+            );
+        }
+        LanguageMode::Annotate | LanguageMode::Off => (),
+    }
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use axum::{Json, response::IntoResponse};
+
+    use super::*;
+
+    const FIXTURES: &[(&str, &str)] = &[
+        (
+            "en",
+            "Thanks for reaching out. Your order has been shipped and should arrive within three business days.",
+        ),
+        (
+            "de",
+            "Vielen Dank für Ihre Nachricht. Ihre Bestellung wurde verschickt und sollte innerhalb von drei Werktagen ankommen.",
+        ),
+        (
+            "fr",
+            "Merci de nous avoir contactés. Votre commande a été expédiée et devrait arriver dans les trois jours ouvrables.",
+        ),
+        (
+            "es",
+            "Gracias por escribirnos. Tu pedido ha sido enviado y debería llegar en un plazo de tres días laborables.",
+        ),
+        (
+            "it",
+            "Grazie per averci contattato. Il tuo ordine è stato spedito e dovrebbe arrivare entro tre giorni lavorativi.",
+        ),
+    ];
+
+    fn response(text: &str) -> Response {
+        Json(json!({
+            "content": [{ "type": "text", "text": text }],
+            "id": "msg_1",
+            "model": "claude",
+            "role": "assistant",
+            "stop_reason": "end_turn",
+            "stop_sequence": null,
+            "type": "message",
+            "usage": { "input_tokens": 1, "output_tokens": 1 },
+        }))
+        .into_response()
+    }
+
+    fn params() -> CreateMessageParams {
+        serde_json::from_value(json!({
+            "model": "claude",
+            "max_tokens": 16,
+            "messages": [{ "role": "user", "content": "Wo ist meine Bestellung?" }],
+        }))
+        .unwrap()
+    }
+
+    fn policy(mode: LanguageMode) -> LanguagePolicy {
+        LanguagePolicy {
+            mode,
+            required: Some("de".into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn detects_fixture_languages() {
+        for (language, text) in FIXTURES {
+            assert_eq!(detect(text, 1000).language(), Some(*language), "{text}");
+        }
+    }
+
+    #[test]
+    fn skips_code_dominant_text() {
+        let code = "Here:\n```rust\nfn main() {\n    let total = values.iter().sum::<u64>();\n    println!(\"{total}\");\n}\n```";
+        assert_eq!(detect(code, 1000), Detection::Skipped("code"));
+        // prose around a short snippet is still detected
+        let mixed = format!("{} Use `cargo build` first.", FIXTURES[1].1);
+        assert_eq!(detect(&mixed, 1000).language(), Some("de"));
+        assert_eq!(detect("Ok, done.", 1000), Detection::Skipped("too short"));
+    }
+
+    #[tokio::test]
+    async fn annotate_and_warn_keep_the_response() {
+        for mode in [LanguageMode::Annotate, LanguageMode::Warn] {
+            let resp = apply(
+                &policy(mode),
+                params(),
+                response(FIXTURES[0].1),
+                |_| async { unreachable!("only enforce retries") },
+            )
+            .await;
+            assert_eq!(resp.headers()[LANGUAGE_HEADER], "en");
+            assert!(resp.headers().get(LANGUAGE_WARNING_HEADER).is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn enforce_retries_once_with_an_instruction() {
+        let calls = AtomicUsize::new(0);
+        let resp = apply(
+            &policy(LanguageMode::Enforce),
+            params(),
+            response(FIXTURES[0].1),
+            |p| {
+                calls.fetch_add(1, Ordering::Relaxed);
+                assert!(p.system.unwrap().as_str().unwrap().contains("German"));
+                async { Ok(response(FIXTURES[1].1)) }
+            },
+        )
+        .await;
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        assert_eq!(resp.headers()[LANGUAGE_HEADER], "de");
+
+        // a retry still in the wrong language delivers the first answer with a warning
+        let resp = apply(
+            &policy(LanguageMode::Enforce),
+            params(),
+            response(FIXTURES[0].1),
+            |_| async { Ok(response(FIXTURES[2].1)) },
+        )
+        .await;
+        assert_eq!(resp.headers()[LANGUAGE_HEADER], "en");
+        assert!(resp.headers().contains_key(LANGUAGE_WARNING_HEADER));
+    }
+}
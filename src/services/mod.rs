@@ -1,3 +1,28 @@
+pub mod account_cache;
+pub mod audit;
+pub mod config_watch;
+pub mod conformance;
+pub mod conversations;
 pub mod cookie_actor;
+pub mod demo;
+pub mod key_usage;
+pub mod language;
+pub mod model_list;
+pub mod queue;
+pub mod rate_limits;
+pub mod redact;
+pub mod resources;
+pub mod response_cache;
+pub mod retry;
+pub mod shutdown;
+pub mod slo;
+pub mod smoke;
+pub mod startup;
+pub mod storage;
+#[cfg(test)]
+pub mod test_instance;
+pub mod throttle;
+pub mod token_refresh;
+pub mod transcript;
 #[cfg(feature = "portable")]
 pub mod update;
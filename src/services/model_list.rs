@@ -0,0 +1,210 @@
+//! Model list served by `/v1/models`, fetched from the Anthropic API
+//!
+//! The ids offered upstream are fetched with a token from the cookie pool and
+//! kept for `model_list_ttl_secs`. Once they expire the last list is still
+//! served while a background fetch replaces it. When a fetch fails the last
+//! known list is served flagged stale, or the built-in [`MODEL_LIST`] until a
+//! fetch first succeeds.
+//!
+//! [`MODEL_LIST`]: crate::config::MODEL_LIST
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex, OnceLock, PoisonError},
+    time::{Duration, Instant},
+};
+
+use chrono::Utc;
+use moka::sync::Cache;
+use tracing::{info, warn};
+
+use crate::{
+    claude_code_state::ClaudeCodeState,
+    config::{CLEWDR_CONFIG, accepted_models, expand_upstream_models, with_aliases},
+    error::ClewdrError,
+    services::cookie_actor::CookieActorHandle,
+};
+
+/// Wait after a failed fetch before requests trigger another one
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+static HANDLE: OnceLock<CookieActorHandle> = OnceLock::new();
+
+static LAST: Mutex<LastFetch> = Mutex::new(LastFetch {
+    good: None,
+    failed_at: None,
+});
+
+/// Ids fetched less than the TTL ago
+type FreshIds = Cache<(), Arc<Vec<String>>>;
+
+/// The fresh ids with the TTL they were built with, rebuilt when it changes
+static FRESH: Mutex<Option<(u64, FreshIds)>> = Mutex::new(None);
+
+/// Serializes fetches so concurrent misses share one
+static FETCH: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+struct LastFetch {
+    /// Ids and unix timestamp of the latest successful fetch
+    good: Option<(Arc<Vec<String>>, i64)>,
+    /// When the latest fetch failed, cleared by the next success
+    failed_at: Option<Instant>,
+}
+
+/// A model list to serve
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelList {
+    /// Ids offered upstream, `None` for the built-in list
+    pub ids: Option<Arc<Vec<String>>>,
+    /// Unix timestamp of the fetch that returned `ids`
+    pub fetched_at: Option<i64>,
+    /// The latest fetch failed, or none succeeded yet
+    pub stale: bool,
+}
+
+impl ModelList {
+    /// Models to list, followed by the aliases
+    pub fn models(&self, aliases: &BTreeMap<String, String>) -> Vec<String> {
+        match &self.ids {
+            Some(ids) => with_aliases(expand_upstream_models(ids), aliases),
+            None => accepted_models(aliases),
+        }
+    }
+}
+
+/// Lets the model list be fetched with cookies of the pool
+///
+/// # Arguments
+/// * `handle` - Handle of the cookie actor
+pub fn init_model_list(handle: CookieActorHandle) {
+    _ = HANDLE.set(handle);
+}
+
+/// The model list to serve, fetched first when none was fetched yet
+///
+/// # Arguments
+/// * `force` - Fetch again even when the list is fresh
+pub async fn current(force: bool) -> ModelList {
+    let config = CLEWDR_CONFIG.load();
+    let ttl_secs = config.model_list_ttl_secs;
+    if ttl_secs == 0 || config.demo {
+        return ModelList {
+            ids: None,
+            fetched_at: None,
+            stale: false,
+        };
+    }
+    if force {
+        refresh(ttl_secs, true).await;
+    } else if fresh(ttl_secs).get(&()).is_none() {
+        if snapshot().ids.is_some() {
+            tokio::spawn(refresh(ttl_secs, false));
+        } else {
+            refresh(ttl_secs, false).await;
+        }
+    }
+    snapshot()
+}
+
+/// Returns the cache of fresh ids matching `ttl_secs`
+fn fresh(ttl_secs: u64) -> FreshIds {
+    let mut built = FRESH.lock().unwrap_or_else(PoisonError::into_inner);
+    match built.as_ref() {
+        Some((ttl, cache)) if *ttl == ttl_secs => cache.to_owned(),
+        _ => {
+            let cache = Cache::builder()
+                .max_capacity(1)
+                .time_to_live(Duration::from_secs(ttl_secs))
+                .build();
+            *built = Some((ttl_secs, cache.to_owned()));
+            cache
+        }
+    }
+}
+
+fn snapshot() -> ModelList {
+    let last = LAST.lock().unwrap_or_else(PoisonError::into_inner);
+    ModelList {
+        ids: last.good.as_ref().map(|(ids, _)| ids.to_owned()),
+        fetched_at: last.good.as_ref().map(|(_, at)| *at),
+        stale: last.good.is_none() || last.failed_at.is_some(),
+    }
+}
+
+async fn refresh(ttl_secs: u64, force: bool) {
+    let _guard = FETCH.lock().await;
+    if !force {
+        // fetched while this task waited, or failed too recently
+        let failed_recently = LAST
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .failed_at
+            .is_some_and(|at| at.elapsed() < RETRY_INTERVAL);
+        if failed_recently || fresh(ttl_secs).get(&()).is_some() {
+            return;
+        }
+    }
+    record(ttl_secs, fetch().await);
+}
+
+async fn fetch() -> Result<Vec<String>, ClewdrError> {
+    let handle = HANDLE.get().ok_or(ClewdrError::UnexpectedNone {
+        msg: "Model list fetched before the cookie actor started",
+    })?;
+    let mut state = ClaudeCodeState::new(handle.to_owned());
+    state.request_cookie().await?;
+    let result = state.fetch_models().await;
+    // keeps a token obtained for the fetch
+    state.return_cookie(None).await;
+    result
+}
+
+/// Stores the outcome of a fetch, an empty list counts as a failure
+fn record(ttl_secs: u64, result: Result<Vec<String>, ClewdrError>) {
+    let mut last = LAST.lock().unwrap_or_else(PoisonError::into_inner);
+    match result {
+        Ok(ids) if !ids.is_empty() => {
+            info!("Fetched {} models from upstream", ids.len());
+            let ids = Arc::new(ids);
+            fresh(ttl_secs).insert((), ids.to_owned());
+            last.good = Some((ids, Utc::now().timestamp()));
+            last.failed_at = None;
+        }
+        Ok(_) => {
+            warn!("Upstream listed no models, keeping the last list");
+            last.failed_at = Some(Instant::now());
+        }
+        Err(e) => {
+            warn!("Failed to fetch models, keeping the last list: {}", e);
+            last.failed_at = Some(Instant::now());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failures_keep_the_last_list() {
+        record(60, Err(ClewdrError::TooManyRetries));
+        assert_eq!(snapshot().ids, None);
+        assert!(snapshot().stale);
+
+        record(60, Ok(vec!["claude-sonnet-4-6".to_string()]));
+        let good = snapshot();
+        assert!(!good.stale);
+        assert!(good.fetched_at.is_some());
+        assert!(fresh(60).get(&()).is_some());
+
+        record(60, Ok(vec![]));
+        let stale = snapshot();
+        assert!(stale.stale);
+        assert_eq!(stale.ids, good.ids);
+        assert!(
+            stale
+                .models(&BTreeMap::new())
+                .contains(&"claude-sonnet-4-6-thinking".to_string())
+        );
+    }
+}
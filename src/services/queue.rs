@@ -0,0 +1,220 @@
+//! Waiting for cookie capacity
+//!
+//! Each cookie serves a bounded number of requests at once. A request that
+//! finds every cookie saturated waits here until a [`CookiePermit`] is dropped
+//! or a cookie becomes available, up to `queue_timeout_ms`. Waiting requests
+//! are served by priority, then in arrival order.
+
+use std::{
+    cmp::Reverse,
+    collections::BTreeSet,
+    sync::{
+        LazyLock, Mutex, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{body::Body, response::Response};
+use futures::StreamExt;
+use tokio::sync::Notify;
+
+use crate::{config::ClewdrCookie, services::cookie_actor::CookieActorHandle};
+
+/// Signalled whenever a cookie may have free capacity again
+pub(crate) static CAPACITY: LazyLock<Notify> = LazyLock::new(Notify::new);
+/// Requests currently waiting for a cookie
+static WAITING: LazyLock<WaitList> = LazyLock::new(WaitList::default);
+/// Moving average of how long a request holds its cookie
+static AVG_HOLD_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the number of requests waiting for a cookie
+pub fn queued() -> usize {
+    WAITING.len()
+}
+
+/// Position of a waiting request, higher priority first, then oldest first
+type Ticket = (Reverse<u32>, u64);
+
+/// Requests waiting for a cookie, in the order they are served
+#[derive(Default)]
+struct WaitList {
+    tickets: Mutex<BTreeSet<Ticket>>,
+    sequence: AtomicU64,
+}
+
+impl WaitList {
+    fn join(&self, max: usize, priority: u32) -> Result<Ticket, usize> {
+        let mut tickets = self.tickets.lock().unwrap_or_else(PoisonError::into_inner);
+        if max > 0 && tickets.len() >= max {
+            return Err(tickets.len());
+        }
+        let ticket = (
+            Reverse(priority),
+            self.sequence.fetch_add(1, Ordering::Relaxed),
+        );
+        tickets.insert(ticket);
+        Ok(ticket)
+    }
+
+    fn is_first(&self, ticket: &Ticket) -> bool {
+        let tickets = self.tickets.lock().unwrap_or_else(PoisonError::into_inner);
+        tickets.first() == Some(ticket)
+    }
+
+    fn leave(&self, ticket: &Ticket) {
+        let mut tickets = self.tickets.lock().unwrap_or_else(PoisonError::into_inner);
+        tickets.remove(ticket);
+    }
+
+    fn len(&self) -> usize {
+        let tickets = self.tickets.lock().unwrap_or_else(PoisonError::into_inner);
+        tickets.len()
+    }
+}
+
+/// Place of a waiting request in the queue, kept for as long as it is alive
+pub(crate) struct QueueSlot {
+    ticket: Ticket,
+}
+
+impl QueueSlot {
+    /// Joins the queue unless it already holds `max` requests
+    ///
+    /// # Arguments
+    /// * `max` - Queue capacity, 0 means unbounded
+    /// * `priority` - Requests with a higher priority are served first
+    ///
+    /// # Returns
+    /// * `Result<Self, usize>` - The slot, or the queue depth if the queue is full
+    pub(crate) fn join(max: usize, priority: u32) -> Result<Self, usize> {
+        WAITING
+            .join(max, priority)
+            .map(|ticket| QueueSlot { ticket })
+    }
+
+    /// Whether this request is the next one to be served
+    pub(crate) fn is_first(&self) -> bool {
+        WAITING.is_first(&self.ticket)
+    }
+}
+
+impl Drop for QueueSlot {
+    fn drop(&mut self) {
+        WAITING.leave(&self.ticket);
+        // the next request in line may take the capacity this one did not use
+        CAPACITY.notify_waiters();
+    }
+}
+
+/// Estimates how long a new request would wait for a cookie
+///
+/// # Arguments
+/// * `queue_depth` - Requests already waiting
+/// * `capacity` - Requests all available cookies serve at once
+/// * `avg_hold_ms` - Average time a request holds its cookie
+///
+/// # Returns
+/// * `u64` - Estimated wait in milliseconds
+pub fn estimate_wait_ms(queue_depth: usize, capacity: usize, avg_hold_ms: u64) -> u64 {
+    let rounds = queue_depth / capacity.max(1) + 1;
+    avg_hold_ms.saturating_mul(rounds as u64)
+}
+
+/// Returns the moving average of how long a request holds its cookie
+pub fn avg_hold_ms() -> u64 {
+    AVG_HOLD_MS.load(Ordering::Relaxed)
+}
+
+fn record_hold(held: Duration) {
+    let held = held.as_millis() as u64;
+    // weight the latest request by 1/8
+    let _ = AVG_HOLD_MS.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |avg| {
+        Some(if avg == 0 { held } else { (avg * 7 + held) / 8 })
+    });
+}
+
+/// One in-flight request on a cookie, released when dropped
+pub struct CookiePermit {
+    cookie: ClewdrCookie,
+    handle: CookieActorHandle,
+    acquired: Instant,
+}
+
+impl CookiePermit {
+    pub(crate) fn new(cookie: ClewdrCookie, handle: CookieActorHandle) -> Self {
+        Self {
+            cookie,
+            handle,
+            acquired: Instant::now(),
+        }
+    }
+}
+
+impl Drop for CookiePermit {
+    fn drop(&mut self) {
+        record_hold(self.acquired.elapsed());
+        self.handle.release(self.cookie.to_owned());
+    }
+}
+
+/// Keeps `permit` alive until the body of `response` is finished or dropped
+///
+/// Streaming responses outlive the request handler, the cookie stays busy
+/// until the last chunk is sent or the client disconnects.
+pub fn hold_permit<T: Send + 'static>(response: Response, permit: Option<T>) -> Response {
+    let Some(permit) = permit else {
+        return response;
+    };
+    let (parts, body) = response.into_parts();
+    let stream = body.into_data_stream().map(move |chunk| {
+        let _ = &permit;
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retries_wait_ahead_of_fresh_requests() {
+        let list = WaitList::default();
+        let fresh = list.join(0, 0).unwrap();
+        let later = list.join(0, 0).unwrap();
+        let retry = list.join(0, 2).unwrap();
+        let first_retry = list.join(0, 1).unwrap();
+        for next in [retry, first_retry, fresh, later] {
+            assert!(list.is_first(&next));
+            list.leave(&next);
+        }
+        assert_eq!(list.len(), 0);
+
+        let capped = WaitList::default();
+        capped.join(1, 0).unwrap();
+        assert_eq!(capped.join(1, 5), Err(1));
+    }
+
+    #[test]
+    fn wait_grows_with_queue_depth() {
+        assert_eq!(estimate_wait_ms(0, 4, 1000), 1000);
+        assert_eq!(estimate_wait_ms(3, 4, 1000), 1000);
+        assert_eq!(estimate_wait_ms(4, 4, 1000), 2000);
+        assert_eq!(estimate_wait_ms(5, 0, 1000), 6000);
+    }
+
+    #[tokio::test]
+    async fn permit_lives_until_body_is_dropped() {
+        use std::sync::Arc;
+
+        let permit = Arc::new(());
+        let response = hold_permit(Response::new(Body::from("done")), Some(permit.clone()));
+        assert_eq!(Arc::strong_count(&permit), 2);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "done");
+        assert_eq!(Arc::strong_count(&permit), 1);
+    }
+}
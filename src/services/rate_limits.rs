@@ -0,0 +1,86 @@
+//! Rate-limit headers last returned for each cookie
+//!
+//! Claude Code responses carry `anthropic-ratelimit-*` headers with the real
+//! remaining quota of the account. The latest set of every cookie is kept
+//! here, whether or not it is forwarded to clients.
+
+use std::{collections::BTreeMap, sync::LazyLock, time::Duration};
+
+use chrono::Utc;
+use http::HeaderMap;
+use moka::sync::Cache;
+use serde::Serialize;
+
+use crate::config::{ClewdrCookie, header_matches};
+
+static LAST_SEEN: LazyLock<Cache<ClewdrCookie, RateLimitSnapshot>> = LazyLock::new(|| {
+    Cache::builder()
+        .max_capacity(1000)
+        .time_to_live(Duration::from_secs(24 * 60 * 60))
+        .build()
+});
+
+/// Rate-limit headers of one upstream response
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct RateLimitSnapshot {
+    /// Unix timestamp of the response
+    pub observed_at: i64,
+    pub headers: BTreeMap<String, String>,
+}
+
+/// Records the rate-limit headers of a response served with `cookie`
+pub fn record(cookie: &ClewdrCookie, headers: &HeaderMap) {
+    let patterns = ["anthropic-ratelimit-*".to_string()];
+    let headers = headers
+        .iter()
+        .filter(|(name, _)| header_matches(&patterns, name.as_str()))
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect::<BTreeMap<_, _>>();
+    if headers.is_empty() {
+        return;
+    }
+    LAST_SEEN.insert(
+        cookie.to_owned(),
+        RateLimitSnapshot {
+            observed_at: Utc::now().timestamp(),
+            headers,
+        },
+    );
+}
+
+/// Returns the latest rate-limit headers seen for `cookie`
+pub fn last_seen(cookie: &ClewdrCookie) -> Option<RateLimitSnapshot> {
+    LAST_SEEN.get(cookie)
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderValue;
+
+    use super::*;
+    use crate::config::CookieStatus;
+
+    #[test]
+    fn keeps_only_rate_limit_headers() {
+        let body = format!("{:0<86}", "rate-limit-test-");
+        let cookie = CookieStatus::new(&format!("sk-ant-sid01-{body}-000001AA"), None)
+            .unwrap()
+            .cookie;
+        let mut headers = HeaderMap::new();
+        headers.insert("request-id", HeaderValue::from_static("req_1"));
+        record(&cookie, &headers);
+        assert!(last_seen(&cookie).is_none());
+
+        headers.insert(
+            "anthropic-ratelimit-unified-status",
+            HeaderValue::from_static("allowed"),
+        );
+        record(&cookie, &headers);
+        let snapshot = last_seen(&cookie).unwrap();
+        assert_eq!(snapshot.headers.len(), 1);
+        assert_eq!(
+            snapshot.headers["anthropic-ratelimit-unified-status"],
+            "allowed"
+        );
+    }
+}
@@ -0,0 +1,485 @@
+//! Redaction of transcripts for sharing in bug reports
+//!
+//! Content is redacted in passes: bulky or private bodies are replaced first,
+//! then every sensitive span is collected from what is left and each of its
+//! occurrences is replaced. A final pass checks that none of the spans or
+//! configured secrets survived, a transcript failing it is never returned.
+
+use std::{collections::BTreeMap, sync::LazyLock};
+
+use regex::{NoExpand, Regex};
+use serde::Serialize;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+
+use crate::{config::RedactionRules, error::ClewdrError, services::transcript::Transcript};
+
+/// Version of the redaction logic, bumped whenever detection or replacement changes
+pub const RULESET_VERSION: u32 = 1;
+
+static EMAIL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}").unwrap()
+});
+/// Capitalized names following an introduction, greeting or sign-off
+static NAME: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"\b(?i:my name is|i am|i'm|call me|this is|regards|thanks|hi|hello|dear|hey),?\s+([A-Z][a-z]+(?: [A-Z][a-z]+)?)",
+    )
+    .unwrap()
+});
+
+/// Keys whose values describe structure, they are never scanned for names
+const STRUCTURAL: [&str; 8] = [
+    "type",
+    "role",
+    "model",
+    "id",
+    "tool_use_id",
+    "media_type",
+    "stop_reason",
+    "name",
+];
+
+/// Marks a transcript as redacted
+#[derive(Debug, Clone, Serialize)]
+pub struct Watermark {
+    pub redacted: bool,
+    pub ruleset_version: u32,
+    /// Truncated sha256 of the rules, secrets and known names only counted
+    pub rules_digest: String,
+    pub replacements: usize,
+    /// Unix timestamp in seconds
+    pub redacted_at: i64,
+}
+
+/// Shareable variant of a transcript
+#[derive(Debug, Clone, Serialize)]
+pub struct RedactedTranscript {
+    pub redaction: Watermark,
+    /// Settings affecting the output format when the transcript is redacted
+    pub settings: Value,
+    pub transcript: Transcript,
+}
+
+/// Replaces every letter and digit, keeping length, whitespace and markup
+fn placeholder(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '@' {
+                'x'
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+fn digest(kind: &str, bytes: &[u8]) -> String {
+    let hash = Sha256::digest(bytes);
+    format!(
+        "[{kind} sha256:{}, {} bytes]",
+        hex::encode(&hash[..8]),
+        bytes.len()
+    )
+}
+
+fn rules_digest(rules: &RedactionRules) -> String {
+    let shape = json!({
+        "emails": rules.emails,
+        "names": rules.names,
+        "known_names": rules.known_names.len(),
+        "secrets": rules.secrets.len(),
+        "placeholder_user_messages": rules.placeholder_user_messages,
+        "digest_tool_results": rules.digest_tool_results,
+    });
+    hex::encode(&Sha256::digest(shape.to_string())[..8])
+}
+
+/// Every string in a value, object keys included
+fn strings<'a>(value: &'a Value, out: &mut Vec<&'a str>) {
+    match value {
+        Value::String(s) => out.push(s),
+        Value::Array(a) => a.iter().for_each(|v| strings(v, out)),
+        Value::Object(o) => o.iter().for_each(|(k, v)| {
+            out.push(k);
+            strings(v, out);
+        }),
+        _ => {}
+    }
+}
+
+struct Redactor<'a> {
+    rules: &'a RedactionRules,
+    /// Detected span and its label
+    spans: BTreeMap<String, &'static str>,
+    patterns: Vec<(Regex, &'static str)>,
+    replacements: usize,
+}
+
+impl<'a> Redactor<'a> {
+    fn new(rules: &'a RedactionRules) -> Self {
+        Self {
+            rules,
+            spans: BTreeMap::new(),
+            patterns: Vec::new(),
+            replacements: 0,
+        }
+    }
+
+    /// Replaces user message bodies and tool results as configured
+    fn strip(&mut self, request: &mut Value) {
+        let Some(messages) = request.get_mut("messages").and_then(Value::as_array_mut) else {
+            return;
+        };
+        for message in messages {
+            let user = message["role"] == "user";
+            match message.get_mut("content") {
+                Some(Value::String(text)) if user && self.rules.placeholder_user_messages => {
+                    *text = placeholder(text);
+                    self.replacements += 1;
+                }
+                Some(Value::Array(blocks)) => {
+                    for block in blocks {
+                        self.strip_block(block, user);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn strip_block(&mut self, block: &mut Value, user: bool) {
+        match block["type"].as_str() {
+            Some("tool_result") if self.rules.digest_tool_results => {
+                if let Some(content) = block.get_mut("content") {
+                    *content = json!(digest("tool result", content.to_string().as_bytes()));
+                    self.replacements += 1;
+                }
+            }
+            Some("text") if user && self.rules.placeholder_user_messages => {
+                if let Some(Value::String(text)) = block.get_mut("text") {
+                    *text = placeholder(text);
+                    self.replacements += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn detect(&mut self, text: &str) {
+        if self.rules.emails {
+            for m in EMAIL.find_iter(text) {
+                self.spans.insert(m.as_str().to_string(), "[email]");
+            }
+        }
+        if self.rules.names {
+            for c in NAME.captures_iter(text) {
+                // "Jane Doe" is often just "Jane" later on
+                for part in c[1].split(' ') {
+                    self.spans.insert(part.to_string(), "[name]");
+                }
+                self.spans.insert(c[1].to_string(), "[name]");
+            }
+        }
+    }
+
+    /// Collects the sensitive spans of every content string
+    fn collect(&mut self, value: &Value, key: Option<&str>) {
+        match value {
+            Value::String(s) if key.is_none_or(|k| k != "data" && !STRUCTURAL.contains(&k)) => {
+                self.detect(s)
+            }
+            Value::Array(a) => a.iter().for_each(|v| self.collect(v, key)),
+            Value::Object(o) => o.iter().for_each(|(k, v)| self.collect(v, Some(k))),
+            _ => {}
+        }
+    }
+
+    /// Builds the replacement patterns, longest span first
+    fn compile(&mut self) -> Result<(), ClewdrError> {
+        let mut spans = self.spans.clone();
+        for name in self.rules.known_names.iter().map(|n| n.trim()) {
+            spans.insert(name.to_string(), "[name]");
+        }
+        for secret in self.rules.secrets.iter().map(|s| s.trim()) {
+            spans.insert(secret.to_string(), "[secret]");
+        }
+        spans.remove("");
+        let mut spans = spans.into_iter().collect::<Vec<_>>();
+        spans.sort_by_key(|(span, _)| std::cmp::Reverse(span.len()));
+        for (span, label) in spans {
+            let escaped = regex::escape(&span);
+            // names never match inside a longer word
+            let word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+            let pattern = match label {
+                "[name]" if word(span.chars().next()) && word(span.chars().last()) => {
+                    format!(r"\b{escaped}\b")
+                }
+                _ => escaped,
+            };
+            let re = Regex::new(&pattern).map_err(|e| ClewdrError::Whatever {
+                message: "Failed to build redaction pattern".into(),
+                source: Some(Box::new(e)),
+            })?;
+            self.patterns.push((re, label));
+        }
+        Ok(())
+    }
+
+    fn replace_text(&mut self, text: &mut String) {
+        for (re, label) in self.patterns.iter() {
+            let count = re.find_iter(text).count();
+            if count > 0 {
+                *text = re.replace_all(text, NoExpand(label)).into_owned();
+                self.replacements += count;
+            }
+        }
+    }
+
+    /// Replaces the collected spans everywhere but in enumerations
+    fn replace(&mut self, value: &mut Value, key: Option<&str>) {
+        match value {
+            Value::String(_) if matches!(key, Some("type" | "role")) => {}
+            // base64 payloads like images cannot be scanned
+            Value::String(s) if key == Some("data") => {
+                *s = digest("data", s.as_bytes());
+                self.replacements += 1;
+            }
+            Value::String(s) => self.replace_text(s),
+            Value::Array(a) => a.iter_mut().for_each(|v| self.replace(v, key)),
+            Value::Object(o) => o.iter_mut().for_each(|(k, v)| self.replace(v, Some(k))),
+            _ => {}
+        }
+    }
+
+    /// Fails if any span or secret survived, or detection finds something new
+    fn verify(&self, values: &[&Value]) -> Result<(), ClewdrError> {
+        let fail = |what: &str| ClewdrError::Whatever {
+            message: format!("Redaction verification failed: {what} survived"),
+            source: None,
+        };
+        for value in values {
+            let mut all = Vec::new();
+            strings(value, &mut all);
+            if let Some((_, label)) = self
+                .patterns
+                .iter()
+                .find(|(re, _)| all.iter().any(|s| re.is_match(s)))
+            {
+                return Err(fail(label));
+            }
+            let mut again = Redactor::new(self.rules);
+            again.collect(value, None);
+            if let Some(label) = again.spans.values().next() {
+                return Err(fail(label));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Produces a shareable variant of a transcript
+///
+/// # Arguments
+/// * `transcript` - Transcript to redact
+/// * `rules` - Redaction rules
+/// * `settings` - Settings snapshot kept alongside the transcript
+///
+/// # Returns
+/// * `Result<RedactedTranscript, ClewdrError>` - The redacted transcript, or an error if verification failed
+pub fn redact_transcript(
+    transcript: &Transcript,
+    rules: &RedactionRules,
+    settings: Value,
+) -> Result<RedactedTranscript, ClewdrError> {
+    let mut redactor = Redactor::new(rules);
+    let mut t = transcript.to_owned();
+    t.cookie_hash = None;
+    redactor.strip(&mut t.request);
+    // non-stream responses keep their JSON structure
+    let mut response = if t.stream {
+        Value::String(std::mem::take(&mut t.response))
+    } else {
+        serde_json::from_str::<Value>(&t.response)
+            .unwrap_or_else(|_| Value::String(std::mem::take(&mut t.response)))
+    };
+    let mut error = t.error.take().map(Value::String);
+
+    redactor.collect(&t.request, None);
+    redactor.collect(&response, None);
+    if let Some(error) = &error {
+        redactor.collect(error, None);
+    }
+    redactor.compile()?;
+    redactor.replace(&mut t.request, None);
+    redactor.replace(&mut response, None);
+    if let Some(error) = error.as_mut() {
+        redactor.replace(error, None);
+    }
+    let error = error.unwrap_or_default();
+    redactor.verify(&[&t.request, &response, &error])?;
+
+    t.response = match response {
+        Value::String(text) => text,
+        body => body.to_string(),
+    };
+    t.error = error.as_str().map(str::to_string);
+    let redacted = RedactedTranscript {
+        redaction: Watermark {
+            redacted: true,
+            ruleset_version: RULESET_VERSION,
+            rules_digest: rules_digest(rules),
+            replacements: redactor.replacements,
+            redacted_at: chrono::Utc::now().timestamp(),
+        },
+        settings,
+        transcript: t,
+    };
+    redactor.verify(&[&serde_json::to_value(&redacted)?])?;
+    Ok(redacted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "sk-ant-REDACTED";
+
+    fn fixture(stream: bool) -> Transcript {
+        let request = json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 1024,
+            "temperature": 0.7,
+            "system": format!("Deployment key {SECRET}, do not share."),
+            "messages": [
+                {
+                    "role": "user",
+                    "content": "Hi, my name is Jane Doe.\n\nWrite to jane.doe@example.com about the *bug*."
+                },
+                {
+                    "role": "assistant",
+                    "content": [
+                        { "type": "text", "text": "Sure Jane, checking the tool." },
+                        { "type": "tool_use", "id": "toolu_1", "name": "lookup", "input": { "q": "Jane" } }
+                    ]
+                },
+                {
+                    "role": "user",
+                    "content": [
+                        { "type": "tool_result", "tool_use_id": "toolu_1", "content": "Jane Doe, 42 Main St, jane@corp.io" },
+                        { "type": "text", "text": "Thanks, Bob here." }
+                    ]
+                }
+            ]
+        });
+        let response = if stream {
+            "Dear Jane Doe, I emailed jane.doe@example.com.\n\nDone.".to_string()
+        } else {
+            json!({
+                "id": "msg_1",
+                "type": "message",
+                "role": "assistant",
+                "model": "claude-sonnet-4-5",
+                "content": [{ "type": "text", "text": "Dear Jane Doe, I emailed jane.doe@example.com." }],
+                "stop_reason": "end_turn",
+                "usage": { "input_tokens": 120, "output_tokens": 12 }
+            })
+            .to_string()
+        };
+        Transcript {
+            id: "1700000000000-0001".into(),
+            timestamp: 1_700_000_000,
+            endpoint: "claude_code".into(),
+            model: "claude-sonnet-4-5".into(),
+            cookie_hash: Some("0011223344556677".into()),
+            stream,
+            status: Some(200),
+            error: None,
+            latency_ms: 1234,
+            request,
+            response,
+        }
+    }
+
+    fn rules() -> RedactionRules {
+        RedactionRules {
+            secrets: vec![SECRET.into()],
+            ..Default::default()
+        }
+    }
+
+    fn assert_clean(redacted: &RedactedTranscript) {
+        let out = serde_json::to_string(redacted).unwrap();
+        for planted in [SECRET, "Jane", "Bob", "example.com", "corp.io", "Main St"] {
+            assert!(!out.contains(planted), "{planted} survived in {out}");
+        }
+        assert!(redacted.redaction.redacted);
+        assert_eq!(redacted.redaction.ruleset_version, RULESET_VERSION);
+        assert!(redacted.transcript.cookie_hash.is_none());
+    }
+
+    #[test]
+    fn removes_planted_pii_and_keeps_structure() {
+        for stream in [false, true] {
+            let redacted = redact_transcript(&fixture(stream), &rules(), json!({})).unwrap();
+            assert_clean(&redacted);
+            let t = &redacted.transcript;
+            assert_eq!(
+                (t.model.as_str(), t.latency_ms, t.stream),
+                ("claude-sonnet-4-5", 1234, stream)
+            );
+            assert_eq!(t.request["max_tokens"], 1024);
+            assert_eq!(t.request["temperature"], 0.7);
+            let messages = t.request["messages"].as_array().unwrap();
+            assert_eq!(messages.len(), 3);
+            assert_eq!(messages[1]["content"][1]["type"], "tool_use");
+            assert_eq!(messages[1]["content"][1]["id"], "toolu_1");
+            let tool_result = messages[2]["content"][0]["content"].as_str().unwrap();
+            assert!(tool_result.starts_with("[tool result sha256:"));
+            assert_eq!(
+                messages[0]["content"],
+                "Hi, my name is [name].\n\nWrite to [email] about the *bug*."
+            );
+            if !stream {
+                let body = serde_json::from_str::<Value>(&t.response).unwrap();
+                assert_eq!(body["usage"]["output_tokens"], 12);
+                assert_eq!(body["stop_reason"], "end_turn");
+            }
+        }
+    }
+
+    #[test]
+    fn placeholders_keep_length_and_paragraphs() {
+        let rules = RedactionRules {
+            placeholder_user_messages: true,
+            ..rules()
+        };
+        let original = fixture(true);
+        let redacted = redact_transcript(&original, &rules, json!({})).unwrap();
+        assert_clean(&redacted);
+        let before = original.request["messages"][0]["content"].as_str().unwrap();
+        let after = redacted.transcript.request["messages"][0]["content"]
+            .as_str()
+            .unwrap();
+        assert_eq!(before.chars().count(), after.chars().count());
+        assert_eq!(before.split("\n\n").count(), after.split("\n\n").count());
+        assert!(after.ends_with("*xxx*."));
+        let text = &redacted.transcript.request["messages"][2]["content"][1]["text"];
+        assert_eq!(text, "xxxxxx, xxx xxxx.");
+    }
+
+    #[test]
+    fn verification_rejects_surviving_secrets() {
+        let rules = rules();
+        let mut redactor = Redactor::new(&rules);
+        redactor.compile().unwrap();
+        let leaked = json!({ "type": format!("oops {SECRET}") });
+        assert!(redactor.verify(&[&leaked]).is_err());
+        assert!(redactor.verify(&[&json!({ "email": "a@b.co" })]).is_err());
+        assert!(
+            redactor
+                .verify(&[&json!({ "text": "nothing here" })])
+                .is_ok()
+        );
+    }
+}
@@ -0,0 +1,273 @@
+//! Process resource usage with per-subsystem attribution
+//!
+//! Caches, buffers and stores implement [`ResourceReporter`] and register
+//! themselves when they are constructed. A background task samples the
+//! process and every reporter each minute and keeps an hour of history.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, LazyLock, Mutex, PoisonError, RwLock},
+    time::Duration,
+};
+
+use serde::Serialize;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(60);
+const HISTORY_SECS: i64 = 60 * 60;
+
+/// Global registry sampled by [`init_resource_sampling`]
+pub static RESOURCES: LazyLock<ResourceRegistry> = LazyLock::new(ResourceRegistry::default);
+
+/// Current usage of one subsystem
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ResourceUsage {
+    /// Accounted bytes, `None` when the subsystem cannot tell
+    pub bytes: Option<u64>,
+    pub entries: u64,
+}
+
+/// Reports the memory a subsystem holds
+///
+/// Implementations should be cheap, they run on every sample and every
+/// `GET /api/resources`.
+pub trait ResourceReporter: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn usage(&self) -> ResourceUsage;
+}
+
+/// Reporter backed by a closure, for statics that cannot implement the trait
+pub struct FnReporter<F> {
+    name: &'static str,
+    usage: F,
+}
+
+impl<F> FnReporter<F>
+where
+    F: Fn() -> ResourceUsage + Send + Sync + 'static,
+{
+    pub fn new(name: &'static str, usage: F) -> Self {
+        Self { name, usage }
+    }
+}
+
+impl<F> ResourceReporter for FnReporter<F>
+where
+    F: Fn() -> ResourceUsage + Send + Sync,
+{
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn usage(&self) -> ResourceUsage {
+        (self.usage)()
+    }
+}
+
+/// Usage of one subsystem at sampling time
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct SubsystemUsage {
+    pub name: &'static str,
+    #[serde(flatten)]
+    pub usage: ResourceUsage,
+}
+
+/// Process and subsystem usage at one point in time
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct ResourceSample {
+    /// Unix timestamp in seconds
+    pub timestamp: i64,
+    /// Resident set size, `None` where the platform does not expose it
+    pub rss_bytes: Option<u64>,
+    pub open_fds: Option<u64>,
+    pub tokio_tasks: Option<usize>,
+    /// Sum of the bytes reported by subsystems
+    pub accounted_bytes: u64,
+    pub subsystems: Vec<SubsystemUsage>,
+}
+
+/// Registered reporters and sample history
+#[derive(Default)]
+pub struct ResourceRegistry {
+    reporters: RwLock<Vec<Arc<dyn ResourceReporter>>>,
+    history: Mutex<VecDeque<ResourceSample>>,
+}
+
+impl ResourceRegistry {
+    pub fn register(&self, reporter: impl ResourceReporter + 'static) {
+        self.reporters
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Arc::new(reporter));
+    }
+
+    /// Collects the usage of every reporter, sorted by name
+    pub fn subsystems(&self) -> Vec<SubsystemUsage> {
+        let mut subsystems = self
+            .reporters
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|r| SubsystemUsage {
+                name: r.name(),
+                usage: r.usage(),
+            })
+            .collect::<Vec<_>>();
+        subsystems.sort_by_key(|s| s.name);
+        subsystems
+    }
+
+    /// Takes a sample of the process and every subsystem
+    pub fn sample(&self, timestamp: i64) -> ResourceSample {
+        let subsystems = self.subsystems();
+        ResourceSample {
+            timestamp,
+            rss_bytes: process_rss(),
+            open_fds: open_fds(),
+            tokio_tasks: tokio::runtime::Handle::try_current()
+                .ok()
+                .map(|h| h.metrics().num_alive_tasks()),
+            accounted_bytes: subsystems.iter().filter_map(|s| s.usage.bytes).sum(),
+            subsystems,
+        }
+    }
+
+    /// Appends a sample and drops samples older than an hour
+    pub fn record(&self, sample: ResourceSample) {
+        let mut history = self.history.lock().unwrap_or_else(PoisonError::into_inner);
+        let oldest = sample.timestamp - HISTORY_SECS;
+        history.push_back(sample);
+        while history.front().is_some_and(|s| s.timestamp <= oldest) {
+            history.pop_front();
+        }
+    }
+
+    /// Samples of the last hour, oldest first
+    pub fn history(&self) -> Vec<ResourceSample> {
+        self.history
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .cloned()
+            .collect()
+    }
+}
+
+/// Registers a reporter in the global registry
+pub fn register_reporter(reporter: impl ResourceReporter + 'static) {
+    RESOURCES.register(reporter);
+}
+
+/// Starts sampling the global registry every minute
+pub fn init_resource_sampling() {
+    tokio::spawn(async {
+        let mut interval = tokio::time::interval(SAMPLE_INTERVAL);
+        loop {
+            interval.tick().await;
+            RESOURCES.record(RESOURCES.sample(chrono::Utc::now().timestamp()));
+        }
+    });
+}
+
+#[cfg(target_os = "linux")]
+fn process_rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let kb = status
+        .lines()
+        .find_map(|l| l.strip_prefix("VmRSS:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kb * 1024)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn process_rss() -> Option<u64> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn open_fds() -> Option<u64> {
+    Some(std::fs::read_dir("/proc/self/fd").ok()?.count() as u64)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_fds() -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use super::*;
+
+    struct FakeBuffer {
+        bytes: Arc<AtomicU64>,
+    }
+
+    impl ResourceReporter for FakeBuffer {
+        fn name(&self) -> &'static str {
+            "fake_buffer"
+        }
+
+        fn usage(&self) -> ResourceUsage {
+            let bytes = self.bytes.load(Ordering::Relaxed);
+            ResourceUsage {
+                bytes: Some(bytes),
+                entries: bytes.div_ceil(1024),
+            }
+        }
+    }
+
+    #[test]
+    fn breakdown_sums_reporters() {
+        let registry = ResourceRegistry::default();
+        let bytes = Arc::new(AtomicU64::new(0));
+        registry.register(FakeBuffer {
+            bytes: bytes.clone(),
+        });
+        registry.register(FnReporter::new("fake_cache", || ResourceUsage {
+            bytes: Some(4096),
+            entries: 2,
+        }));
+        registry.register(FnReporter::new("fake_unknown", || ResourceUsage {
+            bytes: None,
+            entries: 7,
+        }));
+
+        let sample = registry.sample(0);
+        assert_eq!(sample.accounted_bytes, 4096);
+        let names = sample.subsystems.iter().map(|s| s.name).collect::<Vec<_>>();
+        assert_eq!(names, ["fake_buffer", "fake_cache", "fake_unknown"]);
+
+        // drive allocations in the fake buffer
+        bytes.fetch_add(10 * 1024, Ordering::Relaxed);
+        let sample = registry.sample(60);
+        assert_eq!(sample.accounted_bytes, 4096 + 10 * 1024);
+        assert_eq!(sample.subsystems[0].usage.entries, 10);
+        bytes.fetch_sub(10 * 1024, Ordering::Relaxed);
+        assert_eq!(registry.sample(120).accounted_bytes, 4096);
+    }
+
+    #[test]
+    fn history_keeps_one_hour() {
+        let registry = ResourceRegistry::default();
+        for minute in 0..90 {
+            registry.record(registry.sample(minute * 60));
+        }
+        let history = registry.history();
+        assert_eq!(history.len(), 60);
+        assert_eq!(history.first().unwrap().timestamp, 30 * 60);
+        assert_eq!(history.last().unwrap().timestamp, 89 * 60);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn process_usage_on_linux() {
+        let sample = ResourceRegistry::default().sample(0);
+        assert!(sample.rss_bytes.is_some_and(|b| b > 0));
+        assert!(sample.open_fds.is_some_and(|n| n > 0));
+    }
+}
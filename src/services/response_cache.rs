@@ -0,0 +1,299 @@
+//! Cache of non-streaming responses to identical requests
+//!
+//! Off by default, see [`ResponseCacheConfig`]. Entries are keyed by the
+//! endpoint, the client and a sha256 of the request as sent upstream, without
+//! `stream` and `metadata`, so one client is never answered with what another
+//! was sent. Only 200 responses are stored, as the handler produced them, so
+//! the response layers still run on every hit. Bodies too large to cache are
+//! passed through as they come.
+
+use std::{
+    sync::{
+        LazyLock, Mutex, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use axum::{
+    body::{Body, Bytes},
+    response::Response,
+};
+use futures::StreamExt;
+use http::{HeaderValue, StatusCode, header::CONTENT_TYPE};
+use moka::sync::Cache;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use strum::IntoStaticStr;
+
+use crate::{
+    config::{CLEWDR_CONFIG, ClientAuth, KeyEndpoint, ResponseCacheConfig},
+    error::ClewdrError,
+    services::resources::{FnReporter, ResourceUsage, register_reporter},
+    types::claude::CreateMessageParams,
+};
+
+/// Largest response body that is cached
+const MAX_BODY_BYTES: usize = 8 * 1024 * 1024;
+
+/// Whether a response came from the cache, sent as `x-clewdr-cache`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum CacheStatus {
+    Hit,
+    Miss,
+}
+
+#[derive(Clone)]
+struct CachedResponse {
+    content_type: Option<HeaderValue>,
+    body: Bytes,
+}
+
+/// The cache with the settings it was built with, rebuilt when they change
+struct Built {
+    ttl_secs: u64,
+    max_entries: u64,
+    cache: Cache<String, CachedResponse>,
+}
+
+static CACHE: LazyLock<Mutex<Option<Built>>> = LazyLock::new(|| {
+    register_reporter(FnReporter::new("response_cache", || {
+        let cache = current();
+        ResourceUsage {
+            bytes: Some(cache.iter().map(|(_, r)| r.body.len() as u64).sum()),
+            entries: cache.entry_count(),
+        }
+    }));
+    Mutex::new(None)
+});
+
+static HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+
+/// Counters of the response cache since startup
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+pub struct ResponseCacheStats {
+    pub enabled: bool,
+    pub entries: u64,
+    pub hits: u64,
+    pub misses: u64,
+    pub hit_rate: Option<f64>,
+}
+
+/// Returns the cache matching the current config
+fn current() -> Cache<String, CachedResponse> {
+    let config = CLEWDR_CONFIG.load();
+    let ResponseCacheConfig {
+        ttl_secs,
+        max_entries,
+        ..
+    } = config.response_cache;
+    let mut built = CACHE.lock().unwrap_or_else(PoisonError::into_inner);
+    match built.as_ref() {
+        Some(b) if b.ttl_secs == ttl_secs && b.max_entries == max_entries => b.cache.to_owned(),
+        _ => {
+            let cache = Cache::builder()
+                .max_capacity(max_entries)
+                .time_to_live(Duration::from_secs(ttl_secs.max(1)))
+                .build();
+            *built = Some(Built {
+                ttl_secs,
+                max_entries,
+                cache: cache.to_owned(),
+            });
+            cache
+        }
+    }
+}
+
+/// Cache key of a request, `None` when its response may not be cached
+///
+/// # Arguments
+/// * `endpoint` - Proxy endpoint serving the request
+/// * `client` - Who the request authenticated as
+/// * `params` - Request as sent upstream
+pub fn key(
+    endpoint: KeyEndpoint,
+    client: Option<&ClientAuth>,
+    params: &CreateMessageParams,
+) -> Option<String> {
+    if !CLEWDR_CONFIG.load().response_cache.caches(params) {
+        return None;
+    }
+    digest(endpoint, client, params)
+}
+
+/// Hash of the client and the request fields that decide the answer
+fn digest(
+    endpoint: KeyEndpoint,
+    client: Option<&ClientAuth>,
+    params: &CreateMessageParams,
+) -> Option<String> {
+    let mut value = serde_json::to_value(params).ok()?;
+    let object = value.as_object_mut()?;
+    object.remove("stream");
+    object.remove("metadata");
+    let client = client.map(ClientAuth::identity).unwrap_or_default();
+    let digest = Sha256::digest(format!("{endpoint}:{client}:{value}"));
+    Some(hex::encode(digest))
+}
+
+/// Returns the cached response of a key, counting the hit or miss
+pub fn get(key: &str) -> Option<Response> {
+    let Some(cached) = current().get(key) else {
+        MISSES.fetch_add(1, Ordering::Relaxed);
+        return None;
+    };
+    HITS.fetch_add(1, Ordering::Relaxed);
+    let mut response = Response::new(Body::from(cached.body));
+    if let Some(content_type) = cached.content_type {
+        response.headers_mut().insert(CONTENT_TYPE, content_type);
+    }
+    Some(response)
+}
+
+/// Stores a successful response under a key and returns it for sending
+///
+/// # Arguments
+/// * `key` - Key from [`key`]
+/// * `response` - Response of the handler, other statuses and bodies past
+///   [`MAX_BODY_BYTES`] are passed through
+///
+/// # Returns
+/// * `Result<Response, ClewdrError>` - The response, its body buffered when stored
+pub async fn store(key: String, response: Response) -> Result<Response, ClewdrError> {
+    if response.status() != StatusCode::OK {
+        return Ok(response);
+    }
+    let (parts, body) = response.into_parts();
+    let mut stream = body.into_data_stream();
+    let mut chunks = vec![];
+    let mut len = 0;
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| ClewdrError::Whatever {
+            message: "Failed to buffer response for the cache".into(),
+            source: Some(Box::new(e)),
+        })?;
+        len += chunk.len();
+        chunks.push(chunk);
+        if len > MAX_BODY_BYTES {
+            // too large to cache, the rest is sent on as it comes
+            let read = futures::stream::iter(chunks.into_iter().map(Ok));
+            return Ok(Response::from_parts(
+                parts,
+                Body::from_stream(read.chain(stream)),
+            ));
+        }
+    }
+    let body = Bytes::from(chunks.concat());
+    current().insert(
+        key,
+        CachedResponse {
+            content_type: parts.headers.get(CONTENT_TYPE).cloned(),
+            body: body.to_owned(),
+        },
+    );
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+/// Drops every cached response
+///
+/// # Returns
+/// * `u64` - Entries dropped
+pub fn clear() -> u64 {
+    let cache = current();
+    cache.run_pending_tasks();
+    let entries = cache.entry_count();
+    cache.invalidate_all();
+    entries
+}
+
+pub fn stats() -> ResponseCacheStats {
+    let cache = current();
+    cache.run_pending_tasks();
+    let hits = HITS.load(Ordering::Relaxed);
+    let misses = MISSES.load(Ordering::Relaxed);
+    ResponseCacheStats {
+        enabled: CLEWDR_CONFIG.load().response_cache.enabled,
+        entries: cache.entry_count(),
+        hits,
+        misses,
+        hit_rate: (hits + misses > 0).then(|| hits as f64 / (hits + misses) as f64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body;
+
+    use super::*;
+    use crate::types::claude::{Message, Role};
+
+    fn params(stream: bool) -> CreateMessageParams {
+        CreateMessageParams {
+            model: "claude-sonnet-4-5".into(),
+            messages: vec![Message::new_text(Role::User, "hi")],
+            temperature: Some(0.0),
+            stream: Some(stream),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn key_ignores_streaming_and_metadata() {
+        let client = Some(&ClientAuth::Password);
+        let web = digest(KeyEndpoint::ClaudeWeb, client, &params(false));
+        assert_eq!(web, digest(KeyEndpoint::ClaudeWeb, client, &params(true)));
+        assert_ne!(web, digest(KeyEndpoint::ClaudeCode, client, &params(false)));
+        let mut warmer = params(false);
+        warmer.temperature = Some(0.5);
+        assert_ne!(web, digest(KeyEndpoint::ClaudeWeb, client, &warmer));
+        // clients never share entries
+        let friend = ClientAuth::Key("friend".into());
+        assert_ne!(
+            web,
+            digest(KeyEndpoint::ClaudeWeb, Some(&friend), &params(false))
+        );
+        assert_ne!(
+            web,
+            digest(
+                KeyEndpoint::ClaudeWeb,
+                Some(&ClientAuth::Admin),
+                &params(false)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn stores_only_successful_responses() {
+        let key = format!("test-{}", uuid::Uuid::new_v4());
+        let mut failed = Response::new(Body::from("overloaded"));
+        *failed.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+        store(key.to_owned(), failed).await.unwrap();
+        assert!(get(&key).is_none());
+
+        let mut ok = Response::new(Body::from(r#"{"id":"msg_1"}"#));
+        ok.headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        let sent = store(key.to_owned(), ok).await.unwrap();
+        let sent = body::to_bytes(sent.into_body(), usize::MAX).await.unwrap();
+        let hit = get(&key).unwrap();
+        assert_eq!(hit.headers()[CONTENT_TYPE], "application/json");
+        let hit = body::to_bytes(hit.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(hit, sent);
+    }
+
+    #[tokio::test]
+    async fn large_responses_pass_through() {
+        let key = format!("test-{}", uuid::Uuid::new_v4());
+        let large = vec![b'x'; MAX_BODY_BYTES + 1];
+        let sent = store(key.to_owned(), Response::new(Body::from(large.to_owned())))
+            .await
+            .unwrap();
+        assert_eq!(sent.status(), StatusCode::OK);
+        let sent = body::to_bytes(sent.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(sent, large);
+        assert!(get(&key).is_none());
+    }
+}
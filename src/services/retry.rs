@@ -0,0 +1,333 @@
+//! Detection of client retries
+//!
+//! A request retries an earlier one when the same client sent the same body
+//! within `retry_window_secs`. Clients can also send an `idempotency-key` bound to
+//! the body or announce the attempt with `x-retry-attempt`, but neither counts
+//! unless the body was actually seen before, so a header alone never buys
+//! priority. Retries wait ahead of fresh requests in the cookie queue, one
+//! level per earlier attempt up to `max_retry_boost`.
+
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::{
+        LazyLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use http::HeaderMap;
+use moka::sync::Cache;
+use serde::Serialize;
+
+use crate::{
+    config::{CLEWDR_CONFIG, ClientAuth},
+    types::claude::CreateMessageParams,
+};
+
+pub const RETRY_ATTEMPT_HEADER: &str = "x-retry-attempt";
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Longest window kept in memory, whatever `retry_window_secs` says
+const MAX_WINDOW: Duration = Duration::from_secs(3600);
+
+static TRACKER: LazyLock<RetryTracker> = LazyLock::new(RetryTracker::new);
+
+/// How a retry was recognized
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetrySource {
+    /// Not a retry
+    #[default]
+    Fresh,
+    /// The body was seen before
+    Body,
+    /// The idempotency key was seen before with the same body
+    IdempotencyKey,
+    /// The client announced the retry and the body was seen before
+    Header,
+}
+
+/// Retry state of one request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RetryInfo {
+    /// Earlier attempts seen within the window
+    pub attempt: u32,
+    pub source: RetrySource,
+    /// Queue priority granted, at most `max_retry_boost`
+    pub boost: u32,
+}
+
+/// Retry headers of a request, read before its body is parsed
+#[derive(Debug, Clone, Default)]
+pub struct RetryClaim {
+    /// Identity of the client, so no client is boosted by the requests of another
+    client: String,
+    key: Option<String>,
+    attempt: Option<u32>,
+}
+
+impl RetryClaim {
+    /// Reads the retry headers of a request
+    ///
+    /// # Arguments
+    /// * `headers` - Request headers
+    /// * `client` - Who the request authenticated as
+    pub fn from_headers(headers: &HeaderMap, client: Option<&ClientAuth>) -> Self {
+        let get = |name| headers.get(name).and_then(|v| v.to_str().ok());
+        let client = client.map(ClientAuth::identity).unwrap_or_default();
+        Self {
+            key: get(IDEMPOTENCY_KEY_HEADER).map(|k| format!("{client}:{k}")),
+            attempt: get(RETRY_ATTEMPT_HEADER).and_then(|v| v.trim().parse().ok()),
+            client,
+        }
+    }
+
+    /// Records the request and tells whether it retries an earlier one
+    pub fn observe(&self, body: &CreateMessageParams) -> RetryInfo {
+        let (window, max_boost) = {
+            let config = CLEWDR_CONFIG.load();
+            (config.retry_window_secs, config.max_retry_boost)
+        };
+        let hash = body_hash(&self.client, body);
+        TRACKER.observe(hash, self, window, max_boost, now_ms())
+    }
+}
+
+fn now_ms() -> u64 {
+    chrono::Utc::now().timestamp_millis().max(0) as u64
+}
+
+fn body_hash(client: &str, body: &CreateMessageParams) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    client.hash(&mut hasher);
+    serde_json::to_vec(body)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    hasher.finish()
+}
+
+#[derive(Default)]
+struct Counters {
+    requests: AtomicU64,
+    retries: AtomicU64,
+    boosted: AtomicU64,
+    unverified_claims: AtomicU64,
+    boosted_succeeded: AtomicU64,
+    boosted_failed: AtomicU64,
+}
+
+/// How often clients retry and how boosted retries fare
+#[derive(Debug, Clone, Serialize)]
+pub struct RetryStats {
+    pub requests: u64,
+    pub retries: u64,
+    pub boosted: u64,
+    /// Retry headers sent with a body that was never seen
+    pub unverified_claims: u64,
+    pub boosted_succeeded: u64,
+    pub boosted_failed: u64,
+    /// Share of requests that were retries
+    pub retry_rate: Option<f64>,
+    /// Share of finished boosted retries that succeeded
+    pub boosted_success_rate: Option<f64>,
+}
+
+struct RetryTracker {
+    /// Attempts and last sighting in milliseconds of each body
+    bodies: Cache<u64, (u32, u64)>,
+    /// Body each idempotency key was last sent with
+    keys: Cache<String, u64>,
+    counters: Counters,
+}
+
+impl RetryTracker {
+    fn new() -> Self {
+        Self {
+            bodies: Cache::builder()
+                .max_capacity(100_000)
+                .time_to_live(MAX_WINDOW)
+                .build(),
+            keys: Cache::builder()
+                .max_capacity(100_000)
+                .time_to_live(MAX_WINDOW)
+                .build(),
+            counters: Counters::default(),
+        }
+    }
+
+    fn observe(
+        &self,
+        hash: u64,
+        claim: &RetryClaim,
+        window_secs: u64,
+        max_boost: u32,
+        now_ms: u64,
+    ) -> RetryInfo {
+        let seen = self
+            .bodies
+            .get(&hash)
+            .filter(|(_, last)| now_ms.saturating_sub(*last) <= window_secs * 1000)
+            .map(|(attempts, _)| attempts)
+            .unwrap_or_default();
+        self.bodies.insert(hash, (seen + 1, now_ms));
+        let key_matches = claim
+            .key
+            .as_ref()
+            .is_some_and(|k| self.keys.get(k) == Some(hash));
+        if let Some(key) = claim.key.to_owned() {
+            self.keys.insert(key, hash);
+        }
+
+        let counters = &self.counters;
+        counters.requests.fetch_add(1, Ordering::Relaxed);
+        if seen == 0 {
+            if claim.attempt.is_some_and(|n| n > 0) {
+                counters.unverified_claims.fetch_add(1, Ordering::Relaxed);
+            }
+            return RetryInfo::default();
+        }
+        let source = if key_matches {
+            RetrySource::IdempotencyKey
+        } else if claim.attempt.is_some() {
+            RetrySource::Header
+        } else {
+            RetrySource::Body
+        };
+        // only attempts seen here count, whatever the header claims
+        let boost = seen.min(max_boost);
+        counters.retries.fetch_add(1, Ordering::Relaxed);
+        if boost > 0 {
+            counters.boosted.fetch_add(1, Ordering::Relaxed);
+        }
+        RetryInfo {
+            attempt: seen,
+            source,
+            boost,
+        }
+    }
+
+    fn record_outcome(&self, retry: RetryInfo, succeeded: bool) {
+        if retry.boost == 0 {
+            return;
+        }
+        let counter = if succeeded {
+            &self.counters.boosted_succeeded
+        } else {
+            &self.counters.boosted_failed
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn stats(&self) -> RetryStats {
+        let c = &self.counters;
+        let load = |a: &AtomicU64| a.load(Ordering::Relaxed);
+        let ratio = |n: u64, d: u64| (d > 0).then(|| n as f64 / d as f64);
+        let (requests, retries) = (load(&c.requests), load(&c.retries));
+        let (succeeded, failed) = (load(&c.boosted_succeeded), load(&c.boosted_failed));
+        RetryStats {
+            requests,
+            retries,
+            boosted: load(&c.boosted),
+            unverified_claims: load(&c.unverified_claims),
+            boosted_succeeded: succeeded,
+            boosted_failed: failed,
+            retry_rate: ratio(retries, requests),
+            boosted_success_rate: ratio(succeeded, succeeded + failed),
+        }
+    }
+}
+
+/// Records whether a boosted retry succeeded
+pub fn record_outcome(retry: RetryInfo, succeeded: bool) {
+    TRACKER.record_outcome(retry, succeeded);
+}
+
+/// Returns the retry statistics since startup
+pub fn stats() -> RetryStats {
+    TRACKER.stats()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claim(key: Option<&str>, attempt: Option<u32>) -> RetryClaim {
+        RetryClaim {
+            client: "password".into(),
+            key: key.map(str::to_string),
+            attempt,
+        }
+    }
+
+    #[test]
+    fn bodies_are_told_apart_by_client() {
+        let body = CreateMessageParams {
+            model: "claude-sonnet-4-6".into(),
+            ..Default::default()
+        };
+        assert_eq!(body_hash("password", &body), body_hash("password", &body));
+        assert_ne!(body_hash("password", &body), body_hash("key:friend", &body));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(IDEMPOTENCY_KEY_HEADER, "req-1".parse().unwrap());
+        let friend = ClientAuth::Key("friend".into());
+        let mine = RetryClaim::from_headers(&headers, Some(&ClientAuth::Password));
+        let theirs = RetryClaim::from_headers(&headers, Some(&friend));
+        assert_ne!(mine.key, theirs.key);
+    }
+
+    #[test]
+    fn retries_are_boosted_up_to_the_cap() {
+        let tracker = RetryTracker::new();
+        let fresh = claim(None, None);
+        assert_eq!(tracker.observe(1, &fresh, 60, 2, 0), RetryInfo::default());
+        let boosts = (1..=4)
+            .map(|i| tracker.observe(1, &fresh, 60, 2, i * 1000).boost)
+            .collect::<Vec<_>>();
+        assert_eq!(boosts, [1, 2, 2, 2]);
+        // another body arriving in between stays fresh
+        assert_eq!(tracker.observe(2, &fresh, 60, 2, 5000).boost, 0);
+        // past the window the body counts as new
+        assert_eq!(tracker.observe(1, &fresh, 60, 2, 120_000).boost, 0);
+        let stats = tracker.stats();
+        assert_eq!((stats.requests, stats.retries, stats.boosted), (7, 4, 4));
+    }
+
+    #[test]
+    fn retry_header_needs_a_seen_body() {
+        let tracker = RetryTracker::new();
+        let faked = claim(None, Some(9));
+        assert_eq!(tracker.observe(7, &faked, 60, 3, 0).boost, 0);
+        assert_eq!(tracker.stats().unverified_claims, 1);
+        // the header never raises the boost above the attempts seen
+        let retry = tracker.observe(7, &faked, 60, 3, 1000);
+        assert_eq!((retry.source, retry.boost), (RetrySource::Header, 1));
+    }
+
+    #[test]
+    fn idempotency_key_binds_to_the_body() {
+        let tracker = RetryTracker::new();
+        let keyed = claim(Some("req-1"), None);
+        tracker.observe(3, &keyed, 60, 3, 0);
+        assert_eq!(
+            tracker.observe(3, &keyed, 60, 3, 1000).source,
+            RetrySource::IdempotencyKey
+        );
+        // the same key with a different body is a new request
+        assert_eq!(tracker.observe(4, &keyed, 60, 3, 2000).boost, 0);
+
+        tracker.record_outcome(
+            RetryInfo {
+                attempt: 1,
+                source: RetrySource::Body,
+                boost: 1,
+            },
+            true,
+        );
+        tracker.record_outcome(RetryInfo::default(), false);
+        let stats = tracker.stats();
+        assert_eq!((stats.boosted_succeeded, stats.boosted_failed), (1, 0));
+        assert_eq!(stats.boosted_success_rate, Some(1.0));
+    }
+}
@@ -0,0 +1,214 @@
+//! Graceful shutdown
+//!
+//! On SIGINT or SIGTERM the instance starts draining: `/readyz` fails so load
+//! balancers move away, the server stops accepting connections and requests
+//! still arriving on open ones get 503. Requests already running, streams
+//! included, get `drain_timeout_secs` to finish, then background state is
+//! saved before the process exits.
+
+use std::{sync::LazyLock, time::Duration};
+
+use axum::{
+    extract::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+
+use crate::{config::CLEWDR_CONFIG, error::ClewdrError, services::cookie_actor::CookieActorHandle};
+
+/// Longest wait for background state to be saved after draining
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Draining state of the instance
+static DRAIN: LazyLock<Drain> = LazyLock::new(Drain::new);
+
+/// Whether a server is draining, and the waits that follow from it
+struct Drain {
+    draining: watch::Sender<bool>,
+}
+
+impl Drain {
+    fn new() -> Self {
+        Self {
+            draining: watch::channel(false).0,
+        }
+    }
+
+    fn is_draining(&self) -> bool {
+        *self.draining.borrow()
+    }
+
+    /// Resolves once `trigger` does, after marking the server as draining
+    async fn start_on(&self, trigger: impl Future<Output = ()>) {
+        trigger.await;
+        info!(
+            "Shutting down, waiting up to {}s for running requests",
+            CLEWDR_CONFIG.load().drain_timeout_secs
+        );
+        self.draining.send_replace(true);
+    }
+
+    /// Resolves once draining has run for `timeout`, read when draining starts
+    async fn deadline(&self, timeout: impl FnOnce() -> Duration) {
+        let mut draining = self.draining.subscribe();
+        if draining.wait_for(|d| *d).await.is_err() {
+            return std::future::pending().await;
+        }
+        tokio::time::sleep(timeout()).await;
+        warn!("Drain timeout reached, dropping the remaining requests");
+    }
+
+    /// Turns new requests away while draining, health probes keep answering
+    async fn gate(&self, req: Request, next: Next) -> Response {
+        if self.is_draining() && !matches!(req.uri().path(), "/healthz" | "/readyz") {
+            let retry_after_secs = CLEWDR_CONFIG.load().drain_timeout_secs.max(1);
+            return ClewdrError::ShuttingDown { retry_after_secs }.into_response();
+        }
+        next.run(req).await
+    }
+}
+
+/// Whether the instance is shutting down
+pub fn is_draining() -> bool {
+    DRAIN.is_draining()
+}
+
+/// Resolves on SIGINT or SIGTERM, after marking the instance as draining
+pub async fn signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl-C handler");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    DRAIN
+        .start_on(async {
+            tokio::select! {
+                _ = ctrl_c => {}
+                _ = terminate => {}
+            }
+        })
+        .await;
+}
+
+/// Resolves once draining has run for `drain_timeout_secs`
+pub async fn drain_deadline() {
+    DRAIN
+        .deadline(|| Duration::from_secs(CLEWDR_CONFIG.load().drain_timeout_secs))
+        .await;
+}
+
+/// Turns new requests away while draining, health probes keep answering
+pub async fn reject_while_draining(req: Request, next: Next) -> Response {
+    DRAIN.gate(req, next).await
+}
+
+/// Saves state that is written in the background: the cookie pool and SLO snapshot
+///
+/// # Arguments
+/// * `cookies` - Cookie actor handle, its pending snapshots are waited for
+pub async fn flush(cookies: &CookieActorHandle) {
+    let saved = async {
+        if let Err(e) = cookies.flush().await {
+            error!("Failed to save cookies on shutdown: {}", e);
+        }
+        crate::services::slo::save_snapshot().await;
+    };
+    if tokio::time::timeout(FLUSH_TIMEOUT, saved).await.is_err() {
+        error!("Saving state on shutdown timed out");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use axum::{Router, body::Body, http::StatusCode, middleware::from_fn, routing::get};
+    use futures::StreamExt;
+    use tokio::net::TcpListener;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::config::install_test_config;
+
+    const CHUNKS: usize = 5;
+    const CHUNK_INTERVAL: Duration = Duration::from_millis(100);
+
+    async fn slow_stream() -> Body {
+        let chunks = futures::stream::iter(0..CHUNKS).then(|i| async move {
+            tokio::time::sleep(CHUNK_INTERVAL).await;
+            Ok::<_, Infallible>(format!("chunk {i}\n"))
+        });
+        Body::from_stream(chunks)
+    }
+
+    #[tokio::test]
+    async fn draining_finishes_running_streams_and_rejects_new_requests() {
+        install_test_config();
+        // a drain of its own, the instance wide one would turn away other tests
+        let drain: &'static Drain = Box::leak(Box::new(Drain::new()));
+        let router = Router::new()
+            .route("/slow", get(slow_stream))
+            .route("/fast", get(|| async { "ok" }))
+            .layer(from_fn(move |req: Request, next: Next| {
+                drain.gate(req, next)
+            }));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (trigger, triggered) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(
+            axum::serve(listener, router.to_owned())
+                .with_graceful_shutdown(drain.start_on(async {
+                    _ = triggered.await;
+                }))
+                .into_future(),
+        );
+
+        let res = wreq::Client::new()
+            .get(format!("http://{addr}/slow"))
+            .send()
+            .await
+            .unwrap();
+        let mut body = res.bytes_stream();
+        let first = body.next().await.unwrap().unwrap();
+        assert_eq!(first, "chunk 0\n");
+        trigger.send(()).unwrap();
+        while !drain.is_draining() {
+            tokio::task::yield_now().await;
+        }
+
+        // a request arriving on an open connection is turned away
+        let req = Request::get("/fast").body(Body::empty()).unwrap();
+        let res = router.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(res.headers().contains_key(http::header::RETRY_AFTER));
+
+        // the running stream still completes, well before the deadline
+        let rest = async {
+            let mut text = String::from_utf8(first.to_vec()).unwrap();
+            while let Some(chunk) = body.next().await {
+                text.push_str(std::str::from_utf8(&chunk.unwrap()).unwrap());
+            }
+            text
+        };
+        let text = tokio::select! {
+            text = rest => text,
+            _ = drain.deadline(|| Duration::from_secs(5)) => panic!("stream outlived the drain"),
+        };
+        let expected = (0..CHUNKS)
+            .map(|i| format!("chunk {i}\n"))
+            .collect::<String>();
+        assert_eq!(text, expected);
+        server.await.unwrap().unwrap();
+    }
+}
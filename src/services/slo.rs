@@ -0,0 +1,633 @@
+//! Rolling window SLO tracking
+//!
+//! Outcomes are counted in one minute buckets kept for seven days, so window
+//! edges are accurate to a minute. The tracker is snapshotted to disk every
+//! minute; an unclean exit loses at most the outcomes recorded since the last
+//! snapshot.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    path::{Path, PathBuf},
+    sync::{LazyLock, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use axum::{body::Body, response::Response};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use strum::Display;
+use tracing::{error, info, warn};
+
+use crate::{
+    config::{CLEWDR_CONFIG, CONFIG_PATH, SloConfig, SloEndpoint},
+    error::ClewdrError,
+    services::resources::{FnReporter, ResourceUsage, register_reporter},
+};
+
+const MINUTE: i64 = 60;
+const HOUR: i64 = 60 * MINUTE;
+const DAY: i64 = 24 * HOUR;
+/// Windows reported for every objective, the last one is the budget window
+const WINDOWS: [(&str, i64); 3] = [("1h", HOUR), ("24h", DAY), ("7d", 7 * DAY)];
+/// Alerts stay quiet until the alert window holds this many requests
+const MIN_ALERT_REQUESTS: u64 = 10;
+const MAX_EVENTS: usize = 100;
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(60);
+
+static SLO_STATE_PATH: LazyLock<PathBuf> = LazyLock::new(|| {
+    CONFIG_PATH
+        .parent()
+        .map(|p| p.join("slo_state.json"))
+        .unwrap_or_else(|| PathBuf::from("slo_state.json"))
+});
+
+/// Global tracker fed by the Claude providers
+pub static SLO_TRACKER: LazyLock<Mutex<SloTracker>> = LazyLock::new(|| {
+    register_reporter(FnReporter::new("slo_tracker", || {
+        SLO_TRACKER
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .usage()
+    }));
+    Default::default()
+});
+
+/// Kind of burn rate alert
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum BurnAlert {
+    /// Burn rate over the last hour
+    FastBurn,
+    /// Burn rate over the last day
+    SlowBurn,
+}
+
+/// An alert starting or stopping to fire
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SloAlertEvent {
+    pub slo: String,
+    pub kind: BurnAlert,
+    /// `false` when the alert resolved
+    pub firing: bool,
+    pub burn_rate: f64,
+    /// Unix timestamp in seconds
+    pub timestamp: i64,
+}
+
+/// Terminal state of one proxied request
+#[derive(Debug, Clone, Copy)]
+pub struct SloOutcome {
+    pub endpoint: SloEndpoint,
+    /// Whether the response body was fully delivered without error
+    pub completed: bool,
+    pub latency_ms: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+struct Bucket {
+    minute: i64,
+    total: u64,
+    good: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+struct Series {
+    buckets: VecDeque<Bucket>,
+    fast_firing: bool,
+    slow_firing: bool,
+}
+
+impl Series {
+    fn add(&mut self, now: i64, good: bool) {
+        let minute = now.div_euclid(MINUTE);
+        match self.buckets.back_mut() {
+            // a clock going backwards lands in the latest bucket
+            Some(b) if b.minute >= minute => {
+                b.total += 1;
+                b.good += good as u64;
+            }
+            _ => self.buckets.push_back(Bucket {
+                minute,
+                total: 1,
+                good: good as u64,
+            }),
+        }
+    }
+
+    fn prune(&mut self, now: i64) {
+        let (_, retention) = WINDOWS[WINDOWS.len() - 1];
+        let oldest = (now - retention).div_euclid(MINUTE);
+        while self.buckets.front().is_some_and(|b| b.minute <= oldest) {
+            self.buckets.pop_front();
+        }
+    }
+
+    /// `(total, good)` over the last `secs` seconds, including the current minute
+    fn window(&self, now: i64, secs: i64) -> (u64, u64) {
+        let from = now.div_euclid(MINUTE) - secs / MINUTE;
+        self.buckets
+            .iter()
+            .rev()
+            .take_while(|b| b.minute > from)
+            .fold((0, 0), |(total, good), b| (total + b.total, good + b.good))
+    }
+
+    fn burn_rate(&self, slo: &SloConfig, now: i64, secs: i64) -> Option<f64> {
+        let (total, good) = self.window(now, secs);
+        burn_rate(slo, total, good)
+    }
+
+    fn evaluate(&mut self, slo: &SloConfig, now: i64) -> Vec<SloAlertEvent> {
+        let checks = [
+            (BurnAlert::FastBurn, HOUR, slo.fast_burn),
+            (BurnAlert::SlowBurn, DAY, slo.slow_burn),
+        ];
+        let mut events = vec![];
+        for (kind, secs, threshold) in checks {
+            let (total, _) = self.window(now, secs);
+            let rate = self.burn_rate(slo, now, secs).unwrap_or_default();
+            let firing_now = total >= MIN_ALERT_REQUESTS && rate >= threshold;
+            let firing = match kind {
+                BurnAlert::FastBurn => &mut self.fast_firing,
+                BurnAlert::SlowBurn => &mut self.slow_firing,
+            };
+            if *firing != firing_now {
+                *firing = firing_now;
+                events.push(SloAlertEvent {
+                    slo: slo.name.to_owned(),
+                    kind,
+                    firing: firing_now,
+                    burn_rate: rate,
+                    timestamp: now,
+                });
+            }
+        }
+        events
+    }
+}
+
+fn burn_rate(slo: &SloConfig, total: u64, good: u64) -> Option<f64> {
+    if total == 0 {
+        return None;
+    }
+    let bad_ratio = (total - good) as f64 / total as f64;
+    Some(bad_ratio / slo.error_budget())
+}
+
+/// Compliance of one objective over one window
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct SloWindowReport {
+    pub window: &'static str,
+    pub total: u64,
+    pub good: u64,
+    /// Fraction of good requests, missing without traffic
+    pub compliance: Option<f64>,
+    /// Budget consumption speed, `1.0` spends exactly the budget over the window
+    pub burn_rate: Option<f64>,
+}
+
+/// Current state of one objective
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct SloReport {
+    pub name: String,
+    pub target: f64,
+    pub latency_secs: u64,
+    pub endpoints: Vec<SloEndpoint>,
+    pub windows: Vec<SloWindowReport>,
+    /// Fraction of the 7d error budget left, negative once overspent
+    pub budget_remaining: Option<f64>,
+    pub fast_burn_firing: bool,
+    pub slow_burn_firing: bool,
+}
+
+/// Outcome counters and alert state of every configured objective
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SloTracker {
+    series: HashMap<String, Series>,
+    events: VecDeque<SloAlertEvent>,
+}
+
+impl SloTracker {
+    /// Counts an outcome towards every objective covering its endpoint
+    ///
+    /// # Returns
+    /// * `Vec<SloAlertEvent>` - Alerts that started or stopped firing
+    pub fn record(
+        &mut self,
+        slos: &[SloConfig],
+        outcome: &SloOutcome,
+        now: i64,
+    ) -> Vec<SloAlertEvent> {
+        // forget objectives removed from the config
+        self.series
+            .retain(|name, _| slos.iter().any(|s| &s.name == name));
+        let mut fired = vec![];
+        for slo in slos.iter().filter(|s| s.covers(outcome.endpoint)) {
+            let good = outcome.completed && outcome.latency_ms <= slo.latency_secs * 1000;
+            let series = self.series.entry(slo.name.to_owned()).or_default();
+            series.prune(now);
+            series.add(now, good);
+            fired.extend(series.evaluate(slo, now));
+        }
+        for event in fired.iter() {
+            if self.events.len() >= MAX_EVENTS {
+                self.events.pop_front();
+            }
+            self.events.push_back(event.to_owned());
+        }
+        fired
+    }
+
+    /// Computes compliance and burn rate of every objective
+    pub fn report(&self, slos: &[SloConfig], now: i64) -> Vec<SloReport> {
+        let empty = Series::default();
+        slos.iter()
+            .map(|slo| {
+                let series = self.series.get(&slo.name).unwrap_or(&empty);
+                let windows = WINDOWS
+                    .iter()
+                    .map(|&(window, secs)| {
+                        let (total, good) = series.window(now, secs);
+                        SloWindowReport {
+                            window,
+                            total,
+                            good,
+                            compliance: (total > 0).then(|| good as f64 / total as f64),
+                            burn_rate: burn_rate(slo, total, good),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                let budget_remaining = windows
+                    .last()
+                    .and_then(|w| w.burn_rate)
+                    .map(|rate| 1.0 - rate);
+                SloReport {
+                    name: slo.name.to_owned(),
+                    target: slo.target,
+                    latency_secs: slo.latency_secs,
+                    endpoints: slo.endpoints.to_owned(),
+                    windows,
+                    budget_remaining,
+                    fast_burn_firing: series.fast_firing,
+                    slow_burn_firing: series.slow_firing,
+                }
+            })
+            .collect()
+    }
+
+    /// Buckets and events held in memory
+    fn usage(&self) -> ResourceUsage {
+        let buckets = self.series.values().map(|s| s.buckets.len()).sum::<usize>();
+        ResourceUsage {
+            bytes: Some(
+                (buckets * size_of::<Bucket>() + self.events.len() * size_of::<SloAlertEvent>())
+                    as u64,
+            ),
+            entries: buckets as u64,
+        }
+    }
+
+    /// Recent alert events, oldest first
+    pub fn events(&self) -> impl Iterator<Item = &SloAlertEvent> {
+        self.events.iter()
+    }
+
+    /// Writes the tracker to `path` atomically
+    pub async fn save(&self, path: &Path) -> Result<(), ClewdrError> {
+        let tmp = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp, serde_json::to_vec(self)?).await?;
+        Ok(tokio::fs::rename(&tmp, path).await?)
+    }
+
+    /// Reads a tracker written by [`SloTracker::save`]
+    ///
+    /// # Returns
+    /// * `Option<SloTracker>` - `None` when no snapshot exists
+    pub async fn load(path: &Path) -> Result<Option<Self>, ClewdrError> {
+        match tokio::fs::read(path).await {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Records an outcome in the global tracker and logs alert transitions
+pub fn record_outcome(outcome: SloOutcome) {
+    let config = CLEWDR_CONFIG.load();
+    if config.slo.is_empty() {
+        return;
+    }
+    let now = chrono::Utc::now().timestamp();
+    let events = SLO_TRACKER
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .record(&config.slo, &outcome, now);
+    for event in events {
+        if event.firing {
+            warn!(
+                "[SLO] {} {} alert firing, burn rate: {:.2}",
+                event.slo, event.kind, event.burn_rate
+            );
+        } else {
+            info!("[SLO] {} {} alert resolved", event.slo, event.kind);
+        }
+    }
+}
+
+/// Restores the tracker from its snapshot and keeps the snapshot up to date
+///
+/// A snapshot that cannot be read is left alone for inspection, no new
+/// snapshots are written over it.
+///
+/// # Returns
+/// * `Result<(), ClewdrError>` - Error reading the snapshot
+pub async fn init_slo() -> Result<(), ClewdrError> {
+    if CLEWDR_CONFIG.load().no_fs {
+        return Ok(());
+    }
+    if let Some(tracker) = SloTracker::load(&SLO_STATE_PATH).await? {
+        *SLO_TRACKER.lock().unwrap_or_else(PoisonError::into_inner) = tracker;
+    }
+    tokio::spawn(async {
+        let mut interval = tokio::time::interval(SNAPSHOT_INTERVAL);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            save_snapshot().await;
+        }
+    });
+    Ok(())
+}
+
+/// Writes the tracker to its snapshot file, when objectives are configured
+pub async fn save_snapshot() {
+    let config = CLEWDR_CONFIG.load();
+    if config.no_fs || config.slo.is_empty() {
+        return;
+    }
+    let tracker = SLO_TRACKER
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .to_owned();
+    if let Err(e) = tracker.save(&SLO_STATE_PATH).await {
+        error!("Failed to snapshot SLO state: {}", e);
+    }
+}
+
+/// Measures one request from the provider side
+pub struct SloTimer {
+    endpoint: SloEndpoint,
+    start: Instant,
+}
+
+impl SloTimer {
+    /// Starts measuring, returns `None` when no objective is configured
+    pub fn start(endpoint: SloEndpoint) -> Option<Self> {
+        if CLEWDR_CONFIG.load().slo.is_empty() {
+            return None;
+        }
+        Some(Self {
+            endpoint,
+            start: Instant::now(),
+        })
+    }
+
+    fn outcome(&self, completed: bool) -> SloOutcome {
+        SloOutcome {
+            endpoint: self.endpoint,
+            completed,
+            latency_ms: self.start.elapsed().as_millis() as u64,
+        }
+    }
+
+    /// Finishes measuring with the provider result
+    ///
+    /// Successful responses are measured until their body is fully delivered,
+    /// a stream cut off by an error or a disconnect counts as bad.
+    pub fn finish(self, result: Result<Response, ClewdrError>) -> Result<Response, ClewdrError> {
+        let resp = match result {
+            Ok(resp) if resp.status().is_success() => resp,
+            other => {
+                record_outcome(self.outcome(false));
+                return other;
+            }
+        };
+        let (parts, body) = resp.into_parts();
+        let mut guard = Completion {
+            timer: self,
+            completed: false,
+        };
+        let stream = async_stream::stream! {
+            let mut body = body.into_data_stream();
+            let mut failed = false;
+            while let Some(chunk) = body.next().await {
+                failed |= chunk.is_err();
+                yield chunk;
+            }
+            guard.completed = !failed;
+        };
+        Ok(Response::from_parts(parts, Body::from_stream(stream)))
+    }
+}
+
+/// Records the outcome once the response body is dropped
+struct Completion {
+    timer: SloTimer,
+    completed: bool,
+}
+
+impl Drop for Completion {
+    fn drop(&mut self) {
+        record_outcome(self.timer.outcome(self.completed));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Monday 2024-01-01 00:00:00 UTC
+    const T0: i64 = 1_704_067_200;
+
+    fn slo() -> SloConfig {
+        SloConfig {
+            name: "availability".to_string(),
+            target: 0.99,
+            latency_secs: 60,
+            endpoints: vec![],
+            fast_burn: 14.4,
+            slow_burn: 3.0,
+        }
+    }
+
+    fn outcome(completed: bool, latency_ms: u64) -> SloOutcome {
+        SloOutcome {
+            endpoint: SloEndpoint::ClaudeWeb,
+            completed,
+            latency_ms,
+        }
+    }
+
+    fn window<'a>(report: &'a SloReport, name: &str) -> &'a SloWindowReport {
+        report.windows.iter().find(|w| w.window == name).unwrap()
+    }
+
+    #[test]
+    fn rolling_windows_drop_old_outcomes() {
+        let slos = [slo()];
+        let mut tracker = SloTracker::default();
+        // one bad request per day for a week, plus a slow one now
+        for day in 0..7 {
+            tracker.record(&slos, &outcome(false, 10), T0 + day * DAY);
+        }
+        let now = T0 + 6 * DAY + 30 * MINUTE;
+        tracker.record(&slos, &outcome(true, 61_000), now);
+        tracker.record(&slos, &outcome(true, 1_000), now);
+
+        let report = &tracker.report(&slos, now)[0];
+        assert_eq!(
+            (window(report, "1h").total, window(report, "1h").good),
+            (3, 1)
+        );
+        assert_eq!(window(report, "24h").total, 3);
+        assert_eq!(window(report, "7d").total, 9);
+        assert_eq!(window(report, "7d").good, 1);
+        let compliance = window(report, "1h").compliance.unwrap();
+        assert!((compliance - 1.0 / 3.0).abs() < 1e-9);
+
+        // a day later only the last day's outcomes remain in the budget window
+        let later = T0 + 7 * DAY + 30 * MINUTE;
+        tracker.record(&slos, &outcome(true, 1_000), later);
+        let report = &tracker.report(&slos, later)[0];
+        assert_eq!(window(report, "1h").total, 1);
+        assert_eq!(window(report, "24h").total, 1);
+        assert_eq!(window(report, "7d").total, 9);
+    }
+
+    #[test]
+    fn budget_and_burn_rate() {
+        let slos = [slo()];
+        let mut tracker = SloTracker::default();
+        for i in 0..200 {
+            tracker.record(&slos, &outcome(i != 0, 1_000), T0 + i);
+        }
+        let report = &tracker.report(&slos, T0 + 200)[0];
+        let week = window(report, "7d");
+        // 0.5% bad against a 1% budget burns at half speed
+        assert!((week.burn_rate.unwrap() - 0.5).abs() < 1e-9);
+        assert!((report.budget_remaining.unwrap() - 0.5).abs() < 1e-9);
+        assert!(!report.fast_burn_firing);
+    }
+
+    #[test]
+    fn endpoint_scope() {
+        let mut code_only = slo();
+        code_only.endpoints = vec![SloEndpoint::ClaudeCode];
+        let slos = [code_only];
+        let mut tracker = SloTracker::default();
+        tracker.record(&slos, &outcome(false, 10), T0);
+        let report = &tracker.report(&slos, T0)[0];
+        assert_eq!(window(report, "1h").total, 0);
+        assert_eq!(report.budget_remaining, None);
+    }
+
+    #[test]
+    fn fast_burn_fires_and_resolves() {
+        let slos = [slo()];
+        let mut tracker = SloTracker::default();
+        // healthy traffic every minute for a day
+        for m in 0..DAY / MINUTE {
+            assert!(
+                tracker
+                    .record(&slos, &outcome(true, 500), T0 + m * MINUTE)
+                    .is_empty()
+            );
+        }
+        let outage = T0 + DAY;
+        // 59 good requests in the last hour, 14.4 × 1% = 14.4% bad is reached
+        // by the tenth failure (10 / 69 ≈ 14.5%)
+        let mut fired_at = None;
+        for i in 0..20 {
+            let events = tracker.record(&slos, &outcome(false, 500), outage + i);
+            if let Some(event) = events.iter().find(|e| e.kind == BurnAlert::FastBurn) {
+                assert!(event.firing);
+                fired_at = Some(i);
+                break;
+            }
+        }
+        assert_eq!(fired_at, Some(9));
+        let report = &tracker.report(&slos, outage + 9)[0];
+        assert!(report.fast_burn_firing);
+        assert!(!report.slow_burn_firing);
+
+        // once the failures age out of the hour the alert resolves
+        let mut resolved_at = None;
+        for m in 1..=90 {
+            let now = outage + m * MINUTE;
+            let events = tracker.record(&slos, &outcome(true, 500), now);
+            if events
+                .iter()
+                .any(|e| e.kind == BurnAlert::FastBurn && !e.firing)
+            {
+                resolved_at = Some(m);
+                break;
+            }
+        }
+        assert_eq!(resolved_at, Some(60));
+        assert_eq!(tracker.events().count(), 2);
+    }
+
+    #[test]
+    fn slow_burn_fires_without_fast_burn() {
+        let slos = [slo()];
+        let mut tracker = SloTracker::default();
+        // 5% bad spread over a day burns at 5×, above slow and below fast
+        let mut events = vec![];
+        for m in 0..DAY / MINUTE {
+            events.extend(tracker.record(&slos, &outcome(m % 20 != 0, 500), T0 + m * MINUTE));
+        }
+        assert!(events.iter().all(|e| e.kind == BurnAlert::SlowBurn));
+        assert!(events.first().is_some_and(|e| e.firing));
+        let report = &tracker.report(&slos, T0 + DAY - MINUTE)[0];
+        assert!(report.slow_burn_firing);
+        assert!(!report.fast_burn_firing);
+    }
+
+    #[test]
+    fn alerts_need_enough_traffic() {
+        let slos = [slo()];
+        let mut tracker = SloTracker::default();
+        for i in 0..(MIN_ALERT_REQUESTS as i64 - 1) {
+            assert!(
+                tracker
+                    .record(&slos, &outcome(false, 10), T0 + i)
+                    .is_empty()
+            );
+        }
+        assert_eq!(tracker.record(&slos, &outcome(false, 10), T0 + 10).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn restart_recovery() {
+        let slos = [slo()];
+        let mut tracker = SloTracker::default();
+        for i in 0..30 {
+            tracker.record(&slos, &outcome(i % 2 == 0, 10), T0 + i * MINUTE);
+        }
+        let now = T0 + 30 * MINUTE;
+        let path = std::env::temp_dir().join(format!("clewdr_slo_{}.json", std::process::id()));
+        tracker.save(&path).await.unwrap();
+        let restored = SloTracker::load(&path).await.unwrap().unwrap();
+        tokio::fs::remove_file(&path).await.unwrap();
+
+        assert_eq!(restored.report(&slos, now), tracker.report(&slos, now));
+        assert_eq!(restored.events().count(), tracker.events().count());
+        // firing state survives, so the restored tracker does not fire again
+        let mut restored = restored;
+        let events = restored.record(&slos, &outcome(false, 10), now);
+        assert!(events.is_empty());
+        assert!(SloTracker::load(&path).await.unwrap().is_none());
+    }
+}
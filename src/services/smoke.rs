@@ -0,0 +1,433 @@
+use std::time::{Duration, Instant};
+
+use colored::Colorize;
+use serde_json::{Value, json};
+use snafu::ResultExt;
+use url::Url;
+use wreq::{Client, RequestBuilder};
+
+use crate::{
+    config::{ADMIN_HEADER, SMOKE_HEADER},
+    error::{ClewdrError, WreqSnafu},
+};
+
+/// Cheapest model, the completion checks only need a handful of tokens
+pub const SMOKE_MODEL: &str = "claude-haiku-4-5";
+const SMOKE_PROMPT: &str = "Reply with the single word: pong";
+const SMOKE_MAX_TOKENS: u32 = 16;
+
+/// Options of the `smoke` subcommand
+#[derive(clap::Args, Debug, Clone)]
+pub struct SmokeArgs {
+    /// Base URL of the running instance, e.g. http://127.0.0.1:8484
+    #[arg(long)]
+    pub url: Url,
+    /// API password for the completion endpoints
+    #[arg(long)]
+    pub key: String,
+    /// Admin password, enables the admin checks and keeps the completion
+    /// checks out of usage stats, sent next to `key` on completions
+    #[arg(long)]
+    pub admin_key: Option<String>,
+    /// Model used by the completion checks
+    #[arg(long, default_value = SMOKE_MODEL)]
+    pub model: String,
+    /// Run completion checks against the Claude Code endpoints
+    #[arg(long)]
+    pub code: bool,
+    /// Timeout of each request in seconds
+    #[arg(long, default_value_t = 60)]
+    pub timeout: u64,
+}
+
+/// Outcome of a single smoke check
+#[derive(Debug)]
+pub struct SmokeCheck {
+    pub name: &'static str,
+    pub elapsed: Duration,
+    /// Short detail on success, failure reason otherwise
+    pub result: Result<String, String>,
+}
+
+/// Runs scripted checks against a live instance
+pub struct SmokeRunner {
+    client: Client,
+    args: SmokeArgs,
+}
+
+impl SmokeRunner {
+    pub fn new(args: SmokeArgs) -> Result<Self, ClewdrError> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(args.timeout))
+            .build()
+            .context(WreqSnafu {
+                msg: "Failed to create HTTP client",
+            })?;
+        Ok(Self { client, args })
+    }
+
+    /// Runs every check in order, admin checks only when an admin key is given
+    pub async fn run(&self) -> Vec<SmokeCheck> {
+        let mut checks = vec![
+            timed("version", self.check_version()).await,
+            timed("completion", self.check_completion()).await,
+            timed("stream", self.check_stream()).await,
+            timed("count_tokens", self.check_count_tokens()).await,
+            timed("invalid_request", self.check_invalid_request()).await,
+        ];
+        if self.args.admin_key.is_some() {
+            checks.push(timed("admin_auth", self.check_admin_auth()).await);
+            checks.push(timed("cookie_status", self.check_cookie_status()).await);
+        }
+        checks
+    }
+
+    fn url(&self, path: &str) -> Result<Url, String> {
+        self.args.url.join(path).map_err(|e| e.to_string())
+    }
+
+    fn messages_path(&self) -> &'static str {
+        if self.args.code {
+            "code/v1/messages"
+        } else {
+            "v1/messages"
+        }
+    }
+
+    fn api_post(&self, path: &str, body: Value) -> Result<RequestBuilder, String> {
+        let req = self
+            .client
+            .post(self.url(path)?.as_str())
+            .header("x-api-key", &self.args.key)
+            .header(SMOKE_HEADER, "1")
+            .json(&body);
+        // the smoke header is only honoured for the admin
+        Ok(match self.args.admin_key.as_deref() {
+            Some(admin_key) => req.header(ADMIN_HEADER, admin_key),
+            None => req,
+        })
+    }
+
+    fn admin_get(&self, path: &str) -> Result<RequestBuilder, String> {
+        let key = self.args.admin_key.as_deref().unwrap_or_default();
+        Ok(self.client.get(self.url(path)?.as_str()).bearer_auth(key))
+    }
+
+    fn message_body(&self, stream: bool) -> Value {
+        json!({
+            "model": self.args.model,
+            "max_tokens": SMOKE_MAX_TOKENS,
+            "stream": stream,
+            "messages": [{ "role": "user", "content": SMOKE_PROMPT }],
+        })
+    }
+
+    async fn check_version(&self) -> Result<String, String> {
+        let resp = self
+            .client
+            .get(self.url("api/version")?.as_str())
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let status = resp.status();
+        let text = resp.text().await.map_err(|e| e.to_string())?;
+        if !status.is_success() {
+            return Err(format!("status {status}: {text}"));
+        }
+        text.lines()
+            .next()
+            .filter(|l| !l.trim().is_empty())
+            .map(str::to_string)
+            .ok_or_else(|| "empty version".to_string())
+    }
+
+    async fn check_completion(&self) -> Result<String, String> {
+        let resp = self
+            .api_post(self.messages_path(), self.message_body(false))?
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let (status, body) = read_json(resp).await?;
+        if !status.is_success() {
+            return Err(format!("status {status}: {body}"));
+        }
+        check_message_response(&body)
+    }
+
+    async fn check_stream(&self) -> Result<String, String> {
+        let resp = self
+            .api_post(self.messages_path(), self.message_body(true))?
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let status = resp.status();
+        let text = resp.text().await.map_err(|e| e.to_string())?;
+        if !status.is_success() {
+            return Err(format!("status {status}: {text}"));
+        }
+        check_stream_events(&text)
+    }
+
+    async fn check_count_tokens(&self) -> Result<String, String> {
+        let body = json!({
+            "model": self.args.model,
+            "messages": [{ "role": "user", "content": SMOKE_PROMPT }],
+        });
+        let resp = self
+            .api_post("code/v1/messages/count_tokens", body)?
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let (status, body) = read_json(resp).await?;
+        if !status.is_success() {
+            return Err(format!("status {status}: {body}"));
+        }
+        match body["input_tokens"].as_u64() {
+            Some(n) if n > 0 => Ok(format!("{n} input tokens")),
+            _ => Err(format!("missing input_tokens: {body}")),
+        }
+    }
+
+    async fn check_invalid_request(&self) -> Result<String, String> {
+        // no messages, must be rejected before reaching upstream
+        let resp = self
+            .api_post(self.messages_path(), json!({ "model": self.args.model }))?
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let (status, body) = read_json(resp).await?;
+        if !status.is_client_error() {
+            return Err(format!("expected 4xx, got {status}"));
+        }
+        check_error_envelope(&body).map(|t| format!("{status} {t}"))
+    }
+
+    async fn check_admin_auth(&self) -> Result<String, String> {
+        let resp = self
+            .admin_get("api/auth")?
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let status = resp.status();
+        if !status.is_success() {
+            return Err(format!("status {status}"));
+        }
+        Ok(status.to_string())
+    }
+
+    async fn check_cookie_status(&self) -> Result<String, String> {
+        let resp = self
+            .admin_get("api/cookies")?
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let (status, body) = read_json(resp).await?;
+        if !status.is_success() {
+            return Err(format!("status {status}: {body}"));
+        }
+        let count = |key: &str| body[key].as_array().map(Vec::len);
+        match (count("valid"), count("exhausted"), count("invalid")) {
+            (Some(v), Some(e), Some(i)) => Ok(format!("valid {v}, exhausted {e}, invalid {i}")),
+            _ => Err(format!("unexpected cookie status shape: {body}")),
+        }
+    }
+}
+
+async fn timed(
+    name: &'static str,
+    check: impl Future<Output = Result<String, String>>,
+) -> SmokeCheck {
+    let start = Instant::now();
+    let result = check.await;
+    SmokeCheck {
+        name,
+        elapsed: start.elapsed(),
+        result,
+    }
+}
+
+pub(crate) async fn read_json(resp: wreq::Response) -> Result<(wreq::StatusCode, Value), String> {
+    let status = resp.status();
+    let text = resp.text().await.map_err(|e| e.to_string())?;
+    let body =
+        serde_json::from_str(&text).map_err(|_| format!("status {status}, not JSON: {text}"))?;
+    Ok((status, body))
+}
+
+/// Checks the shape of a non-streaming Claude message
+pub fn check_message_response(body: &Value) -> Result<String, String> {
+    if body["type"] != "message" {
+        return Err(format!("unexpected type: {}", body["type"]));
+    }
+    let Some(content) = body["content"].as_array() else {
+        return Err("missing content array".to_string());
+    };
+    Ok(format!(
+        "{} blocks, stop_reason {}",
+        content.len(),
+        body["stop_reason"]
+    ))
+}
+
+/// Parses the JSON events of a Claude stream, skipping pings
+pub fn stream_events(text: &str) -> Vec<Value> {
+    text.lines()
+        .filter_map(|line| line.strip_prefix("data:"))
+        .filter_map(|data| serde_json::from_str::<Value>(data.trim()).ok())
+        .filter(|event| event["type"] != "ping")
+        .collect()
+}
+
+/// Checks the ordering of Claude stream events
+///
+/// The stream must open with `message_start`, deltas must sit between the
+/// start and stop of their block, and it must end with `message_delta` then
+/// `message_stop`. Any `error` event fails the check.
+pub fn check_stream_events(text: &str) -> Result<String, String> {
+    let events = stream_events(text);
+    let types = events
+        .iter()
+        .map(|e| e["type"].as_str().unwrap_or_default())
+        .collect::<Vec<_>>();
+    if types.first() != Some(&"message_start") {
+        return Err(format!("first event is {:?}", types.first()));
+    }
+    if let Some(error) = events.iter().find(|e| e["type"] == "error") {
+        return Err(format!("error event: {}", error["error"]));
+    }
+    let mut open = std::collections::HashSet::new();
+    let mut deltas = 0;
+    for event in &events {
+        let index = event["index"].as_u64();
+        match event["type"].as_str().unwrap_or_default() {
+            "content_block_start" => {
+                open.insert(index);
+            }
+            "content_block_delta" if !open.contains(&index) => {
+                return Err(format!("delta for block {index:?} outside start/stop"));
+            }
+            "content_block_delta" => deltas += 1,
+            "content_block_stop" if !open.remove(&index) => {
+                return Err(format!("stop for block {index:?} that never started"));
+            }
+            _ => (),
+        }
+    }
+    if !open.is_empty() {
+        return Err(format!("{} blocks never stopped", open.len()));
+    }
+    match types.as_slice() {
+        [.., "message_delta", "message_stop"] => {
+            Ok(format!("{} events, {deltas} deltas", events.len()))
+        }
+        _ => Err(format!("unexpected terminal events: {:?}", types.last())),
+    }
+}
+
+/// Checks the `{"error": {"message", "type"}}` envelope of error responses
+pub fn check_error_envelope(body: &Value) -> Result<String, String> {
+    let error = &body["error"];
+    if error["message"].is_null() {
+        return Err(format!("missing error.message: {body}"));
+    }
+    error["type"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| format!("missing error.type: {body}"))
+}
+
+/// Runs the smoke suite and prints a report
+///
+/// # Returns
+/// * `Result<bool, ClewdrError>` - Whether every check passed
+pub async fn run(args: SmokeArgs) -> Result<bool, ClewdrError> {
+    println!("Smoke testing {}", args.url.to_string().blue());
+    let checks = SmokeRunner::new(args)?.run().await;
+    for check in &checks {
+        let elapsed = format!("{}ms", check.elapsed.as_millis());
+        match check.result {
+            Ok(ref detail) => println!(
+                "{} {:<16} {:>8}  {}",
+                "PASS".green(),
+                check.name,
+                elapsed,
+                detail
+            ),
+            Err(ref reason) => println!(
+                "{} {:<16} {:>8}  {}",
+                "FAIL".red(),
+                check.name,
+                elapsed,
+                reason
+            ),
+        }
+    }
+    let failed = checks.iter().filter(|c| c.result.is_err()).count();
+    if failed == 0 {
+        println!("{}", format!("All {} checks passed", checks.len()).green());
+    } else {
+        println!(
+            "{}",
+            format!("{failed}/{} checks failed", checks.len()).red()
+        );
+    }
+    Ok(failed == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{TEST_ADMIN_PASSWORD, TEST_PASSWORD},
+        services::test_instance::{test_instance, upstream_requests},
+    };
+
+    const STREAM: &str = "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{}}\n\n\
+        event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"text\",\"text\":\"\"}}\n\n\
+        event: ping\ndata: {\"type\":\"ping\"}\n\n\
+        event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"pong\"}}\n\n\
+        event: content_block_stop\ndata: {\"type\":\"content_block_stop\",\"index\":0}\n\n\
+        event: message_delta\ndata: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\"}}\n\n\
+        event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n";
+
+    #[test]
+    fn stream_ordering_is_checked() {
+        assert!(check_stream_events(STREAM).is_ok());
+        let truncated = STREAM.rsplit_once("event: message_delta").unwrap().0;
+        assert!(check_stream_events(truncated).is_err());
+        let orphan = STREAM.replace("content_block_start", "content_block_begin");
+        assert!(check_stream_events(&orphan).is_err());
+    }
+
+    #[test]
+    fn error_envelope_is_checked() {
+        let ok = json!({ "error": { "message": "bad", "type": "bad_request", "code": 400 } });
+        assert_eq!(check_error_envelope(&ok).unwrap(), "bad_request");
+        assert!(check_error_envelope(&json!({ "message": "bad" })).is_err());
+    }
+
+    #[tokio::test]
+    async fn suite_passes_through_the_router() {
+        let args = SmokeArgs {
+            url: test_instance(),
+            key: TEST_PASSWORD.to_string(),
+            admin_key: Some(TEST_ADMIN_PASSWORD.to_string()),
+            model: SMOKE_MODEL.to_string(),
+            code: true,
+            timeout: 30,
+        };
+        let checks = SmokeRunner::new(args).unwrap().run().await;
+        assert_eq!(checks.len(), 7);
+        for check in checks {
+            assert!(check.result.is_ok(), "{}: {:?}", check.name, check.result);
+        }
+        // completions reached the upstream adapted for Claude Code
+        let adapted = upstream_requests().iter().any(|body| {
+            body["model"] == SMOKE_MODEL
+                && body["system"][0]["text"]
+                    .as_str()
+                    .is_some_and(|t| t.starts_with("x-anthropic-billing-header"))
+        });
+        assert!(adapted);
+    }
+}
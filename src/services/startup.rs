@@ -0,0 +1,153 @@
+//! Startup progress of each subsystem
+//!
+//! Subsystems that do not depend on each other start concurrently, each one
+//! records when it started, how long it took and whether it failed, so a slow
+//! start can be traced to its cause through `/api/startup`.
+
+use std::{
+    fmt::Display,
+    sync::{LazyLock, Mutex, PoisonError},
+    time::Instant,
+};
+
+use serde::Serialize;
+use tracing::{info, warn};
+
+pub static STARTUP: LazyLock<StartupTracker> = LazyLock::new(StartupTracker::new);
+
+/// State of one subsystem
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubsystemStatus {
+    Starting,
+    Ready,
+    Failed,
+}
+
+/// Progress of one subsystem
+#[derive(Debug, Clone, Serialize)]
+pub struct SubsystemReport {
+    pub name: &'static str,
+    pub status: SubsystemStatus,
+    /// Milliseconds after the process started
+    pub started_ms: u64,
+    /// Time to become ready or fail, `None` while starting
+    pub took_ms: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// Progress of the whole startup
+#[derive(Debug, Clone, Serialize)]
+pub struct StartupReport {
+    /// Every subsystem is ready
+    pub ready: bool,
+    pub subsystems: Vec<SubsystemReport>,
+}
+
+pub struct StartupTracker {
+    started: Instant,
+    subsystems: Mutex<Vec<SubsystemReport>>,
+}
+
+impl StartupTracker {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            subsystems: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn elapsed_ms(&self, since: Instant) -> u64 {
+        since.elapsed().as_millis() as u64
+    }
+
+    /// Runs the initialization of a subsystem and records its progress
+    ///
+    /// # Arguments
+    /// * `name` - Subsystem name shown in the report
+    /// * `init` - Initialization, failing with a displayable error
+    pub async fn track<T, E: Display>(
+        &self,
+        name: &'static str,
+        init: impl Future<Output = Result<T, E>>,
+    ) -> Result<T, E> {
+        let begun = Instant::now();
+        let index = {
+            let mut subsystems = self
+                .subsystems
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            subsystems.push(SubsystemReport {
+                name,
+                status: SubsystemStatus::Starting,
+                started_ms: self.elapsed_ms(self.started),
+                took_ms: None,
+                error: None,
+            });
+            subsystems.len() - 1
+        };
+        let result = init.await;
+        let took_ms = self.elapsed_ms(begun);
+        match &result {
+            Ok(_) => info!("{} ready in {}ms", name, took_ms),
+            Err(e) => warn!("{} failed to start after {}ms: {}", name, took_ms, e),
+        }
+        let mut subsystems = self
+            .subsystems
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let report = &mut subsystems[index];
+        report.took_ms = Some(took_ms);
+        match &result {
+            Ok(_) => report.status = SubsystemStatus::Ready,
+            Err(e) => {
+                report.status = SubsystemStatus::Failed;
+                report.error = Some(e.to_string());
+            }
+        }
+        result
+    }
+
+    pub fn report(&self) -> StartupReport {
+        let subsystems = self
+            .subsystems
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .to_owned();
+        StartupReport {
+            ready: subsystems
+                .iter()
+                .all(|s| s.status == SubsystemStatus::Ready),
+            subsystems,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, time::Duration};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn tracks_concurrent_subsystems() {
+        let tracker = StartupTracker::new();
+        let slow = tracker.track("slow", async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Ok::<_, Infallible>(())
+        });
+        let fast = tracker.track("fast", async { Ok::<_, Infallible>(()) });
+        let failing = tracker.track("failing", async { Err::<(), _>("no state file") });
+        let (_, _, failed) = tokio::join!(slow, fast, failing);
+        assert!(failed.is_err());
+
+        let report = tracker.report();
+        assert!(!report.ready);
+        let get = |name| report.subsystems.iter().find(|s| s.name == name).unwrap();
+        assert_eq!(get("slow").status, SubsystemStatus::Ready);
+        assert!(get("slow").took_ms.unwrap() >= 50);
+        // the fast subsystem did not wait for the slow one
+        assert!(get("fast").took_ms.unwrap() < 50);
+        assert_eq!(get("failing").error.as_deref(), Some("no state file"));
+    }
+}
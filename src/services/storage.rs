@@ -0,0 +1,376 @@
+//! Persistence of the cookie pool
+//!
+//! The cookie actor hands every snapshot of its pool to a [`CookieStore`].
+//! With `storage = "file"` the pool is part of the config file, with
+//! `storage = "sqlite"` it lives in `clewdr.db` next to the config file and
+//! each snapshot is written in one transaction. The first SQLite run imports
+//! the cookies found in the config file, after that the file is saved without
+//! them.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, LazyLock,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use async_trait::async_trait;
+use rusqlite::{Connection, OptionalExtension, params};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{error, info};
+
+use crate::{
+    config::{
+        CLEWDR_CONFIG, CONFIG_PATH, ClewdrConfig, CookieStatus, StorageBackend, UselessCookie,
+    },
+    error::ClewdrError,
+};
+
+static DB_PATH: LazyLock<PathBuf> = LazyLock::new(|| {
+    CONFIG_PATH
+        .parent()
+        .map(|p| p.join("clewdr.db"))
+        .unwrap_or_else(|| PathBuf::from("clewdr.db"))
+});
+
+/// Set once the SQLite store holds the pool
+static POOL_IN_DATABASE: AtomicBool = AtomicBool::new(false);
+
+/// Whether the running pool is kept in SQLite, so the config file leaves it out
+pub fn pool_in_database() -> bool {
+    POOL_IN_DATABASE.load(Ordering::Relaxed)
+}
+
+/// Every cookie the actor knows about
+#[derive(Debug, Clone, Default)]
+pub struct StoredCookies {
+    /// Valid and cooling down cookies, with their tokens and usage
+    pub cookies: HashSet<CookieStatus>,
+    pub invalid: HashSet<UselessCookie>,
+}
+
+/// Backend persisting the cookie pool
+#[async_trait]
+pub trait CookieStore: Send + Sync {
+    /// Loads the stored pool
+    async fn load(&self) -> Result<StoredCookies, ClewdrError>;
+    /// Replaces the stored pool with a snapshot
+    async fn save(&self, snapshot: StoredCookies) -> Result<(), ClewdrError>;
+}
+
+/// Keeps the pool in the config file
+pub struct FileStore;
+
+#[async_trait]
+impl CookieStore for FileStore {
+    async fn load(&self) -> Result<StoredCookies, ClewdrError> {
+        let config = CLEWDR_CONFIG.load();
+        Ok(StoredCookies {
+            cookies: config.cookie_array.to_owned(),
+            invalid: config.wasted_cookie.to_owned(),
+        })
+    }
+
+    async fn save(&self, _snapshot: StoredCookies) -> Result<(), ClewdrError> {
+        // the cookie actor already put the snapshot into the live config
+        CLEWDR_CONFIG.load().save().await
+    }
+}
+
+/// Keeps the pool in a SQLite database
+pub struct SqliteStore {
+    path: PathBuf,
+    /// Pool imported on the first run
+    legacy: StoredCookies,
+}
+
+impl SqliteStore {
+    /// Creates a store backed by the database at `path`
+    ///
+    /// # Arguments
+    /// * `path` - Database file, created on first use
+    /// * `legacy` - Cookies to import if the database was never migrated
+    pub fn new(path: impl Into<PathBuf>, legacy: StoredCookies) -> Self {
+        Self {
+            path: path.into(),
+            legacy,
+        }
+    }
+
+    async fn blocking<T, F>(&self, f: F) -> Result<T, ClewdrError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T, ClewdrError> + Send + 'static,
+    {
+        let path = self.path.to_owned();
+        tokio::task::spawn_blocking(move || f(&mut open(&path)?))
+            .await
+            .map_err(|e| ClewdrError::Whatever {
+                message: "SQLite task failed".into(),
+                source: Some(Box::new(e)),
+            })?
+    }
+}
+
+#[async_trait]
+impl CookieStore for SqliteStore {
+    async fn load(&self) -> Result<StoredCookies, ClewdrError> {
+        let legacy = self.legacy.to_owned();
+        let snapshot = self
+            .blocking(move |conn| {
+                let migrated = conn
+                    .query_row("SELECT value FROM meta WHERE key = 'migrated'", [], |row| {
+                        row.get::<_, String>(0)
+                    })
+                    .optional()?
+                    .is_some();
+                if !migrated {
+                    let tx = conn.transaction()?;
+                    write_snapshot(&tx, &legacy)?;
+                    tx.execute(
+                        "INSERT INTO meta (key, value) VALUES ('migrated', ?1)",
+                        params![chrono::Utc::now().to_rfc3339()],
+                    )?;
+                    tx.commit()?;
+                    info!(
+                        "Imported {} cookies from the config file into SQLite",
+                        legacy.cookies.len() + legacy.invalid.len()
+                    );
+                }
+                read_snapshot(conn)
+            })
+            .await?;
+        // imported, the cookies in the config file are no longer needed
+        POOL_IN_DATABASE.store(true, Ordering::Relaxed);
+        Ok(snapshot)
+    }
+
+    async fn save(&self, snapshot: StoredCookies) -> Result<(), ClewdrError> {
+        self.blocking(move |conn| {
+            let tx = conn.transaction()?;
+            write_snapshot(&tx, &snapshot)?;
+            Ok(tx.commit()?)
+        })
+        .await
+    }
+}
+
+fn open(path: &Path) -> Result<Connection, ClewdrError> {
+    if let Some(parent) = path.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        std::fs::create_dir_all(parent)?;
+    }
+    let conn = Connection::open(path)?;
+    // the journal mode pragma answers with a row
+    conn.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))?;
+    conn.execute_batch(
+        "PRAGMA synchronous = FULL;
+        CREATE TABLE IF NOT EXISTS cookies (
+            cookie TEXT PRIMARY KEY,
+            status TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS invalid_cookies (
+            cookie TEXT PRIMARY KEY,
+            status TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);",
+    )?;
+    Ok(conn)
+}
+
+fn write_snapshot(conn: &Connection, snapshot: &StoredCookies) -> Result<(), ClewdrError> {
+    conn.execute("DELETE FROM cookies", [])?;
+    conn.execute("DELETE FROM invalid_cookies", [])?;
+    let mut insert = conn.prepare("INSERT INTO cookies (cookie, status) VALUES (?1, ?2)")?;
+    for cookie in snapshot.cookies.iter() {
+        insert.execute(params![
+            cookie.cookie.to_string(),
+            serde_json::to_string(cookie)?
+        ])?;
+    }
+    let mut insert =
+        conn.prepare("INSERT INTO invalid_cookies (cookie, status) VALUES (?1, ?2)")?;
+    for cookie in snapshot.invalid.iter() {
+        insert.execute(params![
+            cookie.cookie.to_string(),
+            serde_json::to_string(cookie)?
+        ])?;
+    }
+    Ok(())
+}
+
+fn read_snapshot(conn: &Connection) -> Result<StoredCookies, ClewdrError> {
+    let mut snapshot = StoredCookies::default();
+    let mut select = conn.prepare("SELECT status FROM cookies")?;
+    for status in select.query_map([], |row| row.get::<_, String>(0))? {
+        snapshot.cookies.insert(serde_json::from_str(&status?)?);
+    }
+    let mut select = conn.prepare("SELECT status FROM invalid_cookies")?;
+    for status in select.query_map([], |row| row.get::<_, String>(0))? {
+        snapshot.invalid.insert(serde_json::from_str(&status?)?);
+    }
+    Ok(snapshot)
+}
+
+/// Builds the store selected by `storage`
+pub fn cookie_store(config: &ClewdrConfig) -> Arc<dyn CookieStore> {
+    match config.storage {
+        // demo and no_fs instances never write, the file store honours both
+        StorageBackend::Sqlite if !config.no_fs && !config.demo => Arc::new(SqliteStore::new(
+            DB_PATH.as_path(),
+            StoredCookies {
+                cookies: config.cookie_array.to_owned(),
+                invalid: config.wasted_cookie.to_owned(),
+            },
+        )),
+        _ => Arc::new(FileStore),
+    }
+}
+
+/// Work for the cookie store writer
+#[derive(Debug)]
+pub enum StoreWrite {
+    /// Save a snapshot of the pool
+    Save(StoredCookies),
+    /// Answer once every snapshot sent before is saved
+    Flush(oneshot::Sender<()>),
+}
+
+/// Starts a writer saving snapshots one at a time, in the order they are sent
+pub fn spawn_writer(store: Arc<dyn CookieStore>) -> mpsc::UnboundedSender<StoreWrite> {
+    let (tx, mut rx) = mpsc::unbounded_channel::<StoreWrite>();
+    tokio::spawn(async move {
+        while let Some(first) = rx.recv().await {
+            // only the latest snapshot of a burst matters
+            let mut latest = None;
+            let mut flushed = vec![];
+            let mut take = |write| match write {
+                StoreWrite::Save(snapshot) => latest = Some(snapshot),
+                StoreWrite::Flush(done) => flushed.push(done),
+            };
+            take(first);
+            while let Ok(newer) = rx.try_recv() {
+                take(newer);
+            }
+            if let Some(snapshot) = latest {
+                match store.save(snapshot).await {
+                    Ok(_) => info!("Cookies saved successfully"),
+                    Err(e) => error!("Failed to save cookies: {}", e),
+                }
+            }
+            for done in flushed {
+                let _ = done.send(());
+            }
+        }
+    });
+    tx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Reason;
+
+    fn cookie(i: usize) -> CookieStatus {
+        let body = format!("{:0<86}", format!("store-test-{i:02}-"));
+        CookieStatus::new(&format!("sk-ant-sid01-{body}-{i:06}AA"), None).unwrap()
+    }
+
+    fn temp_db() -> PathBuf {
+        std::env::temp_dir().join(format!("clewdr-store-{}.db", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn config_file_leaves_out_a_pool_kept_elsewhere() {
+        let mut config = toml::from_str::<ClewdrConfig>(r#"password = "client-secret""#).unwrap();
+        config.cookie_array = HashSet::from([cookie(1)]);
+        config.wasted_cookie =
+            HashSet::from([UselessCookie::new(cookie(2).cookie, Reason::Banned)]);
+        let with_pool = config.file_text(true).unwrap();
+        assert!(with_pool.contains("store-test-01") && with_pool.contains("store-test-02"));
+
+        let without = config.file_text(false).unwrap();
+        assert!(!without.contains("store-test"));
+        assert!(without.contains("client-secret"));
+        let read = toml::from_str::<ClewdrConfig>(&without).unwrap();
+        assert!(read.cookie_array.is_empty() && read.wasted_cookie.is_empty());
+    }
+
+    #[tokio::test]
+    async fn migrates_once_then_round_trips() {
+        let path = temp_db();
+        let legacy = StoredCookies {
+            cookies: HashSet::from([cookie(1), cookie(2)]),
+            invalid: HashSet::from([UselessCookie::new(cookie(3).cookie, Reason::Banned)]),
+        };
+        let store = SqliteStore::new(&path, legacy.to_owned());
+        let loaded = store.load().await.unwrap();
+        assert_eq!(loaded.cookies, legacy.cookies);
+        assert_eq!(loaded.invalid.len(), 1);
+
+        let mut with_usage = cookie(1);
+        with_usage.session_usage.total_input_tokens = 42;
+        store
+            .save(StoredCookies {
+                cookies: HashSet::from([with_usage]),
+                invalid: HashSet::new(),
+            })
+            .await
+            .unwrap();
+
+        // a restart must not import the config file again
+        let store = SqliteStore::new(&path, legacy);
+        let loaded = store.load().await.unwrap();
+        assert_eq!(loaded.cookies.len(), 1);
+        let stored = loaded.cookies.iter().next().unwrap();
+        assert_eq!(stored.session_usage.total_input_tokens, 42);
+        assert!(loaded.invalid.is_empty());
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn writer_keeps_latest_snapshot() {
+        let path = temp_db();
+        let store = Arc::new(SqliteStore::new(&path, StoredCookies::default()));
+        store.load().await.unwrap();
+        let writer = spawn_writer(store.clone());
+        for i in 1..=5 {
+            writer
+                .send(StoreWrite::Save(StoredCookies {
+                    cookies: (1..=i).map(cookie).collect(),
+                    invalid: HashSet::new(),
+                }))
+                .unwrap();
+        }
+        drop(writer);
+        for _ in 0..50 {
+            if store.load().await.unwrap().cookies.len() == 5 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert_eq!(store.load().await.unwrap().cookies.len(), 5);
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn flush_waits_for_earlier_snapshots() {
+        let path = temp_db();
+        let store = Arc::new(SqliteStore::new(&path, StoredCookies::default()));
+        store.load().await.unwrap();
+        let writer = spawn_writer(store.clone());
+        let snapshot = StoredCookies {
+            cookies: (1..=3).map(cookie).collect(),
+            invalid: HashSet::new(),
+        };
+        writer.send(StoreWrite::Save(snapshot)).unwrap();
+        let (done, flushed) = oneshot::channel();
+        writer.send(StoreWrite::Flush(done)).unwrap();
+        flushed.await.unwrap();
+        assert_eq!(store.load().await.unwrap().cookies.len(), 3);
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,176 @@
+//! In-process instance of the full router in front of a scripted upstream
+//!
+//! The upstream answers the Anthropic messages API with responses recorded
+//! from it, picked by what the request asks for. Requests to the instance go
+//! through the same router, auth, preprocessing, provider and response layers
+//! as in production; only the Claude Code endpoint is pointed at the upstream
+//! with `rproxy`, and the pool holds one cookie with a token that never
+//! expires. Runner tests drive it over HTTP like a deployed instance.
+
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{LazyLock, Mutex, PoisonError},
+    time::Duration,
+};
+
+use axum::{
+    Json, Router,
+    body::Body,
+    http::{StatusCode, header::CONTENT_TYPE},
+    response::{IntoResponse, Response},
+    routing::post,
+};
+use futures::StreamExt;
+use serde_json::Value;
+use tokio::net::TcpListener;
+use url::Url;
+
+use crate::{
+    config::{CLEWDR_CONFIG, ClewdrConfig, CookieStatus, install_test_config},
+    router::RouterBuilder,
+};
+
+const MESSAGE: &str = include_str!("fixtures/upstream/message.json");
+const STREAM: &str = include_str!("fixtures/upstream/stream.sse");
+const STOP_SEQUENCE_STREAM: &str = include_str!("fixtures/upstream/stop_sequence.sse");
+const TOOL_USE: &str = include_str!("fixtures/upstream/tool_use.json");
+const TOOL_ANSWER: &str = include_str!("fixtures/upstream/tool_answer.json");
+const PROMPT_TOO_LONG: &str = include_str!("fixtures/upstream/prompt_too_long.json");
+const COUNT_TOKENS: &str = include_str!("fixtures/upstream/count_tokens.json");
+
+/// Request bodies past this size get the recorded context window error
+const CONTEXT_WINDOW_BYTES: usize = 1024 * 1024;
+/// Pause between streamed events, so a client can cancel midway
+const EVENT_INTERVAL: Duration = Duration::from_millis(20);
+
+const TEST_COOKIE: &str = r#"
+cookie = "sk-ant-REDACTED"
+session_has_reset = false
+weekly_has_reset = false
+weekly_sonnet_has_reset = false
+weekly_opus_has_reset = false
+
+[token]
+access_token = "test-access-token"
+expires_in = 28800
+refresh_token = "test-refresh-token"
+expires_at = 4102444800.0
+
+[token.organization]
+uuid = "test-org"
+"#;
+
+/// Bodies the upstream received, oldest first
+static RECEIVED: Mutex<Vec<Value>> = Mutex::new(vec![]);
+
+/// Base URL of the instance, started on first use and shared by every test
+static INSTANCE: LazyLock<Url> = LazyLock::new(|| {
+    let (started, url) = std::sync::mpsc::channel();
+    // own runtime, the instance outlives the runtime of any single test
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Runtime::new().expect("test runtime starts");
+        runtime.block_on(async move {
+            let upstream = serve(scripted_upstream()).await;
+            install_test_config();
+            let cookie = toml::from_str::<CookieStatus>(TEST_COOKIE).expect("test cookie parses");
+            CLEWDR_CONFIG.rcu(|config| {
+                let mut config = ClewdrConfig::clone(config);
+                config.rproxy = Some(upstream.to_owned());
+                config.cookie_array.insert(cookie.to_owned());
+                config
+            });
+            let router = RouterBuilder::new()
+                .await
+                .expect("cookie actor starts")
+                .with_default_setup()
+                .build();
+            started
+                .send(serve(router).await)
+                .expect("test waits for the instance");
+            std::future::pending::<()>().await;
+        });
+    });
+    url.recv().expect("test instance starts")
+});
+
+/// Base URL of the shared instance
+pub fn test_instance() -> Url {
+    INSTANCE.to_owned()
+}
+
+/// Bodies the scripted upstream received so far, from every test
+pub fn upstream_requests() -> Vec<Value> {
+    RECEIVED
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .to_owned()
+}
+
+async fn serve(router: Router) -> Url {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let service = router.into_make_service_with_connect_info::<SocketAddr>();
+        axum::serve(listener, service).await.unwrap()
+    });
+    format!("http://{addr}/").parse().unwrap()
+}
+
+fn scripted_upstream() -> Router {
+    Router::new().route("/v1/messages", post(messages)).route(
+        "/v1/messages/count_tokens",
+        post(|| async { recorded(StatusCode::OK, COUNT_TOKENS) }),
+    )
+}
+
+/// Answers like the messages API did for a request of the same kind
+async fn messages(body: String) -> Response {
+    let Ok(params) = serde_json::from_str::<Value>(&body) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    RECEIVED
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push(params.to_owned());
+    let has_tool_result = params["messages"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|m| m["content"].as_array())
+        .flatten()
+        .any(|block| block["type"] == "tool_result");
+    if body.len() > CONTEXT_WINDOW_BYTES {
+        recorded(StatusCode::BAD_REQUEST, PROMPT_TOO_LONG)
+    } else if has_tool_result {
+        recorded(StatusCode::OK, TOOL_ANSWER)
+    } else if params["tool_choice"]["type"] == "tool" {
+        recorded(StatusCode::OK, TOOL_USE)
+    } else if params["stream"] == true && params["stop_sequences"].is_array() {
+        replay(STOP_SEQUENCE_STREAM)
+    } else if params["stream"] == true {
+        replay(STREAM)
+    } else {
+        recorded(StatusCode::OK, MESSAGE)
+    }
+}
+
+fn recorded(status: StatusCode, body: &'static str) -> Response {
+    let value = serde_json::from_str::<Value>(body).expect("fixture is JSON");
+    (status, Json(value)).into_response()
+}
+
+/// Streams a recorded event stream one event at a time
+fn replay(events: &'static str) -> Response {
+    let events = events
+        .split_inclusive("\n\n")
+        .map(|event| Ok::<_, Infallible>(event.to_string()));
+    let stream = futures::stream::iter(events).then(|event| async move {
+        tokio::time::sleep(EVENT_INTERVAL).await;
+        event
+    });
+    Response::builder()
+        .header(CONTENT_TYPE, "text/event-stream")
+        .body(Body::from_stream(stream))
+        .unwrap()
+}
@@ -0,0 +1,416 @@
+//! Per IP rate limiting of the message and auth endpoints
+//!
+//! Each client address has a token bucket for authenticated requests and one
+//! for the rest. A request finding its bucket empty gets 429, and an address
+//! rejected `block_after` times within `violation_window_secs` is blocked for
+//! `block_secs`. Limits are read from the live config on every request.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{LazyLock, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use http::header::AUTHORIZATION;
+use serde::Serialize;
+use tracing::warn;
+
+use crate::{
+    config::{BucketLimit, CLEWDR_CONFIG, ClewdrConfig, KeyEndpoint, RateLimitConfig},
+    error::ClewdrError,
+};
+
+/// Tracked addresses past which idle ones are forgotten
+const PRUNE_AT: usize = 4096;
+
+static CLIENTS: LazyLock<Mutex<HashMap<IpAddr, Client>>> = LazyLock::new(Default::default);
+
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl Bucket {
+    fn new(limit: BucketLimit, now: Instant) -> Self {
+        Self {
+            tokens: limit.burst.into(),
+            refilled: now,
+        }
+    }
+
+    fn refill(&mut self, limit: BucketLimit, now: Instant) {
+        let regained =
+            now.duration_since(self.refilled).as_secs_f64() * f64::from(limit.per_minute) / 60.0;
+        self.tokens = (self.tokens + regained).min(limit.burst.into());
+        self.refilled = now;
+    }
+
+    /// Takes a token, or tells how long until one is regained
+    fn take(&mut self, limit: BucketLimit, now: Instant) -> Result<(), Duration> {
+        self.refill(limit, now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+        if limit.per_minute == 0 || limit.burst == 0 {
+            return Err(Duration::from_secs(60));
+        }
+        let missing = 1.0 - self.tokens;
+        Err(Duration::from_secs_f64(
+            missing * 60.0 / f64::from(limit.per_minute),
+        ))
+    }
+
+    fn is_full(&mut self, limit: BucketLimit, now: Instant) -> bool {
+        self.refill(limit, now);
+        self.tokens >= f64::from(limit.burst)
+    }
+}
+
+struct Client {
+    authenticated: Bucket,
+    unauthenticated: Bucket,
+    /// Rejections since `window_start`
+    violations: u32,
+    window_start: Instant,
+    blocked_until: Option<Instant>,
+    /// Rejections since the address was first limited
+    rejected: u64,
+    /// Unix timestamp of the latest rejection
+    last_rejected: Option<i64>,
+}
+
+impl Client {
+    fn new(config: &RateLimitConfig, now: Instant) -> Self {
+        Self {
+            authenticated: Bucket::new(config.authenticated, now),
+            unauthenticated: Bucket::new(config.unauthenticated, now),
+            violations: 0,
+            window_start: now,
+            blocked_until: None,
+            rejected: 0,
+            last_rejected: None,
+        }
+    }
+
+    /// Whether forgetting the client changes nothing but its counters
+    fn is_idle(&mut self, config: &RateLimitConfig, now: Instant) -> bool {
+        self.blocked_until.is_none_or(|until| until <= now)
+            && now.duration_since(self.window_start).as_secs() >= config.violation_window_secs
+            && self.authenticated.is_full(config.authenticated, now)
+            && self.unauthenticated.is_full(config.unauthenticated, now)
+    }
+}
+
+/// Outcome of the limiter for one request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Allowed,
+    /// The bucket of the client is empty
+    Limited {
+        retry_after: Duration,
+    },
+    /// The client is blocked for repeated violations
+    Blocked {
+        retry_after: Duration,
+    },
+}
+
+fn check_in(
+    clients: &mut HashMap<IpAddr, Client>,
+    config: &RateLimitConfig,
+    ip: IpAddr,
+    authenticated: bool,
+    now: Instant,
+) -> Verdict {
+    if clients.len() >= PRUNE_AT {
+        clients.retain(|_, client| !client.is_idle(config, now));
+    }
+    let client = clients
+        .entry(ip)
+        .or_insert_with(|| Client::new(config, now));
+    if let Some(until) = client.blocked_until {
+        if until > now {
+            return Verdict::Blocked {
+                retry_after: until - now,
+            };
+        }
+        client.blocked_until = None;
+    }
+    let taken = if authenticated {
+        client.authenticated.take(config.authenticated, now)
+    } else {
+        client.unauthenticated.take(config.unauthenticated, now)
+    };
+    let Err(retry_after) = taken else {
+        return Verdict::Allowed;
+    };
+
+    client.rejected += 1;
+    client.last_rejected = Some(Utc::now().timestamp());
+    if now.duration_since(client.window_start).as_secs() >= config.violation_window_secs {
+        client.window_start = now;
+        client.violations = 0;
+    }
+    client.violations += 1;
+    if config.block_after > 0 && client.violations >= config.block_after {
+        let retry_after = Duration::from_secs(config.block_secs);
+        client.blocked_until = Some(now + retry_after);
+        client.violations = 0;
+        warn!(
+            "Blocked {} for {}s after repeated rate limiting",
+            ip, config.block_secs
+        );
+        return Verdict::Blocked { retry_after };
+    }
+    Verdict::Limited { retry_after }
+}
+
+/// Counts a request of `ip` against its limit
+///
+/// # Arguments
+/// * `ip` - Address of the client
+/// * `authenticated` - Whether the request carries a valid password or key
+pub fn check(ip: IpAddr, authenticated: bool) -> Verdict {
+    let config = CLEWDR_CONFIG.load();
+    let mut clients = CLIENTS.lock().unwrap_or_else(PoisonError::into_inner);
+    check_in(
+        &mut clients,
+        &config.rate_limit,
+        ip,
+        authenticated,
+        Instant::now(),
+    )
+}
+
+/// An address that was rate limited since startup or its last reset
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct Offender {
+    pub ip: IpAddr,
+    /// Rejected requests
+    pub rejected: u64,
+    /// Rejections in the current violation window
+    pub violations: u32,
+    /// Seconds left of a block, `None` when not blocked
+    pub blocked_secs: Option<u64>,
+    /// Unix timestamp of the latest rejection
+    pub last_rejected: Option<i64>,
+}
+
+/// Rate limited addresses, most rejected first
+pub fn offenders() -> Vec<Offender> {
+    let now = Instant::now();
+    let clients = CLIENTS.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut offenders = clients
+        .iter()
+        .filter(|(_, c)| c.rejected > 0)
+        .map(|(ip, c)| Offender {
+            ip: *ip,
+            rejected: c.rejected,
+            violations: c.violations,
+            blocked_secs: c
+                .blocked_until
+                .filter(|until| *until > now)
+                .map(|until| (until - now).as_secs().max(1)),
+            last_rejected: c.last_rejected,
+        })
+        .collect::<Vec<_>>();
+    offenders.sort_by(|a, b| b.rejected.cmp(&a.rejected).then(a.ip.cmp(&b.ip)));
+    offenders
+}
+
+/// Forgets the buckets, violations and block of `ip`
+///
+/// # Returns
+/// * `bool` - Whether the address was tracked
+pub fn reset(ip: IpAddr) -> bool {
+    CLIENTS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .remove(&ip.to_canonical())
+        .is_some()
+}
+
+/// Whether a path is one of the limited endpoints
+fn is_limited(path: &str) -> bool {
+    path.starts_with("/api/auth")
+        || path.ends_with("/messages")
+        || path.ends_with("/messages/count_tokens")
+        || path.ends_with("/chat/completions")
+}
+
+/// Whether a request carries the admin password or a key valid for its path
+fn is_authenticated(req: &Request, config: &ClewdrConfig) -> bool {
+    let bearer = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    let api_key = req.headers().get("x-api-key").and_then(|v| v.to_str().ok());
+    let endpoint = KeyEndpoint::of_path(req.uri().path());
+    [bearer, api_key]
+        .into_iter()
+        .flatten()
+        .any(|key| config.admin_auth(key) || config.client_auth(key, endpoint).is_ok())
+}
+
+/// Rejects requests of clients over their limit with 429 and `Retry-After`
+///
+/// Requests pass untouched while `rate_limit.enabled` is off, outside the
+/// limited endpoints, or when the connection address is unknown.
+pub async fn limit_clients(req: Request, next: Next) -> Response {
+    let config = CLEWDR_CONFIG.load();
+    if !config.rate_limit.enabled || !is_limited(req.uri().path()) {
+        return next.run(req).await;
+    }
+    let Some(ConnectInfo(peer)) = req.extensions().get::<ConnectInfo<SocketAddr>>() else {
+        return next.run(req).await;
+    };
+    let forwarded_for = req
+        .headers()
+        .get("x-forwarded-for")
+        .and_then(|v| v.to_str().ok());
+    let ip = config.rate_limit.client_ip(peer.ip(), forwarded_for);
+    let (retry_after, blocked) = match check(ip, is_authenticated(&req, &config)) {
+        Verdict::Allowed => return next.run(req).await,
+        Verdict::Limited { retry_after } => (retry_after, false),
+        Verdict::Blocked { retry_after } => (retry_after, true),
+    };
+    ClewdrError::RateLimited {
+        retry_after_secs: retry_after.as_secs_f64().ceil().max(1.0) as u64,
+        blocked,
+    }
+    .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> RateLimitConfig {
+        RateLimitConfig {
+            enabled: true,
+            authenticated: BucketLimit {
+                burst: 3,
+                per_minute: 60,
+            },
+            unauthenticated: BucketLimit {
+                burst: 1,
+                per_minute: 6,
+            },
+            block_after: 3,
+            violation_window_secs: 60,
+            block_secs: 300,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn buckets_refill_per_tier() {
+        let config = config();
+        let mut clients = HashMap::new();
+        let ip = "203.0.113.5".parse().unwrap();
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(
+                check_in(&mut clients, &config, ip, true, start),
+                Verdict::Allowed
+            );
+        }
+        assert_eq!(
+            check_in(&mut clients, &config, ip, true, start),
+            Verdict::Limited {
+                retry_after: Duration::from_secs(1)
+            }
+        );
+        // the unauthenticated bucket is separate
+        assert_eq!(
+            check_in(&mut clients, &config, ip, false, start),
+            Verdict::Allowed
+        );
+        let later = start + Duration::from_secs(2);
+        assert_eq!(
+            check_in(&mut clients, &config, ip, true, later),
+            Verdict::Allowed
+        );
+        assert_eq!(clients[&ip].rejected, 1);
+    }
+
+    #[test]
+    fn repeated_violations_block() {
+        let config = config();
+        let mut clients = HashMap::new();
+        let ip = "203.0.113.6".parse().unwrap();
+        let start = Instant::now();
+        assert_eq!(
+            check_in(&mut clients, &config, ip, false, start),
+            Verdict::Allowed
+        );
+        for _ in 0..2 {
+            assert!(matches!(
+                check_in(&mut clients, &config, ip, false, start),
+                Verdict::Limited { .. }
+            ));
+        }
+        let blocked = Verdict::Blocked {
+            retry_after: Duration::from_secs(300),
+        };
+        assert_eq!(check_in(&mut clients, &config, ip, false, start), blocked);
+        // blocked even with a full bucket, until the block ends
+        let refilled = start + Duration::from_secs(120);
+        assert!(matches!(
+            check_in(&mut clients, &config, ip, true, refilled),
+            Verdict::Blocked { .. }
+        ));
+        let after = start + Duration::from_secs(301);
+        assert_eq!(
+            check_in(&mut clients, &config, ip, true, after),
+            Verdict::Allowed
+        );
+    }
+
+    #[test]
+    fn idle_clients_are_pruned() {
+        let config = config();
+        let mut clients = HashMap::new();
+        let start = Instant::now();
+        for i in 0..PRUNE_AT as u32 {
+            let ip = IpAddr::from(i.to_be_bytes());
+            check_in(&mut clients, &config, ip, false, start);
+        }
+        let later = start + Duration::from_secs(600);
+        check_in(
+            &mut clients,
+            &config,
+            "203.0.113.7".parse().unwrap(),
+            false,
+            later,
+        );
+        assert_eq!(clients.len(), 1);
+    }
+
+    #[test]
+    fn limited_paths() {
+        for path in [
+            "/v1/messages",
+            "/code/v1/messages",
+            "/code/v1/messages/count_tokens",
+            "/v1/chat/completions",
+            "/code/v1/chat/completions",
+            "/api/auth",
+            "/api/auth/rotate",
+        ] {
+            assert!(is_limited(path), "{path}");
+        }
+        for path in ["/v1/models", "/api/version", "/healthz", "/api/cookies"] {
+            assert!(!is_limited(path), "{path}");
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//! Background refresh of Claude Code OAuth tokens
+//!
+//! Tokens are refreshed once they enter the expiry window of
+//! [`TokenInfo::is_expired`] rather than by the first request that finds them
+//! expired. Background and on-demand refreshes share one lock per cookie, so a
+//! refresh token is only spent once.
+
+use std::{
+    sync::{Arc, LazyLock},
+    time::Duration,
+};
+
+use moka::sync::Cache;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::{
+    claude_code_state::ClaudeCodeState, config::TokenInfo,
+    services::cookie_actor::CookieActorHandle,
+};
+
+const SCAN_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Refresh locks keyed by cookie
+static TOKEN_LOCKS: LazyLock<Cache<String, Arc<Mutex<()>>>> = LazyLock::new(|| {
+    Cache::builder()
+        .max_capacity(1000)
+        .time_to_idle(Duration::from_secs(60 * 60))
+        .build()
+});
+
+/// Tokens issued by the latest refresh of each cookie
+///
+/// A task that waited for the lock holds the token the winner just spent, it
+/// picks the new one up from here instead of refreshing again.
+static REFRESHED: LazyLock<Cache<String, TokenInfo>> = LazyLock::new(|| {
+    Cache::builder()
+        .max_capacity(1000)
+        .time_to_live(Duration::from_secs(60 * 60))
+        .build()
+});
+
+/// Returns the refresh lock of a cookie
+pub fn token_lock(cookie: &str) -> Arc<Mutex<()>> {
+    TOKEN_LOCKS.get_with(cookie.to_string(), Default::default)
+}
+
+/// Records a freshly issued token for tasks waiting on the same cookie
+pub fn remember_token(cookie: &str, token: &TokenInfo) {
+    REFRESHED.insert(cookie.to_string(), token.to_owned());
+}
+
+/// Returns the latest refreshed token of a cookie if it is still fresh
+pub fn refreshed_token(cookie: &str) -> Option<TokenInfo> {
+    REFRESHED.get(cookie).filter(|t| !t.is_expired())
+}
+
+/// Starts scanning valid cookies every minute and refreshing tokens close to expiry
+///
+/// # Arguments
+/// * `handle` - Handle of the cookie actor holding the tokens
+pub fn init_token_refresh(handle: CookieActorHandle) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SCAN_INTERVAL);
+        loop {
+            interval.tick().await;
+            let status = match handle.get_status().await {
+                Ok(status) => status,
+                Err(e) => {
+                    warn!("Token refresh scan failed: {}", e);
+                    continue;
+                }
+            };
+            for cookie in status.valid {
+                if !cookie.token.as_ref().is_some_and(TokenInfo::is_expired) {
+                    continue;
+                }
+                let handle = handle.to_owned();
+                tokio::spawn(async move {
+                    let Ok(mut state) = ClaudeCodeState::from_cookie(handle, cookie) else {
+                        return;
+                    };
+                    match state.refresh_token().await {
+                        Ok(_) => {
+                            info!("Refreshed Claude Code token ahead of expiry");
+                            state.return_cookie(None).await;
+                        }
+                        Err(e) => warn!("Background token refresh failed: {}", e),
+                    }
+                });
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::config::Organization;
+
+    fn token(expires_in: Duration) -> TokenInfo {
+        TokenInfo {
+            access_token: "access".to_string(),
+            expires_in,
+            organization: Organization {
+                uuid: "org".to_string(),
+            },
+            refresh_token: "refresh".to_string(),
+            expires_at: Utc::now() + expires_in,
+        }
+    }
+
+    #[test]
+    fn waiters_reuse_only_fresh_tokens() {
+        remember_token("fresh-cookie", &token(Duration::from_secs(60 * 60)));
+        remember_token("stale-cookie", &token(Duration::from_secs(60)));
+        assert!(refreshed_token("fresh-cookie").is_some());
+        assert!(refreshed_token("stale-cookie").is_none());
+        assert!(refreshed_token("unknown-cookie").is_none());
+    }
+
+    #[tokio::test]
+    async fn one_lock_per_cookie() {
+        let lock = token_lock("locked-cookie");
+        let _guard = lock.lock().await;
+        assert!(token_lock("locked-cookie").try_lock().is_err());
+        assert!(token_lock("other-cookie").try_lock().is_ok());
+    }
+}
@@ -0,0 +1,495 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        LazyLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Instant,
+};
+
+use axum::{body::Body, response::Response};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+use tracing::{error, warn};
+
+use crate::{
+    config::{CLEWDR_CONFIG, CONFIG_PATH, CookieStatus},
+    error::ClewdrError,
+    services::resources::{FnReporter, ResourceUsage, register_reporter},
+    types::claude::CreateMessageParams,
+};
+
+/// Directory of the transcript store, next to the config file
+static TRANSCRIPT_DIR: LazyLock<PathBuf> = LazyLock::new(|| {
+    CONFIG_PATH
+        .parent()
+        .map(|p| p.join("transcripts"))
+        .unwrap_or_else(|| PathBuf::from("transcripts"))
+});
+
+/// Serializes writes so eviction never races with another write
+static WRITE_LOCK: Mutex<()> = Mutex::const_new(());
+
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+/// Bytes and count of response bodies being teed
+struct TeeBuffers {
+    bytes: AtomicU64,
+    entries: AtomicU64,
+}
+
+static TEE_BUFFERS: LazyLock<TeeBuffers> = LazyLock::new(|| {
+    register_reporter(FnReporter::new("transcript_buffers", || ResourceUsage {
+        bytes: Some(TEE_BUFFERS.bytes.load(Ordering::Relaxed)),
+        entries: TEE_BUFFERS.entries.load(Ordering::Relaxed),
+    }));
+    TeeBuffers {
+        bytes: AtomicU64::new(0),
+        entries: AtomicU64::new(0),
+    }
+});
+
+/// A recorded request/response exchange
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transcript {
+    pub id: String,
+    /// Unix timestamp in seconds
+    pub timestamp: i64,
+    pub endpoint: String,
+    pub model: String,
+    /// Truncated sha256 of the cookie that served the request
+    pub cookie_hash: Option<String>,
+    pub stream: bool,
+    /// HTTP status, missing when the request failed before a response
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    pub latency_ms: u64,
+    pub request: Value,
+    /// Response body, or the concatenated text of a stream
+    pub response: String,
+}
+
+/// Transcript without request and response bodies, used for listing
+///
+/// Written next to each transcript as `{id}.summary.json` so listing never
+/// reads the bodies.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptSummary {
+    pub id: String,
+    pub timestamp: i64,
+    pub endpoint: String,
+    pub model: String,
+    pub cookie_hash: Option<String>,
+    pub stream: bool,
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    pub latency_ms: u64,
+    pub response_bytes: usize,
+}
+
+impl From<&Transcript> for TranscriptSummary {
+    fn from(t: &Transcript) -> Self {
+        Self {
+            response_bytes: t.response.len(),
+            id: t.id.to_owned(),
+            timestamp: t.timestamp,
+            endpoint: t.endpoint.to_owned(),
+            model: t.model.to_owned(),
+            cookie_hash: t.cookie_hash.to_owned(),
+            stream: t.stream,
+            status: t.status,
+            error: t.error.to_owned(),
+            latency_ms: t.latency_ms,
+        }
+    }
+}
+
+/// Whether transcripts should be recorded
+pub fn transcripts_enabled() -> bool {
+    let config = CLEWDR_CONFIG.load();
+    config.record_transcripts && !config.no_fs
+}
+
+/// Truncated sha256 of a cookie, safe to show
+pub fn hash_cookie(cookie: &CookieStatus) -> String {
+    let digest = Sha256::digest(cookie.cookie.to_string());
+    hex::encode(&digest[..8])
+}
+
+/// Concatenates the text deltas of a Claude or Claude.ai event stream
+fn stream_text(raw: &str) -> String {
+    raw.lines()
+        .filter_map(|line| line.strip_prefix("data:"))
+        .filter_map(|data| serde_json::from_str::<Value>(data.trim()).ok())
+        .filter_map(|event| {
+            event["delta"]["text"]
+                .as_str()
+                .or_else(|| event["completion"].as_str())
+                .map(str::to_string)
+        })
+        .collect()
+}
+
+/// Captures one exchange from the provider side
+pub struct TranscriptRecorder {
+    transcript: Transcript,
+    start: Instant,
+}
+
+impl TranscriptRecorder {
+    /// Starts recording, returns `None` when recording is disabled
+    pub fn start(endpoint: &str, params: &CreateMessageParams) -> Option<Self> {
+        if !transcripts_enabled() {
+            return None;
+        }
+        let now = chrono::Utc::now();
+        let seq = SEQUENCE.fetch_add(1, Ordering::Relaxed) % 10_000;
+        let transcript = Transcript {
+            id: format!("{:013}-{:04}", now.timestamp_millis(), seq),
+            timestamp: now.timestamp(),
+            endpoint: endpoint.to_string(),
+            model: params.model.to_owned(),
+            cookie_hash: None,
+            stream: params.stream.unwrap_or_default(),
+            status: None,
+            error: None,
+            latency_ms: 0,
+            request: serde_json::to_value(params).unwrap_or_default(),
+            response: String::new(),
+        };
+        Some(Self {
+            transcript,
+            start: Instant::now(),
+        })
+    }
+
+    /// Finishes recording with the provider result
+    ///
+    /// Successful responses are teed, the transcript is written once the
+    /// client has consumed the body, so recording never delays the client.
+    pub fn finish(
+        mut self,
+        cookie: Option<&CookieStatus>,
+        result: Result<Response, ClewdrError>,
+    ) -> Result<Response, ClewdrError> {
+        self.transcript.cookie_hash = cookie.map(hash_cookie);
+        let resp = match result {
+            Ok(resp) => resp,
+            Err(e) => {
+                self.transcript.error = Some(e.to_string());
+                self.transcript.latency_ms = self.start.elapsed().as_millis() as u64;
+                record(self.transcript);
+                return Err(e);
+            }
+        };
+        self.transcript.status = Some(resp.status().as_u16());
+        let (parts, body) = resp.into_parts();
+        TEE_BUFFERS.entries.fetch_add(1, Ordering::Relaxed);
+        let mut tee = Tee {
+            transcript: Some(self.transcript),
+            start: self.start,
+            buf: Vec::new(),
+        };
+        let stream = body.into_data_stream().map(move |chunk| {
+            if let Ok(ref bytes) = chunk {
+                tee.buf.extend_from_slice(bytes);
+                TEE_BUFFERS
+                    .bytes
+                    .fetch_add(bytes.len() as u64, Ordering::Relaxed);
+            }
+            chunk
+        });
+        Ok(Response::from_parts(parts, Body::from_stream(stream)))
+    }
+}
+
+/// Accumulates the body as it is forwarded, writes the transcript on drop
+struct Tee {
+    transcript: Option<Transcript>,
+    start: Instant,
+    buf: Vec<u8>,
+}
+
+impl Drop for Tee {
+    fn drop(&mut self) {
+        let Some(mut transcript) = self.transcript.take() else {
+            return;
+        };
+        TEE_BUFFERS.entries.fetch_sub(1, Ordering::Relaxed);
+        TEE_BUFFERS
+            .bytes
+            .fetch_sub(self.buf.len() as u64, Ordering::Relaxed);
+        let raw = String::from_utf8_lossy(&self.buf);
+        transcript.response = if transcript.stream {
+            stream_text(&raw)
+        } else {
+            raw.into_owned()
+        };
+        transcript.latency_ms = self.start.elapsed().as_millis() as u64;
+        record(transcript);
+    }
+}
+
+/// Writes a transcript in the background and evicts the oldest records
+pub fn record(transcript: Transcript) {
+    let Ok(handle) = tokio::runtime::Handle::try_current() else {
+        return;
+    };
+    handle.spawn(async move {
+        let _lock = WRITE_LOCK.lock().await;
+        let cap = CLEWDR_CONFIG.load().transcript_max_mb * 1024 * 1024;
+        if let Err(e) = write_and_evict(&TRANSCRIPT_DIR, &transcript, cap).await {
+            error!("Failed to record transcript {}: {}", transcript.id, e);
+        }
+    });
+}
+
+fn transcript_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{id}.json"))
+}
+
+fn summary_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{id}.summary.json"))
+}
+
+/// Removes a transcript and its summary
+///
+/// # Returns
+/// * `Result<(), std::io::Error>` - Error removing the transcript, a missing summary is ignored
+async fn remove(dir: &Path, id: &str) -> Result<(), std::io::Error> {
+    tokio::fs::remove_file(transcript_path(dir, id)).await?;
+    match tokio::fs::remove_file(summary_path(dir, id)).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            warn!("Failed to remove transcript summary {}: {}", id, e);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+async fn write_and_evict(dir: &Path, transcript: &Transcript, cap: u64) -> Result<(), ClewdrError> {
+    tokio::fs::create_dir_all(dir).await?;
+    tokio::fs::write(
+        transcript_path(dir, &transcript.id),
+        serde_json::to_vec(transcript)?,
+    )
+    .await?;
+    // the transcript goes first, a summary never points at a missing file
+    tokio::fs::write(
+        summary_path(dir, &transcript.id),
+        serde_json::to_vec(&TranscriptSummary::from(transcript))?,
+    )
+    .await?;
+
+    let mut files = list_files(dir).await?;
+    let mut total = files.iter().map(|(_, size)| size).sum::<u64>();
+    // oldest first, ids sort chronologically
+    files.sort();
+    for (id, size) in files {
+        if total <= cap {
+            break;
+        }
+        if let Err(e) = remove(dir, &id).await {
+            warn!("Failed to evict transcript {}: {}", id, e);
+            continue;
+        }
+        total -= size;
+    }
+    Ok(())
+}
+
+/// Lists `(id, size)` of every stored transcript
+///
+/// The size includes the summary. Summaries left without their transcript
+/// are not listed.
+async fn list_files(dir: &Path) -> Result<Vec<(String, u64)>, ClewdrError> {
+    let mut files = HashMap::<String, (u64, bool)>::new();
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let (id, is_transcript) = match name.strip_suffix(".summary.json") {
+            Some(id) => (id, false),
+            None => match name.strip_suffix(".json") {
+                Some(id) => (id, true),
+                None => continue,
+            },
+        };
+        let size = entry.metadata().await.map(|m| m.len()).unwrap_or_default();
+        let file = files.entry(id.to_string()).or_default();
+        file.0 += size;
+        file.1 |= is_transcript;
+    }
+    Ok(files
+        .into_iter()
+        .filter(|(_, (_, is_transcript))| *is_transcript)
+        .map(|(id, (size, _))| (id, size))
+        .collect())
+}
+
+fn valid_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_digit() || c == '-')
+}
+
+/// Lists transcripts, newest first
+///
+/// # Returns
+/// * `(usize, Vec<TranscriptSummary>)` - Total count and the requested page
+pub async fn list_transcripts(
+    limit: usize,
+    offset: usize,
+) -> Result<(usize, Vec<TranscriptSummary>), ClewdrError> {
+    list_in(&TRANSCRIPT_DIR, limit, offset).await
+}
+
+async fn list_in(
+    dir: &Path,
+    limit: usize,
+    offset: usize,
+) -> Result<(usize, Vec<TranscriptSummary>), ClewdrError> {
+    let mut files = list_files(dir).await?;
+    files.sort_by(|a, b| b.0.cmp(&a.0));
+    let total = files.len();
+    let mut page = vec![];
+    for (id, _) in files.into_iter().skip(offset).take(limit) {
+        if let Some(summary) = load_summary(dir, &id).await? {
+            page.push(summary);
+        }
+    }
+    Ok((total, page))
+}
+
+/// Loads the summary of a transcript
+///
+/// Transcripts recorded before summaries were written are loaded in full
+/// instead.
+async fn load_summary(dir: &Path, id: &str) -> Result<Option<TranscriptSummary>, ClewdrError> {
+    match tokio::fs::read(summary_path(dir, id)).await {
+        Ok(bytes) => Ok(serde_json::from_slice(&bytes).ok()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            Ok(load(dir, id).await?.as_ref().map(TranscriptSummary::from))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Loads a single transcript by id
+pub async fn get_transcript(id: &str) -> Result<Option<Transcript>, ClewdrError> {
+    if !valid_id(id) {
+        return Ok(None);
+    }
+    load(&TRANSCRIPT_DIR, id).await
+}
+
+async fn load(dir: &Path, id: &str) -> Result<Option<Transcript>, ClewdrError> {
+    match tokio::fs::read(transcript_path(dir, id)).await {
+        Ok(bytes) => Ok(serde_json::from_slice(&bytes).ok()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Deletes every stored transcript
+///
+/// # Returns
+/// * `usize` - Number of deleted transcripts
+pub async fn purge_transcripts() -> Result<usize, ClewdrError> {
+    let _lock = WRITE_LOCK.lock().await;
+    let files = list_files(&TRANSCRIPT_DIR).await?;
+    let mut deleted = 0;
+    for (id, _) in files {
+        if remove(&TRANSCRIPT_DIR, &id).await.is_ok() {
+            deleted += 1;
+        }
+    }
+    Ok(deleted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concatenates_stream_text() {
+        let raw = "event: content_block_delta\n\
+            data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hel\"}}\n\n\
+            data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"lo\"}}\n\n\
+            data: {\"type\":\"message_stop\"}\n\n\
+            data: {\"completion\":\"!\"}\n\n";
+        assert_eq!(stream_text(raw), "Hello!");
+    }
+
+    #[test]
+    fn rejects_path_like_ids() {
+        assert!(valid_id("1700000000000-0001"));
+        assert!(!valid_id("../clewdr"));
+        assert!(!valid_id(""));
+    }
+
+    fn transcript(id: &str, response: &str) -> Transcript {
+        Transcript {
+            id: id.to_string(),
+            timestamp: 1_700_000_000,
+            endpoint: "/v1/messages".to_string(),
+            model: "claude-sonnet-4-5".to_string(),
+            cookie_hash: None,
+            stream: false,
+            status: Some(200),
+            error: None,
+            latency_ms: 12,
+            request: serde_json::json!({ "messages": [] }),
+            response: response.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn lists_from_summaries() {
+        let dir = std::env::temp_dir().join(format!("clewdr-transcripts-{}", uuid::Uuid::new_v4()));
+        let old = "1700000000000-0001";
+        let new = "1700000000001-0002";
+        write_and_evict(&dir, &transcript(old, "first"), u64::MAX)
+            .await
+            .unwrap();
+        write_and_evict(&dir, &transcript(new, "second"), u64::MAX)
+            .await
+            .unwrap();
+        // a body the listing would fail to parse, it must not be read
+        tokio::fs::write(transcript_path(&dir, new), b"not json")
+            .await
+            .unwrap();
+        let (total, page) = list_in(&dir, 10, 0).await.unwrap();
+        assert_eq!(total, 2);
+        assert_eq!(page[0].id, new);
+        assert_eq!(page[0].response_bytes, "second".len());
+        assert_eq!(page[1].id, old);
+
+        // transcripts written before summaries existed are still listed
+        tokio::fs::remove_file(summary_path(&dir, old))
+            .await
+            .unwrap();
+        let (_, page) = list_in(&dir, 1, 1).await.unwrap();
+        assert_eq!(page[0].id, old);
+        assert_eq!(page[0].response_bytes, "first".len());
+
+        // eviction removes the summary with its transcript
+        let newest = "1700000000002-0003";
+        write_and_evict(&dir, &transcript(newest, "third"), 1)
+            .await
+            .unwrap();
+        let (total, _) = list_in(&dir, 10, 0).await.unwrap();
+        assert_eq!(total, 0);
+        assert!(
+            !tokio::fs::try_exists(summary_path(&dir, new))
+                .await
+                .unwrap()
+        );
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
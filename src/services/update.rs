@@ -55,7 +55,9 @@ impl ClewdrUpdater {
             .unwrap_or("Xerxes-2");
         let repo_name = env!("CARGO_PKG_NAME");
         let policy = wreq::redirect::Policy::default();
-        let client = wreq::Client::builder()
+        let client = CLEWDR_CONFIG
+            .load()
+            .http_client_builder()
             .redirect(policy)
             .build()
             .context(WreqSnafu {
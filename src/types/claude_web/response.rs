@@ -1,7 +1,7 @@
 use async_stream::try_stream;
 use axum::{
     BoxError, Json,
-    response::{IntoResponse, Sse, sse::Event as SseEvent},
+    response::{IntoResponse, sse::Event as SseEvent},
 };
 use bytes::Bytes;
 use eventsource_stream::{EventStream, Eventsource};
@@ -18,7 +18,7 @@ use crate::{
         ContentBlock, CountMessageTokensResponse, CreateMessageParams, CreateMessageResponse,
         Message, Role,
     },
-    utils::print_out_text,
+    utils::{LogOnDrop, print_out_text, sse_response},
 };
 
 /// Merges server-sent events (SSE) from a stream into a single string
@@ -82,12 +82,14 @@ impl ClaudeWebState {
             // Stream through while accumulating completion text; persist usage at end
             let mut input_tokens = self.usage.input_tokens as u64;
             let handle = self.cookie_actor_handle.clone();
-            let cookie = self.cookie.clone();
+            // smoke checks are not counted, skip persisting totals for them
+            let cookie = self.cookie.clone().filter(|_| !self.smoke);
             let enable_precise = crate::config::CLEWDR_CONFIG.load().enable_web_count_tokens;
             let last_params = self.last_params.clone();
             let endpoint = self.endpoint.clone();
             let proxy = self.proxy.clone();
             let client = self.client.clone();
+            let reply = self.reply.clone();
             // try to get precise input tokens via Claude Code count_tokens if enabled
             if crate::config::CLEWDR_CONFIG.load().enable_web_count_tokens
                 && let Some(tokens) = self.try_code_count_tokens().await
@@ -95,8 +97,7 @@ impl ClaudeWebState {
                 input_tokens = tokens as u64;
             }
 
-            let stream = wreq_res
-                .bytes_stream()
+            let stream = LogOnDrop::new(wreq_res.bytes_stream())
                 .eventsource()
                 .map_err(axum::Error::new);
             let stream = try_stream! {
@@ -112,6 +113,7 @@ impl ClaudeWebState {
                     let e = if let Some(retry) = event.retry { e.retry(retry) } else { e };
                     yield e.data(event.data);
                 }
+                reply.record(&acc);
                 // on end of stream, compute output tokens and persist totals
                 if !acc.is_empty() {
                     // Prefer official count_tokens if enabled and possible; else estimate locally
@@ -144,7 +146,12 @@ impl ClaudeWebState {
                                 }
                             })
                             .unwrap_or(crate::config::ModelFamily::Other);
-                        c.add_and_bucket_usage(input_tokens, out, family);
+                        let model = last_params
+                            .as_ref()
+                            .map(|p| p.model.as_str())
+                            .unwrap_or_default();
+                        let cost = crate::config::cost_now(model, crate::config::BilledTokens::uncached(input_tokens, out), true);
+                        c.add_and_bucket_usage(input_tokens, out, family, cost);
                         let _ = handle.return_cookie(c, None).await;
                     }
                 } else if let Some(mut c) = cookie.clone() {
@@ -163,21 +170,25 @@ impl ClaudeWebState {
                             }
                         })
                         .unwrap_or(crate::config::ModelFamily::Other);
-                    c.add_and_bucket_usage(input_tokens, 0, family);
+                    let model = last_params
+                        .as_ref()
+                        .map(|p| p.model.as_str())
+                        .unwrap_or_default();
+                    let cost = crate::config::cost_now(model, crate::config::BilledTokens::uncached(input_tokens, 0), true);
+                    c.add_and_bucket_usage(input_tokens, 0, family, cost);
                     let _ = handle.return_cookie(c, None).await;
                 }
             };
             // normalize error type for axum SSE
             let stream = stream.map_err(|e: axum::Error| -> BoxError { e.into() });
-            return Ok(Sse::new(stream)
-                .keep_alive(Default::default())
-                .into_response());
+            return Ok(sse_response(stream));
         }
 
         let stream = wreq_res.bytes_stream();
         let stream = stream.eventsource();
         let text = merge_sse(stream).await?;
         print_out_text(text.to_owned(), "claude_web_non_stream.txt");
+        self.reply.record(&text);
         let mut response =
             CreateMessageResponse::text(text.clone(), Default::default(), self.usage.to_owned());
 
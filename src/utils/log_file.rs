@@ -0,0 +1,423 @@
+//! Log file with size and age based rotation and retention
+//!
+//! Lines go to `clewdr.log`. Once it grows past `max_mb` or gets older than
+//! `max_age_hours`, it is renamed to a timestamped file under the same lock
+//! that guards writes, so a line never lands in a file that is being rotated
+//! away. Rotated files are compressed with zstd on a thread of their own, so
+//! the writer never waits for an archive, then files past the retention
+//! limits are removed, oldest first.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Sender, SyncSender},
+    },
+    time::{Duration, SystemTime},
+};
+
+use tracing::warn;
+
+use crate::config::LogRotation;
+
+const COMPRESSED_EXT: &str = "zst";
+
+struct Current {
+    file: File,
+    written: u64,
+    opened: SystemTime,
+}
+
+/// Work for the archiver thread
+enum Archive {
+    /// Compress a rotated file and apply retention
+    Rotated(PathBuf),
+    /// Answer once every file sent before is done
+    Flush(SyncSender<()>),
+}
+
+/// Compresses rotated files and enforces retention, one file at a time
+struct Archiver {
+    dir: PathBuf,
+    stem: String,
+    policy: LogRotation,
+}
+
+/// The live log file and its rotation policy
+pub struct RotatingFile {
+    dir: PathBuf,
+    /// File name without the `.log` extension
+    stem: String,
+    policy: LogRotation,
+    current: Mutex<Current>,
+    /// Tells apart files rotated within the same second
+    seq: AtomicU64,
+    archiver: Sender<Archive>,
+}
+
+impl RotatingFile {
+    /// Opens `<stem>.log` in `dir` for appending
+    pub fn open(dir: &Path, stem: &str, policy: LogRotation) -> io::Result<Arc<Self>> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(format!("{stem}.log"));
+        let current = Self::open_current(&path)?;
+        let (archiver, jobs) = mpsc::channel();
+        let archive = Archiver {
+            dir: dir.to_owned(),
+            stem: stem.to_string(),
+            policy: policy.to_owned(),
+        };
+        // ends once the file is dropped and the channel closes
+        std::thread::Builder::new()
+            .name("log-archiver".into())
+            .spawn(move || archive.run(jobs))?;
+        Ok(Arc::new(Self {
+            dir: dir.to_owned(),
+            stem: stem.to_string(),
+            policy,
+            current: Mutex::new(current),
+            seq: AtomicU64::new(0),
+            archiver,
+        }))
+    }
+
+    fn open_current(path: &Path) -> io::Result<Current> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let meta = file.metadata()?;
+        Ok(Current {
+            written: meta.len(),
+            opened: meta.created().unwrap_or_else(|_| SystemTime::now()),
+            file,
+        })
+    }
+
+    fn live_path(&self) -> PathBuf {
+        self.dir.join(format!("{}.log", self.stem))
+    }
+
+    fn is_due(&self, current: &Current, incoming: usize) -> bool {
+        let max_bytes = self.policy.max_mb * 1024 * 1024;
+        let too_big =
+            max_bytes > 0 && current.written > 0 && current.written + incoming as u64 > max_bytes;
+        let max_age = Duration::from_secs(self.policy.max_age_hours * 3600);
+        let too_old =
+            !max_age.is_zero() && current.opened.elapsed().is_ok_and(|age| age >= max_age);
+        too_big || too_old
+    }
+
+    /// Renames the live file away and opens a fresh one, under the write lock
+    fn swap(&self, current: &mut Current) -> io::Result<PathBuf> {
+        current.file.flush()?;
+        let now = chrono::Utc::now().format("%Y%m%d-%H%M%S");
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        let rotated = self.dir.join(format!("{}.{now}.{seq:06}.log", self.stem));
+        fs::rename(self.live_path(), &rotated)?;
+        *current = Self::open_current(&self.live_path())?;
+        current.opened = SystemTime::now();
+        Ok(rotated)
+    }
+
+    /// Writes one whole line, rotating first when the live file is due
+    pub fn write_line(&self, line: &[u8]) -> io::Result<()> {
+        let rotated = {
+            let mut current = self.current.lock().unwrap_or_else(PoisonError::into_inner);
+            let rotated = if self.is_due(&current, line.len()) {
+                Some(self.swap(&mut current)?)
+            } else {
+                None
+            };
+            current.file.write_all(line)?;
+            current.written += line.len() as u64;
+            rotated
+        };
+        if let Some(rotated) = rotated {
+            self.finish(&rotated);
+        }
+        Ok(())
+    }
+
+    /// Rotates the live file now, whatever its size and age
+    pub fn rotate(&self) -> io::Result<()> {
+        let rotated = {
+            let mut current = self.current.lock().unwrap_or_else(PoisonError::into_inner);
+            if current.written == 0 {
+                return Ok(());
+            }
+            self.swap(&mut current)?
+        };
+        self.finish(&rotated);
+        Ok(())
+    }
+
+    /// Hands a rotated file to the archiver thread
+    fn finish(&self, rotated: &Path) {
+        if self
+            .archiver
+            .send(Archive::Rotated(rotated.to_owned()))
+            .is_err()
+        {
+            warn!("Log archiver stopped, {} left as it is", rotated.display());
+        }
+    }
+
+    /// Waits until every file rotated so far is compressed and retention applied
+    pub fn wait_archived(&self) {
+        let (done, finished) = mpsc::sync_channel(1);
+        if self.archiver.send(Archive::Flush(done)).is_ok() {
+            _ = finished.recv();
+        }
+    }
+
+    /// Rotated files, oldest first
+    pub fn rotated_files(&self) -> io::Result<Vec<PathBuf>> {
+        rotated_files(&self.dir, &self.stem)
+    }
+}
+
+impl Archiver {
+    fn run(self, jobs: mpsc::Receiver<Archive>) {
+        for job in jobs {
+            match job {
+                Archive::Rotated(rotated) => self.finish(&rotated),
+                Archive::Flush(done) => _ = done.send(()),
+            }
+        }
+    }
+
+    /// Compresses a rotated file and enforces retention
+    fn finish(&self, rotated: &Path) {
+        if self.policy.compress
+            && let Err(e) = compress(rotated)
+        {
+            warn!("Failed to compress {}: {}", rotated.display(), e);
+        }
+        if let Err(e) = self.sweep() {
+            warn!("Failed to apply log retention: {}", e);
+        }
+    }
+
+    /// Removes rotated files older than `retain_days`, then the oldest ones past `retain_mb`
+    fn sweep(&self) -> io::Result<()> {
+        let max_age = Duration::from_secs(self.policy.retain_days * 24 * 3600);
+        let mut kept = vec![];
+        for path in rotated_files(&self.dir, &self.stem)? {
+            let Ok(meta) = fs::metadata(&path) else {
+                continue;
+            };
+            let expired = !max_age.is_zero()
+                && meta
+                    .modified()
+                    .ok()
+                    .and_then(|m| m.elapsed().ok())
+                    .is_some_and(|age| age > max_age);
+            if expired {
+                remove(&path)?;
+            } else {
+                kept.push((path, meta.len()));
+            }
+        }
+        let max_bytes = self.policy.retain_mb * 1024 * 1024;
+        if max_bytes == 0 {
+            return Ok(());
+        }
+        let mut total = kept.iter().map(|(_, len)| len).sum::<u64>();
+        for (path, len) in kept {
+            if total <= max_bytes {
+                break;
+            }
+            remove(&path)?;
+            total -= len;
+        }
+        Ok(())
+    }
+}
+
+/// Rotated files of `<stem>.log` in `dir`, oldest first
+pub fn rotated_files(dir: &Path, stem: &str) -> io::Result<Vec<PathBuf>> {
+    let live = format!("{stem}.log");
+    let prefix = format!("{stem}.");
+    let mut files = fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .filter(|e| {
+            let name = e.file_name().to_string_lossy().into_owned();
+            // daily files of older versions are named `clewdr.log.<date>`
+            name != live
+                && name.starts_with(&prefix)
+                && (name.ends_with(".log")
+                    || name.ends_with(&format!(".log.{COMPRESSED_EXT}"))
+                    || name.starts_with(&format!("{live}.")))
+                && !name.ends_with(".tmp")
+        })
+        .map(|e| e.path())
+        .collect::<Vec<_>>();
+    // rotated names sort by timestamp and sequence, compression finishing
+    // out of order would scramble modification times
+    let legacy = format!("{live}.");
+    files.sort_by_key(|p| {
+        let name = p.file_name().unwrap_or_default().to_string_lossy();
+        (!name.starts_with(&legacy), p.to_owned())
+    });
+    Ok(files)
+}
+
+/// Whether a rotated file was compressed with zstd
+pub fn is_compressed(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == COMPRESSED_EXT)
+}
+
+/// Removes a file, another rotation may have removed it already
+fn remove(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Replaces `path` with `path.zst`, the plain file stays until the archive is complete
+fn compress(path: &Path) -> io::Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let target = path.with_file_name(format!("{name}.{COMPRESSED_EXT}"));
+    let tmp = path.with_file_name(format!("{name}.{COMPRESSED_EXT}.tmp"));
+    let mut source = File::open(path)?;
+    let mut encoder = zstd::Encoder::new(File::create(&tmp)?, 0)?;
+    io::copy(&mut source, &mut encoder)?;
+    encoder.finish()?.sync_all()?;
+    fs::rename(&tmp, &target)?;
+    fs::remove_file(path)
+}
+
+/// Writer handed to `tracing_appender::non_blocking`, every write is one log line
+pub struct RotatingWriter(pub Arc<RotatingFile>);
+
+impl Write for RotatingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write_line(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0
+            .current
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .file
+            .flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, io::Read};
+
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("clewdr-log-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn read_all(path: &Path) -> String {
+        let mut text = String::new();
+        if is_compressed(path) {
+            zstd::Decoder::new(File::open(path).unwrap())
+                .unwrap()
+                .read_to_string(&mut text)
+                .unwrap();
+        } else {
+            File::open(path).unwrap().read_to_string(&mut text).unwrap();
+        }
+        text
+    }
+
+    fn policy() -> LogRotation {
+        LogRotation {
+            max_mb: 0,
+            max_age_hours: 0,
+            retain_mb: 0,
+            retain_days: 0,
+            compress: true,
+        }
+    }
+
+    #[test]
+    fn concurrent_rotations_lose_no_lines() {
+        let dir = temp_dir();
+        let log = RotatingFile::open(&dir, "clewdr", policy()).unwrap();
+        let writers = (0..4)
+            .map(|w| {
+                let log = log.clone();
+                std::thread::spawn(move || {
+                    for i in 0..500 {
+                        log.write_line(format!("{w}:{i}\n").as_bytes()).unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        let rotator = {
+            let log = log.clone();
+            std::thread::spawn(move || {
+                for _ in 0..20 {
+                    log.rotate().unwrap();
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+            })
+        };
+        writers.into_iter().for_each(|w| w.join().unwrap());
+        rotator.join().unwrap();
+        log.wait_archived();
+
+        let mut files = log.rotated_files().unwrap();
+        assert!(
+            files
+                .iter()
+                .all(|p| p.extension().unwrap() == COMPRESSED_EXT)
+        );
+        files.push(log.live_path());
+        let lines = files.iter().map(|p| read_all(p)).collect::<String>();
+        let mut seen = HashSet::new();
+        let mut last = [-1i64; 4];
+        for line in lines.lines() {
+            assert!(seen.insert(line.to_string()), "duplicate {line}");
+            let (w, i) = line.split_once(':').unwrap();
+            let (w, i) = (w.parse::<usize>().unwrap(), i.parse::<i64>().unwrap());
+            // files in rotation order keep each writer's sequence
+            assert_eq!(i, last[w] + 1, "gap before {line}");
+            last[w] = i;
+        }
+        assert_eq!(last, [499; 4]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rotates_by_size_and_keeps_the_newest() {
+        let dir = temp_dir();
+        let policy = LogRotation {
+            max_mb: 1,
+            retain_mb: 2,
+            compress: false,
+            ..policy()
+        };
+        let log = RotatingFile::open(&dir, "clewdr", policy).unwrap();
+        // a legacy daily file is the oldest and goes first
+        fs::write(dir.join("clewdr.log.2024-01-01"), vec![b'x'; 1024 * 1024]).unwrap();
+        let line = [b'y'; 1024 * 512];
+        for _ in 0..8 {
+            log.write_line(&line).unwrap();
+        }
+        log.wait_archived();
+        let files = log.rotated_files().unwrap();
+        let total = files
+            .iter()
+            .map(|p| fs::metadata(p).unwrap().len())
+            .sum::<u64>();
+        assert!(total <= 2 * 1024 * 1024);
+        assert_eq!(files.len(), 2);
+        assert!(!dir.join("clewdr.log.2024-01-01").exists());
+        assert_eq!(fs::metadata(log.live_path()).unwrap().len(), 1024 * 1024);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,113 @@
+use tracing::Subscriber;
+use tracing_subscriber::{
+    Layer,
+    fmt::{self, MakeWriter, time::ChronoLocal},
+    registry::LookupSpan,
+};
+
+use crate::config::LogFormat;
+
+/// Log layer writing text lines or tracing-subscriber's JSON lines
+///
+/// JSON lines hold `timestamp`, `level`, `fields` with the message, `target`
+/// and, inside spans, `spans` with each span's name and fields.
+///
+/// # Arguments
+/// * `format` - Format of the lines
+/// * `writer` - Destination of the lines
+/// * `timer` - Time format of text lines, JSON lines always carry full RFC 3339 dates
+/// * `ansi` - Whether text lines are colored
+pub fn log_layer<S, W>(
+    format: LogFormat,
+    writer: W,
+    timer: ChronoLocal,
+    ansi: bool,
+) -> Box<dyn Layer<S> + Send + Sync + 'static>
+where
+    S: Subscriber + for<'a> LookupSpan<'a> + 'static,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    match format {
+        LogFormat::Text => fmt::layer()
+            .with_writer(writer)
+            .with_ansi(ansi)
+            .with_timer(timer)
+            .boxed(),
+        LogFormat::Json => fmt::layer()
+            .json()
+            .with_writer(writer)
+            .with_timer(ChronoLocal::rfc_3339())
+            .with_current_span(false)
+            .with_span_list(true)
+            .boxed(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    use serde_json::Value;
+    use tracing_subscriber::{Registry, layer::SubscriberExt};
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Buffer {
+        type Writer = Buffer;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    fn capture(format: LogFormat, log: impl FnOnce()) -> String {
+        let buffer = Buffer::default();
+        let layer = log_layer(format, buffer.clone(), ChronoLocal::rfc_3339(), false);
+        tracing::subscriber::with_default(Registry::default().with(layer), log);
+        let bytes = buffer.0.lock().unwrap().to_vec();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn json_lines() {
+        let out = capture(LogFormat::Json, || {
+            let span = tracing::info_span!("claude_web", cookie = "sk-ant…");
+            let _guard = span.enter();
+            tracing::warn!(retries = 3, ok = false, "Failed hard");
+        });
+        let entry: Value = serde_json::from_str(out.trim()).unwrap();
+        assert_eq!(entry["level"], "WARN");
+        assert_eq!(entry["target"], module_path!());
+        assert_eq!(entry["fields"]["message"], "Failed hard");
+        assert_eq!(entry["fields"]["retries"], 3);
+        assert_eq!(entry["fields"]["ok"], false);
+        assert_eq!(entry["spans"][0]["name"], "claude_web");
+        assert_eq!(entry["spans"][0]["cookie"], "sk-ant…");
+        assert!(entry["timestamp"].as_str().is_some());
+    }
+
+    #[test]
+    fn text_lines() {
+        let out = capture(LogFormat::Text, || tracing::info!(n = 1, "plain"));
+        assert!(serde_json::from_str::<Value>(out.trim()).is_err());
+        assert!(out.contains("INFO"));
+        assert!(out.contains("plain n=1"));
+    }
+}
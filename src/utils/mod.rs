@@ -1,3 +1,11 @@
+mod log_file;
+mod log_format;
+mod sse;
+
+pub use log_file::{RotatingFile, RotatingWriter, is_compressed, rotated_files};
+pub use log_format::log_layer;
+pub use sse::*;
+
 use axum::body::Body;
 use colored::{ColoredString, Colorize};
 use tokio::spawn;
@@ -53,6 +61,11 @@ pub fn print_out_text(text: String, file_name: &str) {
     });
 }
 
+/// Random value in `[0, 1)`, not for anything secret
+pub fn random_unit() -> f64 {
+    (uuid::Uuid::new_v4().as_u64_pair().0 >> 11) as f64 / (1u64 << 53) as f64
+}
+
 /// Timezone for the API
 pub const TIME_ZONE: &str = "America/New_York";
 
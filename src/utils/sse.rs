@@ -0,0 +1,185 @@
+//! Streaming responses to the client
+//!
+//! Proxies in front of ClewdR close connections that stay silent for too long,
+//! so streamed responses send `: ping` comments while upstream is quiet. The
+//! pings also make a client disconnect visible: the failed write drops the
+//! response body, which drops the upstream stream and cancels the request.
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use axum::{
+    BoxError,
+    response::{
+        IntoResponse, Response, Sse,
+        sse::{Event, KeepAlive},
+    },
+};
+use futures::Stream;
+use tracing::info;
+
+use crate::config::CLEWDR_CONFIG;
+
+/// Wraps a stream of events into an SSE response with the configured keep-alive
+pub fn sse_response<S, E>(stream: S) -> Response
+where
+    S: Stream<Item = Result<Event, E>> + Send + 'static,
+    E: Into<BoxError>,
+{
+    let secs = CLEWDR_CONFIG.load().sse_keep_alive_secs;
+    sse_with_keep_alive(stream, (secs > 0).then(|| Duration::from_secs(secs)))
+}
+
+/// Wraps a stream of events into an SSE response
+///
+/// # Arguments
+/// * `stream` - Events to send, keep-alives only ever go between two of them
+/// * `interval` - Silence before a `: ping` comment is sent, `None` disables it
+pub fn sse_with_keep_alive<S, E>(stream: S, interval: Option<Duration>) -> Response
+where
+    S: Stream<Item = Result<Event, E>> + Send + 'static,
+    E: Into<BoxError>,
+{
+    match interval {
+        Some(interval) => Sse::new(stream)
+            .keep_alive(KeepAlive::new().interval(interval).text("ping"))
+            .into_response(),
+        None => Sse::new(stream).into_response(),
+    }
+}
+
+/// Upstream stream that logs when it is dropped before it finished
+///
+/// Cancelling needs no help: dropping the stream drops the upstream response
+/// and closes its connection. This wrapper only makes the early end visible.
+pub struct LogOnDrop<S> {
+    inner: Pin<Box<S>>,
+    finished: bool,
+}
+
+impl<S> LogOnDrop<S> {
+    /// Wraps an upstream stream
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner: Box::pin(inner),
+            finished: false,
+        }
+    }
+}
+
+impl<S: Stream> Stream for LogOnDrop<S> {
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let poll = self.inner.as_mut().poll_next(cx);
+        if let Poll::Ready(None) = poll {
+            self.finished = true;
+        }
+        poll
+    }
+}
+
+impl<S> Drop for LogOnDrop<S> {
+    fn drop(&mut self) {
+        if !self.finished {
+            info!("Response dropped before upstream finished, upstream request cancelled");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    };
+
+    use bytes::Bytes;
+    use eventsource_stream::Eventsource;
+    use futures::{StreamExt, TryStreamExt};
+
+    use super::*;
+
+    /// Mock upstream sending its chunks with a pause before each
+    fn slow_upstream(
+        chunks: Vec<(u64, &'static str)>,
+        dropped: Arc<AtomicBool>,
+    ) -> impl Stream<Item = Result<Bytes, std::io::Error>> {
+        struct Flag(Arc<AtomicBool>);
+        impl Drop for Flag {
+            fn drop(&mut self) {
+                self.0.store(true, Ordering::SeqCst);
+            }
+        }
+        let flag = Flag(dropped);
+        futures::stream::iter(chunks).then(move |(pause, chunk)| {
+            let _ = &flag;
+            async move {
+                tokio::time::sleep(Duration::from_millis(pause)).await;
+                Ok(Bytes::from_static(chunk.as_bytes()))
+            }
+        })
+    }
+
+    fn proxied(
+        upstream: impl Stream<Item = Result<Bytes, std::io::Error>> + Send + 'static,
+    ) -> Response {
+        let events = LogOnDrop::new(upstream)
+            .eventsource()
+            .map_ok(|e| Event::default().event(e.event).data(e.data));
+        sse_with_keep_alive(events, Some(Duration::from_millis(40)))
+    }
+
+    #[tokio::test]
+    async fn pings_only_between_events() {
+        let dropped = Arc::new(AtomicBool::new(false));
+        // the event is split and upstream is silent in the middle of it
+        let upstream = slow_upstream(
+            vec![
+                (0, "event: completion\ndata: hel"),
+                (150, "lo\n\n"),
+                (0, "event: completion\ndata: done\n\n"),
+            ],
+            dropped.clone(),
+        );
+        let body = axum::body::to_bytes(proxied(upstream).into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        let frames = body.split_terminator("\n\n").collect::<Vec<_>>();
+        assert!(frames.iter().filter(|f| **f == ": ping").count() >= 2);
+        let events = frames
+            .into_iter()
+            .filter(|f| *f != ": ping")
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            [
+                "event: completion\ndata: hello",
+                "event: completion\ndata: done"
+            ]
+        );
+        assert!(dropped.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn client_disconnect_cancels_upstream() {
+        let dropped = Arc::new(AtomicBool::new(false));
+        let upstream = slow_upstream(
+            vec![(0, "data: first\n\n"), (60_000, "data: never sent\n\n")],
+            dropped.clone(),
+        );
+        let mut body = proxied(upstream).into_body().into_data_stream();
+        let first = body.next().await.unwrap().unwrap();
+        assert_eq!(first, "event: message\ndata: first\n\n");
+        // upstream is still generating when the client goes away
+        let ping = body.next().await.unwrap().unwrap();
+        assert_eq!(ping, ": ping\n\n");
+        assert!(!dropped.load(Ordering::SeqCst));
+        drop(body);
+        assert!(dropped.load(Ordering::SeqCst));
+    }
+}